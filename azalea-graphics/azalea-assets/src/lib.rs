@@ -1,8 +1,15 @@
 pub mod processed;
 mod raw;
-use std::{collections::HashMap, fs, path::PathBuf, sync::Arc, time::Instant};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
 
 use azalea_block::BlockState;
+use glam::IVec3;
 use log::*;
 use raw::{
     block_state::{BlockRenderState, Variant},
@@ -12,8 +19,9 @@ use raw::{
 use self::{
     processed::{
         VariantDesc,
-        atlas::{Atlas, PlacedSprite, build_atlas, stitch_sprites},
-        model,
+        atlas::{Atlas, PlacedSprite, SpriteLayers, SpriteUv, build_atlas, stitch_sprites},
+        ctm::{ConnectedTexture, ConnectedTextures, load_connected_textures},
+        model, pick_weighted, position_seed,
     },
     raw::atlas::SpriteAtlas,
 };
@@ -21,27 +29,101 @@ use crate::processed::{atlas::TextureEntry, entity_model::Model};
 
 pub struct Assets {
     path: PathBuf,
-    blockstate_to_models: Vec<Vec<VariantDesc>>,
+    /// The parts of each blockstate's model, every part picks one of its
+    /// models by weight.
+    blockstate_to_models: Vec<Vec<Vec<VariantDesc>>>,
 
     pub block_atlas: Atlas,
+    /// Layers of the block sprites when they are drawn from a texture array
+    /// instead of the atlas.
+    pub block_layers: Option<SpriteLayers>,
     pub grass_colormap: Option<image::RgbaImage>,
     pub foliage_colormap: Option<image::RgbaImage>,
 
     pub block_textures: HashMap<String, TextureEntry>,
+    pub connected_textures: ConnectedTextures,
 
     pub entity_models: HashMap<String, Model>,
 }
 
 impl Assets {
-    pub fn get_variant_descs(&self, state: BlockState) -> &[VariantDesc] {
-        let id = state.id();
-        &self.blockstate_to_models[id as usize]
+    /// Assets that aren't loaded from disk, with only the given block models
+    /// and atlas. Blocks missing from `models` have no model, like blocks
+    /// without a blockstate definition. Every model is a part of its own, none
+    /// are picked at random. Meant for tests of the mesher.
+    pub fn from_models(models: HashMap<BlockState, Vec<VariantDesc>>, block_atlas: Atlas) -> Self {
+        let blockstate_to_models = (0..=BlockState::MAX_STATE)
+            .map(|raw| {
+                let state = BlockState::try_from(raw).unwrap();
+                let descs = models.get(&state).cloned().unwrap_or_default();
+                descs.into_iter().map(|desc| vec![desc]).collect()
+            })
+            .collect();
+
+        Self {
+            path: PathBuf::new(),
+            blockstate_to_models,
+            block_atlas,
+            block_layers: None,
+            grass_colormap: None,
+            foliage_colormap: None,
+            block_textures: HashMap::new(),
+            connected_textures: ConnectedTextures::default(),
+            entity_models: HashMap::new(),
+        }
+    }
+
+    /// The parts of the model of `state`, each a list of models to pick from
+    /// by weight.
+    pub fn get_variant_parts(&self, state: BlockState) -> &[Vec<VariantDesc>] {
+        &self.blockstate_to_models[state.id() as usize]
+    }
+
+    /// Every model `state` may be drawn with, whichever is picked.
+    pub fn get_variant_descs(&self, state: BlockState) -> impl Iterator<Item = &VariantDesc> {
+        self.get_variant_parts(state).iter().flatten()
+    }
+
+    /// The models `state` is drawn with at `pos`, one per part. The same
+    /// position always picks the same models, so re-meshing a section doesn't
+    /// change its look.
+    pub fn variant_descs_at(
+        &self,
+        state: BlockState,
+        pos: IVec3,
+    ) -> impl Iterator<Item = &VariantDesc> {
+        let seed = position_seed(pos);
+        self.get_variant_parts(state)
+            .iter()
+            .filter_map(move |choices| pick_weighted(choices, seed))
+    }
+
+    /// The connected texture replacing `sprite` on faces of `state`, if a pack
+    /// defines one.
+    pub fn get_connected_texture(
+        &self,
+        state: BlockState,
+        sprite: &str,
+    ) -> Option<&ConnectedTexture> {
+        if self.connected_textures.is_empty() {
+            return None;
+        }
+        self.connected_textures.get(state.to_trait().id(), sprite)
     }
 
     pub fn get_sprite_rect(&self, name: &str) -> Option<&PlacedSprite> {
         self.block_atlas.sprites.get(name)
     }
 
+    /// Where the block sprite `name` is in the atlas, in normalized
+    /// coordinates, or its layer if the sprites are in a texture array.
+    pub fn sprite_uv(&self, name: &str) -> Option<SpriteUv> {
+        match &self.block_layers {
+            Some(layers) => layers.sprite_uv(name),
+            None => self.block_atlas.sprite_uv(name),
+        }
+    }
+
     pub fn sample_grass_colormap(&self, temperature: f64, downfall: f64) -> Option<[f32; 3]> {
         self.grass_colormap
             .as_ref()
@@ -85,7 +167,61 @@ fn sample_colormap_at_climate(
     ]
 }
 
-pub fn load_assets(path: impl Into<PathBuf>, max_tex: u32) -> Assets {
+/// The models a variant or multipart case picks from, with their weights.
+fn resolve_variant(
+    variant: &Variant,
+    block_models: &HashMap<String, Arc<model::BlockModel>>,
+) -> Vec<VariantDesc> {
+    let descs = match variant {
+        Variant::Single(desc) => std::slice::from_ref(desc),
+        Variant::Multiple(arr) => arr.as_slice(),
+    };
+    descs
+        .iter()
+        .map(|desc| {
+            let model_name = desc.model.strip_prefix("minecraft:").unwrap_or(&desc.model);
+            VariantDesc {
+                model: block_models[model_name].clone(),
+                x_rotation: desc.x_rotation,
+                y_rotation: desc.y_rotation,
+                uvlock: desc.uvlock,
+                weight: desc.weight,
+            }
+        })
+        .collect()
+}
+
+/// Entries of an extracted `assets/minecraft` directory that [`load_assets`]
+/// can't do without.
+const REQUIRED_ENTRIES: &[&str] = &[
+    "models/block",
+    "blockstates",
+    "textures",
+    "atlases/blocks.json",
+    "entity_models.json",
+];
+
+/// The entries [`load_assets`] needs that don't exist under `path`, empty if
+/// it looks like an extracted `assets/minecraft` directory.
+pub fn missing_entries(path: impl AsRef<Path>) -> Vec<PathBuf> {
+    let path = path.as_ref();
+    REQUIRED_ENTRIES
+        .iter()
+        .map(|entry| path.join(entry))
+        .filter(|entry| !entry.exists())
+        .collect()
+}
+
+/// Loads the assets under `path`. Block textures are shrunk by
+/// `texture_scale` before stitching, see [`TextureEntry::downscale`]. With
+/// `max_layers` the block sprites are also given one layer each of a texture
+/// array, unless there are more of them than that.
+pub fn load_assets(
+    path: impl Into<PathBuf>,
+    max_tex: u32,
+    texture_scale: u32,
+    max_layers: Option<u32>,
+) -> Assets {
     let path = path.into();
 
     let start_total = Instant::now();
@@ -156,7 +292,7 @@ pub fn load_assets(path: impl Into<PathBuf>, max_tex: u32) -> Assets {
     );
 
     let start = Instant::now();
-    let blockstate_to_models: Vec<Vec<VariantDesc>> = (0..=BlockState::MAX_STATE)
+    let blockstate_to_models: Vec<Vec<Vec<VariantDesc>>> = (0..=BlockState::MAX_STATE)
         .map(|raw: u16| {
             let bs = BlockState::try_from(raw).unwrap();
             let dyn_block = bs.to_trait();
@@ -180,32 +316,7 @@ pub fn load_assets(path: impl Into<PathBuf>, max_tex: u32) -> Assets {
                         .map(|(_, v)| v)
                         .unwrap_or(&variants[0].1);
 
-                    match variant {
-                        Variant::Single(desc) => {
-                            let model_name =
-                                desc.model.strip_prefix("minecraft:").unwrap_or(&desc.model);
-                            vec![VariantDesc {
-                                model: block_models[model_name].clone(),
-                                x_rotation: desc.x_rotation,
-                                y_rotation: desc.y_rotation,
-                                uvlock: desc.uvlock,
-                            }]
-                        }
-                        Variant::Multiple(arr) => arr
-                            .first()
-                            .iter()
-                            .map(|desc| {
-                                let model_name =
-                                    desc.model.strip_prefix("minecraft:").unwrap_or(&desc.model);
-                                VariantDesc {
-                                    model: block_models[model_name].clone(),
-                                    x_rotation: desc.x_rotation,
-                                    y_rotation: desc.y_rotation,
-                                    uvlock: desc.uvlock,
-                                }
-                            })
-                            .collect(),
-                    }
+                    vec![resolve_variant(variant, &block_models)]
                 }
                 BlockRenderState::MultiPart(multi_part) => multi_part
                     .iter()
@@ -214,20 +325,8 @@ pub fn load_assets(path: impl Into<PathBuf>, max_tex: u32) -> Assets {
                             .as_ref()
                             .map_or(true, |cond| cond.matches(dyn_block))
                     })
-                    .filter_map(|case| match &case.apply {
-                        Variant::Single(desc) => Some(desc),
-                        Variant::Multiple(arr) => arr.first(),
-                    })
-                    .map(|desc| {
-                        let model_name =
-                            desc.model.strip_prefix("minecraft:").unwrap_or(&desc.model);
-                        VariantDesc {
-                            model: block_models[model_name].clone(),
-                            x_rotation: desc.x_rotation,
-                            y_rotation: desc.y_rotation,
-                            uvlock: desc.uvlock,
-                        }
-                    })
+                    .map(|case| resolve_variant(&case.apply, &block_models))
+                    .filter(|choices| !choices.is_empty())
                     .collect(),
             }
         })
@@ -255,7 +354,20 @@ pub fn load_assets(path: impl Into<PathBuf>, max_tex: u32) -> Assets {
         SpriteAtlas::from_str(&blocks_atlas_json).expect("invalid atlases/blocks.json");
 
     let textures_root = path.join("textures");
-    let block_textures = build_atlas(&textures_root, &blocks_atlas).expect("build entries");
+    let mut block_textures = build_atlas(&textures_root, &blocks_atlas).expect("build entries");
+
+    let connected_textures =
+        load_connected_textures(&path, &mut block_textures).unwrap_or_else(|e| {
+            warn!("Failed to load connected textures: {e}");
+            ConnectedTextures::default()
+        });
+
+    if texture_scale > 1 {
+        for texture in block_textures.values_mut() {
+            texture.downscale(texture_scale);
+        }
+        info!("Downscaled block textures by {texture_scale}");
+    }
 
     let (max_w, max_h) = (max_tex, max_tex);
     let block_atlas = stitch_sprites(&block_textures, max_w, max_h).expect("stitch sprites");
@@ -267,6 +379,24 @@ pub fn load_assets(path: impl Into<PathBuf>, max_tex: u32) -> Assets {
         start.elapsed()
     );
 
+    let block_layers = max_layers.and_then(|max_layers| {
+        let layers = SpriteLayers::new(&block_textures);
+        if layers.len() > max_layers {
+            warn!(
+                "{} block sprites don't fit in a texture array of {max_layers} layers, using the atlas",
+                layers.len()
+            );
+            return None;
+        }
+        info!(
+            "Laid out {} block sprites as {}x{} texture array layers",
+            layers.len(),
+            layers.width,
+            layers.height
+        );
+        Some(layers)
+    });
+
     let start = Instant::now();
     let grass_colormap = load_colormap(&textures_root, "colormap/grass.png");
     let foliage_colormap = load_colormap(&textures_root, "colormap/foliage.png");
@@ -294,7 +424,9 @@ pub fn load_assets(path: impl Into<PathBuf>, max_tex: u32) -> Assets {
         foliage_colormap,
 
         block_textures,
+        connected_textures,
         block_atlas,
+        block_layers,
 
         entity_models,
     }
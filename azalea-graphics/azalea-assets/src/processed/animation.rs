@@ -4,6 +4,8 @@ pub struct Animation {
     height: Option<u32>,
     frametime: Option<u32>,
     frames: Option<Vec<Frame>>,
+    #[serde(default)]
+    interpolate: bool,
 }
 
 #[derive(serde::Deserialize, serde::Serialize, Debug)]
@@ -42,6 +44,19 @@ impl Animation {
         (col * fw, row * fh)
     }
 
+    /// Shrinks the explicit frame size by `factor`, for a sheet that was
+    /// downscaled by the same amount.
+    pub fn downscale(&mut self, factor: u32) {
+        self.width = self.width.map(|w| w / factor);
+        self.height = self.height.map(|h| h / factor);
+    }
+
+    /// Whether frames should be blended into each other instead of switching
+    /// discretely.
+    pub fn interpolate(&self) -> bool {
+        self.interpolate
+    }
+
     pub fn frame_time(&self, frame: usize) -> u32 {
         self.frames
             .as_ref()
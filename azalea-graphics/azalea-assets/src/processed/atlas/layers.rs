@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+use image::{RgbaImage, imageops::FilterType};
+
+use crate::processed::atlas::{SpriteUv, TextureEntry};
+
+/// Block sprites laid out one per layer of a texture array instead of packed
+/// into an atlas. Every layer has the size of the largest sprite frame, smaller
+/// sprites are scaled up to it.
+#[derive(Debug)]
+pub struct SpriteLayers {
+    pub width: u32,
+    pub height: u32,
+    /// Layer of each sprite, in the order of their names.
+    pub layers: HashMap<String, u32>,
+}
+
+impl SpriteLayers {
+    pub fn new(textures: &HashMap<String, TextureEntry>) -> Self {
+        let (width, height) = textures
+            .values()
+            .map(TextureEntry::size)
+            .fold((1, 1), |(w, h), (fw, fh)| (w.max(fw), h.max(fh)));
+
+        let mut names: Vec<_> = textures.keys().collect();
+        names.sort();
+        let layers = names
+            .into_iter()
+            .enumerate()
+            .map(|(layer, name)| (name.clone(), layer as u32))
+            .collect();
+
+        Self {
+            width,
+            height,
+            layers,
+        }
+    }
+
+    pub fn len(&self) -> u32 {
+        self.layers.len() as u32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// The whole of the sprite's layer.
+    pub fn sprite_uv(&self, name: &str) -> Option<SpriteUv> {
+        let &layer = self.layers.get(name)?;
+        Some(SpriteUv {
+            uv: [0.0, 0.0, 1.0, 1.0],
+            width: self.width,
+            height: self.height,
+            layer,
+        })
+    }
+
+    /// Scales a sprite frame up to the layer size, without blurring its
+    /// pixels.
+    pub fn fit_frame(&self, frame: RgbaImage) -> RgbaImage {
+        if frame.dimensions() == (self.width, self.height) {
+            frame
+        } else {
+            image::imageops::resize(&frame, self.width, self.height, FilterType::Nearest)
+        }
+    }
+}
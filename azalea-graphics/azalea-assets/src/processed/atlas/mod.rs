@@ -4,7 +4,7 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use image::RgbaImage;
+use image::{RgbaImage, imageops::FilterType};
 use thiserror::Error;
 
 use crate::{
@@ -12,9 +12,11 @@ use crate::{
     raw::atlas::{SpriteAtlas, SpriteSource},
 };
 
+mod layers;
 mod sticher;
 
-pub use sticher::{Atlas, PlacedSprite, StitchError, stitch_sprites};
+pub use layers::SpriteLayers;
+pub use sticher::{Atlas, PlacedSprite, SpriteUv, StitchError, stitch_sprites};
 
 #[derive(Error, Debug)]
 pub enum AtlasError {
@@ -47,6 +49,32 @@ impl TextureEntry {
             .map(|animation| animation.size(image_size))
             .unwrap_or(image_size)
     }
+
+    /// Shrinks the sheet and its frames by `factor`, rounded down to a power
+    /// of two that still divides the frame size so frames stay aligned.
+    pub fn downscale(&mut self, factor: u32) {
+        let (fw, fh) = self.size();
+        let mut factor = if factor > 1 { 1 << factor.ilog2() } else { 1 };
+        while factor > 1 && (fw % factor != 0 || fh % factor != 0) {
+            factor /= 2;
+        }
+        if factor == 1 {
+            return;
+        }
+
+        let (w, h) = self.data.dimensions();
+        self.data =
+            image::imageops::resize(&self.data, w / factor, h / factor, FilterType::Triangle);
+        if let Some(animation) = &mut self.animation {
+            animation.downscale(factor);
+        }
+    }
+}
+
+/// Contents of a `.png.mcmeta` file, only the animation section is used.
+#[derive(serde::Deserialize, Debug)]
+struct TextureMeta {
+    animation: Option<Animation>,
 }
 
 pub fn build_atlas(
@@ -123,7 +151,7 @@ pub fn build_atlas(
     Ok(textures)
 }
 
-fn load_texture_entry(path: &Path) -> Result<TextureEntry, AtlasError> {
+pub(crate) fn load_texture_entry(path: &Path) -> Result<TextureEntry, AtlasError> {
     let data = image::open(path)?.into_rgba8();
 
     let mcmeta_path = {
@@ -134,7 +162,7 @@ fn load_texture_entry(path: &Path) -> Result<TextureEntry, AtlasError> {
 
     let animation = if mcmeta_path.exists() {
         let json = fs::read_to_string(&mcmeta_path)?;
-        Some(serde_json::from_str::<Animation>(&json)?)
+        serde_json::from_str::<TextureMeta>(&json)?.animation
     } else {
         None
     };
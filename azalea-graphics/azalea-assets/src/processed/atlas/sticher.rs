@@ -19,6 +19,50 @@ pub struct Atlas {
     pub sprites: HashMap<String, PlacedSprite>,
 }
 
+/// A sprite's rect in normalized atlas coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpriteUv {
+    /// `[min_u, min_v, max_u, max_v]` of the sprite's edges.
+    pub uv: [f32; 4],
+    /// Size of the sprite in pixels.
+    pub width: u32,
+    pub height: u32,
+    /// Texture array layer holding the sprite, always 0 for the atlas.
+    pub layer: u32,
+}
+
+impl SpriteUv {
+    /// The rect shrunk by `texels` on each side, half a texel is enough so
+    /// sampling at its edges never bleeds into the neighbouring sprites.
+    pub fn inset_uv(&self, texels: f32) -> [f32; 4] {
+        let [u0, v0, u1, v1] = self.uv;
+        // never past the center, or the rect would flip
+        let texels = texels.clamp(0.0, self.width.min(self.height) as f32 * 0.5);
+        let du = (u1 - u0) / self.width as f32 * texels;
+        let dv = (v1 - v0) / self.height as f32 * texels;
+        [u0 + du, v0 + dv, u1 - du, v1 - dv]
+    }
+}
+
+impl Atlas {
+    pub fn sprite_uv(&self, name: &str) -> Option<SpriteUv> {
+        let sprite = self.sprites.get(name)?;
+        let w = self.width as f32;
+        let h = self.height as f32;
+        Some(SpriteUv {
+            uv: [
+                sprite.x as f32 / w,
+                sprite.y as f32 / h,
+                (sprite.x + sprite.width) as f32 / w,
+                (sprite.y + sprite.height) as f32 / h,
+            ],
+            width: sprite.width,
+            height: sprite.height,
+            layer: 0,
+        })
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum StitchError {
     #[error("Cannot fit sprites into atlas of size {max_width}x{max_height}")]
@@ -0,0 +1,178 @@
+//! Connected textures in the OptiFine/Continuity format. Only the full `ctm`
+//! method with its 47 tiles is supported.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use log::*;
+
+use crate::{
+    processed::atlas::{AtlasError, TextureEntry, load_texture_entry},
+    raw::ctm::CtmProperties,
+};
+
+/// Number of tiles in a full `ctm` set.
+pub const CTM_TILES: usize = 47;
+
+/// Which neighbours a connected texture joins with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connect {
+    /// Neighbours of the same block.
+    Block,
+    /// Neighbours whose face uses the same texture.
+    Tile,
+}
+
+#[derive(Debug)]
+pub struct ConnectedTexture {
+    /// Sprite names of the tiles, in the standard 47 tile layout.
+    pub tiles: Vec<String>,
+    pub connect: Connect,
+}
+
+#[derive(Default)]
+pub struct ConnectedTextures {
+    /// Keyed by block id without namespace.
+    by_block: HashMap<String, Arc<ConnectedTexture>>,
+    /// Keyed by the sprite name of the replaced texture.
+    by_tile: HashMap<String, Arc<ConnectedTexture>>,
+}
+
+impl ConnectedTextures {
+    pub fn is_empty(&self) -> bool {
+        self.by_block.is_empty() && self.by_tile.is_empty()
+    }
+
+    /// The connected texture replacing `sprite` on faces of `block`, rules
+    /// matching the texture win over ones matching the block.
+    pub fn get(&self, block: &str, sprite: &str) -> Option<&ConnectedTexture> {
+        self.by_tile
+            .get(sprite)
+            .or_else(|| self.by_block.get(block))
+            .map(Arc::as_ref)
+    }
+}
+
+/// Loads every `ctm` properties file below `optifine/ctm` and adds its tiles to
+/// `textures`, so they end up in the block atlas.
+pub fn load_connected_textures(
+    root: &Path,
+    textures: &mut HashMap<String, TextureEntry>,
+) -> Result<ConnectedTextures, AtlasError> {
+    let mut connected = ConnectedTextures::default();
+
+    let ctm_root = root.join("optifine/ctm");
+    if !ctm_root.is_dir() {
+        return Ok(connected);
+    }
+
+    let mut files = Vec::new();
+    find_properties(&ctm_root, &mut files)?;
+
+    for path in files {
+        let props = CtmProperties::from_str(&fs::read_to_string(&path)?);
+        if props.method != "ctm" {
+            debug!(
+                "Skipping {}, method {:?} is not supported",
+                path.display(),
+                props.method
+            );
+            continue;
+        }
+        if props.tiles.len() < CTM_TILES {
+            warn!(
+                "Skipping {}, expected {CTM_TILES} tiles but got {}",
+                path.display(),
+                props.tiles.len()
+            );
+            continue;
+        }
+
+        let dir = path.parent().unwrap();
+        // plain names are next to the properties file, paths start at the root
+        let tile_paths: Vec<PathBuf> = props.tiles[..CTM_TILES]
+            .iter()
+            .map(|tile| {
+                if tile.contains('/') {
+                    root.join(format!("{tile}.png"))
+                } else {
+                    dir.join(format!("{tile}.png"))
+                }
+            })
+            .collect();
+        if let Some(missing) = tile_paths.iter().find(|p| !p.exists()) {
+            warn!(
+                "Skipping {}, missing tile {}",
+                path.display(),
+                missing.display()
+            );
+            continue;
+        }
+
+        let mut tiles = Vec::with_capacity(CTM_TILES);
+        for tile_path in tile_paths {
+            let name = tile_path
+                .strip_prefix(root)
+                .unwrap()
+                .with_extension("")
+                .to_string_lossy()
+                .replace('\\', "/");
+            textures.insert(name.clone(), load_texture_entry(&tile_path)?);
+            tiles.push(name);
+        }
+
+        let mut match_tiles = props.match_tiles;
+        // without any rule the file name is the texture it replaces
+        if match_tiles.is_empty() && props.match_blocks.is_empty() {
+            match_tiles.push(path.file_stem().unwrap().to_string_lossy().to_string());
+        }
+
+        let connect = match props.connect.as_deref() {
+            Some("tile") => Connect::Tile,
+            Some("block") => Connect::Block,
+            _ if match_tiles.is_empty() => Connect::Block,
+            _ => Connect::Tile,
+        };
+        let texture = Arc::new(ConnectedTexture { tiles, connect });
+
+        for block in &props.match_blocks {
+            let block = strip_namespace(block);
+            // block state filters are not supported, the rule applies to all states
+            let block = block.split_once(':').map_or(block, |(id, _)| id);
+            connected
+                .by_block
+                .insert(block.to_string(), texture.clone());
+        }
+        for tile in &match_tiles {
+            let tile = strip_namespace(tile);
+            let sprite = if tile.contains('/') {
+                tile.to_string()
+            } else {
+                format!("block/{tile}")
+            };
+            connected.by_tile.insert(sprite, texture.clone());
+        }
+    }
+
+    Ok(connected)
+}
+
+fn find_properties(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), AtlasError> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_properties(&path, files)?;
+        } else if path.extension().is_some_and(|e| e == "properties") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn strip_namespace(s: &str) -> &str {
+    s.strip_prefix("minecraft:").unwrap_or(s)
+}
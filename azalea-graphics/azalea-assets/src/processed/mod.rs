@@ -1,9 +1,12 @@
 use std::sync::Arc;
 
+use glam::IVec3;
+
 use crate::processed::model::BlockModel;
 
 pub mod animation;
 pub mod atlas;
+pub mod ctm;
 pub mod model;
 pub mod entity_model;
 
@@ -16,4 +19,119 @@ pub struct VariantDesc {
     pub y_rotation: i32,
 
     pub uvlock: bool,
+
+    /// Chance of this model relative to the others it is picked from.
+    pub weight: u32,
+}
+
+/// Java: `Mth.getSeed`, the hash vanilla seeds the random model choice of a
+/// block with.
+pub fn position_seed(pos: IVec3) -> i64 {
+    let seed = (pos.x.wrapping_mul(3129871) as i64)
+        ^ (pos.z as i64).wrapping_mul(116129781)
+        ^ pos.y as i64;
+    let seed = seed
+        .wrapping_mul(seed)
+        .wrapping_mul(42317861)
+        .wrapping_add(seed.wrapping_mul(11));
+    seed >> 16
+}
+
+/// The model of `choices` that `seed` falls on when each takes up its weight,
+/// `None` if there is nothing to pick from.
+pub fn pick_weighted(choices: &[VariantDesc], seed: i64) -> Option<&VariantDesc> {
+    let total: u64 = choices.iter().map(|desc| desc.weight as u64).sum();
+    if total == 0 {
+        return choices.first();
+    }
+    let mut roll = seed.rem_euclid(total as i64) as u64;
+    choices.iter().find(|desc| {
+        let hit = roll < desc.weight as u64;
+        roll = roll.saturating_sub(desc.weight as u64);
+        hit
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn variants(weights: &[u32]) -> Vec<VariantDesc> {
+        let model = Arc::new(BlockModel {
+            ambient_occlusion: true,
+            textures: HashMap::new(),
+            elements: Vec::new(),
+            render_layer: None,
+        });
+        weights
+            .iter()
+            .enumerate()
+            .map(|(i, &weight)| VariantDesc {
+                model: Arc::clone(&model),
+                x_rotation: 0,
+                // tells the variants apart
+                y_rotation: i as i32,
+                uvlock: false,
+                weight,
+            })
+            .collect()
+    }
+
+    fn pick(choices: &[VariantDesc], seed: i64) -> Option<i32> {
+        pick_weighted(choices, seed).map(|desc| desc.y_rotation)
+    }
+
+    #[test]
+    fn same_position_same_pick() {
+        let choices = variants(&[1, 2, 3]);
+        for pos in [IVec3::new(1, 2, 3), IVec3::new(-5, -3, -7), IVec3::ZERO] {
+            let seed = position_seed(pos);
+            assert_eq!(seed, position_seed(pos));
+            assert_eq!(pick(&choices, seed), pick(&choices, position_seed(pos)));
+        }
+    }
+
+    #[test]
+    fn picks_follow_the_weights() {
+        let choices = variants(&[3, 1]);
+        let mut counts = [0; 2];
+        for x in -32..32 {
+            for y in 0..4 {
+                for z in -32..32 {
+                    let seed = position_seed(IVec3::new(x, y, z));
+                    counts[pick(&choices, seed).unwrap() as usize] += 1;
+                }
+            }
+        }
+
+        let share = counts[0] as f32 / (counts[0] + counts[1]) as f32;
+        assert!((share - 0.75).abs() < 0.02, "{share}");
+    }
+
+    #[test]
+    fn negative_seeds() {
+        let choices = variants(&[1, 1]);
+        assert!(position_seed(IVec3::new(-5, -3, -7)) < 0);
+        assert_eq!(pick(&choices, -1), Some(1));
+        assert_eq!(pick(&choices, -2), Some(0));
+        assert_eq!(pick(&choices, i64::MIN), Some(0));
+    }
+
+    #[test]
+    fn single_weight() {
+        let choices = variants(&[1]);
+        for seed in [i64::MIN, -1, 0, 1, i64::MAX] {
+            assert_eq!(pick(&choices, seed), Some(0));
+        }
+    }
+
+    #[test]
+    fn nothing_to_pick() {
+        assert!(pick_weighted(&[], 0).is_none());
+        assert!(pick_weighted(&[], -1).is_none());
+        // only weights of zero fall back to the first choice
+        assert_eq!(pick(&variants(&[0, 0]), 5), Some(0));
+    }
 }
@@ -1,13 +1,37 @@
 use std::collections::HashMap;
 
 use crate::raw;
-pub use crate::raw::model::{Cube, Face};
+pub use crate::raw::model::{Cube, Face, Faces};
 
 #[derive(Debug)]
 pub struct BlockModel {
     pub ambient_occlusion: bool,
     pub textures: HashMap<String, String>,
     pub elements: Vec<Cube>,
+    /// The model's `render_type`, vanilla models don't set one.
+    pub render_layer: Option<RenderLayer>,
+}
+
+/// Pass blocks are drawn in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderLayer {
+    Opaque,
+    /// Opaque with fully transparent texels discarded, like leaves and glass.
+    Cutout,
+    /// Blended over what's behind it, like water and stained glass.
+    Translucent,
+}
+
+impl RenderLayer {
+    /// Parses a model's `render_type`, with or without the namespace.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.strip_prefix("minecraft:").unwrap_or(name) {
+            "solid" => Some(Self::Opaque),
+            "cutout" | "cutout_mipped" | "cutout_mipped_all" => Some(Self::Cutout),
+            "translucent" | "tripwire" => Some(Self::Translucent),
+            _ => None,
+        }
+    }
 }
 
 impl BlockModel {
@@ -38,6 +62,13 @@ impl BlockModel {
         let mut elements = raw.elements.clone().unwrap_or_default();
 
         let mut ambient = raw.ambientocclusion.unwrap_or(true);
+        let mut render_layer = raw.render_type.as_deref().and_then(|name| {
+            let layer = RenderLayer::from_name(name);
+            if layer.is_none() {
+                log::warn!("Unknown render type {name}");
+            }
+            layer
+        });
 
         if let Some(parent_name) = &raw.parent {
             let name = parent_name
@@ -57,6 +88,10 @@ impl BlockModel {
                 if raw.ambientocclusion.is_none() {
                     ambient = parent.ambient_occlusion;
                 }
+
+                if raw.render_type.is_none() {
+                    render_layer = parent.render_layer;
+                }
             } else {
                 log::warn!("parent {} is empty", name);
             }
@@ -70,6 +105,7 @@ impl BlockModel {
             ambient_occlusion: ambient,
             textures,
             elements,
+            render_layer,
         }
     }
 }
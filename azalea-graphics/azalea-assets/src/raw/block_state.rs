@@ -87,11 +87,19 @@ pub struct VariantDesc {
 
     #[serde(default)]
     pub uvlock: bool,
+
+    /// Chance of this model relative to the others of a variant array.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
 }
 
 #[cfg(test)]
 mod tests {
-    use super::BlockRenderState;
+    use super::{BlockRenderState, Variant};
 
     #[test]
     fn deserialize_test() {
@@ -112,4 +120,27 @@ mod tests {
             .unwrap();
         }
     }
+
+    #[test]
+    fn variant_weights() {
+        let state = BlockRenderState::from_str(
+            r#"{
+    "variants": {
+        "": [
+            { "model": "block/stone", "weight": 10 },
+            { "model": "block/stone_mirrored" }
+        ]
+    }
+}"#,
+        )
+        .unwrap();
+        let BlockRenderState::Variants(variants) = state else {
+            panic!("expected variants");
+        };
+        let Variant::Multiple(descs) = &variants[0].1 else {
+            panic!("expected a model array");
+        };
+        let weights: Vec<_> = descs.iter().map(|desc| desc.weight).collect();
+        assert_eq!(weights, [10, 1]);
+    }
 }
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+
+/// An OptiFine/Continuity connected textures `.properties` file, only the keys
+/// used by the mesher are kept.
+#[derive(Debug, Default)]
+pub struct CtmProperties {
+    pub method: String,
+    /// Tile names as written, numbers refer to `<n>.png` next to the file.
+    pub tiles: Vec<String>,
+    pub match_blocks: Vec<String>,
+    pub match_tiles: Vec<String>,
+    pub connect: Option<String>,
+}
+
+impl CtmProperties {
+    pub fn from_str(s: &str) -> Self {
+        let props = parse_properties(s);
+        let list = |key: &str| -> Vec<String> {
+            props
+                .get(key)
+                .map(|v| v.split_whitespace().map(str::to_string).collect())
+                .unwrap_or_default()
+        };
+
+        Self {
+            method: props.get("method").cloned().unwrap_or_default(),
+            tiles: list("tiles").iter().flat_map(|t| expand_range(t)).collect(),
+            match_blocks: list("matchBlocks"),
+            match_tiles: list("matchTiles"),
+            connect: props.get("connect").cloned(),
+        }
+    }
+}
+
+/// Parses `key=value` lines of a java properties file, `#` and `!` start
+/// comments.
+fn parse_properties(s: &str) -> HashMap<String, String> {
+    s.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('!'))
+        .filter_map(|line| {
+            let (key, value) = line.split_once(['=', ':'])?;
+            Some((key.trim().to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Expands `0-3` into `0 1 2 3`, anything else is returned as is.
+fn expand_range(tile: &str) -> Vec<String> {
+    if let Some((start, end)) = tile.split_once('-')
+        && let (Ok(start), Ok(end)) = (start.parse::<u32>(), end.parse::<u32>())
+    {
+        (start..=end).map(|i| i.to_string()).collect()
+    } else {
+        vec![tile.to_string()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ctm_properties() {
+        let props = CtmProperties::from_str(
+            r#"
+# connected glass
+method=ctm
+tiles=0-46
+matchBlocks=glass minecraft:white_stained_glass
+connect = block
+"#,
+        );
+
+        assert_eq!(props.method, "ctm");
+        assert_eq!(props.tiles.len(), 47);
+        assert_eq!(props.tiles[0], "0");
+        assert_eq!(props.tiles[46], "46");
+        assert_eq!(
+            props.match_blocks,
+            ["glass", "minecraft:white_stained_glass"]
+        );
+        assert!(props.match_tiles.is_empty());
+        assert_eq!(props.connect.as_deref(), Some("block"));
+    }
+
+    #[test]
+    fn tiles_mix_ranges_and_names() {
+        let props = CtmProperties::from_str("tiles=0-1 extra 3\nmatchTiles=sandstone_side");
+
+        assert_eq!(props.tiles, ["0", "1", "extra", "3"]);
+        assert_eq!(props.match_tiles, ["sandstone_side"]);
+    }
+}
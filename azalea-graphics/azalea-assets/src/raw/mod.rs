@@ -1,4 +1,5 @@
 pub(crate) mod atlas;
 pub(crate) mod block_state;
+pub(crate) mod ctm;
 pub(crate) mod model;
 pub(crate) mod entity_model;
@@ -8,6 +8,8 @@ pub struct BlockModel {
     pub textures: HashMap<String, String>,
     pub elements: Option<Vec<Cube>>,
     pub display: Option<HashMap<String, Display>>,
+    /// Pass the model is drawn in, a modded extension, e.g. `minecraft:cutout`.
+    pub render_type: Option<String>,
 }
 
 impl BlockModel {
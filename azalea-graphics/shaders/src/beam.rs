@@ -0,0 +1,71 @@
+//! Beacon beams, drawn as an inner and an outer open box per beam segment that
+//! are added onto the color behind them.
+
+use spirv_std::{
+    glam::{Vec2, Vec3, Vec4},
+    spirv,
+};
+
+use crate::{color::srgb_to_linear, terrain::WorldUniform};
+
+#[repr(C)]
+pub struct BeamPushConstants {
+    /// Bottom center of the segment, w is its height in blocks.
+    pub base: Vec4,
+    /// sRGB color of the segment.
+    pub color: Vec4,
+}
+
+/// Half the width of the inner beam and of the glow around it, in blocks.
+const INNER_RADIUS: f32 = 0.2;
+const OUTER_RADIUS: f32 = 0.25;
+
+/// Corner `i` of the square around the beam, counter clockwise from above.
+fn corner(i: i32) -> Vec2 {
+    match i {
+        0 => Vec2::new(-1.0, -1.0),
+        1 => Vec2::new(-1.0, 1.0),
+        2 => Vec2::new(1.0, 1.0),
+        _ => Vec2::new(1.0, -1.0),
+    }
+}
+
+/// 6 vertices for each of the 4 sides, instance 0 is the inner beam and 1 the
+/// glow.
+#[spirv(vertex)]
+pub fn beam_vert(
+    #[spirv(descriptor_set = 0, binding = 1, uniform)] uniform: &WorldUniform,
+    #[spirv(push_constant)] pc: &BeamPushConstants,
+
+    #[spirv(vertex_index)] vertex_index: i32,
+    #[spirv(instance_index)] instance_index: u32,
+
+    out_color: &mut Vec4,
+    #[spirv(position)] out_pos: &mut Vec4,
+) {
+    let side = vertex_index / 6;
+    let (u, v) = match vertex_index % 6 {
+        0 => (0.0, 0.0),
+        1 => (1.0, 0.0),
+        2 => (1.0, 1.0),
+        3 => (0.0, 0.0),
+        4 => (1.0, 1.0),
+        _ => (0.0, 1.0),
+    };
+
+    let (radius, alpha) = if instance_index == 0 {
+        (INNER_RADIUS, 0.75)
+    } else {
+        (OUTER_RADIUS, 0.125)
+    };
+    let xz = corner(side).lerp(corner((side + 1) % 4), u) * radius;
+    let pos = pc.base.truncate() + Vec3::new(xz.x, v * pc.base.w, xz.y);
+
+    *out_pos = uniform.view_proj * pos.extend(1.0);
+    *out_color = srgb_to_linear(pc.color.truncate()).extend(alpha);
+}
+
+#[spirv(fragment)]
+pub fn beam_frag(in_color: Vec4, frag_color: &mut Vec4) {
+    *frag_color = in_color;
+}
@@ -0,0 +1,56 @@
+//! Conversions between sRGB encoded and linear colors. Lighting and blending
+//! happen on linear colors, the color targets encode them back to sRGB.
+
+use spirv_std::{
+    glam::{Vec3, Vec4},
+    num_traits::Float,
+};
+
+fn channel_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn channel_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn srgb_to_linear(color: Vec3) -> Vec3 {
+    Vec3::new(
+        channel_to_linear(color.x),
+        channel_to_linear(color.y),
+        channel_to_linear(color.z),
+    )
+}
+
+pub fn linear_to_srgb(color: Vec3) -> Vec3 {
+    Vec3::new(
+        channel_to_srgb(color.x),
+        channel_to_srgb(color.y),
+        channel_to_srgb(color.z),
+    )
+}
+
+/// Converts an sRGB color with premultiplied alpha, the alpha itself is always
+/// linear.
+pub fn srgba_to_linear(color: Vec4) -> Vec4 {
+    if color.w <= 0.0 {
+        return color;
+    }
+    (srgb_to_linear(color.truncate() / color.w) * color.w).extend(color.w)
+}
+
+/// Converts a linear color with premultiplied alpha, see [`srgba_to_linear`].
+pub fn linear_to_srgba(color: Vec4) -> Vec4 {
+    if color.w <= 0.0 {
+        return color;
+    }
+    (linear_to_srgb(color.truncate() / color.w) * color.w).extend(color.w)
+}
@@ -1,8 +1,11 @@
 use spirv_std::{
-    glam::{IVec3, Mat4, Vec3, Vec4},
+    glam::{IVec2, IVec3, Mat4, Vec2, Vec3, Vec4, Vec4Swizzles},
+    num_traits::Float,
     spirv,
 };
 
+use crate::terrain::WorldUniform;
+
 #[repr(C)]
 pub struct PC {
     pub view_proj: Mat4,
@@ -11,6 +14,43 @@ pub struct PC {
     pub height: i32,
 }
 
+#[repr(C)]
+pub struct LinePushConstants {
+    pub viewport: Vec2,
+    pub line_width: f32,
+}
+
+#[repr(C)]
+pub struct BoxPushConstants {
+    pub min: Vec4,
+    pub max: Vec4,
+    pub color: Vec4,
+}
+
+#[repr(C)]
+pub struct GizmoPushConstants {
+    pub origin: Vec4,
+    /// Chunk x and z the grid is centered on.
+    pub grid_center: IVec2,
+    /// Chunks drawn in each direction from `grid_center`.
+    pub grid_radius: i32,
+}
+
+#[repr(C)]
+pub struct ChunkBorderPushConstants {
+    /// Chunk x and z of the camera.
+    pub chunk: IVec2,
+    /// Bottom and top of the world, in blocks.
+    pub min_y: i32,
+    pub max_y: i32,
+}
+
+/// Length of the gizmo's axis lines, in blocks.
+const GIZMO_AXIS_LENGTH: f32 = 16.0;
+const GIZMO_GRID_COLOR: Vec4 = Vec4::new(1.0, 1.0, 1.0, 0.2);
+/// Blocks a highlight outline extends past the block on each side.
+const HIGHLIGHT_GROW: f32 = 0.002;
+
 fn chunk_coords(instance: u32, pc: &PC) -> IVec3 {
     let side = pc.radius * 2 + 1;
     let layer_size = side * side;
@@ -23,30 +63,9 @@ fn chunk_coords(instance: u32, pc: &PC) -> IVec3 {
     IVec3::new(x - pc.radius, y, z - pc.radius)
 }
 
-#[spirv(vertex)]
-pub fn aabb_vert(
-    #[spirv(descriptor_set = 0, binding = 0, storage_buffer)] visible: &[u32],
-    #[spirv(descriptor_set = 0, binding = 1, uniform)] pc: &PC,
-
-    #[spirv(vertex_index)] vertex_index: i32,
-    #[spirv(instance_index)] instance_index: u32,
-
-    #[spirv(position)] out_pos: &mut Vec4,
-    out_color: &mut Vec4,
-) {
-    let chunk = instance_index;
-
-    if visible[chunk as usize] == 0 {
-        *out_pos = Vec4::new(2.0, 2.0, 2.0, 1.0);
-        *out_color = Vec4::ZERO;
-        return;
-    }
-
-    let coord = chunk_coords(chunk, pc);
-    let base = pc.grid_origin_ws.truncate() + coord.as_vec3() * 16.0;
-    let bmin = base;
-    let bmax = base + Vec3::splat(16.0);
-
+/// Corner of the unit cube for one of the 24 line list vertices making up its
+/// 12 edges.
+fn box_edge_vertex(vertex_index: i32) -> Vec3 {
     let vidx = match vertex_index {
         0 => 0,
         1 => 1,
@@ -75,7 +94,7 @@ pub fn aabb_vert(
         _ => 0,
     };
 
-    let unit = match vidx {
+    match vidx {
         0 => Vec3::new(0.0, 0.0, 0.0),
         1 => Vec3::new(1.0, 0.0, 0.0),
         2 => Vec3::new(1.0, 1.0, 0.0),
@@ -85,12 +104,264 @@ pub fn aabb_vert(
         6 => Vec3::new(1.0, 1.0, 1.0),
         7 => Vec3::new(0.0, 1.0, 1.0),
         _ => Vec3::ZERO,
+    }
+}
+
+/// Colors a chunk by the nearest depth the visibility pass stored for it, red
+/// when close and blue when far away.
+fn depth_color(depth: f32) -> Vec4 {
+    // reverse-Z depth falls off with 1 / distance, log2 spreads it out evenly
+    let t = (-depth.log2() / 16.0).clamp(0.0, 1.0);
+    Vec4::new(1.0 - t, 0.2, t, 1.0)
+}
+
+/// Chunk box bounds, or `None` if the visibility pass culled the chunk or
+/// skipped it for being empty.
+fn chunk_box(visible: &[f32], pc: &PC, chunk: u32) -> Option<(Vec3, Vec3, f32)> {
+    let depth = visible[chunk as usize];
+    if depth <= f32::MIN_POSITIVE {
+        return None;
+    }
+
+    let coord = chunk_coords(chunk, pc);
+    let bmin = pc.grid_origin_ws.truncate() + coord.as_vec3() * 16.0;
+    Some((bmin, bmin + Vec3::splat(16.0), depth))
+}
+
+#[spirv(vertex)]
+pub fn aabb_vert(
+    #[spirv(descriptor_set = 0, binding = 0, storage_buffer)] visible: &[f32],
+    #[spirv(descriptor_set = 0, binding = 1, uniform)] pc: &PC,
+
+    #[spirv(vertex_index)] vertex_index: i32,
+    #[spirv(instance_index)] instance_index: u32,
+
+    #[spirv(position)] out_pos: &mut Vec4,
+    out_color: &mut Vec4,
+) {
+    let Some((bmin, bmax, depth)) = chunk_box(visible, pc, instance_index) else {
+        *out_pos = Vec4::new(2.0, 2.0, 2.0, 1.0);
+        *out_color = Vec4::ZERO;
+        return;
     };
 
-    let world = bmin + (bmax - bmin) * unit;
+    let world = bmin + (bmax - bmin) * box_edge_vertex(vertex_index);
 
     *out_pos = pc.view_proj * world.extend(1.0);
-    *out_color = Vec4::new(1.0, 0.0, 0.0, 1.0);
+    *out_color = depth_color(depth);
+}
+
+/// Thick lines for devices without `wideLines`, every edge is expanded into a
+/// screen space quad of 6 vertices.
+#[spirv(vertex)]
+pub fn aabb_quad_vert(
+    #[spirv(descriptor_set = 0, binding = 0, storage_buffer)] visible: &[f32],
+    #[spirv(descriptor_set = 0, binding = 1, uniform)] pc: &PC,
+    #[spirv(push_constant)] line: &LinePushConstants,
+
+    #[spirv(vertex_index)] vertex_index: i32,
+    #[spirv(instance_index)] instance_index: u32,
+
+    #[spirv(position)] out_pos: &mut Vec4,
+    out_color: &mut Vec4,
+    out_edge: &mut f32,
+) {
+    let Some((bmin, bmax, depth)) = chunk_box(visible, pc, instance_index) else {
+        *out_pos = Vec4::new(2.0, 2.0, 2.0, 1.0);
+        *out_color = Vec4::ZERO;
+        *out_edge = 0.0;
+        return;
+    };
+
+    let edge = vertex_index / 6;
+    let (end, side) = match vertex_index % 6 {
+        0 => (0, -1.0),
+        1 => (1, -1.0),
+        2 => (1, 1.0),
+        3 => (0, -1.0),
+        4 => (1, 1.0),
+        _ => (0, 1.0),
+    };
+
+    let a = pc.view_proj * (bmin + (bmax - bmin) * box_edge_vertex(edge * 2)).extend(1.0);
+    let b = pc.view_proj * (bmin + (bmax - bmin) * box_edge_vertex(edge * 2 + 1)).extend(1.0);
+
+    let dir = ((b.xy() / b.w - a.xy() / a.w) * line.viewport).normalize_or_zero();
+    let normal = Vec2::new(-dir.y, dir.x);
+    // ndc spans 2 units across the viewport
+    let offset = normal * side * line.line_width / line.viewport;
+
+    let clip = if end == 0 { a } else { b };
+    *out_pos = clip + (offset * clip.w).extend(0.0).extend(0.0);
+    *out_color = depth_color(depth);
+    *out_edge = side;
+}
+
+#[spirv(fragment)]
+pub fn aabb_quad_frag(
+    #[spirv(push_constant)] line: &LinePushConstants,
+    in_color: Vec4,
+    in_edge: f32,
+    frag_color: &mut Vec4,
+) {
+    // fade out the outermost pixel on both sides of the line
+    let coverage = ((1.0 - in_edge.abs()) * line.line_width * 0.5).clamp(0.0, 1.0);
+    *frag_color = in_color.xyz().extend(in_color.w * coverage);
+}
+
+#[spirv(vertex)]
+pub fn box_vert(
+    #[spirv(descriptor_set = 0, binding = 1, uniform)] uniform: &WorldUniform,
+    #[spirv(push_constant)] pc: &BoxPushConstants,
+
+    #[spirv(vertex_index)] vertex_index: i32,
+
+    #[spirv(position)] out_pos: &mut Vec4,
+    out_color: &mut Vec4,
+) {
+    let bmin = pc.min.truncate();
+    let bmax = pc.max.truncate();
+    let world = bmin + (bmax - bmin) * box_edge_vertex(vertex_index);
+
+    *out_pos = uniform.view_proj * world.extend(1.0);
+    *out_color = pc.color;
+}
+
+/// Outline of one highlighted block per instance, grown slightly so the lines
+/// don't z-fight with the block's faces.
+#[spirv(vertex)]
+pub fn highlight_vert(
+    #[spirv(descriptor_set = 0, binding = 1, uniform)] uniform: &WorldUniform,
+
+    in_pos: Vec4,
+    in_color: Vec4,
+    #[spirv(vertex_index)] vertex_index: i32,
+
+    #[spirv(position)] out_pos: &mut Vec4,
+    out_color: &mut Vec4,
+) {
+    let bmin = in_pos.truncate() - Vec3::splat(HIGHLIGHT_GROW);
+    let bmax = in_pos.truncate() + Vec3::splat(1.0 + HIGHLIGHT_GROW);
+    let world = bmin + (bmax - bmin) * box_edge_vertex(vertex_index);
+
+    *out_pos = uniform.view_proj * world.extend(1.0);
+    *out_color = in_color;
+}
+
+/// Line list of the x, y and z axes at the gizmo origin in the first 6
+/// vertices, followed by the chunk borders around `grid_center` on the origin's
+/// ground plane.
+#[spirv(vertex)]
+pub fn gizmo_vert(
+    #[spirv(descriptor_set = 0, binding = 1, uniform)] uniform: &WorldUniform,
+    #[spirv(push_constant)] pc: &GizmoPushConstants,
+
+    #[spirv(vertex_index)] vertex_index: i32,
+
+    #[spirv(position)] out_pos: &mut Vec4,
+    out_color: &mut Vec4,
+) {
+    let origin = pc.origin.truncate();
+    let end = (vertex_index % 2) as f32;
+
+    let (world, color) = if vertex_index < 6 {
+        let (dir, color) = match vertex_index / 2 {
+            0 => (Vec3::X, Vec4::new(1.0, 0.2, 0.2, 1.0)),
+            1 => (Vec3::Y, Vec4::new(0.2, 1.0, 0.2, 1.0)),
+            _ => (Vec3::Z, Vec4::new(0.2, 0.4, 1.0, 1.0)),
+        };
+        (origin + dir * GIZMO_AXIS_LENGTH * end, color)
+    } else {
+        // the 2r + 1 chunks in each direction have 2r + 2 borders
+        let lines_per_axis = pc.grid_radius * 2 + 2;
+        let line = (vertex_index - 6) / 2;
+        let min = (pc.grid_center - pc.grid_radius).as_vec2() * 16.0;
+        let size = (lines_per_axis - 1) as f32 * 16.0;
+        let offset = (line % lines_per_axis) as f32 * 16.0;
+
+        let (x, z) = if line < lines_per_axis {
+            // constant x, running along z
+            (min.x + offset, min.y + size * end)
+        } else {
+            (min.x + size * end, min.y + offset)
+        };
+        (Vec3::new(x, origin.y, z), GIZMO_GRID_COLOR)
+    };
+
+    *out_pos = uniform.view_proj * world.extend(1.0);
+    *out_color = color;
+}
+
+/// Vertices of the lines at the corners of the camera's chunk and its
+/// neighbours, see [`chunk_border_vert`].
+const NEIGHBOUR_CORNER_VERTICES: i32 = 4 * 4 * 2;
+/// Vertices of the lines every 2 blocks along the edges of the camera's chunk.
+const CHUNK_EDGE_VERTICES: i32 = 4 * 8 * 2;
+const CHUNK_BORDER_RED: Vec4 = Vec4::new(1.0, 0.0, 0.0, 1.0);
+const CHUNK_BORDER_YELLOW: Vec4 = Vec4::new(1.0, 1.0, 0.0, 1.0);
+const CHUNK_BORDER_BLUE: Vec4 = Vec4::new(0.25, 0.25, 1.0, 1.0);
+
+/// Corner of the chunk's ground plane, counterclockwise from the origin.
+fn chunk_corner(corner: i32) -> IVec2 {
+    match corner {
+        0 => IVec2::new(0, 0),
+        1 => IVec2::new(16, 0),
+        2 => IVec2::new(16, 16),
+        _ => IVec2::new(0, 16),
+    }
+}
+
+/// Line list of vanilla's chunk border view (F3+G): red lines at the corners
+/// of the neighbouring chunks, yellow lines every 2 blocks along the edges of
+/// the camera's chunk and yellow rings around it every 2 blocks of height.
+/// The chunk's corners and the section borders are blue.
+#[spirv(vertex)]
+pub fn chunk_border_vert(
+    #[spirv(descriptor_set = 0, binding = 1, uniform)] uniform: &WorldUniform,
+    #[spirv(push_constant)] pc: &ChunkBorderPushConstants,
+
+    #[spirv(vertex_index)] vertex_index: i32,
+
+    #[spirv(position)] out_pos: &mut Vec4,
+    out_color: &mut Vec4,
+) {
+    let end = vertex_index % 2;
+    let vertical_y = pc.min_y + (pc.max_y - pc.min_y) * end;
+
+    // x and z within the chunk, and y
+    let (xz, y, color) = if vertex_index < NEIGHBOUR_CORNER_VERTICES {
+        let line = vertex_index / 2;
+        let corner = IVec2::new(line % 4 - 1, line / 4 - 1) * 16;
+        (corner, vertical_y, CHUNK_BORDER_RED)
+    } else if vertex_index < NEIGHBOUR_CORNER_VERTICES + CHUNK_EDGE_VERTICES {
+        let line = (vertex_index - NEIGHBOUR_CORNER_VERTICES) / 2;
+        let edge = line / 8;
+        let step = line % 8;
+        let start = chunk_corner(edge);
+        let dir = (chunk_corner((edge + 1) % 4) - start) / 16;
+        let color = if step == 0 {
+            CHUNK_BORDER_BLUE
+        } else {
+            CHUNK_BORDER_YELLOW
+        };
+        (start + dir * step * 2, vertical_y, color)
+    } else {
+        let line = (vertex_index - NEIGHBOUR_CORNER_VERTICES - CHUNK_EDGE_VERTICES) / 2;
+        let y = pc.min_y + line / 4 * 2;
+        let side = line % 4;
+        let corner = chunk_corner((side + end) % 4);
+        let color = if y % 16 == 0 {
+            CHUNK_BORDER_BLUE
+        } else {
+            CHUNK_BORDER_YELLOW
+        };
+        (corner, y, color)
+    };
+
+    let xz = pc.chunk * 16 + xz;
+    let world = Vec3::new(xz.x as f32, y as f32, xz.y as f32);
+    *out_pos = uniform.view_proj * world.extend(1.0);
+    *out_color = color;
 }
 
 #[spirv(fragment)]
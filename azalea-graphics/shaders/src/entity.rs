@@ -1,17 +1,27 @@
 use spirv_std::{
     Image, RuntimeArray,
-    glam::{Mat4, Vec2, Vec3, Vec4},
+    glam::{Mat2, Mat4, Vec2, Vec3, Vec4},
     image::SampledImage,
     spirv,
 };
 
-use crate::terrain::WorldUniform;
+use crate::{color::srgb_to_linear, terrain::WorldUniform};
 
 #[repr(C)]
 pub struct PC {
+    /// sRGB tint, like the dye color of a banner pattern.
+    color: Vec4,
     texture: u32,
     transform_offset: u32,
+    /// Non zero to ignore the texture and lighting, see [`FLAT_COLOR`].
+    flat: u32,
+    /// Milliseconds the enchantment glint has scrolled, see [`glint_frag`].
+    glint_time: f32,
 }
+
+/// Color of every entity when the texture is bypassed for debugging.
+const FLAT_COLOR: Vec4 = Vec4::new(1.0, 0.0, 1.0, 1.0);
+
 #[spirv(vertex)]
 pub fn vert(
     #[spirv(descriptor_set = 0, binding = 0, uniform)] uniform: &WorldUniform,
@@ -24,23 +34,116 @@ pub fn vert(
 
     out_uv: &mut Vec2,
     out_texture: &mut u32,
+    out_color: &mut Vec4,
 
     #[spirv(position)] out_pos: &mut Vec4,
 ) {
     *out_pos = uniform.view_proj * transforms[in_transform_id as usize + pc.transform_offset as usize] * in_pos.extend(1.0);
     *out_uv = in_uv;
     *out_texture = pc.texture;
+    *out_color = (srgb_to_linear(pc.color.truncate()) * uniform.ambient_light).extend(pc.color.w);
 }
 
 #[spirv(fragment)]
 pub fn frag(
     in_uv: Vec2,
     #[spirv(flat)] in_tex: u32,
+    in_color: Vec4,
+    #[spirv(push_constant)] pc: &PC,
     #[spirv(descriptor_set = 1, binding = 0)] textures: &RuntimeArray<
         SampledImage<Image!(2D, type=f32, sampled)>,
     >,
     frag_color: &mut Vec4,
 ) {
+    if pc.flat != 0 {
+        *frag_color = FLAT_COLOR;
+        return;
+    }
     let tex_color: Vec4 = unsafe { textures.index(in_tex as usize).sample(in_uv) };
-    *frag_color = tex_color;
+    *frag_color = tex_color * in_color;
+}
+
+/// Glint texture repeats across an item sprite, vanilla scales the atlas uvs by
+/// 8 which covers a sprite about eight times.
+const GLINT_SCALE: f32 = 1.0 / 8.0;
+/// Milliseconds the glint takes to scroll once along u and v.
+const GLINT_PERIOD_U: f32 = 110000.0;
+const GLINT_PERIOD_V: f32 = 30000.0;
+/// The glint texture is turned by 10 degrees.
+const GLINT_ROTATION: f32 = 0.17453292;
+
+/// The scrolling enchantment glint, drawn additively over a model that was
+/// already drawn at the same depth.
+#[spirv(fragment)]
+pub fn glint_frag(
+    in_uv: Vec2,
+    #[spirv(flat)] in_tex: u32,
+    #[spirv(push_constant)] pc: &PC,
+    #[spirv(descriptor_set = 1, binding = 0)] textures: &RuntimeArray<
+        SampledImage<Image!(2D, type=f32, sampled)>,
+    >,
+    frag_color: &mut Vec4,
+) {
+    let scroll = Vec2::new(
+        -(pc.glint_time % GLINT_PERIOD_U) / GLINT_PERIOD_U,
+        (pc.glint_time % GLINT_PERIOD_V) / GLINT_PERIOD_V,
+    );
+    let uv = scroll + Mat2::from_angle(GLINT_ROTATION) * (in_uv * GLINT_SCALE);
+    let uv = uv - uv.floor();
+    *frag_color = unsafe { textures.index(in_tex as usize).sample(uv) };
+}
+
+/// One block top a shadow falls on, see [`shadow_vert`].
+#[repr(C)]
+pub struct ShadowPushConstants {
+    /// Corner of the block top with the lowest x and z, w is the alpha of the
+    /// shadow in the middle.
+    min: Vec4,
+    /// Opposite corner of the block top, w is the radius of the shadow.
+    max: Vec4,
+    /// X and z of the entity the shadow belongs to.
+    center: Vec2,
+}
+
+/// Lifts shadows off the block they lie on so they don't z-fight with it.
+const SHADOW_OFFSET: f32 = 1.0 / 256.0;
+
+/// Draws the block top given by the push constants as two triangles.
+#[spirv(vertex)]
+pub fn shadow_vert(
+    #[spirv(descriptor_set = 0, binding = 0, uniform)] uniform: &WorldUniform,
+    #[spirv(push_constant)] pc: &ShadowPushConstants,
+    #[spirv(vertex_index)] vertex_index: i32,
+
+    out_offset: &mut Vec2,
+    #[spirv(position)] out_pos: &mut Vec4,
+) {
+    let (u, v) = match vertex_index {
+        0 => (0.0, 0.0),
+        1 => (0.0, 1.0),
+        2 => (1.0, 1.0),
+        3 => (0.0, 0.0),
+        4 => (1.0, 1.0),
+        _ => (1.0, 0.0),
+    };
+    let pos = Vec3::new(
+        pc.min.x + (pc.max.x - pc.min.x) * u,
+        pc.max.y + SHADOW_OFFSET,
+        pc.min.z + (pc.max.z - pc.min.z) * v,
+    );
+
+    *out_offset = Vec2::new(pos.x, pos.z) - pc.center;
+    *out_pos = uniform.view_proj * pos.extend(1.0);
+}
+
+/// Darkens the block top with a radial gradient around the entity.
+#[spirv(fragment)]
+pub fn shadow_frag(
+    #[spirv(push_constant)] pc: &ShadowPushConstants,
+    in_offset: Vec2,
+    frag_color: &mut Vec4,
+) {
+    let dist = in_offset.length() / pc.max.w;
+    let falloff = (1.0 - dist).clamp(0.0, 1.0);
+    *frag_color = Vec4::new(0.0, 0.0, 0.0, pc.min.w * falloff * falloff);
 }
@@ -0,0 +1,129 @@
+use spirv_std::{
+    glam::{IVec3, IVec4, UVec3},
+    spirv,
+};
+
+/// Words per vertex, the layout of `BlockVertex`: position, ao, uv, tint and
+/// texture layer.
+const VERTEX_WORDS: usize = 10;
+/// Words per cube face in the face table, the uvs of its four corners followed
+/// by its texture layer, see `CubeFace` in the cpu mesher.
+const FACE_WORDS: usize = 9;
+
+/// Words of occluder bits in front of the faces in the input, one bit per block
+/// of the padded 18x18x18 section.
+const OCCLUDER_WORDS: usize = (18 * 18 * 18 + 31) / 32;
+
+#[repr(C)]
+pub struct GpuMesherPushConstants {
+    /// World position of the section's first block.
+    pub origin: IVec4,
+    pub face_count: u32,
+    /// Start of the indices in the output, in words.
+    pub index_offset: u32,
+}
+
+/// Corners of each face in the order of the cpu mesher's `FACES`: up, down,
+/// south, north, east and west.
+const CORNERS: [[[i32; 3]; 4]; 6] = [
+    [[0, 1, 0], [0, 1, 1], [1, 1, 1], [1, 1, 0]],
+    [[0, 0, 0], [1, 0, 0], [1, 0, 1], [0, 0, 1]],
+    [[0, 0, 1], [1, 0, 1], [1, 1, 1], [0, 1, 1]],
+    [[0, 0, 0], [0, 1, 0], [1, 1, 0], [1, 0, 0]],
+    [[1, 0, 0], [1, 1, 0], [1, 1, 1], [1, 0, 1]],
+    [[0, 0, 0], [0, 0, 1], [0, 1, 1], [0, 1, 0]],
+];
+
+fn is_occluder(input: &[u32], p: IVec3) -> bool {
+    if p.x < 0 || p.y < 0 || p.z < 0 || p.x >= 18 || p.y >= 18 || p.z >= 18 {
+        return false;
+    }
+    let bit = ((p.x * 18 + p.y) * 18 + p.z) as usize;
+    (input[bit / 32] >> (bit % 32)) & 1 != 0
+}
+
+/// Same as `compute_ao` in the cpu mesher.
+fn corner_ao(input: &[u32], local: IVec3, corner: IVec3, face: usize) -> f32 {
+    let o = corner * 2 - IVec3::ONE;
+
+    let (side1, side2) = match face {
+        // up, down
+        0 | 1 => (IVec3::new(0, o.y, o.z), IVec3::new(o.x, o.y, 0)),
+        // south, north
+        2 | 3 => (IVec3::new(0, o.y, o.z), IVec3::new(o.x, 0, o.z)),
+        // east, west
+        _ => (IVec3::new(o.x, 0, o.z), IVec3::new(o.x, o.y, 0)),
+    };
+    let side1 = is_occluder(input, local + side1);
+    let side2 = is_occluder(input, local + side2);
+    let corner = is_occluder(input, local + o);
+
+    if side1 && side2 {
+        0.0
+    } else {
+        let mut ao = 3.0;
+        if side1 || side2 {
+            ao -= 1.0;
+        }
+        if corner {
+            ao -= 1.0;
+        }
+        ao
+    }
+}
+
+/// Builds the quad of one packed cube face, see `pack_face` in the cpu mesher.
+/// The output holds `face_count * 4` vertices followed by the indices.
+#[spirv(compute(threads(64)))]
+pub fn mesh_cubes(
+    #[spirv(global_invocation_id)] gid: UVec3,
+    #[spirv(push_constant)] pc: &GpuMesherPushConstants,
+    #[spirv(descriptor_set = 0, binding = 0, storage_buffer)] cube_faces: &[f32],
+    #[spirv(descriptor_set = 0, binding = 1, storage_buffer)] input: &[u32],
+    #[spirv(descriptor_set = 0, binding = 2, storage_buffer)] output: &mut [u32],
+) {
+    let face_index = gid.x;
+    if face_index >= pc.face_count {
+        return;
+    }
+
+    let packed = input[OCCLUDER_WORDS + face_index as usize];
+    let local = IVec3::new(
+        (packed & 15) as i32,
+        ((packed >> 4) & 15) as i32,
+        ((packed >> 8) & 15) as i32,
+    ) + IVec3::ONE;
+    let face = ((packed >> 12) & 7) as usize;
+    let cube = (packed >> 15) as usize;
+
+    let base = pc.origin.truncate() + local - IVec3::ONE;
+    let first_vertex = face_index * 4;
+    let cube_face = (cube * 6 + face) * FACE_WORDS;
+
+    for i in 0..4 {
+        let c = CORNERS[face][i];
+        let corner = IVec3::new(c[0], c[1], c[2]);
+        let position = (base + corner).as_vec3();
+        let uv = cube_face + i * 2;
+
+        let v = (first_vertex as usize + i) * VERTEX_WORDS;
+        output[v] = position.x.to_bits();
+        output[v + 1] = position.y.to_bits();
+        output[v + 2] = position.z.to_bits();
+        output[v + 3] = corner_ao(input, local, corner, face).to_bits();
+        output[v + 4] = cube_faces[uv].to_bits();
+        output[v + 5] = cube_faces[uv + 1].to_bits();
+        output[v + 6] = 1.0f32.to_bits();
+        output[v + 7] = 1.0f32.to_bits();
+        output[v + 8] = 1.0f32.to_bits();
+        output[v + 9] = cube_faces[cube_face + 8].to_bits();
+    }
+
+    let i = pc.index_offset as usize + face_index as usize * 6;
+    output[i] = first_vertex;
+    output[i + 1] = first_vertex + 1;
+    output[i + 2] = first_vertex + 2;
+    output[i + 3] = first_vertex;
+    output[i + 4] = first_vertex + 2;
+    output[i + 5] = first_vertex + 3;
+}
@@ -47,6 +47,8 @@ pub fn reduce(
     let d2 = src.read(p01);
     let d3 = src.read(p11);
 
+    // depth is reversed, the smallest depth is the farthest occluder, which is
+    // what the visibility culling needs to stay conservative
     let d = d0.min(d1).min(d2.min(d3));
     unsafe { dst.write(o, Vec4::new(d, 0.0, 0.0, 0.0)) };
 }
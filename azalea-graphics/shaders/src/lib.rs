@@ -1,7 +1,14 @@
 #![no_std]
+pub mod beam;
+pub mod color;
 pub mod debug;
+pub mod gpu_mesher;
 pub mod hiz;
+pub mod oit;
+pub mod post_process;
+pub mod sky;
 pub mod terrain;
 pub mod entity;
 pub mod ui;
 pub mod visibility;
+pub mod weather;
@@ -0,0 +1,43 @@
+//! Resolve of the weighted blended order independent transparency pass, see
+//! [`crate::terrain::water_oit_frag`].
+
+use spirv_std::{
+    Image,
+    arch::kill,
+    glam::{IVec2, Vec4, Vec4Swizzles},
+    spirv,
+};
+
+type InputAttachment = Image!(subpass, type=f32, sampled=false);
+
+/// A triangle covering the whole screen.
+#[spirv(vertex)]
+pub fn resolve_vert(
+    #[spirv(vertex_index)] vertex_index: i32,
+    #[spirv(position)] out_pos: &mut Vec4,
+) {
+    let x = ((vertex_index << 1) & 2) as f32;
+    let y = (vertex_index & 2) as f32;
+    *out_pos = Vec4::new(x * 2.0 - 1.0, y * 2.0 - 1.0, 0.0, 1.0);
+}
+
+/// Divides the weighted color sums back out and blends the average over the
+/// opaque color by how much of the background is still visible.
+#[spirv(fragment)]
+pub fn resolve_frag(
+    #[spirv(descriptor_set = 0, binding = 0, input_attachment_index = 0)] accum: &InputAttachment,
+    #[spirv(descriptor_set = 0, binding = 1, input_attachment_index = 1)]
+    revealage: &InputAttachment,
+    frag_color: &mut Vec4,
+) {
+    let revealage: Vec4 = revealage.read_subpass(IVec2::ZERO);
+    let revealage = revealage.x;
+    // nothing translucent covers this pixel
+    if revealage >= 1.0 {
+        kill()
+    }
+
+    let accum: Vec4 = accum.read_subpass(IVec2::ZERO);
+    let color = accum.xyz() / accum.w.max(1e-5);
+    *frag_color = color.extend(1.0 - revealage);
+}
@@ -0,0 +1,57 @@
+//! Fullscreen effects drawn over the finished world and entities.
+
+use spirv_std::{
+    Image,
+    glam::{IVec2, Mat4, Vec2, Vec4, Vec4Swizzles},
+    num_traits::Float,
+    spirv,
+};
+
+type InputAttachment = Image!(subpass, type=f32, sampled=false);
+
+#[repr(C)]
+pub struct FogPushConstants {
+    pub inv_view_proj: Mat4,
+    /// Camera position, w is the fog density per block.
+    pub camera: Vec4,
+    /// Fog color, w is how much even the nearest pixels are tinted.
+    pub color: Vec4,
+}
+
+/// A triangle covering the whole screen, passing on its normalized device
+/// coordinates.
+#[spirv(vertex)]
+pub fn fog_vert(
+    #[spirv(vertex_index)] vertex_index: i32,
+    out_ndc: &mut Vec2,
+    #[spirv(position)] out_pos: &mut Vec4,
+) {
+    let x = ((vertex_index << 1) & 2) as f32;
+    let y = (vertex_index & 2) as f32;
+    *out_ndc = Vec2::new(x * 2.0 - 1.0, y * 2.0 - 1.0);
+    *out_pos = out_ndc.extend(0.0).extend(1.0);
+}
+
+/// Exponential fog by the distance to the depth buffer, used while the camera
+/// is in water or lava.
+#[spirv(fragment)]
+pub fn fog_frag(
+    #[spirv(push_constant)] pc: &FogPushConstants,
+    #[spirv(descriptor_set = 0, binding = 0, input_attachment_index = 0)] depth: &InputAttachment,
+    in_ndc: Vec2,
+    frag_color: &mut Vec4,
+) {
+    let depth: Vec4 = depth.read_subpass(IVec2::ZERO);
+    let depth = depth.x;
+
+    // reverse z, nothing was drawn at the infinitely far plane
+    let fog = if depth <= 0.0 {
+        1.0
+    } else {
+        let world = pc.inv_view_proj * in_ndc.extend(depth).extend(1.0);
+        let dist = (world.xyz() / world.w - pc.camera.xyz()).length();
+        1.0 - (-dist * pc.camera.w).exp()
+    };
+
+    *frag_color = pc.color.xyz().extend(fog.max(pc.color.w));
+}
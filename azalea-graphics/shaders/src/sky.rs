@@ -0,0 +1,339 @@
+use spirv_std::{
+    Image,
+    arch::kill,
+    glam::{Mat4, Vec2, Vec3, Vec4, Vec4Swizzles},
+    image::SampledImage,
+    num_traits::Float,
+    spirv,
+};
+
+use crate::{color::srgb_to_linear, terrain::WorldUniform};
+
+/// Size of a single cloud cell in blocks, same as vanilla.
+const CELL_SIZE: f32 = 12.0;
+/// Height of fancy cloud boxes in blocks.
+const THICKNESS: f32 = 4.0;
+/// Distance of the sun and moon quads from the camera, in blocks.
+const CELESTIAL_DISTANCE: f32 = 100.0;
+
+type CelestialTexture = SampledImage<Image!(2D, type=f32, sampled)>;
+
+#[repr(C)]
+pub struct CloudPushConstants {
+    pub color: Vec4,
+    pub camera_x: f32,
+    pub camera_z: f32,
+    pub height: f32,
+    pub scroll: f32,
+    pub radius: i32,
+}
+
+fn hash(x: i32, z: i32) -> f32 {
+    let mut h = (x as u32).wrapping_mul(0x8da6_b343) ^ (z as u32).wrapping_mul(0xd816_3841);
+    h = (h ^ (h >> 13)).wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 16;
+    (h & 0xffff) as f32 / 65535.0
+}
+
+fn value_noise(x: f32, z: f32) -> f32 {
+    let ix = x.floor();
+    let iz = z.floor();
+    let fx = x - ix;
+    let fz = z - iz;
+    let fx = fx * fx * (3.0 - 2.0 * fx);
+    let fz = fz * fz * (3.0 - 2.0 * fz);
+
+    let ix = ix as i32;
+    let iz = iz as i32;
+    let a = hash(ix, iz);
+    let b = hash(ix + 1, iz);
+    let c = hash(ix, iz + 1);
+    let d = hash(ix + 1, iz + 1);
+
+    let top = a + (b - a) * fx;
+    let bottom = c + (d - c) * fx;
+    top + (bottom - top) * fz
+}
+
+/// Whether the cloud cell at the given cell coordinates is filled.
+fn is_cloud(x: i32, z: i32) -> bool {
+    let x = x as f32;
+    let z = z as f32;
+    let density = value_noise(x / 6.0, z / 6.0) * 0.75 + value_noise(x / 2.0, z / 2.0) * 0.25;
+    density > 0.55
+}
+
+fn cell_at(world_x: f32, world_z: f32, scroll: f32) -> (i32, i32) {
+    (
+        ((world_x + scroll) / CELL_SIZE).floor() as i32,
+        (world_z / CELL_SIZE).floor() as i32,
+    )
+}
+
+/// Fades clouds out towards the edge of the cloud radius.
+fn edge_fade(world_x: f32, world_z: f32, pc: &CloudPushConstants) -> f32 {
+    let dx = world_x - pc.camera_x;
+    let dz = world_z - pc.camera_z;
+    let dist = (dx * dx + dz * dz).sqrt() / (pc.radius as f32 * CELL_SIZE);
+    (1.0 - (dist - 0.8) / 0.2).clamp(0.0, 1.0)
+}
+
+#[spirv(vertex)]
+pub fn cloud_flat_vert(
+    #[spirv(descriptor_set = 0, binding = 1, uniform)] uniform: &WorldUniform,
+    #[spirv(push_constant)] pc: &CloudPushConstants,
+
+    #[spirv(vertex_index)] vertex_index: i32,
+
+    out_world: &mut Vec3,
+    #[spirv(position)] out_pos: &mut Vec4,
+) {
+    let (u, v) = match vertex_index {
+        0 => (-1.0, -1.0),
+        1 => (1.0, -1.0),
+        2 => (1.0, 1.0),
+        3 => (-1.0, -1.0),
+        4 => (1.0, 1.0),
+        _ => (-1.0, 1.0),
+    };
+
+    let extent = pc.radius as f32 * CELL_SIZE;
+    let world = Vec3::new(
+        pc.camera_x + u * extent,
+        pc.height,
+        pc.camera_z + v * extent,
+    );
+
+    *out_world = world;
+    *out_pos = uniform.view_proj * world.extend(1.0);
+}
+
+#[spirv(fragment)]
+pub fn cloud_flat_frag(
+    #[spirv(push_constant)] pc: &CloudPushConstants,
+    in_world: Vec3,
+    frag_color: &mut Vec4,
+) {
+    let (cx, cz) = cell_at(in_world.x, in_world.z, pc.scroll);
+    if !is_cloud(cx, cz) {
+        kill()
+    }
+
+    let alpha = pc.color.w * edge_fade(in_world.x, in_world.z, pc);
+    *frag_color = pc.color.xyz().extend(alpha);
+}
+
+#[spirv(vertex)]
+pub fn cloud_box_vert(
+    #[spirv(descriptor_set = 0, binding = 1, uniform)] uniform: &WorldUniform,
+    #[spirv(push_constant)] pc: &CloudPushConstants,
+
+    #[spirv(vertex_index)] vertex_index: i32,
+    #[spirv(instance_index)] instance_index: u32,
+
+    out_color: &mut Vec4,
+    #[spirv(position)] out_pos: &mut Vec4,
+) {
+    let side = pc.radius * 2 + 1;
+    let dx = instance_index as i32 % side - pc.radius;
+    let dz = instance_index as i32 / side - pc.radius;
+
+    let (camera_cx, camera_cz) = cell_at(pc.camera_x, pc.camera_z, pc.scroll);
+    let cx = camera_cx + dx;
+    let cz = camera_cz + dz;
+
+    let face = vertex_index / 6;
+    let corner = match vertex_index % 6 {
+        0 => 0,
+        1 => 1,
+        2 => 2,
+        3 => 0,
+        4 => 2,
+        _ => 3,
+    };
+
+    // side faces shared with a neighbouring cloud cell are never visible
+    let hidden = match face {
+        2 => is_cloud(cx + 1, cz),
+        3 => is_cloud(cx - 1, cz),
+        4 => is_cloud(cx, cz + 1),
+        5 => is_cloud(cx, cz - 1),
+        _ => false,
+    };
+
+    if !is_cloud(cx, cz) || hidden {
+        *out_pos = Vec4::new(2.0, 2.0, 2.0, 1.0);
+        *out_color = Vec4::ZERO;
+        return;
+    }
+
+    // corners are wound counter-clockwise when looking at the face from outside
+    let unit = match (face, corner) {
+        // up
+        (0, 0) => Vec3::new(0.0, 1.0, 0.0),
+        (0, 1) => Vec3::new(0.0, 1.0, 1.0),
+        (0, 2) => Vec3::new(1.0, 1.0, 1.0),
+        (0, _) => Vec3::new(1.0, 1.0, 0.0),
+        // down
+        (1, 0) => Vec3::new(0.0, 0.0, 0.0),
+        (1, 1) => Vec3::new(1.0, 0.0, 0.0),
+        (1, 2) => Vec3::new(1.0, 0.0, 1.0),
+        (1, _) => Vec3::new(0.0, 0.0, 1.0),
+        // east
+        (2, 0) => Vec3::new(1.0, 0.0, 0.0),
+        (2, 1) => Vec3::new(1.0, 1.0, 0.0),
+        (2, 2) => Vec3::new(1.0, 1.0, 1.0),
+        (2, _) => Vec3::new(1.0, 0.0, 1.0),
+        // west
+        (3, 0) => Vec3::new(0.0, 0.0, 0.0),
+        (3, 1) => Vec3::new(0.0, 0.0, 1.0),
+        (3, 2) => Vec3::new(0.0, 1.0, 1.0),
+        (3, _) => Vec3::new(0.0, 1.0, 0.0),
+        // south
+        (4, 0) => Vec3::new(0.0, 0.0, 1.0),
+        (4, 1) => Vec3::new(1.0, 0.0, 1.0),
+        (4, 2) => Vec3::new(1.0, 1.0, 1.0),
+        (4, _) => Vec3::new(0.0, 1.0, 1.0),
+        // north
+        (_, 0) => Vec3::new(0.0, 0.0, 0.0),
+        (_, 1) => Vec3::new(0.0, 1.0, 0.0),
+        (_, 2) => Vec3::new(1.0, 1.0, 0.0),
+        (_, _) => Vec3::new(1.0, 0.0, 0.0),
+    };
+
+    let shade = match face {
+        0 => 1.0,
+        1 => 0.7,
+        2 | 3 => 0.9,
+        _ => 0.8,
+    };
+
+    let world = Vec3::new(
+        cx as f32 * CELL_SIZE - pc.scroll,
+        pc.height,
+        cz as f32 * CELL_SIZE,
+    ) + unit * Vec3::new(CELL_SIZE, THICKNESS, CELL_SIZE);
+
+    let alpha = pc.color.w * edge_fade(world.x, world.z, pc);
+    *out_color = (pc.color.xyz() * shade).extend(alpha);
+    *out_pos = uniform.view_proj * world.extend(1.0);
+}
+
+#[spirv(fragment)]
+pub fn cloud_box_frag(in_color: Vec4, frag_color: &mut Vec4) {
+    *frag_color = in_color;
+}
+
+#[repr(C)]
+pub struct CelestialPushConstants {
+    /// Direction from the camera to the body, w is half the side of its quad.
+    pub direction: Vec4,
+    /// Min and max uv of the sprite in its texture.
+    pub uv: Vec4,
+    /// Camera position, w is the alpha.
+    pub camera: Vec4,
+    /// 0 for the sun, 1 for the moon.
+    pub kind: u32,
+}
+
+/// A quad facing the camera from `direction`, with one edge along the z axis
+/// the sun and moon turn around.
+#[spirv(vertex)]
+pub fn celestial_vert(
+    #[spirv(descriptor_set = 0, binding = 1, uniform)] uniform: &WorldUniform,
+    #[spirv(push_constant)] pc: &CelestialPushConstants,
+
+    #[spirv(vertex_index)] vertex_index: i32,
+
+    out_uv: &mut Vec2,
+    #[spirv(position)] out_pos: &mut Vec4,
+) {
+    let (u, v) = match vertex_index {
+        0 => (0.0, 0.0),
+        1 => (1.0, 0.0),
+        2 => (1.0, 1.0),
+        3 => (0.0, 0.0),
+        4 => (1.0, 1.0),
+        _ => (0.0, 1.0),
+    };
+
+    let dir = pc.direction.xyz();
+    let side = Vec3::Z;
+    let up = dir.cross(side);
+    let offset = (side * (u * 2.0 - 1.0) + up * (v * 2.0 - 1.0)) * pc.direction.w;
+    let world = pc.camera.xyz() + dir * CELESTIAL_DISTANCE + offset;
+
+    *out_uv = pc.uv.xy().lerp(pc.uv.zw(), Vec2::new(u, v));
+    *out_pos = uniform.view_proj * world.extend(1.0);
+}
+
+#[spirv(fragment)]
+pub fn celestial_frag(
+    #[spirv(push_constant)] pc: &CelestialPushConstants,
+    #[spirv(descriptor_set = 1, binding = 0)] sun: &CelestialTexture,
+    #[spirv(descriptor_set = 1, binding = 1)] moon: &CelestialTexture,
+    in_uv: Vec2,
+    frag_color: &mut Vec4,
+) {
+    let color: Vec4 = if pc.kind == 1 {
+        moon.sample(in_uv)
+    } else {
+        sun.sample(in_uv)
+    };
+    let alpha = color.w * pc.camera.w;
+    if alpha < 0.01 {
+        kill()
+    }
+
+    *frag_color = color.xyz().extend(alpha);
+}
+
+#[repr(C)]
+pub struct SkyPushConstants {
+    pub inv_view_proj: Mat4,
+}
+
+/// A triangle covering the whole screen, passing on its normalized device
+/// coordinates.
+#[spirv(vertex)]
+pub fn sky_vert(
+    #[spirv(vertex_index)] vertex_index: i32,
+    out_ndc: &mut Vec2,
+    #[spirv(position)] out_pos: &mut Vec4,
+) {
+    let x = ((vertex_index << 1) & 2) as f32;
+    let y = (vertex_index & 2) as f32;
+    *out_ndc = Vec2::new(x * 2.0 - 1.0, y * 2.0 - 1.0);
+    *out_pos = out_ndc.extend(0.0).extend(1.0);
+}
+
+/// Fades from the horizon color to the zenith color by the height of the view
+/// direction, with the dawn and dusk glow on the side of the sun.
+#[spirv(fragment)]
+pub fn sky_frag(
+    #[spirv(descriptor_set = 0, binding = 1, uniform)] uniform: &WorldUniform,
+    #[spirv(push_constant)] pc: &SkyPushConstants,
+    in_ndc: Vec2,
+    frag_color: &mut Vec4,
+) {
+    // reverse z, 1 is on the near plane
+    let near = pc.inv_view_proj * in_ndc.extend(1.0).extend(1.0);
+    let far = pc.inv_view_proj * in_ndc.extend(0.5).extend(1.0);
+    let dir = (far.xyz() / far.w - near.xyz() / near.w).normalize();
+
+    let height = dir.y.max(0.0).sqrt();
+    let mut color = uniform
+        .sky_horizon
+        .xyz()
+        .lerp(uniform.sky_zenith.xyz(), height);
+
+    let sun = Vec2::new(uniform.sun_direction.x, uniform.sun_direction.z).normalize_or_zero();
+    let facing = Vec2::new(dir.x, dir.z)
+        .normalize_or_zero()
+        .dot(sun)
+        .max(0.0);
+    let glow = uniform.sunrise.w * facing * facing * (1.0 - dir.y.abs() * 2.0).max(0.0);
+    color = color.lerp(uniform.sunrise.xyz(), glow);
+
+    *frag_color = srgb_to_linear(color).extend(1.0);
+}
@@ -5,9 +5,62 @@ use spirv_std::{
     spirv,
 };
 
+use crate::color::srgb_to_linear;
+
+/// Block textures, the atlas is a single layer.
+type BlockTextures = SampledImage<Image!(2D, type=f32, sampled, arrayed)>;
+
+/// Set in a vertex's layer for faces whose transparent texels are drawn
+/// opaque, like fast leaves.
+const OPAQUE_LAYER: u32 = 1 << 31;
+
 #[repr(C)]
 pub struct WorldUniform {
     pub view_proj: Mat4,
+    /// 0 disables ambient occlusion, 1 is full strength.
+    pub ao_strength: f32,
+    /// How bright the sky makes blocks that don't glow, lower at night.
+    pub ambient_light: f32,
+    /// Non zero draws blocks in their vertex tint only, which the mesher sets
+    /// to a color per block state.
+    pub state_colors: u32,
+    pub _padding: f32,
+    /// Sky color straight up, sRGB.
+    pub sky_zenith: Vec4,
+    /// Sky color at the horizon, sRGB.
+    pub sky_horizon: Vec4,
+    /// Glow at the horizon towards the sun at dawn and dusk, sRGB. w is its
+    /// strength.
+    pub sunrise: Vec4,
+    pub sun_direction: Vec4,
+}
+
+/// Brightness of a fragment from its interpolated ao, emissive vertices have
+/// a negative ao and ignore it.
+fn brightness(ao: f32) -> f32 {
+    if ao < 0.0 { 1.0 } else { ao }
+}
+
+/// The textured color of a fragment, or only its tint when debugging block
+/// states. The texture's alpha is still used so cutouts keep their shape.
+fn surface_color(tex_color: Vec4, tint: Vec3, uniform: &WorldUniform) -> Vec3 {
+    if uniform.state_colors != 0 {
+        tint
+    } else {
+        tex_color.xyz() * tint
+    }
+}
+
+/// Normalizes a vertex's ao, softens it by the configured strength and darkens
+/// it by the ambient light. Lerping per vertex is the same as per fragment
+/// since the ao is interpolated linearly, emissive vertices keep their negative
+/// ao.
+fn vertex_ao(ao: f32, uniform: &WorldUniform) -> f32 {
+    if ao < 0.0 {
+        ao
+    } else {
+        (1.0 + (ao / 3.0 - 1.0) * uniform.ao_strength) * uniform.ambient_light
+    }
 }
 
 #[spirv(vertex)]
@@ -18,17 +71,21 @@ pub fn block_vert(
     in_ao: f32,
     in_uv: Vec2,
     in_tint: Vec3,
+    in_layer: u32,
 
     out_uv: &mut Vec2,
     out_ao: &mut f32,
     out_tint: &mut Vec3,
+    #[spirv(flat)] out_layer: &mut u32,
 
     #[spirv(position)] out_pos: &mut Vec4,
 ) {
     *out_pos = pc.view_proj * in_pos.extend(1.0);
     *out_uv = in_uv;
-    *out_ao = in_ao / 3.0;
-    *out_tint = in_tint;
+    *out_ao = vertex_ao(in_ao, pc);
+    // biome colors are sRGB like the textures, which are sampled as linear
+    *out_tint = srgb_to_linear(in_tint);
+    *out_layer = in_layer;
 }
 
 #[spirv(fragment)]
@@ -36,17 +93,23 @@ pub fn block_frag(
     in_uv: Vec2,
     in_ao: f32,
     in_tint: Vec3,
-    #[spirv(descriptor_set = 0, binding = 0)] block_atlas: &SampledImage<
-        Image!(2D, type=f32, sampled),
-    >,
+    #[spirv(flat)] in_layer: u32,
+    #[spirv(descriptor_set = 0, binding = 0)] block_textures: &BlockTextures,
+    #[spirv(descriptor_set = 0, binding = 1, uniform)] pc: &WorldUniform,
     frag_color: &mut Vec4,
 ) {
-    let tex_color: Vec4 = block_atlas.sample(in_uv);
-    if tex_color.w < 0.1 {
+    let layer = in_layer & !OPAQUE_LAYER;
+    let tex_color: Vec4 = block_textures.sample(in_uv.extend(layer as f32));
+    let alpha = if in_layer & OPAQUE_LAYER != 0 {
+        1.0
+    } else {
+        tex_color.w
+    };
+    if alpha < 0.1 {
         kill()
     }
 
-    *frag_color = (tex_color.xyz() * in_tint * in_ao).extend(tex_color.w);
+    *frag_color = (surface_color(tex_color, in_tint, pc) * brightness(in_ao)).extend(alpha);
 }
 
 #[spirv(vertex)]
@@ -57,17 +120,21 @@ pub fn water_vert(
     in_ao: f32,
     in_uv: Vec2,
     in_tint: Vec3,
+    in_layer: u32,
 
     out_uv: &mut Vec2,
     out_ao: &mut f32,
     out_tint: &mut Vec3,
+    #[spirv(flat)] out_layer: &mut u32,
 
     #[spirv(position)] clip_pos: &mut Vec4,
 ) {
     *clip_pos = pc.view_proj * in_pos.extend(1.0);
     *out_uv = in_uv;
-    *out_ao = in_ao / 3.0;
-    *out_tint = in_tint;
+    *out_ao = vertex_ao(in_ao, pc);
+    // biome colors are sRGB like the textures, which are sampled as linear
+    *out_tint = srgb_to_linear(in_tint);
+    *out_layer = in_layer;
 }
 
 #[spirv(fragment)]
@@ -75,11 +142,40 @@ pub fn water_frag(
     in_uv: Vec2,
     in_ao: f32,
     in_tint: Vec3,
-    #[spirv(descriptor_set = 0, binding = 0)] block_atlas: &SampledImage<
-        Image!(2D, type=f32, sampled),
-    >,
+    #[spirv(flat)] in_layer: u32,
+    #[spirv(descriptor_set = 0, binding = 0)] block_textures: &BlockTextures,
+    #[spirv(descriptor_set = 0, binding = 1, uniform)] pc: &WorldUniform,
     frag_color: &mut Vec4,
 ) {
-    let tex_color: Vec4 = block_atlas.sample(in_uv);
-    *frag_color = (tex_color.xyz() * in_tint * in_ao).extend(tex_color.w);
+    let tex_color: Vec4 = block_textures.sample(in_uv.extend(in_layer as f32));
+    *frag_color = (surface_color(tex_color, in_tint, pc) * brightness(in_ao)).extend(tex_color.w);
+}
+
+/// Weighted blended order independent transparency (McGuire and Bavoil 2013).
+/// Instead of blending in draw order, each fragment adds its weighted color to
+/// the accumulation attachment and multiplies the revealage attachment by
+/// `1 - alpha`, the resolve pass in [`crate::oit`] turns them into the final
+/// color.
+#[spirv(fragment)]
+pub fn water_oit_frag(
+    in_uv: Vec2,
+    in_ao: f32,
+    in_tint: Vec3,
+    #[spirv(flat)] in_layer: u32,
+    #[spirv(frag_coord)] frag_coord: Vec4,
+    #[spirv(descriptor_set = 0, binding = 0)] block_textures: &BlockTextures,
+    #[spirv(descriptor_set = 0, binding = 1, uniform)] pc: &WorldUniform,
+    out_accum: &mut Vec4,
+    out_revealage: &mut f32,
+) {
+    let tex_color: Vec4 = block_textures.sample(in_uv.extend(in_layer as f32));
+    let color = surface_color(tex_color, in_tint, pc) * brightness(in_ao);
+    let alpha = tex_color.w;
+
+    // w is one over the view depth, nearer fragments weigh more
+    let depth = 1.0 / frag_coord.w / 200.0;
+    let weight = alpha * (0.03 / (1e-5 + depth * depth * depth * depth)).clamp(1e-2, 3e3);
+
+    *out_accum = (color * alpha).extend(alpha) * weight;
+    *out_revealage = alpha;
 }
@@ -4,9 +4,13 @@ use spirv_std::{
     spirv,
 };
 
+use crate::color::{linear_to_srgba, srgba_to_linear};
+
 #[repr(C)]
 pub struct BlockPushConstants {
     screen_size: Vec2,
+    /// 1 if the target encodes to sRGB itself.
+    srgb_target: u32,
 }
 
 #[spirv(vertex)]
@@ -31,8 +35,11 @@ pub fn egui_vert(
     *out_uv = in_uv;
 }
 
+/// Egui's vertex colors are sRGB while its textures are sampled as linear, so
+/// one of them is converted to match the target.
 #[spirv(fragment)]
 pub fn egui_frag(
+    #[spirv(push_constant)] pc: &BlockPushConstants,
     #[spirv(descriptor_set = 0, binding = 0)] texture: &SampledImage<Image!(2D, type=f32, sampled)>,
 
     in_rgba: Vec4,
@@ -40,7 +47,11 @@ pub fn egui_frag(
 
     frag_color: &mut Vec4,
 ) {
-    let tex_color = texture.sample(in_uv);
+    let tex_color: Vec4 = texture.sample(in_uv);
 
-    *frag_color = in_rgba * tex_color;
+    *frag_color = if pc.srgb_target == 1 {
+        srgba_to_linear(in_rgba) * tex_color
+    } else {
+        in_rgba * linear_to_srgba(tex_color)
+    };
 }
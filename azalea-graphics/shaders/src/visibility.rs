@@ -16,6 +16,7 @@ pub fn cull_chunks(
     #[spirv(descriptor_set = 0, binding = 0, storage_buffer)] visible: &mut [f32],
 
     #[spirv(descriptor_set = 0, binding = 1, uniform)] uniform: &Uniform,
+    #[spirv(descriptor_set = 0, binding = 2, storage_buffer)] empty: &[u32],
     #[spirv(descriptor_set = 1, binding = 0)] hiz: &SampledImage<Image!(2D, type=f32, sampled)>,
     #[spirv(global_invocation_id)] gid: IVec3,
 ) {
@@ -32,6 +33,13 @@ pub fn cull_chunks(
 
     let index = (dy * side * side) + ((dz + uniform.radius) * side) + (dx + uniform.radius);
 
+    // sections without geometry are never drawn, they are only kept visible
+    // at the lowest priority so the mesher still picks them up once changed
+    if (empty[index as usize / 32] >> (index as u32 % 32)) & 1 != 0 {
+        visible[index as usize] = f32::MIN_POSITIVE;
+        return;
+    }
+
     let base =
         uniform.grid_origin_ws.truncate() + Vec3::new(dx as f32, dy as f32, dz as f32) * CHUNK_SIZE;
     let bmin = base;
@@ -83,32 +91,31 @@ pub fn cull_chunks(
         }
     }
 
+    // depth is reversed, so the nearest corner has the greatest depth, and
+    // starts at the far plane
     let mut min_xy = Vec2::splat(1.0);
     let mut max_xy = Vec2::splat(0.0);
-    let mut near_depth = 1.0f32;
-    let mut any_valid = false;
+    let mut near_depth = 0.0f32;
 
     for i in 0..8 {
         let clip = corners_clip[i];
+        // a corner behind the camera has no screen rect to test, the section
+        // reaches past the near plane and may cover the whole screen
         if clip.w <= 0.0 {
-            continue;
+            visible[index as usize] = 1.0;
+            return;
         }
         let ndc = clip.truncate() / clip.w;
         let uv = ndc.truncate() * 0.5 + Vec2::splat(0.5);
         let d = ndc.z;
 
-        any_valid = true;
         min_xy = min_xy.min(uv);
         max_xy = max_xy.max(uv);
-        near_depth = near_depth.min(d);
+        near_depth = near_depth.max(d);
     }
 
     min_xy = min_xy.clamp(Vec2::splat(0.0), Vec2::splat(1.0));
     max_xy = max_xy.clamp(Vec2::splat(0.0), Vec2::splat(1.0));
-    if !any_valid {
-        visible[index as usize] = 0.0;
-        return;
-    }
 
     let extent = max_xy - min_xy;
     if extent.x <= 0.0 || extent.y <= 0.0 {
@@ -118,7 +125,9 @@ pub fn cull_chunks(
 
     let tex_size: UVec2 = hiz.query_size_lod(0);
     let texel_size: Vec2 = extent * tex_size.as_vec2();
-    let mip = texel_size.x.max(texel_size.y).log2().floor();
+    // at this level the rect spans at most two texels each way, so the four
+    // corner samples see every texel under it
+    let mip = texel_size.x.max(texel_size.y).log2().ceil();
 
     let rect = Vec4::new(min_xy.x, min_xy.y, max_xy.x, max_xy.y);
 
@@ -126,6 +135,7 @@ pub fn cull_chunks(
     let sample2 = hiz.sample_by_lod(rect.zy(), mip).x;
     let sample3 = hiz.sample_by_lod(rect.xw(), mip).x;
     let sample4 = hiz.sample_by_lod(rect.zw(), mip).x;
+    // the farthest occluder depth under the rect, see `hiz::reduce`
     let max_z = sample1.min(sample2).min(sample3.min(sample4));
 
     visible[index as usize] = if near_depth > max_z { near_depth } else { 0.0 };
@@ -0,0 +1,164 @@
+//! Rain and snow, drawn as camera facing quads that fall through a box around
+//! the camera and wrap around its edges.
+
+use core::f32::consts::TAU;
+
+use spirv_std::{
+    Image,
+    arch::kill,
+    glam::{IVec2, Vec2, Vec3, Vec4},
+    image::SampledImage,
+    num_traits::Float,
+    spirv,
+};
+
+use crate::terrain::WorldUniform;
+
+type WeatherTexture = SampledImage<Image!(2D, type=f32, sampled)>;
+
+/// Columns of streaks side by side in the rain and snow textures.
+const COLUMNS: u32 = 4;
+/// Blocks fallen per tick.
+const RAIN_SPEED: f32 = 0.8;
+const SNOW_SPEED: f32 = 0.08;
+
+#[repr(C)]
+pub struct WeatherPushConstants {
+    /// Camera position, w is the time in ticks.
+    pub camera: Vec4,
+    /// Block x and z of the first column of the height grid.
+    pub grid_origin: IVec2,
+    pub grid_side: i32,
+    /// 0 for rain, 1 for snow.
+    pub kind: u32,
+    /// Half the side of the box the particles fall through, in blocks.
+    pub radius: f32,
+    pub alpha: f32,
+}
+
+fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x
+}
+
+fn random(seed: u32) -> f32 {
+    (hash(seed) & 0xffff) as f32 / 65535.0
+}
+
+/// Moves `x` by whole multiples of `size` into `[min, min + size)`.
+fn wrap(x: f32, min: f32, size: f32) -> f32 {
+    let t = (x - min) / size;
+    min + (t - t.floor()) * size
+}
+
+/// Whether rain falling at `pos` is below the highest motion blocking block of
+/// its column. Columns outside the grid are never covered.
+fn covered(heights: &[i32], pc: &WeatherPushConstants, pos: Vec3) -> bool {
+    let x = pos.x.floor() as i32 - pc.grid_origin.x;
+    let z = pos.z.floor() as i32 - pc.grid_origin.y;
+    if x < 0 || z < 0 || x >= pc.grid_side || z >= pc.grid_side {
+        return false;
+    }
+    (pos.y.floor() as i32) < heights[(z * pc.grid_side + x) as usize]
+}
+
+#[spirv(vertex)]
+pub fn weather_vert(
+    #[spirv(descriptor_set = 0, binding = 1, uniform)] uniform: &WorldUniform,
+    #[spirv(descriptor_set = 1, binding = 2, storage_buffer)] heights: &[i32],
+    #[spirv(push_constant)] pc: &WeatherPushConstants,
+
+    #[spirv(vertex_index)] vertex_index: i32,
+    #[spirv(instance_index)] instance_index: u32,
+
+    out_uv: &mut Vec2,
+    out_alpha: &mut f32,
+    #[spirv(position)] out_pos: &mut Vec4,
+) {
+    let seed = instance_index * 8;
+    let size = pc.radius * 2.0;
+    let time = pc.camera.w;
+    let snow = pc.kind == 1;
+
+    let (speed, width, height, v_span) = if snow {
+        (SNOW_SPEED, 1.0, 1.0, 1.0 / 16.0)
+    } else {
+        (RAIN_SPEED, 1.0, 4.0, 0.25)
+    };
+
+    // particles are fixed in the world and wrap around the box, so moving the
+    // camera doesn't drag them along
+    let mut pos = Vec3::new(
+        random(seed) * size,
+        random(seed + 1) * size - time * speed,
+        random(seed + 2) * size,
+    );
+    if snow {
+        let phase = random(seed + 3) * TAU;
+        pos.x += (time * 0.05 + phase).sin() * 0.5;
+        pos.z += (time * 0.04 + phase).cos() * 0.5;
+    }
+    let camera = pc.camera.truncate();
+    let min = camera - Vec3::splat(pc.radius);
+    let pos = Vec3::new(
+        wrap(pos.x, min.x, size),
+        wrap(pos.y, min.y, size),
+        wrap(pos.z, min.z, size),
+    );
+
+    if covered(heights, pc, pos) {
+        *out_pos = Vec4::new(2.0, 2.0, 2.0, 1.0);
+        *out_uv = Vec2::ZERO;
+        *out_alpha = 0.0;
+        return;
+    }
+
+    let (u, v) = match vertex_index {
+        0 => (0.0, 0.0),
+        1 => (1.0, 0.0),
+        2 => (1.0, 1.0),
+        3 => (0.0, 0.0),
+        4 => (1.0, 1.0),
+        _ => (0.0, 1.0),
+    };
+
+    // only turned around the y axis, so streaks stay vertical
+    let to_camera = Vec2::new(camera.x - pos.x, camera.z - pos.z).normalize_or_zero();
+    let right = Vec3::new(-to_camera.y, 0.0, to_camera.x);
+    let world = pos + right * (u - 0.5) * width + Vec3::Y * v * height;
+
+    let column = (hash(seed + 4) % COLUMNS) as f32;
+    let v_offset = random(seed + 5) * (1.0 - v_span);
+    *out_uv = Vec2::new((column + u) / COLUMNS as f32, v_offset + (1.0 - v) * v_span);
+
+    // fade out towards the sides of the box so wrapping isn't visible
+    let dist = Vec2::new(pos.x - camera.x, pos.z - camera.z).length() / pc.radius;
+    *out_alpha = pc.alpha * (1.0 - (dist - 0.7) / 0.3).clamp(0.0, 1.0);
+    *out_pos = uniform.view_proj * world.extend(1.0);
+}
+
+#[spirv(fragment)]
+pub fn weather_frag(
+    #[spirv(push_constant)] pc: &WeatherPushConstants,
+    #[spirv(descriptor_set = 1, binding = 0)] rain: &WeatherTexture,
+    #[spirv(descriptor_set = 1, binding = 1)] snow: &WeatherTexture,
+    in_uv: Vec2,
+    in_alpha: f32,
+    frag_color: &mut Vec4,
+) {
+    let color: Vec4 = if pc.kind == 1 {
+        snow.sample(in_uv)
+    } else {
+        rain.sample(in_uv)
+    };
+    let alpha = color.w * in_alpha;
+    if alpha < 0.01 {
+        kill()
+    }
+
+    *frag_color = color.truncate().extend(alpha);
+}
@@ -1,7 +1,11 @@
-use std::{sync::Arc, time::Instant};
+use std::{path::PathBuf, sync::Arc, time::Instant};
 
-use azalea::core::position::{ChunkPos, ChunkSectionPos};
+use azalea::{
+    core::position::{BlockPos, ChunkPos, ChunkSectionPos},
+    ecs::message::Message,
+};
 use crossbeam::channel::{Receiver, Sender, unbounded};
+use glam::{IVec3, Vec3};
 use parking_lot::{Mutex, RwLock};
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 use winit::{
@@ -12,16 +16,48 @@ use winit::{
     window::{CursorGrabMode, Window, WindowId},
 };
 
-use crate::renderer::{RenderState, Renderer};
+use crate::renderer::{
+    RenderState, Renderer, benchmark,
+    vulkan::swapchain::{PresentMode, SwapchainColorSpace},
+    world_renderer::{
+        BannerLayer, BeaconBeam, CustomRenderHook, DebugBox, Weather, WorldBorder, WorldTime,
+    },
+};
 
 pub enum WorldUpdate {
     ChunkAdded(ChunkPos),
+    /// The chunk was unloaded, its meshes are freed.
+    ChunkRemoved(ChunkPos),
     SectionChange(ChunkSectionPos),
     WorldAdded(Arc<RwLock<azalea::world::Instance>>),
+    DebugBox(Option<DebugBox>),
+    WorldBorder(Option<WorldBorder>),
+    Weather(Weather),
+    Time(WorldTime),
+    /// Patterns of the banner at the position, replacing the ones it had.
+    BannerPatterns(IVec3, Vec<BannerLayer>),
+    /// Every active beacon beam, replacing the ones sent before.
+    BeaconBeams(Vec<BeaconBeam>),
+    /// Blocks outlined in the color, on top of the ones highlighted before.
+    HighlightBlocks(Vec<BlockPos>, [f32; 4]),
+    ClearHighlights,
+    /// Eye position of the bot.
+    PlayerPosition(Vec3),
+    AddRenderHook(Box<dyn CustomRenderHook>),
 }
 
+/// Events sent from the renderer, the plugin forwards everything but `Closed`
+/// and `DeviceLost` as an ECS message.
+#[derive(Message, Clone, Debug)]
 pub enum RendererEvent {
     Closed,
+    /// The gpu was lost and the renderer shut down, the app has to be
+    /// restarted.
+    DeviceLost,
+    /// The first frame was drawn.
+    FirstFrame,
+    /// Nothing was left to mesh for the first time after a world was added.
+    WorldMeshed { sections: u64 },
 }
 
 #[derive(Clone)]
@@ -37,6 +73,10 @@ impl RendererHandle {
         self.tx.send(WorldUpdate::ChunkAdded(pos)).unwrap()
     }
 
+    pub fn remove_chunk(&self, pos: ChunkPos) {
+        self.tx.send(WorldUpdate::ChunkRemoved(pos)).unwrap()
+    }
+
     pub fn send_section(&self, pos: ChunkSectionPos) {
         self.tx.send(WorldUpdate::SectionChange(pos)).unwrap()
     }
@@ -44,15 +84,146 @@ impl RendererHandle {
     pub fn add_world(&self, world: Arc<RwLock<azalea::world::Instance>>) {
         self.tx.send(WorldUpdate::WorldAdded(world)).unwrap()
     }
+
+    /// Draws a colored box outline between `min` and `max` in world space.
+    pub fn set_debug_box(&self, min: Vec3, max: Vec3, color: [f32; 4]) {
+        self.tx
+            .send(WorldUpdate::DebugBox(Some(DebugBox { min, max, color })))
+            .unwrap()
+    }
+
+    pub fn clear_debug_box(&self) {
+        self.tx.send(WorldUpdate::DebugBox(None)).unwrap()
+    }
+
+    /// Outlines the blocks at `positions` in `color` until
+    /// [`Self::clear_highlights`], for showing pathfinding goals and the like.
+    pub fn highlight_blocks(&self, positions: Vec<BlockPos>, color: [f32; 4]) {
+        self.tx
+            .send(WorldUpdate::HighlightBlocks(positions, color))
+            .unwrap()
+    }
+
+    pub fn clear_highlights(&self) {
+        self.tx.send(WorldUpdate::ClearHighlights).unwrap()
+    }
+
+    /// Azalea does not keep track of the world border, so it has to be passed
+    /// in from the border packets by the caller.
+    pub fn set_world_border(&self, border: Option<WorldBorder>) {
+        self.tx.send(WorldUpdate::WorldBorder(border)).unwrap()
+    }
+
+    /// Sets the rain level from the game event packets, snow is picked by the
+    /// biome the camera is in.
+    pub fn set_weather(&self, weather: Weather) {
+        self.tx.send(WorldUpdate::Weather(weather)).unwrap()
+    }
+
+    /// Sets the time of day from the time packets, which moves the sun and
+    /// moon.
+    pub fn set_time(&self, time: WorldTime) {
+        self.tx.send(WorldUpdate::Time(time)).unwrap()
+    }
+
+    /// Sets the dyed patterns drawn on the banner at `pos`, from its block
+    /// entity data.
+    pub fn set_banner_patterns(&self, pos: IVec3, layers: Vec<BannerLayer>) {
+        self.tx
+            .send(WorldUpdate::BannerPatterns(pos, layers))
+            .unwrap()
+    }
+
+    /// Sets the beams drawn above the active beacons, azalea doesn't track
+    /// beacons either.
+    pub fn set_beacon_beams(&self, beams: Vec<BeaconBeam>) {
+        self.tx.send(WorldUpdate::BeaconBeams(beams)).unwrap()
+    }
+
+    /// Where the bot's eyes are, the camera can be teleported there from the
+    /// debug ui.
+    pub fn set_player_position(&self, position: Vec3) {
+        self.tx.send(WorldUpdate::PlayerPosition(position)).unwrap()
+    }
+
+    /// Registers a hook that draws custom geometry into the world pass every
+    /// frame, see [`CustomRenderHook`].
+    pub fn add_render_hook(&self, hook: impl CustomRenderHook + 'static) {
+        self.tx
+            .send(WorldUpdate::AddRenderHook(Box::new(hook)))
+            .unwrap()
+    }
 }
 
-#[derive(Debug, clap::Parser)]
+#[derive(Clone, Debug, clap::Parser)]
 pub struct RendererArgs {
     #[arg(short, long)]
     pub debug: bool,
 
     #[arg(short, long)]
     pub timestamps: bool,
+
+    /// Render this many frames offscreen along a fixed camera path without a
+    /// window, print frame times and exit.
+    #[arg(long)]
+    pub benchmark: Option<u32>,
+
+    #[arg(long, default_value_t = 1920)]
+    pub benchmark_width: u32,
+
+    #[arg(long, default_value_t = 1080)]
+    pub benchmark_height: u32,
+
+    /// Frames recorded ahead of the gpu, lower values reduce input latency and
+    /// higher ones smooth out frame times. Clamped to 1..=3.
+    #[arg(long, default_value_t = 2)]
+    pub frames_in_flight: usize,
+
+    /// PNG used as the skin of the bot, in the 64x64 layout of the default
+    /// skins.
+    #[arg(long)]
+    pub skin: Option<PathBuf>,
+
+    /// Shrinks block textures by this factor when building the atlas, to save
+    /// vram with high resolution resource packs. Rounded down to a power of
+    /// two.
+    #[arg(long, default_value_t = 1)]
+    pub texture_scale: u32,
+
+    /// Draws blocks from a texture array with one layer per sprite instead of
+    /// the atlas, so sprites never bleed into each other. Every layer is as
+    /// large as the largest sprite, which costs more vram with mixed texture
+    /// sizes.
+    #[arg(long)]
+    pub texture_array: bool,
+
+    /// Loads the shaders from this SPIR-V file instead of the ones built into
+    /// the binary. F6 reloads the file and rebuilds every pipeline.
+    #[arg(long)]
+    pub shader_path: Option<PathBuf>,
+
+    /// Kind of swapchain format to prefer. The output is sRGB encoded either
+    /// way, `unorm` renders into sRGB images and copies them to the swapchain.
+    #[arg(long, value_enum, default_value_t)]
+    pub color_space: SwapchainColorSpace,
+
+    /// Number of swapchain images to ask for, clamped to what the surface
+    /// supports. Defaults to one more than the surface's minimum.
+    #[arg(long)]
+    pub swapchain_images: Option<u32>,
+
+    /// Present mode to ask for, falls back to `fifo` if the surface doesn't
+    /// support it.
+    #[arg(long, value_enum, default_value_t)]
+    pub present_mode: PresentMode,
+
+    /// Vertical field of view in degrees.
+    #[arg(long, default_value_t = 90.0)]
+    pub fov: f32,
+
+    /// Extracted vanilla assets, relative to the working directory.
+    #[arg(long, default_value = "assets/minecraft")]
+    pub assets: PathBuf,
 }
 
 pub struct App {
@@ -66,6 +237,7 @@ pub struct App {
     last_frame_time: Instant,
 
     is_focused: bool,
+    first_frame_drawn: bool,
 
     args: RendererArgs,
 }
@@ -89,16 +261,51 @@ impl App {
             renderer: None,
             last_frame_time: Instant::now(),
             is_focused: false,
+            first_frame_drawn: false,
         };
 
         (handle, app)
     }
 
     pub fn run(mut self) {
+        if let Some(frames) = self.args.benchmark {
+            self.run_benchmark(frames);
+            return;
+        }
+
         let event_loop = EventLoop::new().unwrap();
         event_loop.set_control_flow(ControlFlow::Poll);
         event_loop.run_app(&mut self).unwrap();
     }
+
+    /// Grabs and hides the cursor for mouse look, or releases it so the debug ui
+    /// can be used without moving the camera.
+    fn set_cursor_grab(&mut self, grab: bool) {
+        let Some(window) = &self.window else {
+            return;
+        };
+
+        let result = if grab {
+            window
+                .set_cursor_grab(CursorGrabMode::Locked)
+                .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined))
+        } else {
+            window.set_cursor_grab(CursorGrabMode::None)
+        };
+
+        if result.is_ok() {
+            window.set_cursor_visible(!grab);
+            self.is_focused = grab;
+        }
+    }
+
+    fn run_benchmark(self, frames: u32) {
+        match benchmark::run(&self.args, frames, &self.cmd_rx, self.entities.clone()) {
+            Ok(stats) => println!("{stats}"),
+            Err(e) => log::error!("Benchmark failed: {e}"),
+        }
+        let _ = self.evt_tx.send(RendererEvent::Closed);
+    }
 }
 
 impl ApplicationHandler for App {
@@ -112,15 +319,22 @@ impl ApplicationHandler for App {
         let window_handle = window.window_handle().unwrap();
         let display_handle = window.display_handle().unwrap();
 
-        let renderer = Renderer::new(
+        let renderer = match Renderer::new(
             &window_handle,
             &display_handle,
             size,
             event_loop,
             &self.args,
             self.entities.clone(),
-        )
-        .expect("Failed to create renderer");
+        ) {
+            Ok(renderer) => renderer,
+            Err(e) => {
+                log::error!("Failed to create renderer: {e:#}");
+                event_loop.exit();
+                let _ = self.evt_tx.send(RendererEvent::Closed);
+                return;
+            }
+        };
         self.renderer = Some(renderer);
         self.window = Some(window);
     }
@@ -164,14 +378,38 @@ impl ApplicationHandler for App {
                             window.set_title(&format!("{}ms", ms));
                         }
 
+                        // the first frame also waits for the renderer to start
+                        if self.first_frame_drawn {
+                            renderer.check_frame_time(dt);
+                        }
                         renderer.update(dt);
 
-                        renderer.maybe_recreate();
-                        if let Some(window) = &self.window {
-                            renderer.run_debug_ui(window, ms);
+                        let result = renderer.maybe_recreate().and_then(|()| {
+                            if let Some(window) = &self.window {
+                                renderer.run_debug_ui(window, ms);
+                            }
+                            let drawn = renderer.draw_frame(&self.cmd_rx)?;
+                            renderer.maybe_recreate()?;
+                            Ok(drawn)
+                        });
+
+                        match result {
+                            Ok(drawn) => {
+                                if drawn && !self.first_frame_drawn {
+                                    self.first_frame_drawn = true;
+                                    let _ = self.evt_tx.send(RendererEvent::FirstFrame);
+                                }
+                                if let Some(sections) = renderer.take_world_meshed() {
+                                    let _ =
+                                        self.evt_tx.send(RendererEvent::WorldMeshed { sections });
+                                }
+                            }
+                            Err(e) => {
+                                log::error!("{e}");
+                                event_loop.exit();
+                                let _ = self.evt_tx.send(RendererEvent::DeviceLost);
+                            }
                         }
-                        renderer.draw_frame(&self.cmd_rx);
-                        renderer.maybe_recreate();
                     }
                 }
 
@@ -183,35 +421,26 @@ impl ApplicationHandler for App {
 
                         if event.state == ElementState::Pressed {
                             match code {
-                                KeyCode::Escape => {
-                                    if let Some(window) = &self.window
-                                        && window.set_cursor_grab(CursorGrabMode::None).is_ok()
-                                    {
-                                        window.set_cursor_visible(true);
-                                        self.is_focused = false;
-                                    }
-                                }
+                                KeyCode::Escape => self.set_cursor_grab(false),
+                                KeyCode::Tab => self.set_cursor_grab(!self.is_focused),
                                 _ => {}
                             }
                         }
                     }
                 }
+                WindowEvent::Focused(false) => self.set_cursor_grab(false),
                 WindowEvent::MouseWheel { delta, .. } => {
                     if let Some(renderer) = &mut self.renderer {
                         renderer.handle_mouse_scroll(&delta);
                     }
                 }
                 WindowEvent::MouseInput { state, button, .. } => {
-                    if let Some(window) = &self.window
+                    if let Some(renderer) = &self.renderer
+                        && renderer.camera_config().grab_on_click
                         && button == MouseButton::Left
                         && state == ElementState::Pressed
-                        && window
-                            .set_cursor_grab(CursorGrabMode::Locked)
-                            .or_else(|e| window.set_cursor_grab(CursorGrabMode::Confined))
-                            .is_ok()
                     {
-                        self.is_focused = true;
-                        window.set_cursor_visible(false);
+                        self.set_cursor_grab(true);
                     }
                 }
                 _ => {}
@@ -1,31 +1,60 @@
-use std::num::NonZero;
+use std::{collections::HashSet, num::NonZero};
 
 use azalea::{
     app::{App, AppExit, Plugin, Update},
     block_update::{QueuedServerBlockUpdates, handle_block_update_event},
+    blocks::{BlockState, BlockTrait},
     chunks::{ReceiveChunkEvent, handle_receive_chunk_event},
-    core::position::{ChunkPos, ChunkSectionPos},
+    core::{
+        position::{BlockPos, ChunkPos, ChunkSectionPos},
+        tick::GameTick,
+    },
     ecs::{
         entity::Entity,
         message::{MessageReader, MessageWriter},
-        query::Changed,
+        query::{Changed, With},
         schedule::IntoScheduleConfigs,
-        system::{Query, Res, SystemState},
+        system::{Query, Res, ResMut, SystemState},
         world::World,
     },
-    entity::EntityKindComponent,
+    entity::{
+        EntityKindComponent, LocalEntity, Position, dimensions::EntityDimensions, metadata::Fuse,
+    },
     local_player::InstanceHolder,
+    packet::game::ReceiveGamePacketEvent,
+    physics::collision::BlockWithShape,
     prelude::*,
+    protocol::packets::game::{ClientboundGamePacket, c_game_event::EventType},
+    registry::{Block, BlockEntityKind, EntityKind, tags},
+    world::{Instance, MinecraftEntityId},
 };
 use crossbeam::channel::TryRecvError;
+use glam::{IVec3, Vec3};
+use simdnbt::owned::Nbt;
 
-use crate::{app::{RendererEvent, RendererHandle}, renderer::RenderState};
+use crate::{
+    app::{RendererEvent, RendererHandle},
+    renderer::{
+        RenderState,
+        entity_renderer::{block_entity::dye_color, state::falling_block::FallingBlockStates},
+        world_renderer::{BannerLayer, BeaconBeam, BeamSegment, Weather, WorldTime},
+    },
+};
 
 #[derive(Resource, Clone)]
 pub struct RendererResource {
     pub handle: RendererHandle,
 }
 
+/// Beacons from the chunk and block entity packets, azalea doesn't keep block
+/// entities.
+#[derive(Resource, Default)]
+struct Beacons {
+    positions: HashSet<IVec3>,
+    /// The beams last sent to the renderer.
+    sent: Vec<BeaconBeam>,
+}
+
 pub struct RendererPlugin {
     pub handle: RendererHandle,
 }
@@ -46,7 +75,25 @@ impl Plugin for RendererPlugin {
             Update,
             handle_block_updates.before(handle_block_update_event),
         );
+        app.init_resource::<FallingBlockStates>();
+        app.add_systems(Update, track_falling_blocks.before(get_entities));
+        app.add_systems(GameTick, tick_tnt_fuses);
         app.add_systems(Update, get_entities);
+        app.add_systems(Update, forward_weather);
+        app.add_systems(Update, forward_time);
+        app.add_systems(Update, forward_banner_patterns.after(add_world));
+        app.init_resource::<Beacons>();
+        app.add_systems(Update, track_beacons);
+        app.add_systems(
+            Update,
+            forward_beacon_beams
+                .after(track_beacons)
+                .after(handle_receive_chunk_event)
+                .after(handle_block_update_event),
+        );
+        app.add_systems(Update, forward_chunk_unloads);
+        app.add_systems(Update, forward_player_position);
+        app.add_message::<RendererEvent>();
         app.add_systems(Update, poll_renderer_events);
     }
 }
@@ -58,7 +105,16 @@ pub fn handle_block_updates(
     for (queued, instance_holder) in query.iter() {
         let world = instance_holder.instance.read();
         for (pos, block_state) in &queued.list {
-            renderer.handle.send_section(ChunkSectionPos::from(pos));
+            // blocks on a section edge are also in the padding of the sections
+            // next to it, which affects their culling and ao
+            let sections = |v: i32| (v - 1).div_euclid(16)..=(v + 1).div_euclid(16);
+            for x in sections(pos.x) {
+                for y in sections(pos.y) {
+                    for z in sections(pos.z) {
+                        renderer.handle.send_section(ChunkSectionPos::new(x, y, z));
+                    }
+                }
+            }
         }
     }
 }
@@ -74,6 +130,296 @@ fn forward_chunk_updates(
     }
 }
 
+/// Azalea ignores the weather events, so the rain level is taken from the
+/// packets directly.
+fn forward_weather(
+    mut events: MessageReader<ReceiveGamePacketEvent>,
+    renderer: Res<RendererResource>,
+) {
+    for event in events.read() {
+        let ClientboundGamePacket::GameEvent(p) = event.packet.as_ref() else {
+            continue;
+        };
+        let rain_level = match p.event {
+            EventType::RainLevelChange => p.param,
+            EventType::StopRaining => 0.0,
+            _ => continue,
+        };
+        renderer.handle.set_weather(Weather { rain_level });
+    }
+}
+
+/// Azalea doesn't keep the time of day either.
+fn forward_time(
+    mut events: MessageReader<ReceiveGamePacketEvent>,
+    renderer: Res<RendererResource>,
+) {
+    for event in events.read() {
+        if let ClientboundGamePacket::SetTime(p) = event.packet.as_ref() {
+            renderer.handle.set_time(WorldTime {
+                day_time: p.day_time,
+                advancing: p.tick_day_time,
+            });
+        }
+    }
+}
+
+/// Azalea doesn't keep block entity data, so the banner patterns are read from
+/// the chunk and block entity packets.
+fn forward_banner_patterns(
+    mut events: MessageReader<ReceiveGamePacketEvent>,
+    renderer: Res<RendererResource>,
+) {
+    for event in events.read() {
+        match event.packet.as_ref() {
+            ClientboundGamePacket::LevelChunkWithLight(p) => {
+                for block_entity in &p.chunk_data.block_entities {
+                    if block_entity.kind != BlockEntityKind::Banner {
+                        continue;
+                    }
+                    let pos = IVec3::new(
+                        p.x * 16 + (block_entity.packed_xz >> 4) as i32,
+                        block_entity.y as i16 as i32,
+                        p.z * 16 + (block_entity.packed_xz & 15) as i32,
+                    );
+                    renderer
+                        .handle
+                        .set_banner_patterns(pos, banner_layers(&block_entity.data));
+                }
+            }
+            ClientboundGamePacket::BlockEntityData(p)
+                if p.block_entity_type == BlockEntityKind::Banner =>
+            {
+                let pos = IVec3::new(p.pos.x, p.pos.y, p.pos.z);
+                renderer
+                    .handle
+                    .set_banner_patterns(pos, banner_layers(&p.tag));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The `patterns` of a banner's block entity data. Patterns given inline
+/// instead of by id aren't supported.
+fn banner_layers(nbt: &Nbt) -> Vec<BannerLayer> {
+    let Nbt::Some(nbt) = nbt else {
+        return Vec::new();
+    };
+    let Some(patterns) = nbt.list("patterns").and_then(|list| list.compounds()) else {
+        return Vec::new();
+    };
+    patterns
+        .iter()
+        .filter_map(|layer| {
+            let pattern = layer.string("pattern")?.to_str();
+            let color = dye_color(&layer.string("color")?.to_str())?;
+            Some(BannerLayer {
+                pattern: pattern.trim_start_matches("minecraft:").to_owned(),
+                color,
+            })
+        })
+        .collect()
+}
+
+fn track_beacons(mut events: MessageReader<ReceiveGamePacketEvent>, mut beacons: ResMut<Beacons>) {
+    for event in events.read() {
+        match event.packet.as_ref() {
+            ClientboundGamePacket::LevelChunkWithLight(p) => {
+                for block_entity in &p.chunk_data.block_entities {
+                    if block_entity.kind == BlockEntityKind::Beacon {
+                        beacons.positions.insert(IVec3::new(
+                            p.x * 16 + (block_entity.packed_xz >> 4) as i32,
+                            block_entity.y as i16 as i32,
+                            p.z * 16 + (block_entity.packed_xz & 15) as i32,
+                        ));
+                    }
+                }
+            }
+            ClientboundGamePacket::BlockEntityData(p)
+                if p.block_entity_type == BlockEntityKind::Beacon =>
+            {
+                beacons
+                    .positions
+                    .insert(IVec3::new(p.pos.x, p.pos.y, p.pos.z));
+            }
+            ClientboundGamePacket::ForgetLevelChunk(p) => {
+                beacons.positions.retain(|pos| {
+                    (pos.x.div_euclid(16), pos.z.div_euclid(16)) != (p.pos.x, p.pos.z)
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The block of a falling block is only in the packet that spawns it.
+fn track_falling_blocks(
+    mut events: MessageReader<ReceiveGamePacketEvent>,
+    mut falling_blocks: ResMut<FallingBlockStates>,
+) {
+    for event in events.read() {
+        match event.packet.as_ref() {
+            ClientboundGamePacket::AddEntity(p) if p.entity_type == EntityKind::FallingBlock => {
+                if let Ok(state) = BlockState::try_from(p.data) {
+                    falling_blocks.states.insert(p.id, state);
+                }
+            }
+            ClientboundGamePacket::RemoveEntities(p) => {
+                for id in &p.entity_ids {
+                    falling_blocks.states.remove(id);
+                    falling_blocks.fuses.remove(id);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The server only sends the fuse of primed TNT when it's spawned, the client
+/// counts it down like vanilla does.
+fn tick_tnt_fuses(
+    query: Query<(&MinecraftEntityId, &Fuse)>,
+    mut falling_blocks: ResMut<FallingBlockStates>,
+) {
+    for (id, spawn_fuse) in &query {
+        *falling_blocks.fuses.entry(*id).or_insert(spawn_fuse.0) -= 1;
+    }
+}
+
+/// Sends the beams of the tracked beacons whenever one of them changes, and
+/// forgets beacons that were broken.
+fn forward_beacon_beams(
+    query: Query<&InstanceHolder, With<LocalEntity>>,
+    renderer: Res<RendererResource>,
+    mut beacons: ResMut<Beacons>,
+) {
+    let Some(holder) = query.iter().next() else {
+        return;
+    };
+    let world = holder.instance.read();
+    let beacons = &mut *beacons;
+
+    beacons.positions.retain(|pos| {
+        world
+            .get_block_state(BlockPos::new(pos.x, pos.y, pos.z))
+            .is_none_or(|state| Block::from(state) == Block::Beacon)
+    });
+    let mut beams: Vec<_> = beacons
+        .positions
+        .iter()
+        .filter_map(|&pos| beacon_beam(&world, pos))
+        .collect();
+    beams.sort_by_key(|beam| (beam.pos.x, beam.pos.y, beam.pos.z));
+
+    if beams != beacons.sent {
+        renderer.handle.set_beacon_beams(beams.clone());
+        beacons.sent = beams;
+    }
+}
+
+/// The beam of the beacon at `pos` as vanilla's `BeaconBlockEntity` builds
+/// it, `None` without a pyramid under the beacon or with an opaque block above
+/// it. Only the first layer of the pyramid is checked, it's enough for a beam.
+fn beacon_beam(world: &Instance, pos: IVec3) -> Option<BeaconBeam> {
+    let block_at = |x: i32, y: i32, z: i32| world.get_block_state(BlockPos::new(x, y, z));
+
+    for x in -1..=1 {
+        for z in -1..=1 {
+            let base = block_at(pos.x + x, pos.y - 1, pos.z + z)?;
+            if !tags::blocks::BEACON_BASE_BLOCKS.contains(&Block::from(base)) {
+                return None;
+            }
+        }
+    }
+
+    // the beacon itself starts a white segment, the first glass above it
+    // starts its own color and every later color is mixed with the one below
+    let mut segments = vec![BeamSegment {
+        height: 1,
+        color: [1.0; 3],
+    }];
+    let top = world.chunks.min_y + world.chunks.height as i32;
+    for y in pos.y + 1..top {
+        let Some(state) = block_at(pos.x, y, pos.z) else {
+            break;
+        };
+        let block = Block::from(state);
+
+        if let Some(color) = glass_color(block).and_then(dye_color) {
+            let below = segments[segments.len() - 1];
+            if segments.len() == 1 {
+                segments.push(BeamSegment { height: 1, color });
+            } else if below.color == color {
+                segments.last_mut().unwrap().height += 1;
+            } else {
+                segments.push(BeamSegment {
+                    height: 1,
+                    color: std::array::from_fn(|i| (below.color[i] + color[i]) / 2.0),
+                });
+            }
+        } else if state.to_trait().behavior().can_occlude
+            && state.is_collision_shape_full()
+            && block != Block::Bedrock
+        {
+            return None;
+        } else {
+            segments.last_mut().unwrap().height += 1;
+        }
+    }
+
+    Some(BeaconBeam { pos, segments })
+}
+
+/// Dye of the stained glass blocks and panes that color beacon beams.
+fn glass_color(block: Block) -> Option<&'static str> {
+    Some(match block {
+        Block::WhiteStainedGlass | Block::WhiteStainedGlassPane => "white",
+        Block::OrangeStainedGlass | Block::OrangeStainedGlassPane => "orange",
+        Block::MagentaStainedGlass | Block::MagentaStainedGlassPane => "magenta",
+        Block::LightBlueStainedGlass | Block::LightBlueStainedGlassPane => "light_blue",
+        Block::YellowStainedGlass | Block::YellowStainedGlassPane => "yellow",
+        Block::LimeStainedGlass | Block::LimeStainedGlassPane => "lime",
+        Block::PinkStainedGlass | Block::PinkStainedGlassPane => "pink",
+        Block::GrayStainedGlass | Block::GrayStainedGlassPane => "gray",
+        Block::LightGrayStainedGlass | Block::LightGrayStainedGlassPane => "light_gray",
+        Block::CyanStainedGlass | Block::CyanStainedGlassPane => "cyan",
+        Block::PurpleStainedGlass | Block::PurpleStainedGlassPane => "purple",
+        Block::BlueStainedGlass | Block::BlueStainedGlassPane => "blue",
+        Block::BrownStainedGlass | Block::BrownStainedGlassPane => "brown",
+        Block::GreenStainedGlass | Block::GreenStainedGlassPane => "green",
+        Block::RedStainedGlass | Block::RedStainedGlassPane => "red",
+        Block::BlackStainedGlass | Block::BlackStainedGlassPane => "black",
+        _ => return None,
+    })
+}
+
+/// Azalea drops unloaded chunks from the world without an event, so they're
+/// taken from the packets like the weather.
+fn forward_chunk_unloads(
+    mut events: MessageReader<ReceiveGamePacketEvent>,
+    renderer: Res<RendererResource>,
+) {
+    for event in events.read() {
+        if let ClientboundGamePacket::ForgetLevelChunk(p) = event.packet.as_ref() {
+            renderer.handle.remove_chunk(p.pos);
+        }
+    }
+}
+
+fn forward_player_position(
+    query: Query<(&Position, &EntityDimensions), (With<LocalEntity>, Changed<Position>)>,
+    renderer: Res<RendererResource>,
+) {
+    for (pos, dimensions) in &query {
+        renderer.handle.set_player_position(Vec3::new(
+            pos.x as f32,
+            pos.y as f32 + dimensions.eye_height,
+            pos.z as f32,
+        ));
+    }
+}
+
 fn add_world(
     renderer: Res<RendererResource>,
     added: Query<&InstanceHolder, Changed<InstanceHolder>>,
@@ -105,14 +451,27 @@ fn get_entities(
     *entities_mutex.lock() = entites;
 }
 
-fn poll_renderer_events(renderer: Res<RendererResource>, mut writer: MessageWriter<AppExit>) {
-    match renderer.handle.rx.try_recv() {
-        Ok(RendererEvent::Closed) => {
-            writer.write(AppExit::Success);
-        }
-        Err(TryRecvError::Empty) => {}
-        Err(TryRecvError::Disconnected) => {
-            writer.write(AppExit::Error(NonZero::new(1).unwrap()));
+fn poll_renderer_events(
+    renderer: Res<RendererResource>,
+    mut exit: MessageWriter<AppExit>,
+    mut events: MessageWriter<RendererEvent>,
+) {
+    loop {
+        match renderer.handle.rx.try_recv() {
+            Ok(RendererEvent::Closed) => {
+                exit.write(AppExit::Success);
+            }
+            Ok(RendererEvent::DeviceLost) => {
+                exit.write(AppExit::Error(NonZero::new(1).unwrap()));
+            }
+            Ok(event) => {
+                events.write(event);
+            }
+            Err(TryRecvError::Empty) => break,
+            Err(TryRecvError::Disconnected) => {
+                exit.write(AppExit::Error(NonZero::new(1).unwrap()));
+                break;
+            }
         }
     }
 }
@@ -0,0 +1,61 @@
+//! Raises and lowers the render distance to keep the frame time near a target.
+
+use std::time::Duration;
+
+use crate::renderer::world_renderer::WorldRendererConfig;
+
+/// Weight of a new frame in the smoothed frame time.
+const SMOOTHING: f32 = 0.05;
+/// The distance is lowered above this fraction of the target frame time and
+/// raised below the other, so it doesn't flip between two distances.
+const LOWER_ABOVE: f32 = 1.1;
+const RAISE_BELOW: f32 = 0.8;
+/// Time between two changes, changing the distance waits for the gpu and the
+/// frame time needs a moment to settle on the new distance.
+const COOLDOWN: Duration = Duration::from_secs(2);
+
+/// Hysteresis controller for `render_distance`, moving it one chunk at a time.
+#[derive(Default)]
+pub struct AdaptiveDistance {
+    /// Exponential moving average of the cpu frame time in ms.
+    smoothed_ms: Option<f32>,
+    since_change: Duration,
+}
+
+impl AdaptiveDistance {
+    /// Feeds the time of the last frame, returns the distance to switch to if
+    /// it should change.
+    pub fn update(&mut self, frame_time: Duration, config: &WorldRendererConfig) -> Option<u32> {
+        let ms = frame_time.as_secs_f32() * 1000.0;
+        let smoothed = match self.smoothed_ms {
+            Some(smoothed) => smoothed + (ms - smoothed) * SMOOTHING,
+            None => ms,
+        };
+        self.smoothed_ms = Some(smoothed);
+
+        self.since_change += frame_time;
+        if self.since_change < COOLDOWN {
+            return None;
+        }
+
+        let min = config.min_render_distance;
+        let max = config.max_render_distance.max(min);
+        let current = config.render_distance;
+        let target = config.target_frame_time_ms;
+        let new = if smoothed > target * LOWER_ABOVE {
+            current.saturating_sub(1).clamp(min, max)
+        } else if smoothed < target * RAISE_BELOW {
+            (current + 1).clamp(min, max)
+        } else {
+            current.clamp(min, max)
+        };
+        if new == current {
+            return None;
+        }
+
+        self.since_change = Duration::ZERO;
+        // the old average was measured at the old distance
+        self.smoothed_ms = None;
+        Some(new)
+    }
+}
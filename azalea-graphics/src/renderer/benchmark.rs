@@ -0,0 +1,152 @@
+//! Headless benchmark, flies the camera along a fixed path while rendering
+//! offscreen and reports frame times.
+
+use std::{
+    f32::consts::TAU,
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crossbeam::channel::Receiver;
+use parking_lot::Mutex;
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    app::{RendererArgs, WorldUpdate},
+    renderer::{RenderState, Renderer, camera::Camera},
+};
+
+/// Give up waiting for the world to finish meshing after this long and start
+/// measuring anyway.
+const WARMUP_TIMEOUT: Duration = Duration::from_secs(120);
+/// Radius of the circle the camera flies along, in blocks.
+const PATH_RADIUS: f32 = 64.0;
+const PATH_HEIGHT: f32 = 120.0;
+/// Simulated time between frames, so the path does not depend on frame rate.
+const FRAME_DT: Duration = Duration::from_micros(16_667);
+
+pub struct BenchmarkStats {
+    pub frames: u32,
+    pub total: Duration,
+    /// CPU frame times, sorted.
+    pub frame_times: Vec<Duration>,
+    /// Average GPU frame time in ms, when timestamps are enabled.
+    pub gpu_frame_time: Option<f32>,
+}
+
+impl BenchmarkStats {
+    fn percentile(&self, p: f32) -> Duration {
+        let index = ((self.frame_times.len() - 1) as f32 * p).round() as usize;
+        self.frame_times[index]
+    }
+}
+
+impl fmt::Display for BenchmarkStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let avg = self.total / self.frames.max(1);
+
+        writeln!(f, "frames: {}", self.frames)?;
+        writeln!(f, "total: {:.2}s", self.total.as_secs_f64())?;
+        writeln!(f, "avg fps: {:.1}", 1.0 / avg.as_secs_f64())?;
+        if !self.frame_times.is_empty() {
+            writeln!(
+                f,
+                "frame time: avg {:.2}ms, min {:.2}ms, p50 {:.2}ms, p99 {:.2}ms, max {:.2}ms",
+                ms(avg),
+                ms(self.frame_times[0]),
+                ms(self.percentile(0.5)),
+                ms(self.percentile(0.99)),
+                ms(self.frame_times[self.frame_times.len() - 1]),
+            )?;
+        }
+        if let Some(gpu) = self.gpu_frame_time {
+            writeln!(f, "gpu frame time: avg {gpu:.2}ms")?;
+        }
+        Ok(())
+    }
+}
+
+/// Where the camera is at `t` in `0..1` along the benchmark path, a full circle
+/// around the origin looking slightly down and along the direction of travel.
+fn camera_at(t: f32) -> Camera {
+    let angle = t * TAU;
+    let position = glam::vec3(
+        angle.cos() * PATH_RADIUS,
+        PATH_HEIGHT,
+        angle.sin() * PATH_RADIUS,
+    );
+    // tangent of the circle
+    let yaw = angle.to_degrees() + 90.0;
+    Camera::new(position, yaw, -20.0)
+}
+
+/// Renders `frames` frames into an offscreen target without opening a window.
+///
+/// World updates are read from `cmd_rx` like in the windowed renderer, the
+/// measurement starts once a world was added and all of it is meshed.
+pub fn run(
+    args: &RendererArgs,
+    frames: u32,
+    cmd_rx: &Receiver<WorldUpdate>,
+    entities: Arc<Mutex<Vec<RenderState>>>,
+) -> anyhow::Result<BenchmarkStats> {
+    let size = PhysicalSize::new(args.benchmark_width, args.benchmark_height);
+    let mut renderer = Renderer::new_headless(size, args, entities)?;
+
+    renderer.camera = camera_at(0.0);
+
+    let warmup_start = Instant::now();
+    loop {
+        renderer.draw_frame(cmd_rx)?;
+
+        if let Some(sections) = renderer.take_world_meshed() {
+            log::info!("Meshed {sections} sections");
+            break;
+        }
+        if warmup_start.elapsed() > WARMUP_TIMEOUT {
+            log::warn!("World did not finish meshing, measuring anyway");
+            break;
+        }
+    }
+    log::info!("Warmup took {:.2}s", warmup_start.elapsed().as_secs_f64());
+
+    let mut frame_times = Vec::with_capacity(frames as usize);
+    let mut gpu_total = 0.0;
+    let mut gpu_samples = 0;
+
+    let start = Instant::now();
+    let mut last = start;
+    for i in 0..frames {
+        renderer.camera = camera_at(i as f32 / frames as f32);
+        renderer.update(FRAME_DT);
+
+        // results of the frame about to be reused, its fence is waited on anyway
+        if i >= renderer.sync.frames_in_flight() as u32
+            && let Some(timings) = renderer.collect_timings(renderer.sync.current_frame)
+        {
+            gpu_total += timings.frame_time();
+            gpu_samples += 1;
+        }
+
+        renderer.draw_frame(cmd_rx)?;
+
+        let now = Instant::now();
+        frame_times.push(now - last);
+        last = now;
+    }
+
+    unsafe { renderer.context.device().device_wait_idle()? };
+    let total = start.elapsed();
+    renderer.destroy();
+
+    frame_times.sort();
+
+    Ok(BenchmarkStats {
+        frames,
+        total,
+        frame_times,
+        gpu_frame_time: (gpu_samples > 0).then(|| gpu_total / gpu_samples as f32),
+    })
+}
@@ -0,0 +1,139 @@
+//! Settings a [`Renderer`] is created with, for embedding it outside of the
+//! example bot.
+
+use std::{path::PathBuf, sync::Arc};
+
+use clap::Parser;
+use parking_lot::Mutex;
+use raw_window_handle::{DisplayHandle, WindowHandle};
+use winit::{dpi::PhysicalSize, event_loop::ActiveEventLoop};
+
+use crate::{
+    app::RendererArgs,
+    renderer::{
+        RenderState, Renderer,
+        render_targets::RenderTargets,
+        vulkan::{context::VkContext, swapchain::PresentMode},
+        world_renderer::WorldRendererConfig,
+    },
+};
+
+/// Builds a [`Renderer`] from the command line defaults with some settings
+/// changed.
+#[derive(Clone)]
+pub struct RendererBuilder {
+    args: RendererArgs,
+    config: WorldRendererConfig,
+}
+
+impl Default for RendererBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RendererBuilder {
+    /// The settings of the command line without any arguments.
+    pub fn new() -> Self {
+        Self::from_args(RendererArgs::parse_from(["azalea-graphics"]))
+    }
+
+    pub fn from_args(args: RendererArgs) -> Self {
+        Self {
+            args,
+            config: WorldRendererConfig::default(),
+        }
+    }
+
+    /// Replaces the whole world renderer config, the debug ui can still
+    /// change it later.
+    pub fn config(mut self, config: WorldRendererConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Render distance in chunks.
+    pub fn render_distance(mut self, chunks: u32) -> Self {
+        self.config.render_distance = chunks;
+        self
+    }
+
+    /// Threads meshing sections.
+    pub fn worker_threads(mut self, threads: u32) -> Self {
+        self.config.worker_threads = threads;
+        self
+    }
+
+    pub fn present_mode(mut self, present_mode: PresentMode) -> Self {
+        self.args.present_mode = present_mode;
+        self
+    }
+
+    /// Vertical field of view in degrees.
+    pub fn fov(mut self, degrees: f32) -> Self {
+        self.args.fov = degrees;
+        self
+    }
+
+    /// Directory the extracted vanilla assets are loaded from.
+    pub fn assets_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.args.assets = path.into();
+        self
+    }
+
+    /// Enables the Vulkan validation layers and debug labels.
+    pub fn validation(mut self, enabled: bool) -> Self {
+        self.args.debug = enabled;
+        self
+    }
+
+    pub fn build(
+        self,
+        window_handle: &WindowHandle,
+        display_handle: &DisplayHandle,
+        size: PhysicalSize<u32>,
+        event_loop: &ActiveEventLoop,
+        entities: Arc<Mutex<Vec<RenderState>>>,
+    ) -> anyhow::Result<Renderer> {
+        let context = VkContext::new(window_handle, display_handle, &self.args);
+        let render_targets = RenderTargets::new(
+            &context,
+            size.width,
+            size.height,
+            self.args.color_space,
+            self.args.swapchain_images,
+            self.args.present_mode,
+        );
+
+        Renderer::with_targets(
+            context,
+            render_targets,
+            size,
+            Some(event_loop),
+            &self.args,
+            self.config,
+            entities,
+        )
+    }
+
+    /// Builds a renderer without a window that draws into offscreen images,
+    /// there is no debug ui and nothing is presented.
+    pub fn build_headless(
+        self,
+        size: PhysicalSize<u32>,
+        entities: Arc<Mutex<Vec<RenderState>>>,
+    ) -> anyhow::Result<Renderer> {
+        let context = VkContext::new_headless(&self.args);
+        let render_targets = RenderTargets::new_offscreen(&context, size.width, size.height);
+
+        Renderer::with_targets(
+            context,
+            render_targets,
+            size,
+            None,
+            &self.args,
+            self.config,
+            entities,
+        )
+    }
+}
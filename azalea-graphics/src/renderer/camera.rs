@@ -1,6 +1,9 @@
-use std::{f32::consts::FRAC_PI_2, time::Duration};
+use std::{
+    f32::consts::FRAC_PI_2,
+    time::{Duration, Instant},
+};
 
-use glam::{Mat4, Vec3};
+use glam::{Mat4, Vec2, Vec3};
 use winit::{
     dpi::PhysicalPosition,
     event::{ElementState, MouseScrollDelta},
@@ -8,6 +11,11 @@ use winit::{
 };
 
 const SAFE_FRAC_PI_2: f32 = FRAC_PI_2 - 0.0001;
+/// Roughly how many pixels a touchpad scrolls for one mouse wheel line.
+const PIXELS_PER_LINE: f32 = 20.0;
+/// Radians turned per pixel of mouse motion at a sensitivity of 1, about
+/// vanilla's default.
+const RADIANS_PER_PIXEL: f32 = 0.0025;
 
 #[derive(Debug)]
 pub struct Camera {
@@ -25,13 +33,15 @@ impl Camera {
         }
     }
 
-    pub fn calc_view(&self) -> Mat4 {
+    pub fn forward(&self) -> Vec3 {
         let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
         let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
 
-        let forward = Vec3::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw).normalize();
+        Vec3::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw).normalize()
+    }
 
-        Mat4::look_to_rh(self.position, forward, Vec3::Y)
+    pub fn calc_view(&self) -> Mat4 {
+        Mat4::look_to_rh(self.position, self.forward(), Vec3::Y)
     }
 }
 
@@ -39,6 +49,8 @@ pub struct Projection {
     aspect: f32,
     fovy: f32,
     znear: f32,
+    /// `None` puts the far plane at infinity.
+    zfar: Option<f32>,
 }
 
 impl Projection {
@@ -47,6 +59,7 @@ impl Projection {
             aspect: width as f32 / height as f32,
             fovy: fovy_deg.to_radians(),
             znear,
+            zfar: None,
         }
     }
 
@@ -54,109 +67,287 @@ impl Projection {
         self.aspect = width as f32 / height as f32;
     }
 
+    pub fn near_far(&self) -> (f32, Option<f32>) {
+        (self.znear, self.zfar)
+    }
+
+    /// Moves the clip planes, a far plane of `None` is at infinity. Depth stays
+    /// reversed either way, with 1 at the near and 0 at the far plane, which
+    /// the 0 depth clear, the depth tests and the HiZ culling rely on. Far
+    /// planes that aren't past the near plane are ignored.
+    pub fn set_near_far(&mut self, znear: f32, zfar: Option<f32>) {
+        assert!(znear > 0.0, "the near plane must be in front of the camera");
+        self.znear = znear;
+        self.zfar = zfar.filter(|&zfar| zfar > znear);
+        if self.zfar.is_none() && zfar.is_some() {
+            log::warn!("Far plane {zfar:?} is not past the near plane {znear}, using infinity");
+        }
+    }
+
     pub fn calc_proj(&self) -> Mat4 {
-        let mut proj = Mat4::perspective_infinite_reverse_rh(self.fovy, self.aspect, self.znear);
+        let mut proj = match self.zfar {
+            // near and far swapped for reversed depth
+            Some(zfar) => Mat4::perspective_rh(self.fovy, self.aspect, zfar, self.znear),
+            None => Mat4::perspective_infinite_reverse_rh(self.fovy, self.aspect, self.znear),
+        };
         proj.col_mut(1)[1] *= -1.0;
         proj
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct CameraConfig {
+    /// Mouse look speed, independent of the frame rate.
+    pub sensitivity: f32,
+    /// Movement speed is multiplied by this for every scrolled line.
+    pub scroll_factor: f32,
+    pub invert_scroll: bool,
+    pub min_speed: f32,
+    pub max_speed: f32,
+    /// Movement speed is multiplied by this while Ctrl is held.
+    pub sprint_factor: f32,
+    /// Movement speed is multiplied by this while Alt is held, for precise
+    /// positioning.
+    pub slow_factor: f32,
+    /// Grab the cursor for mouse look when clicking into the window, otherwise
+    /// only the toggle key (Tab) grabs it.
+    pub grab_on_click: bool,
+    /// Gamepad stick deflections below this are ignored, so worn sticks don't
+    /// drift.
+    pub gamepad_deadzone: f32,
+    /// Radians per second turned with the look stick fully deflected.
+    pub gamepad_look_speed: f32,
+    /// Eases mouse look out over `mouse_smoothing` instead of applying each
+    /// motion in the frame it arrives, for smooth pans in recordings.
+    pub smooth_mouse: bool,
+    /// Seconds for the smoothed look to cover about two thirds of the mouse
+    /// motion. Measured in real time, so it feels the same at any frame rate.
+    pub mouse_smoothing: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            sensitivity: 1.0,
+            scroll_factor: 1.1,
+            invert_scroll: false,
+            min_speed: 0.1,
+            max_speed: 100.0,
+            sprint_factor: 4.0,
+            slow_factor: 0.2,
+            grab_on_click: true,
+            gamepad_deadzone: 0.15,
+            gamepad_look_speed: 3.0,
+            smooth_mouse: false,
+            mouse_smoothing: 0.05,
+        }
+    }
+}
+
+/// How long a movement key was held since the camera was last updated, so a
+/// press that starts and ends during a long frame still moves the camera.
+#[derive(Debug, Default)]
+struct HeldKey {
+    pressed_at: Option<Instant>,
+    held: Duration,
+}
+
+impl HeldKey {
+    fn set(&mut self, pressed: bool, now: Instant) {
+        match (pressed, self.pressed_at) {
+            (true, None) => self.pressed_at = Some(now),
+            (false, Some(pressed_at)) => {
+                self.held += now.saturating_duration_since(pressed_at);
+                self.pressed_at = None;
+            }
+            // key repeat, or a release without a press while unfocused
+            _ => {}
+        }
+    }
+
+    /// Seconds the key was held since the last call.
+    fn take(&mut self, now: Instant) -> f32 {
+        if let Some(pressed_at) = &mut self.pressed_at {
+            self.held += now.saturating_duration_since(*pressed_at);
+            *pressed_at = now;
+        }
+        std::mem::take(&mut self.held).as_secs_f32()
+    }
+}
+
 #[derive(Debug)]
 pub struct CameraController {
-    amount_left: f32,
-    amount_right: f32,
-    amount_forward: f32,
-    amount_backward: f32,
-    amount_up: f32,
-    amount_down: f32,
+    left: HeldKey,
+    right: HeldKey,
+    forward: HeldKey,
+    backward: HeldKey,
+    up: HeldKey,
+    down: HeldKey,
+    sprinting: bool,
+    slowed: bool,
+    /// Mouse motion in pixels not yet turned by, everything since the last
+    /// update unless the look is smoothed.
     rotate_horizontal: f32,
     rotate_vertical: f32,
     scroll: f32,
+    /// Gamepad movement along the camera's right, up and forward axes, and
+    /// look stick deflection, each from -1 to 1.
+    stick_move: Vec3,
+    stick_look: Vec2,
+    /// Time of the last update, the sticks move the camera by the time since.
+    last_update: Option<Instant>,
     pub speed: f32,
-    pub sensitivity: f32,
+    pub config: CameraConfig,
 }
 
 impl CameraController {
-    pub fn new(speed: f32, sensitivity: f32) -> Self {
+    pub fn new(speed: f32, config: CameraConfig) -> Self {
         Self {
-            amount_left: 0.0,
-            amount_right: 0.0,
-            amount_forward: 0.0,
-            amount_backward: 0.0,
-            amount_up: 0.0,
-            amount_down: 0.0,
+            left: HeldKey::default(),
+            right: HeldKey::default(),
+            forward: HeldKey::default(),
+            backward: HeldKey::default(),
+            up: HeldKey::default(),
+            down: HeldKey::default(),
+            sprinting: false,
+            slowed: false,
             rotate_horizontal: 0.0,
             rotate_vertical: 0.0,
             scroll: 0.0,
+            stick_move: Vec3::ZERO,
+            stick_look: Vec2::ZERO,
+            last_update: None,
             speed,
-            sensitivity,
+            config,
         }
     }
 
     pub fn process_keyboard(&mut self, key: KeyCode, state: ElementState) -> bool {
-        let amount = if state == ElementState::Pressed {
-            1.0
-        } else {
-            0.0
-        };
-        match key {
-            KeyCode::KeyW | KeyCode::ArrowUp => {
-                self.amount_forward = amount;
-                true
-            }
-            KeyCode::KeyS | KeyCode::ArrowDown => {
-                self.amount_backward = amount;
-                true
-            }
-            KeyCode::KeyA | KeyCode::ArrowLeft => {
-                self.amount_left = amount;
-                true
-            }
-            KeyCode::KeyD | KeyCode::ArrowRight => {
-                self.amount_right = amount;
-                true
+        let pressed = state == ElementState::Pressed;
+        let key = match key {
+            KeyCode::KeyW | KeyCode::ArrowUp => &mut self.forward,
+            KeyCode::KeyS | KeyCode::ArrowDown => &mut self.backward,
+            KeyCode::KeyA | KeyCode::ArrowLeft => &mut self.left,
+            KeyCode::KeyD | KeyCode::ArrowRight => &mut self.right,
+            KeyCode::Space => &mut self.up,
+            KeyCode::ShiftLeft | KeyCode::ShiftRight => &mut self.down,
+            KeyCode::ControlLeft | KeyCode::ControlRight => {
+                self.sprinting = pressed;
+                return true;
             }
-            KeyCode::Space => {
-                self.amount_up = amount;
-                true
+            KeyCode::AltLeft | KeyCode::AltRight => {
+                self.slowed = pressed;
+                return true;
             }
-            KeyCode::ShiftLeft | KeyCode::ShiftRight => {
-                self.amount_down = amount;
-                true
-            }
-            _ => false,
-        }
+            _ => return false,
+        };
+        key.set(pressed, Instant::now());
+        true
     }
 
+    /// Several motion events can arrive between two frames, they all count.
+    /// The deltas are winit's `DeviceEvent::MouseMotion`, raw and without the
+    /// OS pointer acceleration where the platform supports it.
     pub fn handle_mouse(&mut self, mouse_dx: f64, mouse_dy: f64) {
-        self.rotate_horizontal = mouse_dx as f32;
-        self.rotate_vertical = mouse_dy as f32;
+        self.rotate_horizontal += mouse_dx as f32;
+        self.rotate_vertical += mouse_dy as f32;
+    }
+
+    /// Sets the analog gamepad input, `movement` is the left stick with the
+    /// triggers as y and `look` is the right stick. Both stay in effect until
+    /// the next call.
+    pub fn set_gamepad_sticks(&mut self, movement: Vec3, look: Vec2) {
+        let deadzone = self.config.gamepad_deadzone;
+        let horizontal = apply_deadzone(Vec2::new(movement.x, movement.z), deadzone);
+        let vertical = apply_deadzone(Vec2::new(movement.y, 0.0), deadzone).x;
+        self.stick_move = Vec3::new(horizontal.x, vertical, horizontal.y);
+        self.stick_look = apply_deadzone(look, deadzone);
     }
 
     pub fn handle_mouse_scroll(&mut self, delta: &MouseScrollDelta) {
-        let scroll_delta = match delta {
+        let mut lines = match delta {
             MouseScrollDelta::LineDelta(_, scroll) => *scroll,
-            MouseScrollDelta::PixelDelta(PhysicalPosition { y, .. }) => *y as f32,
+            MouseScrollDelta::PixelDelta(PhysicalPosition { y, .. }) => *y as f32 / PIXELS_PER_LINE,
         };
+        if self.config.invert_scroll {
+            lines = -lines;
+        }
+
+        // geometric so each notch feels the same at any speed
+        self.speed = (self.speed * self.config.scroll_factor.powf(lines))
+            .clamp(self.config.min_speed, self.config.max_speed);
+    }
 
-        self.speed = (self.speed + scroll_delta * self.sensitivity).clamp(0.1, 100.0);
+    /// Drops the movement and mouse motion not applied yet, so the camera
+    /// stays where it was put. Keys still held keep moving it from `now` on.
+    pub fn stop(&mut self, now: Instant) {
+        for key in [
+            &mut self.left,
+            &mut self.right,
+            &mut self.forward,
+            &mut self.backward,
+            &mut self.up,
+            &mut self.down,
+        ] {
+            key.take(now);
+        }
+        self.rotate_horizontal = 0.0;
+        self.rotate_vertical = 0.0;
     }
 
-    pub fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
-        let dt = dt.as_secs_f32();
+    /// The speed with the sprint and slow modifiers applied.
+    pub fn movement_speed(&self) -> f32 {
+        let mut speed = self.speed;
+        if self.sprinting {
+            speed *= self.config.sprint_factor;
+        }
+        if self.slowed {
+            speed *= self.config.slow_factor;
+        }
+        speed
+    }
 
+    /// Moves the camera by how long each key was held since the last update,
+    /// measured with the real time of the key events rather than the frame
+    /// time. The modifiers held at the time of the update apply to the whole
+    /// movement.
+    pub fn update_camera(&mut self, camera: &mut Camera, now: Instant) {
         let (yaw_sin, yaw_cos) = camera.yaw.sin_cos();
         let forward = Vec3::new(yaw_cos, 0.0, yaw_sin).normalize();
         let right = Vec3::new(-yaw_sin, 0.0, yaw_cos).normalize();
 
-        camera.position += forward * (self.amount_forward - self.amount_backward) * self.speed * dt;
-        camera.position += right * (self.amount_right - self.amount_left) * self.speed * dt;
-        camera.position.y += (self.amount_up - self.amount_down) * self.speed * dt;
+        let forward_time = self.forward.take(now) - self.backward.take(now);
+        let right_time = self.right.take(now) - self.left.take(now);
+        let up_time = self.up.take(now) - self.down.take(now);
+        let speed = self.movement_speed();
+        camera.position += forward * forward_time * speed;
+        camera.position += right * right_time * speed;
+        camera.position.y += up_time * speed;
 
-        camera.yaw += self.rotate_horizontal * self.sensitivity * dt;
-        camera.pitch -= self.rotate_vertical * self.sensitivity * dt;
-        self.rotate_horizontal = 0.0;
-        self.rotate_vertical = 0.0;
+        let dt = self.last_update.map_or(0.0, |last| {
+            now.saturating_duration_since(last).as_secs_f32()
+        });
+        self.last_update = Some(now);
+
+        // smoothing turns by a share of the motion left that depends on the
+        // time since the last update, and keeps the rest for later updates
+        let share = if self.config.smooth_mouse {
+            1.0 - (-dt / self.config.mouse_smoothing.max(f32::EPSILON)).exp()
+        } else {
+            1.0
+        };
+        let horizontal = self.rotate_horizontal * share;
+        let vertical = self.rotate_vertical * share;
+        self.rotate_horizontal -= horizontal;
+        self.rotate_vertical -= vertical;
+        let look = self.config.sensitivity * RADIANS_PER_PIXEL;
+        camera.yaw += horizontal * look;
+        camera.pitch -= vertical * look;
+        camera.position += forward * self.stick_move.z * speed * dt;
+        camera.position += right * self.stick_move.x * speed * dt;
+        camera.position.y += self.stick_move.y * speed * dt;
+        camera.yaw += self.stick_look.x * self.config.gamepad_look_speed * dt;
+        camera.pitch += self.stick_look.y * self.config.gamepad_look_speed * dt;
 
         if camera.pitch < -SAFE_FRAC_PI_2 {
             camera.pitch = -SAFE_FRAC_PI_2;
@@ -165,3 +356,62 @@ impl CameraController {
         }
     }
 }
+
+/// Zeroes deflections shorter than `deadzone` and rescales the rest, so the
+/// stick still reaches full speed and starts moving smoothly at the edge of the
+/// deadzone.
+fn apply_deadzone(stick: Vec2, deadzone: f32) -> Vec2 {
+    let length = stick.length();
+    if length <= deadzone {
+        return Vec2::ZERO;
+    }
+    let scaled = ((length - deadzone) / (1.0 - deadzone).max(f32::EPSILON)).min(1.0);
+    stick / length * scaled
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec4;
+
+    use super::*;
+    use crate::renderer::world_renderer::visibility::aabb_visible;
+
+    /// Depth of the point `distance` blocks in front of a camera looking down
+    /// -z.
+    fn depth(proj: &Projection, distance: f32) -> f32 {
+        let clip = proj.calc_proj() * Vec4::new(0.0, 0.0, -distance, 1.0);
+        clip.z / clip.w
+    }
+
+    #[test]
+    fn depth_is_reversed() {
+        let mut proj = Projection::new(16, 9, 70.0, 0.1);
+        assert!((depth(&proj, 0.1) - 1.0).abs() < 1e-4);
+        assert!(depth(&proj, 1e6) < 1e-4);
+        assert!(depth(&proj, 10.0) > depth(&proj, 20.0));
+
+        proj.set_near_far(0.1, Some(100.0));
+        assert!((depth(&proj, 0.1) - 1.0).abs() < 1e-4);
+        assert!(depth(&proj, 100.0).abs() < 1e-4);
+        assert!(depth(&proj, 10.0) > depth(&proj, 20.0));
+    }
+
+    #[test]
+    fn boxes_past_the_far_plane_are_culled() {
+        let mut proj = Projection::new(16, 9, 70.0, 0.1);
+        proj.set_near_far(0.1, Some(100.0));
+        // looking down -z
+        let view_proj = proj.calc_proj() * Camera::new(Vec3::ZERO, -90.0, 0.0).calc_view();
+
+        let at = |z: f32| {
+            aabb_visible(
+                &view_proj,
+                Vec3::new(-1.0, -1.0, z - 1.0),
+                Vec3::new(1.0, 1.0, z + 1.0),
+            )
+        };
+        assert!(at(-10.0));
+        assert!(!at(-200.0));
+        assert!(!at(10.0));
+    }
+}
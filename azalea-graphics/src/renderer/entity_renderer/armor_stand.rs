@@ -0,0 +1,44 @@
+use azalea::entity::Rotations;
+use glam::Vec3;
+
+use super::{state::armor_stand::ArmorStandRenderState, transform::ModelTransforms};
+
+/// Parts turned by the body pose, the sticks holding the body up follow it.
+const BODY_PARTS: [&str; 4] = [
+    "body",
+    "right_body_stick",
+    "left_body_stick",
+    "shoulder_stick",
+];
+
+fn to_radians(rotations: &Rotations) -> Vec3 {
+    Vec3::new(
+        rotations.x.to_radians(),
+        rotations.y.to_radians(),
+        rotations.z.to_radians(),
+    )
+}
+
+/// Poses the armor stand model from the rotations the server sent (Java:
+/// `ArmorStandEntityModel.setAngles`).
+pub fn set_armor_stand_angles(transforms: &mut ModelTransforms, state: &ArmorStandRenderState) {
+    let mut pose = |part: &str, rotations: &Rotations, visible: bool| {
+        if let Some(part) = transforms.get_mut(part) {
+            part.rotation = to_radians(rotations);
+            part.visible = visible;
+        }
+    };
+
+    pose("head", &state.head_pose, true);
+    for part in BODY_PARTS {
+        pose(part, &state.body_pose, true);
+    }
+    pose("right_arm", &state.right_arm_pose, state.show_arms);
+    pose("left_arm", &state.left_arm_pose, state.show_arms);
+    pose("right_leg", &state.right_leg_pose, true);
+    pose("left_leg", &state.left_leg_pose, true);
+
+    if let Some(base_plate) = transforms.get_mut("base_plate") {
+        base_plate.visible = state.show_base_plate;
+    }
+}
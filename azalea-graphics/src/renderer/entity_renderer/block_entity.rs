@@ -0,0 +1,254 @@
+use std::f32::consts::PI;
+
+use azalea::{
+    blocks::{
+        BlockState,
+        properties::{ChestType, FacingCardinal, Part},
+    },
+    registry::Block,
+};
+use glam::{Mat4, Vec3};
+
+use crate::renderer::world_renderer::{BannerLayer, BlockEntity};
+
+/// Java: `BannerPatternLayers`, at most 16 patterns are drawn over the base.
+const MAX_BANNER_PATTERNS: usize = 16;
+
+/// The entity model, texture and transform a block entity is drawn with.
+pub struct BlockEntityModel {
+    pub model: &'static str,
+    pub texture: String,
+    pub transform: Mat4,
+    /// sRGB dye color of a banner pattern, which is blended over the models
+    /// before it. `None` for opaque models.
+    pub tint: Option<[f32; 3]>,
+}
+
+/// Whether the block is drawn by the entity renderer instead of the mesher.
+pub fn is_block_entity(state: BlockState) -> bool {
+    let block = Block::from(state);
+    let chest = matches!(
+        block,
+        Block::Chest | Block::TrappedChest | Block::EnderChest
+    );
+    chest || bed_color(block).is_some() || banner_color(state).is_some()
+}
+
+/// Box around everything the block entity draws, relative to its block.
+pub fn block_entity_bounds(state: BlockState) -> (Vec3, Vec3) {
+    match banner_color(state) {
+        // standing banners are two blocks tall, wall banners hang down a block
+        Some(_) if is_wall_banner(state) => (Vec3::NEG_Y, Vec3::ONE),
+        Some(_) => (Vec3::ZERO, Vec3::new(1.0, 2.0, 1.0)),
+        None => (Vec3::ZERO, Vec3::ONE),
+    }
+}
+
+/// The models to draw for a block entity in order, `banner_patterns` are the
+/// layers of a banner's block entity data.
+pub fn block_entity_models(
+    block_entity: &BlockEntity,
+    banner_patterns: &[BannerLayer],
+) -> Vec<BlockEntityModel> {
+    if let Some(color) = banner_color(block_entity.state) {
+        return banner_models(block_entity, color, banner_patterns).unwrap_or_default();
+    }
+    block_entity_model(block_entity).into_iter().collect()
+}
+
+fn block_entity_model(block_entity: &BlockEntity) -> Option<BlockEntityModel> {
+    let state = block_entity.state;
+    let block = Block::from(state);
+    let origin = Mat4::from_translation(block_entity.pos.as_vec3());
+
+    if let Some(color) = bed_color(block) {
+        let head = state.property::<Part>() == Some(Part::Head);
+        let facing = state.property::<FacingCardinal>()?;
+
+        // Java: `BedRenderer.renderPiece`, the model is modelled lying on its side
+        let transform = origin
+            * Mat4::from_translation(Vec3::new(0.0, 9.0 / 16.0, 0.0))
+            * Mat4::from_rotation_x(PI / 2.0)
+            * around_center(Mat4::from_rotation_z(PI + y_rot(facing)));
+
+        return Some(BlockEntityModel {
+            model: if head {
+                "minecraft:bed_head#main"
+            } else {
+                "minecraft:bed_foot#main"
+            },
+            texture: format!("textures/entity/bed/{color}.png"),
+            transform,
+            tint: None,
+        });
+    }
+
+    let name = match block {
+        Block::Chest => "normal",
+        Block::TrappedChest => "trapped",
+        Block::EnderChest => "ender",
+        _ => return None,
+    };
+    let facing = state.property::<FacingCardinal>()?;
+    let chest_type = state.property::<ChestType>().unwrap_or(ChestType::Single);
+
+    // Java: `ChestRenderer.render`
+    let transform = origin * around_center(Mat4::from_rotation_y(-y_rot(facing)));
+
+    let (model, texture) = match chest_type {
+        ChestType::Single => (
+            "minecraft:chest#main",
+            format!("textures/entity/chest/{name}.png"),
+        ),
+        ChestType::Left => (
+            "minecraft:double_chest_left#main",
+            format!("textures/entity/chest/{name}_left.png"),
+        ),
+        ChestType::Right => (
+            "minecraft:double_chest_right#main",
+            format!("textures/entity/chest/{name}_right.png"),
+        ),
+    };
+
+    Some(BlockEntityModel {
+        model,
+        texture,
+        transform,
+        tint: None,
+    })
+}
+
+/// Java: `BannerRenderer.submit`, the pole and flag with the plain banner
+/// texture, then the base color and each pattern blended over the flag.
+fn banner_models(
+    block_entity: &BlockEntity,
+    base_color: [f32; 3],
+    patterns: &[BannerLayer],
+) -> Option<Vec<BlockEntityModel>> {
+    let state = block_entity.state;
+    let wall = is_wall_banner(state);
+
+    let (translation, y_rot, model, flag) = if wall {
+        let facing = state.property::<FacingCardinal>()?;
+        (
+            Vec3::new(0.5, -1.0 / 6.0, 0.5),
+            y_rot(facing),
+            "minecraft:wall_banner#main",
+            "minecraft:wall_banner_flag#main",
+        )
+    } else {
+        // Java: `RotationSegment.convertToDegrees`, sixteen steps per turn
+        let rotation = state
+            .to_trait()
+            .get_property("rotation")?
+            .parse::<f32>()
+            .ok()?;
+        (
+            Vec3::new(0.5, 0.0, 0.5),
+            rotation * PI / 8.0,
+            "minecraft:standing_banner#main",
+            "minecraft:standing_banner_flag#main",
+        )
+    };
+    // the flag doesn't sway in the wind yet
+    let transform = Mat4::from_translation(block_entity.pos.as_vec3() + translation)
+        * Mat4::from_rotation_y(-y_rot)
+        * Mat4::from_scale(Vec3::new(2.0 / 3.0, -2.0 / 3.0, -2.0 / 3.0));
+
+    let plain = |model| BlockEntityModel {
+        model,
+        texture: "textures/entity/banner_base.png".to_owned(),
+        transform,
+        tint: None,
+    };
+    let layer = |pattern: &str, color| BlockEntityModel {
+        model: flag,
+        texture: format!("textures/entity/banner/{pattern}.png"),
+        transform,
+        tint: Some(color),
+    };
+
+    let mut models = vec![plain(model), plain(flag), layer("base", base_color)];
+    models.extend(
+        patterns
+            .iter()
+            .take(MAX_BANNER_PATTERNS)
+            .map(|p| layer(&p.pattern, p.color)),
+    );
+    Some(models)
+}
+
+/// Applies `rotation` around the center of the block.
+fn around_center(rotation: Mat4) -> Mat4 {
+    Mat4::from_translation(Vec3::splat(0.5)) * rotation * Mat4::from_translation(Vec3::splat(-0.5))
+}
+
+/// Java: `Direction.toYRot`, in radians.
+fn y_rot(facing: FacingCardinal) -> f32 {
+    match facing {
+        FacingCardinal::South => 0.0,
+        FacingCardinal::West => PI / 2.0,
+        FacingCardinal::North => PI,
+        FacingCardinal::East => PI * 1.5,
+    }
+}
+
+fn is_wall_banner(state: BlockState) -> bool {
+    state.to_trait().id().ends_with("_wall_banner")
+}
+
+/// Base color of a standing or wall banner.
+fn banner_color(state: BlockState) -> Option<[f32; 3]> {
+    let id = state.to_trait().id();
+    let color = id
+        .strip_suffix("_wall_banner")
+        .or_else(|| id.strip_suffix("_banner"))?;
+    dye_color(color)
+}
+
+/// Java: `DyeColor.getTextureDiffuseColor`, sRGB.
+pub fn dye_color(name: &str) -> Option<[f32; 3]> {
+    let rgb: u32 = match name {
+        "white" => 0xf9fffe,
+        "orange" => 0xf9801d,
+        "magenta" => 0xc74ebd,
+        "light_blue" => 0x3ab3da,
+        "yellow" => 0xfed83d,
+        "lime" => 0x80c71f,
+        "pink" => 0xf38baa,
+        "gray" => 0x474f52,
+        "light_gray" => 0x9d9d97,
+        "cyan" => 0x169c9c,
+        "purple" => 0x8932b8,
+        "blue" => 0x3c44aa,
+        "brown" => 0x835432,
+        "green" => 0x5e7c16,
+        "red" => 0xb02e26,
+        "black" => 0x1d1d21,
+        _ => return None,
+    };
+    let channel = |shift: u32| ((rgb >> shift) & 0xff) as f32 / 255.0;
+    Some([channel(16), channel(8), channel(0)])
+}
+
+fn bed_color(block: Block) -> Option<&'static str> {
+    Some(match block {
+        Block::WhiteBed => "white",
+        Block::OrangeBed => "orange",
+        Block::MagentaBed => "magenta",
+        Block::LightBlueBed => "light_blue",
+        Block::YellowBed => "yellow",
+        Block::LimeBed => "lime",
+        Block::PinkBed => "pink",
+        Block::GrayBed => "gray",
+        Block::LightGrayBed => "light_gray",
+        Block::CyanBed => "cyan",
+        Block::PurpleBed => "purple",
+        Block::BlueBed => "blue",
+        Block::BrownBed => "brown",
+        Block::GreenBed => "green",
+        Block::RedBed => "red",
+        Block::BlackBed => "black",
+        _ => return None,
+    })
+}
@@ -0,0 +1,173 @@
+use std::f32::consts::PI;
+
+use azalea::{
+    core::direction::Direction,
+    inventory::{
+        ItemStack,
+        components::{EnchantmentGlintOverride, Enchantments},
+    },
+    registry::Item,
+};
+use azalea_assets::Assets;
+use glam::{Mat4, Vec2, Vec3};
+
+use super::{state::item_frame::ItemFrameRenderState, types::EntityVertex};
+
+/// Size of the frame's back plate, the wooden border is left out.
+const FRAME_SIZE: f32 = 12.0 / 16.0;
+/// Items are drawn as a flat sprite at half a block.
+const ITEM_SIZE: f32 = 0.5;
+/// The entity position is the center of the one pixel thick frame.
+const FRAME_FRONT: f32 = 1.0 / 32.0;
+/// Keeps the item from z-fighting with the frame.
+const ITEM_OFFSET: f32 = 1.0 / 64.0;
+
+/// A two sided quad in the XY plane, centered on the origin and facing +Z.
+fn quad(size: f32, uv_min: Vec2, uv_max: Vec2) -> [EntityVertex; 12] {
+    let h = size / 2.0;
+    let corners = [
+        (Vec3::new(-h, -h, 0.0), Vec2::new(uv_min.x, uv_max.y)),
+        (Vec3::new(h, -h, 0.0), Vec2::new(uv_max.x, uv_max.y)),
+        (Vec3::new(h, h, 0.0), Vec2::new(uv_max.x, uv_min.y)),
+        (Vec3::new(-h, h, 0.0), Vec2::new(uv_min.x, uv_min.y)),
+    ];
+    // front facing counter clockwise, then the same triangles wound the other way
+    [0, 1, 2, 0, 2, 3, 0, 2, 1, 0, 3, 2].map(|i| EntityVertex {
+        pos: corners[i].0,
+        transform_id: 0,
+        uv: corners[i].1,
+    })
+}
+
+pub fn frame_quad() -> [EntityVertex; 12] {
+    quad(
+        FRAME_SIZE,
+        Vec2::splat(2.0 / 16.0),
+        Vec2::splat(14.0 / 16.0),
+    )
+}
+
+pub fn item_quad() -> [EntityVertex; 12] {
+    quad(ITEM_SIZE, Vec2::ZERO, Vec2::ONE)
+}
+
+/// Rotates +Z onto the direction the frame faces.
+fn facing(direction: Direction) -> Mat4 {
+    match direction {
+        Direction::South => Mat4::IDENTITY,
+        Direction::West => Mat4::from_rotation_y(-PI / 2.0),
+        Direction::North => Mat4::from_rotation_y(PI),
+        Direction::East => Mat4::from_rotation_y(PI / 2.0),
+        Direction::Up => Mat4::from_rotation_x(-PI / 2.0),
+        Direction::Down => Mat4::from_rotation_x(PI / 2.0),
+    }
+}
+
+fn origin(state: &ItemFrameRenderState) -> Mat4 {
+    let pos = Vec3::new(state.x as f32, state.y as f32, state.z as f32);
+    Mat4::from_translation(pos) * facing(state.direction)
+}
+
+pub fn frame_transform(state: &ItemFrameRenderState) -> Mat4 {
+    origin(state) * Mat4::from_translation(Vec3::new(0.0, 0.0, FRAME_FRONT))
+}
+
+pub fn item_transform(state: &ItemFrameRenderState) -> Mat4 {
+    // every step turns the item an eighth clockwise when looking at the frame
+    origin(state)
+        * Mat4::from_translation(Vec3::new(0.0, 0.0, FRAME_FRONT + ITEM_OFFSET))
+        * Mat4::from_rotation_z(-(state.rotation as f32) * PI / 4.0)
+}
+
+pub fn frame_texture(state: &ItemFrameRenderState) -> &'static str {
+    if state.glow_frame {
+        "textures/block/glow_item_frame.png"
+    } else {
+        "textures/block/item_frame.png"
+    }
+}
+
+/// Items that have the enchantment glint without being enchanted.
+const GLINT_ITEMS: [Item; 7] = [
+    Item::EnchantedBook,
+    Item::EnchantedGoldenApple,
+    Item::ExperienceBottle,
+    Item::WrittenBook,
+    Item::NetherStar,
+    Item::EndCrystal,
+    Item::DebugStick,
+];
+
+/// Whether the item is drawn with the enchantment glint, like vanilla's
+/// `hasFoil`: the glint override component if it is set, otherwise whether
+/// the item is enchanted.
+pub fn has_glint(stack: &ItemStack) -> bool {
+    let Some(data) = stack.as_present() else {
+        return false;
+    };
+    let components = &data.component_patch;
+    if let Some(glint) = components.get::<EnchantmentGlintOverride>() {
+        return glint.show_glint;
+    }
+    GLINT_ITEMS.contains(&data.kind)
+        || components
+            .get::<Enchantments>()
+            .is_some_and(|enchantments| !enchantments.levels.is_empty())
+}
+
+/// The texture an item is drawn with in a frame. Block items don't have an item
+/// texture, those fall back to the block's texture.
+pub fn item_texture(assets: &Assets, item: Item) -> Option<String> {
+    let id = item.to_string();
+    let name = id.strip_prefix("minecraft:").unwrap_or(&id);
+
+    ["item", "block"]
+        .iter()
+        .map(|dir| format!("textures/{dir}/{name}.png"))
+        .find(|path| assets.get_path(path).exists())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use azalea::registry::{DataRegistry, Enchantment};
+
+    use super::*;
+
+    fn enchanted(item: Item) -> ItemStack {
+        ItemStack::new(item, 1).with_component(Enchantments {
+            levels: HashMap::from([(Enchantment::new_raw(0), 1)]),
+        })
+    }
+
+    fn glint_override(stack: ItemStack, show_glint: bool) -> ItemStack {
+        stack.with_component(EnchantmentGlintOverride { show_glint })
+    }
+
+    #[test]
+    fn enchanted_items_glint() {
+        assert!(has_glint(&enchanted(Item::DiamondSword)));
+        assert!(has_glint(&ItemStack::new(Item::NetherStar, 1)));
+        assert!(!has_glint(&ItemStack::new(Item::DiamondSword, 1)));
+        assert!(!has_glint(&ItemStack::Empty));
+    }
+
+    #[test]
+    fn empty_enchantments_dont_glint() {
+        let stack = ItemStack::new(Item::DiamondSword, 1).with_component(Enchantments {
+            levels: HashMap::new(),
+        });
+        assert!(!has_glint(&stack));
+    }
+
+    #[test]
+    fn override_wins() {
+        let hidden = glint_override(enchanted(Item::DiamondSword), false);
+        assert!(!has_glint(&hidden));
+        let hidden = glint_override(ItemStack::new(Item::NetherStar, 1), false);
+        assert!(!has_glint(&hidden));
+        let shown = glint_override(ItemStack::new(Item::DiamondSword, 1), true);
+        assert!(has_glint(&shown));
+    }
+}
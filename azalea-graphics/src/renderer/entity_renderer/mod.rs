@@ -1,17 +1,27 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, f32::consts::PI, path::Path, sync::Arc};
 
 use ash::vk;
+use azalea::{core::direction::Direction, registry::Item};
 use azalea_assets::Assets;
-use glam::{Mat4, Vec3};
+use glam::{DVec3, IVec3, Mat4, Vec2, Vec3};
 use parking_lot::Mutex;
 use vk_mem::MemoryUsage;
 
 use self::{
+    armor_stand::set_armor_stand_angles,
+    block_entity::{block_entity_bounds, block_entity_models},
+    item_frame::{
+        frame_quad, frame_texture, frame_transform, has_glint, item_quad, item_texture,
+        item_transform,
+    },
     models::zombie::ZombieModel,
-    pipelines::create_entity_pipeline,
-    state::RenderState,
+    pipelines::{
+        EntityPipelineKind, create_entity_pipeline, create_entity_pipeline_layout,
+        create_shadow_pipeline,
+    },
+    state::{RenderState, entity::EntityRenderState, living_entity::LivingEntityRenderState},
     transform::ModelTransforms,
-    types::{EntityPushConstants, EntityVertex},
+    types::{EntityPushConstants, EntityVertex, ShadowPushConstants},
 };
 use crate::renderer::{
     Uniform,
@@ -20,9 +30,27 @@ use crate::renderer::{
     render_targets::RenderTargets,
     texture_manager::TextureManager,
     utils::create_framebuffers,
-    vulkan::{buffer::Buffer, context::VkContext, frame_sync::MAX_FRAMES_IN_FLIGHT},
+    vulkan::{buffer::Buffer, context::VkContext},
+    world_renderer::{BannerLayer, BlockEntity, FallingBlock, visibility::aabb_visible},
 };
 
+/// Extra space around an entity's hitbox for limbs that swing outside of it.
+const CULL_MARGIN: f32 = 0.5;
+/// Number of model part transforms that fit in a frame's transform buffer at
+/// first, it grows when a frame needs more.
+const INITIAL_TRANSFORMS: usize = 1024;
+const ARMOR_STAND_MODEL: &str = "minecraft:armor_stand#main";
+const PLAYER_MODEL: &str = "minecraft:player#main";
+const DEFAULT_SKIN: &str = "textures/entity/player/wide/steve.png";
+const GLINT_TEXTURE: &str = "textures/misc/enchanted_glint_item.png";
+/// Vanilla scrolls the glint 8 times faster than real time, and it repeats
+/// after this many of those milliseconds, the least common multiple of the
+/// periods along u and v in the entity shader.
+const GLINT_PERIOD_MS: u64 = 330000;
+
+mod armor_stand;
+pub(crate) mod block_entity;
+mod item_frame;
 mod models;
 mod pipelines;
 mod render_pass;
@@ -44,23 +72,46 @@ pub struct EntityRenderer {
 
     entity_pipeline: vk::Pipeline,
     entity_pipeline_layout: vk::PipelineLayout,
+    /// Blends tinted layers like banner patterns over the models.
+    layer_pipeline: vk::Pipeline,
+    /// Adds the enchantment glint over enchanted items.
+    glint_pipeline: vk::Pipeline,
+    /// Whether the assets have [`GLINT_TEXTURE`], enchanted items are drawn
+    /// without the glint otherwise.
+    has_glint_texture: bool,
+    shadow_pipeline: vk::Pipeline,
+    shadow_pipeline_layout: vk::PipelineLayout,
     loaded_models: HashMap<String, EntityModel>,
+    /// Flat quads for item frames and the items inside them.
+    frame_quad: EntityModel,
+    item_quad: EntityModel,
+    /// Texture path of items shown in frames, `None` if there is none.
+    item_textures: HashMap<Item, Option<String>>,
+    /// Skin of the local player, other players use [`DEFAULT_SKIN`].
+    local_skin: Option<String>,
 
     model_vertices: Buffer,
-    transform_buffers: [Buffer; MAX_FRAMES_IN_FLIGHT],
+    transform_buffers: Vec<Buffer>,
+    /// Transforms uploaded in the last frame, and the most in any frame.
+    transform_count: usize,
+    max_transform_count: usize,
 
     world_descriptor_layout: vk::DescriptorSetLayout,
     world_descriptor_pool: vk::DescriptorPool,
-    world_descriptor_sets: [vk::DescriptorSet; MAX_FRAMES_IN_FLIGHT],
+    world_descriptor_sets: Vec<vk::DescriptorSet>,
 
     entities: Arc<Mutex<Vec<RenderState>>>,
 }
 
+#[derive(Clone, Copy)]
 struct PendingDraw {
     vertex_offset: u32,
     vertex_count: u32,
     transform_offset: u32,
     texture: u32,
+    color: [f32; 4],
+    /// Drawn again with the enchantment glint on top.
+    glint: bool,
 }
 
 impl EntityRenderer {
@@ -71,7 +122,8 @@ impl EntityRenderer {
         render_targets: &RenderTargets,
         texture_manager: &TextureManager,
         entities: Arc<Mutex<Vec<RenderState>>>,
-        uniforms: &[Buffer; MAX_FRAMES_IN_FLIGHT],
+        uniforms: &[Buffer],
+        skin: Option<&Path>,
     ) -> Self {
         let mut buf = Vec::new();
 
@@ -96,6 +148,17 @@ impl EntityRenderer {
             })
             .collect();
 
+        let mut push_quad = |vertices: [EntityVertex; 12]| {
+            let offset = buf.len() as u32;
+            buf.extend(vertices);
+            EntityModel {
+                offset,
+                size: buf.len() as u32 - offset,
+            }
+        };
+        let frame_quad = push_quad(frame_quad());
+        let item_quad = push_quad(item_quad());
+
         let mut staging = Buffer::new_staging(
             ctx,
             (buf.len() * size_of::<EntityVertex>()) as vk::DeviceSize,
@@ -120,16 +183,17 @@ impl EntityRenderer {
         let framebuffers = create_framebuffers(ctx, render_targets, render_pass);
 
         // Create transform buffers (storage buffers for entity transforms)
-        const MAX_TRANSFORMS: usize = 1024;
-        let transform_buffers: [Buffer; MAX_FRAMES_IN_FLIGHT] = std::array::from_fn(|_| {
-            Buffer::new(
-                ctx,
-                (MAX_TRANSFORMS * size_of::<Mat4>()) as vk::DeviceSize,
-                vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
-                MemoryUsage::AutoPreferDevice,
-                false,
-            )
-        });
+        let transform_buffers: Vec<Buffer> = (0..ctx.frames_in_flight())
+            .map(|_| {
+                Buffer::new(
+                    ctx,
+                    (INITIAL_TRANSFORMS * size_of::<Mat4>()) as vk::DeviceSize,
+                    vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+                    MemoryUsage::AutoPreferDevice,
+                    false,
+                )
+            })
+            .collect();
 
         // Descriptor set layout for world uniforms and transforms
         let world_descriptor_layout = unsafe {
@@ -156,24 +220,25 @@ impl EntityRenderer {
             ctx.device()
                 .create_descriptor_pool(
                     &vk::DescriptorPoolCreateInfo::default()
-                        .max_sets(MAX_FRAMES_IN_FLIGHT as u32)
+                        .max_sets(ctx.frames_in_flight() as u32)
                         .pool_sizes(&[
                             vk::DescriptorPoolSize {
                                 ty: vk::DescriptorType::UNIFORM_BUFFER,
-                                descriptor_count: MAX_FRAMES_IN_FLIGHT as u32,
+                                descriptor_count: ctx.frames_in_flight() as u32,
                             },
                             vk::DescriptorPoolSize {
                                 ty: vk::DescriptorType::STORAGE_BUFFER,
-                                descriptor_count: MAX_FRAMES_IN_FLIGHT as u32,
+                                descriptor_count: ctx.frames_in_flight() as u32,
                             },
                         ]),
                     None,
                 )
                 .unwrap()
         };
+        ctx.track_object(world_descriptor_pool);
 
-        let layouts = [world_descriptor_layout; MAX_FRAMES_IN_FLIGHT];
-        let world_descriptor_sets: [_; _] = unsafe {
+        let layouts = vec![world_descriptor_layout; ctx.frames_in_flight()];
+        let world_descriptor_sets = unsafe {
             ctx.device()
                 .allocate_descriptor_sets(
                     &vk::DescriptorSetAllocateInfo::default()
@@ -181,12 +246,10 @@ impl EntityRenderer {
                         .set_layouts(&layouts),
                 )
                 .unwrap()
-                .try_into()
-                .unwrap()
         };
 
         // Update descriptor sets with uniform buffers and transform buffers
-        for i in 0..MAX_FRAMES_IN_FLIGHT {
+        for i in 0..ctx.frames_in_flight() {
             unsafe {
                 ctx.device().update_descriptor_sets(
                     &[
@@ -214,13 +277,35 @@ impl EntityRenderer {
             };
         }
 
-        let (entity_pipeline_layout, entity_pipeline) = create_entity_pipeline(
+        let entity_pipeline_layout = create_entity_pipeline_layout(
             ctx,
-            module,
             world_descriptor_layout,
             texture_manager.descriptor_set_layout(),
+        );
+        let entity_pipeline = create_entity_pipeline(
+            ctx,
+            module,
+            entity_pipeline_layout,
             render_pass,
+            EntityPipelineKind::Model,
         );
+        let layer_pipeline = create_entity_pipeline(
+            ctx,
+            module,
+            entity_pipeline_layout,
+            render_pass,
+            EntityPipelineKind::Layer,
+        );
+        let glint_pipeline = create_entity_pipeline(
+            ctx,
+            module,
+            entity_pipeline_layout,
+            render_pass,
+            EntityPipelineKind::Glint,
+        );
+        let has_glint_texture = assets.get_path(GLINT_TEXTURE).exists();
+        let (shadow_pipeline_layout, shadow_pipeline) =
+            create_shadow_pipeline(ctx, module, world_descriptor_layout, render_pass);
 
         Self {
             assets,
@@ -228,22 +313,43 @@ impl EntityRenderer {
             world_descriptor_pool,
             world_descriptor_sets,
             loaded_models,
+            frame_quad,
+            item_quad,
+            item_textures: HashMap::new(),
+            // texture ids are joined onto the assets path, which keeps absolute
+            // paths as they are
+            local_skin: skin.map(|skin| {
+                std::path::absolute(skin)
+                    .unwrap_or_else(|_| skin.to_path_buf())
+                    .to_string_lossy()
+                    .into_owned()
+            }),
             render_pass,
             framebuffers,
             model_vertices,
             transform_buffers,
+            transform_count: 0,
+            max_transform_count: 0,
             entity_pipeline,
             entity_pipeline_layout,
+            layer_pipeline,
+            glint_pipeline,
+            has_glint_texture,
+            shadow_pipeline,
+            shadow_pipeline_layout,
             entities,
         }
     }
 
-    fn render_model(&self, frame_ctx: &mut FrameCtx, draw: &PendingDraw) {
+    fn render_model(&self, frame_ctx: &mut FrameCtx, draw: &PendingDraw, glint_time: f32) {
         let device = frame_ctx.ctx.device();
 
         let push_constants = EntityPushConstants {
+            color: draw.color,
             tex_id: draw.texture,
             transform_offset: draw.transform_offset,
+            flat: frame_ctx.config.flat_entity_color as u32,
+            glint_time,
         };
         unsafe {
             device.cmd_push_constants(
@@ -258,17 +364,131 @@ impl EntityRenderer {
             );
             device.cmd_draw(frame_ctx.cmd, draw.vertex_count, 1, draw.vertex_offset, 0)
         };
+        frame_ctx.stats.entities.record(draw.vertex_count);
     }
 
-    pub fn render(&mut self, frame_ctx: &mut FrameCtx, texture_manager: &mut TextureManager) {
-        let states = self.entities.lock();
-        if states.is_empty() {
+    /// Grows the transform buffer of the frame to fit `count` transforms and
+    /// points the frame's descriptor set at the new buffer.
+    fn reserve_transforms(&mut self, frame_ctx: &mut FrameCtx, count: usize) {
+        let frame = frame_ctx.frame_index;
+        let capacity = self.transform_buffers[frame].size as usize / size_of::<Mat4>();
+        if count <= capacity {
             return;
         }
 
+        let new_capacity = count.next_power_of_two();
+        log::debug!("Growing entity transform buffer to {new_capacity} transforms");
+        let buffer = Buffer::new(
+            frame_ctx.ctx,
+            (new_capacity * size_of::<Mat4>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryUsage::AutoPreferDevice,
+            false,
+        );
+        let old = std::mem::replace(&mut self.transform_buffers[frame], buffer);
+        frame_ctx.delete(old);
+
+        unsafe {
+            frame_ctx.ctx.device().update_descriptor_sets(
+                &[vk::WriteDescriptorSet::default()
+                    .buffer_info(&[vk::DescriptorBufferInfo {
+                        offset: 0,
+                        range: vk::WHOLE_SIZE,
+                        buffer: self.transform_buffers[frame].buffer,
+                    }])
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .dst_set(self.world_descriptor_sets[frame])
+                    .dst_binding(1)],
+                &[],
+            )
+        };
+    }
+
+    /// Transforms uploaded in the last frame and the most in any frame so far.
+    pub fn transform_counts(&self) -> (usize, usize) {
+        (self.transform_count, self.max_transform_count)
+    }
+
+    fn render_shadows(&self, frame_ctx: &mut FrameCtx, shadows: &[ShadowPushConstants]) {
+        let device = frame_ctx.ctx.device();
+        unsafe {
+            device.cmd_bind_pipeline(
+                frame_ctx.cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.shadow_pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                frame_ctx.cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.shadow_pipeline_layout,
+                0,
+                &[self.world_descriptor_sets[frame_ctx.frame_index]],
+                &[],
+            );
+            for shadow in shadows {
+                device.cmd_push_constants(
+                    frame_ctx.cmd,
+                    self.shadow_pipeline_layout,
+                    vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    std::slice::from_raw_parts(
+                        shadow as *const _ as *const u8,
+                        std::mem::size_of::<ShadowPushConstants>(),
+                    ),
+                );
+                device.cmd_draw(frame_ctx.cmd, 6, 1, 0, 0);
+                frame_ctx.stats.entities.record(6);
+            }
+        }
+    }
+
+    /// Falling blocks and primed TNT within `max_distance` of `camera_pos`,
+    /// for [`crate::renderer::world_renderer::WorldRenderer::set_falling_blocks`].
+    pub fn falling_blocks(&self, camera_pos: Vec3, max_distance: f32) -> Vec<FallingBlock> {
+        let max_distance_sq = (max_distance as f64).powi(2);
+        self.entities
+            .lock()
+            .iter()
+            .filter_map(|state| {
+                let (RenderState::FallingBlock(s) | RenderState::PrimedTnt(s)) = state else {
+                    return None;
+                };
+                let pos = DVec3::new(s.x, s.y, s.z);
+                (pos.distance_squared(camera_pos.as_dvec3()) <= max_distance_sq).then(|| {
+                    FallingBlock {
+                        pos: pos.as_vec3(),
+                        state: s.block,
+                        fuse: s.fuse,
+                    }
+                })
+            })
+            .collect()
+    }
+
+    pub fn render<'a>(
+        &mut self,
+        frame_ctx: &mut FrameCtx,
+        texture_manager: &mut TextureManager,
+        block_entities: impl Iterator<Item = &'a BlockEntity>,
+        banner_patterns: &HashMap<IVec3, Vec<BannerLayer>>,
+        ticks: u64,
+    ) {
+        let mut states = self.entities.lock();
+        let cull = frame_ctx.config.cull_entities && !frame_ctx.config.render_everything;
+        let max_distance_sq = if frame_ctx.config.render_everything {
+            f64::INFINITY
+        } else {
+            (frame_ctx.config.entity_render_distance as f64).powi(2)
+        };
+        let camera_pos = frame_ctx.camera_pos.as_dvec3();
+        let shadows_enabled = frame_ctx.config.render_entity_shadows;
+
         // Collect all transforms and prepare draw calls
         let mut all_transforms = Vec::new();
         let mut pending: Vec<PendingDraw> = Vec::new();
+        // drawn after all models, so they blend over the model below them
+        let mut layers: Vec<PendingDraw> = Vec::new();
+        let mut shadows = Vec::new();
 
         let zombie_model_data = self
             .assets
@@ -277,26 +497,31 @@ impl EntityRenderer {
             .expect("Zombie model not found");
         let zombie_model = ZombieModel::new(zombie_model_data);
 
-        for state in states.iter() {
-            match state {
+        for state in states.iter_mut() {
+            let entity = state.entity_mut();
+            entity.squared_distance_to_camera =
+                DVec3::new(entity.x, entity.y, entity.z).distance_squared(camera_pos);
+            if entity.squared_distance_to_camera > max_distance_sq {
+                continue;
+            }
+
+            match &*state {
                 RenderState::Zombie(s) => {
+                    if cull && !entity_visible(&frame_ctx.view_proj, s) {
+                        continue;
+                    }
+
+                    if shadows_enabled {
+                        push_shadows(&mut shadows, s, frame_ctx.camera_pos);
+                    }
+
                     let transform_offset = all_transforms.len() as u32;
 
                     // Create transforms and animate
                     let mut model_transforms = ModelTransforms::new(zombie_model_data);
                     zombie_model.set_angles(&mut model_transforms, s);
 
-                    // Build world transform following Minecraft's matrix stack operations:
-                    // matrixStack.scale(baseScale, baseScale, baseScale)
-                    // setupTransforms() -> rotation by bodyYaw
-                    // matrixStack.scale(-1, -1, 1)
-                    // matrixStack.translate(0, -1.501, 0)
-                    
-                    // Start with world position
-                    let mut world_transform = Mat4::from_scale(Vec3::splat(s.base_scale));
-                    
-
-                    world_transform *= Mat4::from_translation(Vec3::new(s.x as f32, s.y as f32, s.z as f32));
+                    let world_transform = world_transform(s);
 
                     // Convert to Mat4 array and add to buffer
                     let transforms =
@@ -312,18 +537,198 @@ impl EntityRenderer {
                         vertex_count: model.size,
                         transform_offset,
                         texture,
+                        color: [1.0; 4],
+                        glint: false,
+                    });
+                }
+                RenderState::ArmorStand(s) => {
+                    // markers are only used by maps and commands to hold a position
+                    if s.is_marker {
+                        continue;
+                    }
+                    if cull && !entity_visible(&frame_ctx.view_proj, s) {
+                        continue;
+                    }
+                    let (Some(model_data), Some(&model)) = (
+                        self.assets.entity_models.get(ARMOR_STAND_MODEL),
+                        self.loaded_models.get(ARMOR_STAND_MODEL),
+                    ) else {
+                        continue;
+                    };
+
+                    let mut model_transforms = ModelTransforms::new(model_data);
+                    set_armor_stand_angles(&mut model_transforms, s);
+                    let transforms = model_transforms.to_transforms(model_data, world_transform(s));
+
+                    let transform_offset = all_transforms.len() as u32;
+                    all_transforms.extend(transforms);
+
+                    pending.push(PendingDraw {
+                        vertex_offset: model.offset,
+                        vertex_count: model.size,
+                        transform_offset,
+                        texture: texture_manager
+                            .get_texture(frame_ctx, "textures/entity/armorstand/wood.png"),
+                        color: [1.0; 4],
+                        glint: false,
+                    });
+                }
+                RenderState::Player(s) => {
+                    if cull && !entity_visible(&frame_ctx.view_proj, s) {
+                        continue;
+                    }
+                    let (Some(model_data), Some(&model)) = (
+                        self.assets.entity_models.get(PLAYER_MODEL),
+                        self.loaded_models.get(PLAYER_MODEL),
+                    ) else {
+                        continue;
+                    };
+
+                    // drawn in the rest pose until there is a player model to animate it
+                    let model_transforms = ModelTransforms::new(model_data);
+                    let transforms = model_transforms.to_transforms(model_data, world_transform(s));
+
+                    let transform_offset = all_transforms.len() as u32;
+                    all_transforms.extend(transforms);
+
+                    if shadows_enabled {
+                        push_shadows(&mut shadows, s, frame_ctx.camera_pos);
+                    }
+
+                    let skin = match &self.local_skin {
+                        Some(skin) if s.is_local => skin.as_str(),
+                        _ => DEFAULT_SKIN,
+                    };
+                    pending.push(PendingDraw {
+                        vertex_offset: model.offset,
+                        vertex_count: model.size,
+                        transform_offset,
+                        texture: texture_manager.get_texture(frame_ctx, skin),
+                        color: [1.0; 4],
+                        glint: false,
+                    });
+                }
+                RenderState::ItemFrame(s) => {
+                    let center = Vec3::new(s.x as f32, s.y as f32, s.z as f32);
+                    if cull
+                        && !aabb_visible(
+                            &frame_ctx.view_proj,
+                            center - Vec3::splat(0.5),
+                            center + Vec3::splat(0.5),
+                        )
+                    {
+                        continue;
+                    }
+                    pending.push(PendingDraw {
+                        vertex_offset: self.frame_quad.offset,
+                        vertex_count: self.frame_quad.size,
+                        transform_offset: all_transforms.len() as u32,
+                        texture: texture_manager.get_texture(frame_ctx, frame_texture(s)),
+                        color: [1.0; 4],
+                        glint: false,
+                    });
+                    all_transforms.push(frame_transform(s));
+
+                    if s.item.is_empty() {
+                        continue;
+                    }
+                    let item = s.item.kind();
+                    let assets = &self.assets;
+                    let Some(texture) = self
+                        .item_textures
+                        .entry(item)
+                        .or_insert_with(|| item_texture(assets, item))
+                    else {
+                        continue;
+                    };
+
+                    pending.push(PendingDraw {
+                        vertex_offset: self.item_quad.offset,
+                        vertex_count: self.item_quad.size,
+                        transform_offset: all_transforms.len() as u32,
+                        texture: texture_manager.get_texture(frame_ctx, texture),
+                        color: [1.0; 4],
+                        glint: has_glint(&s.item),
                     });
+                    all_transforms.push(item_transform(s));
                 }
+                // drawn by the world renderer with the block meshes
+                RenderState::FallingBlock(_) | RenderState::PrimedTnt(_) => {}
             }
         }
 
         drop(states); // Release lock
 
+        if frame_ctx.config.render_block_entities {
+            for block_entity in block_entities {
+                let pos = block_entity.pos.as_vec3();
+                let (min, max) = block_entity_bounds(block_entity.state);
+                if !frame_ctx.config.render_everything
+                    && !aabb_visible(&frame_ctx.view_proj, pos + min, pos + max)
+                {
+                    continue;
+                }
+                let patterns = banner_patterns
+                    .get(&block_entity.pos)
+                    .map_or(&[][..], Vec::as_slice);
+
+                // banner patterns are the flag drawn again, so they reuse its
+                // transforms
+                let mut last_transforms: Option<(&str, Mat4, u32)> = None;
+                for desc in block_entity_models(block_entity, patterns) {
+                    let (Some(model_data), Some(&model)) = (
+                        self.assets.entity_models.get(desc.model),
+                        self.loaded_models.get(desc.model),
+                    ) else {
+                        continue;
+                    };
+
+                    let transform_offset = match last_transforms {
+                        Some((name, transform, offset))
+                            if name == desc.model && transform == desc.transform =>
+                        {
+                            offset
+                        }
+                        _ => {
+                            let model_transforms = ModelTransforms::new(model_data);
+                            let transforms =
+                                model_transforms.to_transforms(model_data, desc.transform);
+                            let offset = all_transforms.len() as u32;
+                            all_transforms.extend(transforms);
+                            last_transforms = Some((desc.model, desc.transform, offset));
+                            offset
+                        }
+                    };
+
+                    let draw = PendingDraw {
+                        vertex_offset: model.offset,
+                        vertex_count: model.size,
+                        transform_offset,
+                        texture: texture_manager.get_texture(frame_ctx, &desc.texture),
+                        color: desc.tint.map_or([1.0; 4], |[r, g, b]| [r, g, b, 1.0]),
+                        glint: false,
+                    };
+                    if desc.tint.is_some() {
+                        layers.push(draw);
+                    } else {
+                        pending.push(draw);
+                    }
+                }
+            }
+        }
+
+        self.transform_count = all_transforms.len();
+        self.max_transform_count = self.max_transform_count.max(all_transforms.len());
         if pending.is_empty() {
             return;
         }
 
+        // loaded before the texture descriptor set is bound below
+        let glint_texture = (self.has_glint_texture && pending.iter().any(|draw| draw.glint))
+            .then(|| texture_manager.get_texture(frame_ctx, GLINT_TEXTURE));
+
         // Upload transforms to GPU
+        self.reserve_transforms(frame_ctx, all_transforms.len());
         frame_ctx.upload_to(
             &all_transforms,
             &self.transform_buffers[frame_ctx.frame_index],
@@ -332,6 +737,10 @@ impl EntityRenderer {
         let device = frame_ctx.ctx.device();
         self.begin(frame_ctx);
 
+        if !shadows.is_empty() {
+            self.render_shadows(frame_ctx, &shadows);
+        }
+
         unsafe {
             device.cmd_bind_vertex_buffers(frame_ctx.cmd, 0, &[self.model_vertices.buffer], &[0]);
             device.cmd_bind_pipeline(
@@ -352,9 +761,38 @@ impl EntityRenderer {
             );
         }
 
+        let glint_time = glint_time(ticks, frame_ctx.partial_tick);
+
         // Render all entities
         for draw in pending.iter() {
-            self.render_model(frame_ctx, draw);
+            self.render_model(frame_ctx, draw, glint_time);
+        }
+
+        if !layers.is_empty() {
+            unsafe {
+                device.cmd_bind_pipeline(
+                    frame_ctx.cmd,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.layer_pipeline,
+                );
+            }
+            for draw in layers.iter() {
+                self.render_model(frame_ctx, draw, glint_time);
+            }
+        }
+
+        if let Some(texture) = glint_texture {
+            unsafe {
+                device.cmd_bind_pipeline(
+                    frame_ctx.cmd,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.glint_pipeline,
+                );
+            }
+            for draw in pending.iter().filter(|draw| draw.glint) {
+                let glint = PendingDraw { texture, ..*draw };
+                self.render_model(frame_ctx, &glint, glint_time);
+            }
         }
 
         self.end(frame_ctx);
@@ -364,19 +802,6 @@ impl EntityRenderer {
         let device = frame_ctx.ctx.device();
         let cmd = frame_ctx.cmd;
         let extent = frame_ctx.render_targets.extent();
-        let clear_values = [
-            vk::ClearValue {
-                color: vk::ClearColorValue {
-                    float32: [0.0, 0.0, 0.0, 1.0],
-                },
-            },
-            vk::ClearValue {
-                depth_stencil: vk::ClearDepthStencilValue {
-                    depth: 0.0,
-                    stencil: 0,
-                },
-            },
-        ];
 
         let rp_info = vk::RenderPassBeginInfo::default()
             .render_pass(self.render_pass)
@@ -384,8 +809,7 @@ impl EntityRenderer {
             .render_area(vk::Rect2D {
                 offset: vk::Offset2D { x: 0, y: 0 },
                 extent,
-            })
-            .clear_values(&clear_values);
+            });
 
         unsafe {
             device.cmd_begin_render_pass(cmd, &rp_info, vk::SubpassContents::INLINE);
@@ -416,6 +840,61 @@ impl EntityRenderer {
         unsafe { frame_ctx.ctx.device().cmd_end_render_pass(frame_ctx.cmd) };
     }
 
+    /// Rebuilds the pipelines from a new shader module, the device must be idle.
+    pub fn reload_shaders(
+        &mut self,
+        ctx: &VkContext,
+        module: vk::ShaderModule,
+        texture_manager: &TextureManager,
+    ) {
+        for pipeline in [
+            self.entity_pipeline,
+            self.layer_pipeline,
+            self.glint_pipeline,
+            self.shadow_pipeline,
+        ] {
+            ctx.untrack_object(pipeline);
+        }
+        unsafe {
+            ctx.device()
+                .destroy_pipeline_layout(self.entity_pipeline_layout, None);
+            ctx.device().destroy_pipeline(self.entity_pipeline, None);
+            ctx.device().destroy_pipeline(self.layer_pipeline, None);
+            ctx.device().destroy_pipeline(self.glint_pipeline, None);
+            ctx.device()
+                .destroy_pipeline_layout(self.shadow_pipeline_layout, None);
+            ctx.device().destroy_pipeline(self.shadow_pipeline, None);
+        }
+        self.entity_pipeline_layout = create_entity_pipeline_layout(
+            ctx,
+            self.world_descriptor_layout,
+            texture_manager.descriptor_set_layout(),
+        );
+        self.entity_pipeline = create_entity_pipeline(
+            ctx,
+            module,
+            self.entity_pipeline_layout,
+            self.render_pass,
+            EntityPipelineKind::Model,
+        );
+        self.layer_pipeline = create_entity_pipeline(
+            ctx,
+            module,
+            self.entity_pipeline_layout,
+            self.render_pass,
+            EntityPipelineKind::Layer,
+        );
+        self.glint_pipeline = create_entity_pipeline(
+            ctx,
+            module,
+            self.entity_pipeline_layout,
+            self.render_pass,
+            EntityPipelineKind::Glint,
+        );
+        (self.shadow_pipeline_layout, self.shadow_pipeline) =
+            create_shadow_pipeline(ctx, module, self.world_descriptor_layout, self.render_pass);
+    }
+
     pub fn recreate_swapchain(&mut self, ctx: &VkContext, render_targets: &RenderTargets) {
         for fb in self.framebuffers.drain(..) {
             unsafe {
@@ -433,12 +912,26 @@ impl EntityRenderer {
                 ctx.device().destroy_framebuffer(framebuffer, None);
             }
         }
+        for pipeline in [
+            self.entity_pipeline,
+            self.layer_pipeline,
+            self.glint_pipeline,
+            self.shadow_pipeline,
+        ] {
+            ctx.untrack_object(pipeline);
+        }
         unsafe {
             ctx.device()
                 .destroy_pipeline_layout(self.entity_pipeline_layout, None);
             ctx.device().destroy_pipeline(self.entity_pipeline, None);
+            ctx.device().destroy_pipeline(self.layer_pipeline, None);
+            ctx.device().destroy_pipeline(self.glint_pipeline, None);
+            ctx.device()
+                .destroy_pipeline_layout(self.shadow_pipeline_layout, None);
+            ctx.device().destroy_pipeline(self.shadow_pipeline, None);
             ctx.device()
                 .destroy_descriptor_set_layout(self.world_descriptor_layout, None);
+            ctx.untrack_object(self.world_descriptor_pool);
             ctx.device()
                 .destroy_descriptor_pool(self.world_descriptor_pool, None);
         }
@@ -449,6 +942,85 @@ impl EntityRenderer {
     }
 }
 
+/// Glint scroll time in the milliseconds of the entity shader at `ticks` plus
+/// `partial_tick`, wrapped so it stays exact as an `f32`.
+fn glint_time(ticks: u64, partial_tick: f32) -> f32 {
+    let millis = ticks * 50 + (partial_tick * 50.0) as u64;
+    (millis * 8 % GLINT_PERIOD_MS) as f32
+}
+
+fn entity_visible(view_proj: &Mat4, state: &EntityRenderState) -> bool {
+    let half_width = state.width / 2.0 + CULL_MARGIN;
+    let pos = Vec3::new(state.x as f32, state.y as f32, state.z as f32);
+
+    let min = pos - Vec3::new(half_width, CULL_MARGIN, half_width);
+    let max = pos + Vec3::new(half_width, state.height + CULL_MARGIN, half_width);
+
+    aabb_visible(view_proj, min, max)
+}
+
+/// Queues a quad for each block top the entity's shadow falls on. Shadows fade
+/// out with the distance to the camera like in Java, and are gone 16 blocks
+/// away.
+fn push_shadows(shadows: &mut Vec<ShadowPushConstants>, state: &EntityRenderState, camera: Vec3) {
+    let pos = Vec3::new(state.x as f32, state.y as f32, state.z as f32);
+    let opacity = 1.0 - pos.distance_squared(camera) / 256.0;
+    if opacity <= 0.0 {
+        return;
+    }
+
+    for piece in &state.shadow_pieces {
+        let corner = pos + Vec3::new(piece.relative_x, piece.relative_y, piece.relative_z);
+        let to_vec3 = |v: azalea::core::position::Vec3| {
+            corner + Vec3::new(v.x as f32, v.y as f32, v.z as f32)
+        };
+        let bounds = piece.shape_below.bounds();
+        shadows.push(ShadowPushConstants {
+            min: to_vec3(bounds.min).extend(piece.alpha * opacity),
+            max: to_vec3(bounds.max).extend(state.shadow_radius),
+            center: Vec2::new(pos.x, pos.z),
+        });
+    }
+}
+
+/// Builds the model's world transform, including the whole-body adjustments
+/// for poses (Java: `LivingEntityRenderer.setupTransforms` and
+/// `getPositionOffset`). Per-part posing is left to the model's `set_angles`.
+fn world_transform(state: &LivingEntityRenderState) -> Mat4 {
+    let mut pos = Vec3::new(state.x as f32, state.y as f32, state.z as f32);
+
+    let body_rotation = Mat4::from_rotation_y(-state.body_yaw.to_radians());
+
+    let rotation = match state.pose {
+        EntityPose::Crouching => {
+            // sneaking models sit two pixels lower
+            pos.y -= state.base_scale * 2.0 / 16.0;
+            body_rotation
+        }
+        EntityPose::Sleeping => {
+            let facing = match state.sleeping_direction {
+                Some(Direction::South) => 0.0,
+                Some(Direction::East) => 90.0,
+                Some(Direction::North) => 180.0,
+                Some(Direction::West) => -90.0,
+                _ => -state.body_yaw,
+            }
+            .to_radians();
+
+            // the feet are moved towards the foot of the bed so the head ends up on the
+            // pillow
+            let head_dir = Vec3::new(facing.sin(), 0.0, facing.cos());
+            pos -= head_dir * (state.standing_eye_height - 0.1);
+
+            // lay the model on its back with the head pointing along `head_dir`
+            Mat4::from_rotation_y(facing + PI) * Mat4::from_rotation_x(-PI / 2.0)
+        }
+        _ => body_rotation,
+    };
+
+    Mat4::from_translation(pos) * rotation * Mat4::from_scale(Vec3::splat(state.base_scale))
+}
+
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EntityPose {
@@ -537,3 +1109,39 @@ pub enum ArmPose {
     TootHorn,
     Brush,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Glint milliseconds that pass in one tick.
+    const TICK_MS: u64 = 50 * 8;
+
+    #[test]
+    fn glint_time_counts_partial_ticks() {
+        assert_eq!(glint_time(0, 0.0), 0.0);
+        assert_eq!(glint_time(1, 0.0), TICK_MS as f32);
+        assert_eq!(glint_time(1, 0.5), (TICK_MS + TICK_MS / 2) as f32);
+    }
+
+    #[test]
+    fn glint_time_wraps_at_the_period() {
+        let period_ticks = GLINT_PERIOD_MS / TICK_MS;
+        assert_eq!(GLINT_PERIOD_MS % TICK_MS, 0);
+        assert_eq!(
+            glint_time(period_ticks - 1, 0.0),
+            (GLINT_PERIOD_MS - TICK_MS) as f32
+        );
+        assert_eq!(glint_time(period_ticks, 0.0), 0.0);
+        assert_eq!(glint_time(period_ticks + 1, 0.0), TICK_MS as f32);
+    }
+
+    #[test]
+    fn glint_time_stays_exact_after_long_sessions() {
+        // a year of ticks
+        let ticks = 20 * 60 * 60 * 24 * 365;
+        let expected = ticks * TICK_MS % GLINT_PERIOD_MS;
+        assert_eq!(glint_time(ticks, 0.0), expected as f32);
+        assert!(glint_time(ticks, 0.999) < GLINT_PERIOD_MS as f32);
+    }
+}
@@ -1,17 +1,19 @@
 use ash::vk;
 
-use crate::renderer::{entity_renderer::types::{EntityPushConstants, EntityVertex}, vulkan::context::VkContext};
+use crate::renderer::{
+    entity_renderer::types::{EntityPushConstants, EntityVertex, ShadowPushConstants},
+    render_targets::DEPTH_COMPARE_OP,
+    vulkan::context::VkContext,
+};
 
-pub fn create_entity_pipeline(
+pub fn create_entity_pipeline_layout(
     ctx: &VkContext,
-    module: vk::ShaderModule,
     world_set_layout: vk::DescriptorSetLayout,
     textures_set_layout: vk::DescriptorSetLayout,
-    render_pass: vk::RenderPass,
-) -> (vk::PipelineLayout, vk::Pipeline) {
+) -> vk::PipelineLayout {
     let device = ctx.device();
 
-    let pipeline_layout = unsafe {
+    unsafe {
         device
             .create_pipeline_layout(
                 &vk::PipelineLayoutCreateInfo::default()
@@ -24,10 +26,36 @@ pub fn create_entity_pipeline(
                 None,
             )
             .unwrap()
-    };
+    }
+}
+
+/// What an entity pipeline draws.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EntityPipelineKind {
+    Model,
+    /// Tinted layers drawn over a model with the same transforms, like banner
+    /// patterns.
+    Layer,
+    /// The enchantment glint added onto a model with the same transforms.
+    Glint,
+}
+
+/// Layers and glints are blended over what's already there and pass the depth
+/// test at the depth of the model below.
+pub fn create_entity_pipeline(
+    ctx: &VkContext,
+    module: vk::ShaderModule,
+    pipeline_layout: vk::PipelineLayout,
+    render_pass: vk::RenderPass,
+    kind: EntityPipelineKind,
+) -> vk::Pipeline {
+    let device = ctx.device();
 
     let vert_entry = std::ffi::CString::new("entity::vert").unwrap();
-    let frag_entry = std::ffi::CString::new("entity::frag").unwrap();
+    let frag_entry = match kind {
+        EntityPipelineKind::Glint => std::ffi::CString::new("entity::glint_frag").unwrap(),
+        _ => std::ffi::CString::new("entity::frag").unwrap(),
+    };
 
     let shader_stages = [
         vk::PipelineShaderStageCreateInfo::default()
@@ -64,17 +92,136 @@ pub fn create_entity_pipeline(
     let multisampling = vk::PipelineMultisampleStateCreateInfo::default()
         .rasterization_samples(vk::SampleCountFlags::TYPE_1);
 
-    let color_blend_attachment = vk::PipelineColorBlendAttachmentState::default().color_write_mask(
-        vk::ColorComponentFlags::R
-            | vk::ColorComponentFlags::G
-            | vk::ColorComponentFlags::B
-            | vk::ColorComponentFlags::A,
-    );
+    // the glint is added like vanilla's glint transparency, scaled by its own
+    // color so the dark parts of the texture don't brighten the model
+    let (src_color, dst_color) = match kind {
+        EntityPipelineKind::Glint => (vk::BlendFactor::SRC_COLOR, vk::BlendFactor::ONE),
+        _ => (
+            vk::BlendFactor::SRC_ALPHA,
+            vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+        ),
+    };
+    let color_blend_attachment = vk::PipelineColorBlendAttachmentState::default()
+        .color_write_mask(
+            vk::ColorComponentFlags::R
+                | vk::ColorComponentFlags::G
+                | vk::ColorComponentFlags::B
+                | vk::ColorComponentFlags::A,
+        )
+        .blend_enable(kind != EntityPipelineKind::Model)
+        .src_color_blend_factor(src_color)
+        .dst_color_blend_factor(dst_color)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+        .alpha_blend_op(vk::BlendOp::ADD);
+
+    let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
+        .depth_test_enable(true)
+        .depth_write_enable(kind == EntityPipelineKind::Model)
+        .depth_compare_op(DEPTH_COMPARE_OP);
+
+    let attachments = [color_blend_attachment];
+    let color_blending = vk::PipelineColorBlendStateCreateInfo::default().attachments(&attachments);
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state =
+        vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+    let pipeline_info = vk::GraphicsPipelineCreateInfo::default()
+        .stages(&shader_stages)
+        .vertex_input_state(&vertex_input)
+        .input_assembly_state(&input_assembly)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterizer)
+        .multisample_state(&multisampling)
+        .depth_stencil_state(&depth_stencil)
+        .color_blend_state(&color_blending)
+        .dynamic_state(&dynamic_state)
+        .layout(pipeline_layout)
+        .render_pass(render_pass)
+        .subpass(0);
+
+    let pipelines = unsafe {
+        device
+            .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info], None)
+            .expect("Failed to create pipeline")
+    };
+    ctx.track_object(pipelines[0]);
+    pipelines[0]
+}
+
+/// Blends the shadows under entities over the terrain, without writing depth.
+pub fn create_shadow_pipeline(
+    ctx: &VkContext,
+    module: vk::ShaderModule,
+    world_set_layout: vk::DescriptorSetLayout,
+    render_pass: vk::RenderPass,
+) -> (vk::PipelineLayout, vk::Pipeline) {
+    let device = ctx.device();
+
+    let pipeline_layout = unsafe {
+        device
+            .create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo::default()
+                    .set_layouts(&[world_set_layout])
+                    .push_constant_ranges(&[vk::PushConstantRange {
+                        stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                        offset: 0,
+                        size: size_of::<ShadowPushConstants>() as u32,
+                    }]),
+                None,
+            )
+            .unwrap()
+    };
+
+    let vert_entry = std::ffi::CString::new("entity::shadow_vert").unwrap();
+    let frag_entry = std::ffi::CString::new("entity::shadow_frag").unwrap();
+
+    let shader_stages = [
+        vk::PipelineShaderStageCreateInfo::default()
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .module(module)
+            .name(&vert_entry),
+        vk::PipelineShaderStageCreateInfo::default()
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .module(module)
+            .name(&frag_entry),
+    ];
+
+    // the corners come from the push constants
+    let vertex_input = vk::PipelineVertexInputStateCreateInfo::default();
+
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .primitive_restart_enable(false);
+
+    let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let rasterizer = vk::PipelineRasterizationStateCreateInfo::default()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .line_width(1.0);
+
+    let multisampling = vk::PipelineMultisampleStateCreateInfo::default()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+    let color_blend_attachment = vk::PipelineColorBlendAttachmentState::default()
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(vk::BlendOp::ADD)
+        .color_write_mask(
+            vk::ColorComponentFlags::R | vk::ColorComponentFlags::G | vk::ColorComponentFlags::B,
+        );
 
     let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
         .depth_test_enable(true)
-        .depth_write_enable(true)
-        .depth_compare_op(vk::CompareOp::GREATER_OR_EQUAL);
+        .depth_write_enable(false)
+        .depth_compare_op(DEPTH_COMPARE_OP);
 
     let attachments = [color_blend_attachment];
     let color_blending = vk::PipelineColorBlendStateCreateInfo::default().attachments(&attachments);
@@ -103,6 +250,7 @@ pub fn create_entity_pipeline(
             .expect("Failed to create pipeline")
     };
     let pipeline = pipelines[0];
+    ctx.track_object(pipeline);
 
     (pipeline_layout, pipeline)
 }
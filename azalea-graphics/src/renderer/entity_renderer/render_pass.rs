@@ -4,7 +4,7 @@ use crate::renderer::{render_targets::RenderTargets, vulkan::context::VkContext}
 
 pub fn create_entity_render_pass(ctx: &VkContext, render_targets: &RenderTargets) -> vk::RenderPass {
     let color_attachment = vk::AttachmentDescription::default()
-        .format(render_targets.swapchain.format)
+        .format(render_targets.format())
         .samples(vk::SampleCountFlags::TYPE_1)
         .load_op(vk::AttachmentLoadOp::LOAD)
         .store_op(vk::AttachmentStoreOp::STORE)
@@ -1,4 +1,4 @@
-use std::ops::Deref;
+use std::ops::{Deref, DerefMut};
 
 use azalea::{core::arm::Arm, ecs::{entity::Entity, world::World}};
 
@@ -33,3 +33,9 @@ impl Deref for ArmedEntityRenderState {
         &self.parent
     }
 }
+
+impl DerefMut for ArmedEntityRenderState {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.parent
+    }
+}
@@ -0,0 +1,94 @@
+use std::ops::{Deref, DerefMut};
+
+use azalea::{
+    ecs::{entity::Entity, world::World},
+    entity::{
+        LookDirection, Rotations,
+        metadata::{
+            ArmorStandMarker, BodyPose, HeadPose, LeftArmPose, LeftLegPose, RightArmPose,
+            RightLegPose, ShowArms, ShowBasePlate, Small,
+        },
+    },
+};
+
+use super::biped::BipedRenderState;
+
+#[derive(Debug, Clone)]
+pub struct ArmorStandRenderState {
+    pub parent: BipedRenderState,
+    pub is_marker: bool,
+    pub is_small: bool,
+    pub show_arms: bool,
+    pub show_base_plate: bool,
+    pub head_pose: Rotations,
+    pub body_pose: Rotations,
+    pub left_arm_pose: Rotations,
+    pub right_arm_pose: Rotations,
+    pub left_leg_pose: Rotations,
+    pub right_leg_pose: Rotations,
+}
+
+impl ArmorStandRenderState {
+    pub fn new(world: &mut World, entity: Entity) -> Self {
+        let mut parent = BipedRenderState::new(world, entity);
+
+        let y_rot = world
+            .get::<LookDirection>(entity)
+            .map(|look| look.y_rot())
+            .unwrap_or_default();
+        let is_small = world.get::<Small>(entity).is_some_and(|s| s.0);
+        // armor stands have no separate body rotation
+        parent.parent.parent.body_yaw = y_rot;
+        if is_small {
+            parent.parent.parent.base_scale = 0.5;
+        }
+
+        Self {
+            parent,
+            is_marker: world.get::<ArmorStandMarker>(entity).is_some_and(|m| m.0),
+            is_small,
+            show_arms: world.get::<ShowArms>(entity).is_some_and(|s| s.0),
+            // the flag azalea calls `ShowBasePlate` is Java's `CLIENT_FLAG_NO_BASEPLATE`
+            show_base_plate: world.get::<ShowBasePlate>(entity).is_none_or(|s| !s.0),
+            head_pose: world
+                .get::<HeadPose>(entity)
+                .map(|p| p.0.clone())
+                .unwrap_or_default(),
+            body_pose: world
+                .get::<BodyPose>(entity)
+                .map(|p| p.0.clone())
+                .unwrap_or_default(),
+            left_arm_pose: world
+                .get::<LeftArmPose>(entity)
+                .map(|p| p.0.clone())
+                .unwrap_or_default(),
+            right_arm_pose: world
+                .get::<RightArmPose>(entity)
+                .map(|p| p.0.clone())
+                .unwrap_or_default(),
+            left_leg_pose: world
+                .get::<LeftLegPose>(entity)
+                .map(|p| p.0.clone())
+                .unwrap_or_default(),
+            right_leg_pose: world
+                .get::<RightLegPose>(entity)
+                .map(|p| p.0.clone())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+// Deref chains through all ancestors
+impl Deref for ArmorStandRenderState {
+    type Target = BipedRenderState;
+
+    fn deref(&self) -> &Self::Target {
+        &self.parent
+    }
+}
+
+impl DerefMut for ArmorStandRenderState {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.parent
+    }
+}
@@ -1,4 +1,4 @@
-use std::ops::Deref;
+use std::ops::{Deref, DerefMut};
 
 use azalea::{
     core::arm::Arm,
@@ -6,7 +6,7 @@ use azalea::{
     inventory::ItemStack,
 };
 
-use crate::renderer::entity_renderer::state::armed_entity::ArmedEntityRenderState;
+use crate::renderer::entity_renderer::{EntityPose, state::armed_entity::ArmedEntityRenderState};
 
 #[derive(Debug, Clone)]
 pub struct BipedRenderState {
@@ -34,8 +34,10 @@ pub struct BipedRenderState {
 
 impl BipedRenderState {
     pub fn new(world: &mut World, entity: Entity) -> Self {
+        let parent = ArmedEntityRenderState::new(world, entity);
+        let is_in_sneaking_pose = parent.pose == EntityPose::Crouching;
         Self {
-            parent: ArmedEntityRenderState::new(world, entity),
+            parent,
             limb_amplitude_inverse: 1.0,
             equipped_head_stack: ItemStack::Empty,
             equipped_chest_stack: ItemStack::Empty,
@@ -46,7 +48,7 @@ impl BipedRenderState {
             hand_swing_progress: 0.0,
             crossbow_pull_time: 0.0,
             item_use_time: 0,
-            is_in_sneaking_pose: false,
+            is_in_sneaking_pose,
             is_gliding: false,
             is_swimming: false,
             has_vehicle: false,
@@ -66,3 +68,9 @@ impl Deref for BipedRenderState {
         &self.parent
     }
 }
+
+impl DerefMut for BipedRenderState {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.parent
+    }
+}
@@ -1,7 +1,10 @@
 use azalea::{
+    core::position::BlockPos,
     ecs::{entity::Entity, world::World},
-    entity::Position,
-    physics::collision::VoxelShape,
+    entity::{EntityKindComponent, Pose, Position, dimensions::EntityDimensions},
+    physics::collision::{BlockWithShape, VoxelShape},
+    registry::EntityKind,
+    world::{Instance, InstanceContainer, InstanceName},
 };
 use glam::Vec3;
 
@@ -55,6 +58,8 @@ impl Default for LeashData {
     }
 }
 
+/// The top of a block a shadow falls on. The position is of the block's
+/// corner relative to the entity, `shape_below` is the block's shape.
 #[derive(Debug, Clone)]
 pub struct ShadowPiece {
     pub relative_x: f32,
@@ -67,17 +72,35 @@ pub struct ShadowPiece {
 impl EntityRenderState {
     pub fn new(world: &mut World, entity: Entity) -> Self {
         let pos = world.get::<Position>(entity).unwrap();
+        let (width, height, eye_height) = world
+            .get::<EntityDimensions>(entity)
+            .map(|d| (d.width, d.height, d.eye_height))
+            .unwrap_or_default();
+        let sneaking = world.get::<Pose>(entity) == Some(&Pose::Crouching);
+        let shadow_radius = world
+            .get::<EntityKindComponent>(entity)
+            .map(|kind| shadow_radius(kind.0))
+            .unwrap_or_default();
+        let instance = world
+            .get::<InstanceName>(entity)
+            .and_then(|name| world.get_resource::<InstanceContainer>()?.get(name));
+        let shadow_pieces = match instance {
+            Some(instance) if shadow_radius > 0.0 => {
+                shadow_pieces(&instance.read(), [pos.x, pos.y, pos.z], shadow_radius)
+            }
+            _ => Vec::new(),
+        };
         Self {
             x: pos.x,
             y: pos.y,
             z: pos.z,
             age: 0.0,
-            width: 0.0,
-            height: 0.0,
-            standing_eye_height: 0.0,
+            width,
+            height,
+            standing_eye_height: eye_height,
             squared_distance_to_camera: 0.0,
             invisible: false,
-            sneaking: false,
+            sneaking,
             on_fire: false,
             light: 0,
             outline_color: 0,
@@ -85,12 +108,63 @@ impl EntityRenderState {
             //display_name: None,
             name_label_pos: None,
             leash_datas: None,
-            shadow_radius: 0.0,
-            shadow_pieces: Vec::new(),
+            shadow_radius,
+            shadow_pieces,
         }
     }
 }
 
+/// Radius of the shadow under each kind of entity (Java: the `shadowRadius` set
+/// by each `EntityRenderer`).
+fn shadow_radius(kind: EntityKind) -> f32 {
+    match kind {
+        EntityKind::Zombie | EntityKind::Player => 0.5,
+        _ => 0.0,
+    }
+}
+
+/// Finds the blocks below the entity that its shadow falls on (Java:
+/// `EntityRenderer.updateShadow` and `addShadowPiece`). Pieces get fainter the
+/// further below the entity they are, the light level isn't taken into account
+/// yet.
+fn shadow_pieces(world: &Instance, [x, y, z]: [f64; 3], radius: f32) -> Vec<ShadowPiece> {
+    let radius = radius.min(32.0) as f64;
+    let mut pieces = Vec::new();
+
+    for block_z in (z - radius).floor() as i32..=(z + radius).floor() as i32 {
+        for block_x in (x - radius).floor() as i32..=(x + radius).floor() as i32 {
+            for block_y in (y - radius).floor() as i32..=y.floor() as i32 {
+                let opacity = 1.0 - (y - block_y as f64) as f32 * 0.5;
+                if opacity < 0.0 {
+                    continue;
+                }
+
+                let below = BlockPos::new(block_x, block_y - 1, block_z);
+                let Some(state) = world.get_block_state(below) else {
+                    continue;
+                };
+                if state.is_air() || !state.is_collision_shape_full() {
+                    continue;
+                }
+                let shape = state.outline_shape();
+                if shape.is_empty() {
+                    continue;
+                }
+
+                pieces.push(ShadowPiece {
+                    relative_x: (below.x as f64 - x) as f32,
+                    relative_y: (below.y as f64 - y) as f32,
+                    relative_z: (below.z as f64 - z) as f32,
+                    shape_below: shape.clone(),
+                    alpha: (opacity * 0.5).min(1.0),
+                });
+            }
+        }
+    }
+
+    pieces
+}
+
 // Optional methods from Java
 impl EntityRenderState {
     pub fn has_outline(&self) -> bool {
@@ -0,0 +1,90 @@
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+};
+
+use azalea::{
+    blocks::BlockState,
+    ecs::{entity::Entity, prelude::Resource, world::World},
+    entity::metadata::{Fuse, TntBlockState},
+    registry::Block,
+    world::MinecraftEntityId,
+};
+
+use super::entity::EntityRenderState;
+
+/// Block states of falling block entities, which are only sent in the packet
+/// that spawns them.
+#[derive(Resource, Default)]
+pub struct FallingBlockStates {
+    pub states: HashMap<MinecraftEntityId, BlockState>,
+    /// Ticks left on the fuse of primed TNT. The server only sends the fuse
+    /// when the TNT is spawned, so it is counted down here instead of in
+    /// azalea's `Fuse` component.
+    pub fuses: HashMap<MinecraftEntityId, i32>,
+}
+
+/// An entity drawn as a single block, a falling block or primed TNT.
+#[derive(Debug, Clone)]
+pub struct FallingBlockRenderState {
+    pub parent: EntityRenderState,
+    pub block: BlockState,
+    /// Ticks until primed TNT explodes, `None` for falling blocks.
+    pub fuse: Option<i32>,
+}
+
+impl FallingBlockRenderState {
+    pub fn new(world: &mut World, entity: Entity) -> Self {
+        let block = world
+            .get::<MinecraftEntityId>(entity)
+            .and_then(|id| {
+                world
+                    .get_resource::<FallingBlockStates>()?
+                    .states
+                    .get(id)
+                    .copied()
+            })
+            .unwrap_or_else(|| Block::Sand.into());
+        Self {
+            parent: EntityRenderState::new(world, entity),
+            block,
+            fuse: None,
+        }
+    }
+
+    pub fn primed_tnt(world: &mut World, entity: Entity) -> Self {
+        let fuse = world
+            .get::<MinecraftEntityId>(entity)
+            .and_then(|id| {
+                world
+                    .get_resource::<FallingBlockStates>()?
+                    .fuses
+                    .get(id)
+                    .copied()
+            })
+            .or_else(|| world.get::<Fuse>(entity).map(|f| f.0))
+            .unwrap_or(80);
+        Self {
+            parent: EntityRenderState::new(world, entity),
+            block: world
+                .get::<TntBlockState>(entity)
+                .map(|b| b.0)
+                .unwrap_or_else(|| Block::Tnt.into()),
+            fuse: Some(fuse),
+        }
+    }
+}
+
+impl Deref for FallingBlockRenderState {
+    type Target = EntityRenderState;
+
+    fn deref(&self) -> &Self::Target {
+        &self.parent
+    }
+}
+
+impl DerefMut for FallingBlockRenderState {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.parent
+    }
+}
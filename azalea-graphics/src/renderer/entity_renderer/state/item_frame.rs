@@ -0,0 +1,57 @@
+use std::ops::{Deref, DerefMut};
+
+use azalea::{
+    core::direction::Direction,
+    ecs::{entity::Entity, world::World},
+    entity::metadata::{GlowItemFrame, ItemFrameDirection, ItemFrameItem, Rotation},
+    inventory::ItemStack,
+};
+
+use super::entity::EntityRenderState;
+
+#[derive(Debug, Clone)]
+pub struct ItemFrameRenderState {
+    pub parent: EntityRenderState,
+    /// The direction the front of the frame faces, away from the block it hangs
+    /// on.
+    pub direction: Direction,
+    pub item: ItemStack,
+    /// Rotation of the item in eighths of a full turn.
+    pub rotation: u8,
+    pub glow_frame: bool,
+}
+
+impl ItemFrameRenderState {
+    pub fn new(world: &mut World, entity: Entity) -> Self {
+        Self {
+            parent: EntityRenderState::new(world, entity),
+            direction: world
+                .get::<ItemFrameDirection>(entity)
+                .map(|d| d.0)
+                .unwrap_or(Direction::South),
+            item: world
+                .get::<ItemFrameItem>(entity)
+                .map(|i| i.0.clone())
+                .unwrap_or_default(),
+            rotation: world
+                .get::<Rotation>(entity)
+                .map(|r| r.0.rem_euclid(8) as u8)
+                .unwrap_or(0),
+            glow_frame: world.get::<GlowItemFrame>(entity).is_some(),
+        }
+    }
+}
+
+impl Deref for ItemFrameRenderState {
+    type Target = EntityRenderState;
+
+    fn deref(&self) -> &Self::Target {
+        &self.parent
+    }
+}
+
+impl DerefMut for ItemFrameRenderState {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.parent
+    }
+}
@@ -1,6 +1,10 @@
-use std::ops::Deref;
+use std::ops::{Deref, DerefMut};
 
-use azalea::{core::direction::Direction, ecs::{entity::Entity, world::World}};
+use azalea::{
+    core::direction::Direction,
+    ecs::{entity::Entity, world::World},
+    entity::Pose,
+};
 
 use crate::renderer::entity_renderer::{EntityPose, state::entity::EntityRenderState};
 
@@ -32,6 +36,10 @@ pub struct LivingEntityRenderState {
 
 impl LivingEntityRenderState {
     pub fn new(world: &mut World, entity: Entity) -> Self {
+        let pose = world
+            .get::<Pose>(entity)
+            .and_then(|pose| EntityPose::from_index(*pose as u8))
+            .unwrap_or(EntityPose::Standing);
         Self {
             parent: EntityRenderState::new(world, entity),
             body_yaw: 0.0,
@@ -50,7 +58,7 @@ impl LivingEntityRenderState {
             hurt: false,
             invisible_to_player: false,
             sleeping_direction: None,
-            pose: EntityPose::Standing, // matches Java default
+            pose,
             head_item_animation_progress: 0.0,
         }
     }
@@ -64,3 +72,9 @@ impl Deref for LivingEntityRenderState {
         &self.parent
     }
 }
+
+impl DerefMut for LivingEntityRenderState {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.parent
+    }
+}
@@ -1,3 +1,4 @@
+use armor_stand::ArmorStandRenderState;
 use azalea::{
     ecs::{
         entity::{Entity},
@@ -5,16 +6,30 @@ use azalea::{
     },
     registry::EntityKind,
 };
+use entity::EntityRenderState;
+use falling_block::FallingBlockRenderState;
+use item_frame::ItemFrameRenderState;
+use player::PlayerRenderState;
 use zombie::ZombieRenderState;
 
 pub mod armed_entity;
+pub mod armor_stand;
 pub mod biped;
 pub mod entity;
+pub mod falling_block;
+pub mod item_frame;
 pub mod living_entity;
+pub mod player;
 pub mod zombie;
 
 pub enum RenderState {
     Zombie(ZombieRenderState),
+    ArmorStand(ArmorStandRenderState),
+    ItemFrame(ItemFrameRenderState),
+    Player(PlayerRenderState),
+    /// Drawn by the world renderer with the block meshes.
+    FallingBlock(FallingBlockRenderState),
+    PrimedTnt(FallingBlockRenderState),
 }
 
 impl RenderState {
@@ -25,7 +40,31 @@ impl RenderState {
     ) -> Option<Self> {
         match entity_kind {
             EntityKind::Zombie => Some(Self::Zombie(ZombieRenderState::new(world, entity))),
+            EntityKind::ArmorStand => {
+                Some(Self::ArmorStand(ArmorStandRenderState::new(world, entity)))
+            }
+            EntityKind::ItemFrame | EntityKind::GlowItemFrame => {
+                Some(Self::ItemFrame(ItemFrameRenderState::new(world, entity)))
+            }
+            EntityKind::Player => Some(Self::Player(PlayerRenderState::new(world, entity))),
+            EntityKind::FallingBlock => Some(Self::FallingBlock(FallingBlockRenderState::new(
+                world, entity,
+            ))),
+            EntityKind::Tnt => Some(Self::PrimedTnt(FallingBlockRenderState::primed_tnt(
+                world, entity,
+            ))),
             _ => None,
         }
     }
+
+    /// The part of the state shared by every kind of entity.
+    pub fn entity_mut(&mut self) -> &mut EntityRenderState {
+        match self {
+            Self::Zombie(s) => s,
+            Self::ArmorStand(s) => s,
+            Self::ItemFrame(s) => s,
+            Self::Player(s) => s,
+            Self::FallingBlock(s) | Self::PrimedTnt(s) => s,
+        }
+    }
 }
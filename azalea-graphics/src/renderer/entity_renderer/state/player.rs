@@ -0,0 +1,48 @@
+use std::ops::{Deref, DerefMut};
+
+use azalea::{
+    ecs::{entity::Entity, world::World},
+    entity::{LocalEntity, LookDirection},
+};
+
+use super::biped::BipedRenderState;
+
+#[derive(Debug, Clone)]
+pub struct PlayerRenderState {
+    pub parent: BipedRenderState,
+    /// The player controlled by this client, drawn with the skin passed to the
+    /// renderer.
+    pub is_local: bool,
+}
+
+impl PlayerRenderState {
+    pub fn new(world: &mut World, entity: Entity) -> Self {
+        let mut parent = BipedRenderState::new(world, entity);
+
+        if let Some(look) = world.get::<LookDirection>(entity) {
+            // the body follows the head, there's no body rotation to smooth yet
+            parent.parent.parent.body_yaw = look.y_rot();
+            parent.parent.parent.pitch = look.x_rot();
+        }
+
+        Self {
+            parent,
+            is_local: world.get::<LocalEntity>(entity).is_some(),
+        }
+    }
+}
+
+// Deref chains through all ancestors
+impl Deref for PlayerRenderState {
+    type Target = BipedRenderState;
+
+    fn deref(&self) -> &Self::Target {
+        &self.parent
+    }
+}
+
+impl DerefMut for PlayerRenderState {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.parent
+    }
+}
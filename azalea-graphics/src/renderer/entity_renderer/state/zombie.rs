@@ -1,4 +1,4 @@
-use std::ops::Deref;
+use std::ops::{Deref, DerefMut};
 
 use azalea::{ecs::{entity::Entity, world::World}, entity::metadata::{Aggressive, DrownedConversion}};
 
@@ -28,3 +28,9 @@ impl Deref for ZombieRenderState {
         &self.parent
     }
 }
+
+impl DerefMut for ZombieRenderState {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.parent
+    }
+}
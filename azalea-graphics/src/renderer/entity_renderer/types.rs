@@ -1,7 +1,7 @@
 use std::mem::offset_of;
 
 use ash::vk;
-use glam::{Mat4, Vec2, Vec3};
+use glam::{Mat4, Vec2, Vec3, Vec4};
 
 pub struct EntityVertex {
     pub pos: Vec3,
@@ -44,6 +44,27 @@ impl EntityVertex {
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct EntityPushConstants {
+    /// sRGB color the texture is multiplied by, white for most models.
+    pub color: [f32; 4],
     pub tex_id: u32,
     pub transform_offset: u32,
+    /// Non zero to skip the texture and draw the model in a flat color.
+    pub flat: u32,
+    /// Milliseconds the enchantment glint has scrolled, wrapped to
+    /// [`super::GLINT_PERIOD_MS`].
+    pub glint_time: f32,
+}
+
+/// One block top a shadow falls on, matching `ShadowPushConstants` in the
+/// entity shader.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ShadowPushConstants {
+    /// Corner with the lowest x and z, w is the alpha in the middle of the
+    /// shadow.
+    pub min: Vec4,
+    /// Opposite corner, w is the radius of the shadow.
+    pub max: Vec4,
+    /// X and z of the entity.
+    pub center: Vec2,
 }
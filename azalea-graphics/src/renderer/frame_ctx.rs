@@ -16,18 +16,71 @@ pub struct FrameCtx<'a> {
     pub view_proj: glam::Mat4,
     pub camera_pos: glam::Vec3,
     pub frame_index: usize,
+    /// Fraction of the current tick that has elapsed, in `0.0..1.0`.
+    pub partial_tick: f32,
     pub config: WorldRendererConfig,
     pub timestamps: Option<&'a TimestampQueryPool>,
     pub frame_sync: &'a mut FrameSync,
     pub render_targets: &'a RenderTargets,
+    /// Draws recorded so far this frame.
+    pub stats: DrawStats,
+}
+
+/// Draw calls and geometry submitted in one frame, shown in the debug UI.
+#[derive(Clone, Copy, Default)]
+pub struct DrawStats {
+    /// Block, water and falling block meshes.
+    pub terrain: DrawCounts,
+    /// Entity models, their layers and shadows.
+    pub entities: DrawCounts,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct DrawCounts {
+    pub draws: u32,
+    /// Vertices read by the draws, indices for indexed draws.
+    pub vertices: u64,
+    pub triangles: u64,
+}
+
+impl DrawCounts {
+    /// Counts one draw of a triangle list with `vertices` vertices or
+    /// indices.
+    pub fn record(&mut self, vertices: u32) {
+        self.draws += 1;
+        self.vertices += vertices as u64;
+        self.triangles += vertices as u64 / 3;
+    }
 }
 
 impl FrameCtx<'_> {
-    /// Upload data to a buffer using a staging buffer that is automatically
-    /// deleted.
+    /// Upload data to the start of a buffer using a staging buffer that is
+    /// automatically deleted. Only the bytes of `data` are copied, the rest of
+    /// `dst` is left untouched.
     pub fn upload_to<T>(&mut self, data: &[T], dst: &Buffer) {
-        let mut staging = Buffer::new_staging(self.ctx, dst.size);
+        self.upload_to_offset(data, dst, 0);
+    }
+
+    /// Like [`Self::upload_to`], but writes `data` at `offset` bytes into
+    /// `dst`. The offset has to be aligned to `minUniformBufferOffsetAlignment`
+    /// so the range can be bound as a dynamic uniform buffer.
+    pub fn upload_to_offset<T>(&mut self, data: &[T], dst: &Buffer, offset: vk::DeviceSize) {
+        let size = size_of_val(data) as vk::DeviceSize;
+        if size == 0 {
+            return;
+        }
+        let alignment = self.ctx.features().min_uniform_buffer_offset_alignment;
+        assert!(
+            offset % alignment.max(1) == 0,
+            "upload offset {offset} is not aligned to {alignment}"
+        );
+        assert!(
+            offset + size <= dst.size,
+            "upload of {size} bytes at {offset} overflows a buffer of {} bytes",
+            dst.size
+        );
 
+        let mut staging = Buffer::new_staging(self.ctx, size);
         staging.upload_data(self.ctx, 0, data);
 
         unsafe {
@@ -37,8 +90,8 @@ impl FrameCtx<'_> {
                 dst.buffer,
                 &[vk::BufferCopy::default()
                     .src_offset(0)
-                    .dst_offset(0)
-                    .size(dst.size)],
+                    .dst_offset(offset)
+                    .size(size)],
             );
         }
         self.delete(staging);
@@ -0,0 +1,88 @@
+//! Flying the camera with a gamepad. The left stick moves, the right stick
+//! looks around and the triggers move down and up. The shoulder buttons and
+//! the d-pad act as the keys they are mapped to in [`button_key`].
+
+use gilrs::{Axis, Button, EventType, Gilrs};
+use glam::{Vec2, Vec3};
+use winit::{event::ElementState, keyboard::KeyCode};
+
+/// Input read from the connected gamepads since the last poll.
+pub struct GamepadInput {
+    /// Buttons pressed or released, as the keys they stand in for.
+    pub keys: Vec<(KeyCode, ElementState)>,
+    /// Left stick as x and z, with the right trigger minus the left one as y.
+    pub movement: Vec3,
+    /// Right stick.
+    pub look: Vec2,
+}
+
+pub struct Gamepads {
+    gilrs: Gilrs,
+}
+
+impl Gamepads {
+    /// `None` if the platform's gamepad backend couldn't be initialized.
+    pub fn new() -> Option<Self> {
+        match Gilrs::new() {
+            Ok(gilrs) => Some(Self { gilrs }),
+            Err(e) => {
+                log::warn!("Gamepad support is unavailable: {e}");
+                None
+            }
+        }
+    }
+
+    pub fn poll(&mut self) -> GamepadInput {
+        let mut keys = Vec::new();
+        while let Some(event) = self.gilrs.next_event() {
+            let (button, state) = match event.event {
+                EventType::ButtonPressed(button, _) => (button, ElementState::Pressed),
+                EventType::ButtonReleased(button, _) => (button, ElementState::Released),
+                EventType::Connected => {
+                    log::info!("Gamepad connected: {}", self.gilrs.gamepad(event.id).name());
+                    continue;
+                }
+                _ => continue,
+            };
+            if let Some(key) = button_key(button) {
+                keys.push((key, state));
+            }
+        }
+
+        // the sticks of every connected gamepad add up
+        let mut movement = Vec3::ZERO;
+        let mut look = Vec2::ZERO;
+        for (_, gamepad) in self.gilrs.gamepads() {
+            let trigger = |button| gamepad.button_data(button).map_or(0.0, |data| data.value());
+            movement += Vec3::new(
+                gamepad.value(Axis::LeftStickX),
+                trigger(Button::RightTrigger2) - trigger(Button::LeftTrigger2),
+                gamepad.value(Axis::LeftStickY),
+            );
+            look += Vec2::new(
+                gamepad.value(Axis::RightStickX),
+                gamepad.value(Axis::RightStickY),
+            );
+        }
+
+        GamepadInput {
+            keys,
+            movement: movement.clamp(Vec3::NEG_ONE, Vec3::ONE),
+            look: look.clamp(Vec2::NEG_ONE, Vec2::ONE),
+        }
+    }
+}
+
+/// The key a gamepad button acts as: the shoulders sprint and slow down, the
+/// d-pad toggles the debug views like their function keys.
+fn button_key(button: Button) -> Option<KeyCode> {
+    match button {
+        Button::RightTrigger => Some(KeyCode::ControlLeft),
+        Button::LeftTrigger => Some(KeyCode::AltLeft),
+        Button::DPadUp => Some(KeyCode::F2),
+        Button::DPadDown => Some(KeyCode::F3),
+        Button::DPadLeft => Some(KeyCode::F4),
+        Button::DPadRight => Some(KeyCode::F5),
+        _ => None,
+    }
+}
@@ -1,15 +1,20 @@
+use std::path::Path;
+
+use anyhow::{Context, bail};
 use ash::{Device, vk};
-use vk_mem::Alloc;
+use vk_mem::{Alloc, MemoryUsage};
 
 use crate::renderer::{
     frame_ctx::FrameCtx,
-    vulkan::{context::VkContext, image::AllocatedImage},
+    vulkan::{buffer::Buffer, context::VkContext, image::AllocatedImage},
 };
 
 pub struct HiZPyramid {
     pub image: vk::Image,
     pub allocation: vk_mem::Allocation,
     pub sampler: vk::Sampler,
+    pub width: u32,
+    pub height: u32,
     pub mip_levels: u32,
     pub mip_views: Vec<vk::ImageView>,
     pub full_view: vk::ImageView,
@@ -35,6 +40,7 @@ impl HiZPyramid {
             .usage(
                 vk::ImageUsageFlags::STORAGE
                     | vk::ImageUsageFlags::SAMPLED
+                    | vk::ImageUsageFlags::TRANSFER_SRC
                     | vk::ImageUsageFlags::TRANSFER_DST,
             )
             .sharing_mode(vk::SharingMode::EXCLUSIVE);
@@ -48,6 +54,7 @@ impl HiZPyramid {
                 .create_image(&image_info, &alloc_info)
                 .unwrap()
         };
+        ctx.track_object(image);
 
         ctx.label_object(image, "HiZ Image");
         let sampler_info = vk::SamplerCreateInfo::default()
@@ -98,12 +105,139 @@ impl HiZPyramid {
             image,
             allocation,
             sampler,
+            width,
+            height,
             mip_levels,
             mip_views,
             full_view,
         }
     }
 
+    /// Reads back mip `level` and saves it as a grayscale png, with depths
+    /// normalized to the range found in the level. The pyramid must have been
+    /// generated and not be in use by the gpu.
+    pub fn dump_level(&self, ctx: &VkContext, level: u32, path: &Path) -> anyhow::Result<()> {
+        if level >= self.mip_levels {
+            bail!(
+                "level {level} out of range, the pyramid has {} levels",
+                self.mip_levels
+            );
+        }
+        let width = (self.width >> level).max(1);
+        let height = (self.height >> level).max(1);
+
+        let mut readback = Buffer::new(
+            ctx,
+            (width * height) as vk::DeviceSize * size_of::<f32>() as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryUsage::AutoPreferHost,
+            true,
+        );
+
+        let range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: level,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let barrier = |old_layout, new_layout, src_access, dst_access| {
+            vk::ImageMemoryBarrier::default()
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .image(self.image)
+                .subresource_range(range)
+        };
+
+        let cmd = ctx.begin_one_time_commands();
+        unsafe {
+            let device = ctx.device();
+            device.cmd_pipeline_barrier(
+                cmd,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier(
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::AccessFlags::SHADER_READ,
+                    vk::AccessFlags::TRANSFER_READ,
+                )],
+            );
+            device.cmd_copy_image_to_buffer(
+                cmd,
+                self.image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                readback.buffer,
+                &[vk::BufferImageCopy::default()
+                    .image_subresource(vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        mip_level: level,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    })
+                    .image_extent(vk::Extent3D {
+                        width,
+                        height,
+                        depth: 1,
+                    })],
+            );
+            device.cmd_pipeline_barrier(
+                cmd,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier(
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::AccessFlags::TRANSFER_READ,
+                    vk::AccessFlags::SHADER_READ,
+                )],
+            );
+        }
+        ctx.end_one_time_commands(cmd);
+
+        let depths = unsafe {
+            let allocator = ctx.allocator();
+            allocator.invalidate_allocation(&readback.allocation, 0, vk::WHOLE_SIZE)?;
+            let ptr = allocator.map_memory(&mut readback.allocation)?;
+            let depths =
+                std::slice::from_raw_parts(ptr as *const f32, (width * height) as usize).to_vec();
+            allocator.unmap_memory(&mut readback.allocation);
+            depths
+        };
+        readback.destroy(ctx);
+
+        let (min, max) = depths
+            .iter()
+            .filter(|d| d.is_finite())
+            .fold((f32::MAX, f32::MIN), |(min, max), &d| {
+                (min.min(d), max.max(d))
+            });
+        let scale = if max > min { 255.0 / (max - min) } else { 0.0 };
+        let pixels = depths
+            .iter()
+            .map(|&d| ((d - min) * scale).clamp(0.0, 255.0) as u8)
+            .collect();
+
+        image::GrayImage::from_raw(width, height, pixels)
+            .context("readback size does not match the level")?
+            .save(path)
+            .with_context(|| format!("saving {}", path.display()))?;
+
+        log::info!(
+            "Saved HiZ level {level} ({width}x{height}, depth {min}..{max}) to {}",
+            path.display()
+        );
+        Ok(())
+    }
+
     pub fn destroy(&mut self, ctx: &VkContext) {
         unsafe {
             let d = ctx.device();
@@ -113,6 +247,7 @@ impl HiZPyramid {
                 d.destroy_image_view(v, None);
             }
             d.destroy_sampler(self.sampler, None);
+            ctx.untrack_object(self.image);
             ctx.allocator()
                 .destroy_image(self.image, &mut self.allocation);
         }
@@ -240,6 +375,19 @@ impl HiZCompute {
         this
     }
 
+    /// Rebuilds the pipelines from a new shader module, the device must be
+    /// idle.
+    pub fn reload_shaders(&mut self, ctx: &VkContext, module: vk::ShaderModule) {
+        for pipeline in [self.copy_pipeline, self.reduce_pipeline] {
+            ctx.untrack_object(pipeline);
+            unsafe { ctx.device().destroy_pipeline(pipeline, None) };
+        }
+        self.copy_pipeline =
+            create_compute_pipeline(ctx, module, "hiz::copy", self.copy_pipeline_layout);
+        self.reduce_pipeline =
+            create_compute_pipeline(ctx, module, "hiz::reduce", self.reduce_pipeline_layout);
+    }
+
     pub fn recreate(
         &mut self,
         ctx: &VkContext,
@@ -253,6 +401,7 @@ impl HiZCompute {
         let new_mips = pyramids[0].mip_levels;
 
         if new_frames != self.frames || new_mips != self.mip_levels {
+            ctx.untrack_object(self.pool);
             unsafe { ctx.device().destroy_descriptor_pool(self.pool, None) };
             let (pool, copy_sets, reduce_sets) = Self::alloc_sets(
                 ctx,
@@ -301,6 +450,7 @@ impl HiZCompute {
                 .create_descriptor_pool(&pool_info, None)
                 .unwrap()
         };
+        ctx.track_object(pool);
 
         let copy_layouts = vec![copy_layout; copy_total];
         let copy_alloc = vk::DescriptorSetAllocateInfo::default()
@@ -538,6 +688,9 @@ impl HiZCompute {
     }
 
     pub fn destroy(&mut self, ctx: &VkContext) {
+        ctx.untrack_object(self.copy_pipeline);
+        ctx.untrack_object(self.reduce_pipeline);
+        ctx.untrack_object(self.pool);
         unsafe {
             let d = ctx.device();
             d.destroy_sampler(self.depth_sampler, None);
@@ -571,6 +724,7 @@ fn create_compute_pipeline(
             .device()
             .create_compute_pipelines(vk::PipelineCache::null(), std::slice::from_ref(&ci), None)
             .unwrap()[0];
+        ctx.track_object(pipeline);
         pipeline
     }
 }
@@ -67,18 +67,38 @@ impl<V> Mesh<V> {
         }
     }
 
-    /// Upload staging mesh into GPU-local memory
-    pub fn upload(&self, ctx: &VkContext, cmd: vk::CommandBuffer) -> Mesh<V> {
-        let gpu_buffer = Buffer::new(
+    /// Device local mesh with room for `vertex_count` vertices and
+    /// `index_count` indices, written by a compute shader.
+    pub fn new_storage(ctx: &VkContext, vertex_count: usize, index_count: usize) -> Self {
+        let vertex_size = (size_of::<V>() * vertex_count) as vk::DeviceSize;
+        let index_size = (size_of::<u32>() * index_count) as vk::DeviceSize;
+
+        let align = align_of::<u32>() as vk::DeviceSize;
+        let index_offset = (vertex_size + align - 1) & !(align - 1);
+        let total_size = index_offset + index_size;
+
+        let buffer = Buffer::new(
             ctx,
-            self.buffer.size,
+            total_size,
             vk::BufferUsageFlags::VERTEX_BUFFER
                 | vk::BufferUsageFlags::INDEX_BUFFER
-                | vk::BufferUsageFlags::TRANSFER_DST,
+                | vk::BufferUsageFlags::STORAGE_BUFFER,
             MemoryUsage::AutoPreferDevice,
             false,
         );
 
+        Self {
+            buffer,
+            vertex_offset: 0,
+            index_offset,
+            index_count: index_count as u32,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Upload staging mesh into `gpu_buffer`, a device local vertex and index
+    /// buffer at least as large as the staging buffer.
+    pub fn upload(&self, ctx: &VkContext, cmd: vk::CommandBuffer, gpu_buffer: Buffer) -> Mesh<V> {
         self.buffer.copy_to(ctx, &gpu_buffer, cmd);
 
         Mesh {
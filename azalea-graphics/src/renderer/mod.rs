@@ -1,14 +1,23 @@
-use std::{array::from_fn, io::Cursor, sync::Arc, time::Duration};
+use std::{
+    fs::File,
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+use anyhow::Context;
 use ash::{util::read_spv, vk};
+use azalea::core::{direction::Direction, position::BlockPos};
+pub use builder::RendererBuilder;
 use crossbeam::channel::Receiver;
 pub use entity_renderer::state::RenderState;
 use parking_lot::Mutex;
 use raw_window_handle::{DisplayHandle, WindowHandle};
 use vk_mem::MemoryUsage;
 use vulkan::{
-    context::VkContext,
-    frame_sync::{FrameSync, MAX_FRAMES_IN_FLIGHT},
+    context::{DeviceLost, VkContext, check_device_lost},
+    frame_sync::FrameSync,
 };
 use winit::{
     dpi::PhysicalSize,
@@ -19,29 +28,37 @@ use winit::{
 };
 
 use self::{
-    camera::{Camera, CameraController, Projection},
-    ui::EguiVulkan,
+    adaptive_distance::AdaptiveDistance,
+    camera::{Camera, CameraConfig, CameraController, Projection},
+    ui::{EguiVulkan, UiStyle},
     world_renderer::{WorldRenderer, WorldRendererFeatures},
 };
 use crate::{
     app::{RendererArgs, WorldUpdate},
     renderer::{
         entity_renderer::EntityRenderer,
-        frame_ctx::FrameCtx,
+        frame_ctx::{DrawStats, FrameCtx},
+        post_process::PostProcess,
         render_targets::RenderTargets,
         texture_manager::TextureManager,
         timings::Timings,
         vulkan::{buffer::Buffer, timestamp::TimestampQueryPool},
-        world_renderer::WorldRendererConfig,
+        world_renderer::{LeavesMode, WorldRendererConfig},
     },
 };
 
+mod adaptive_distance;
+pub mod benchmark;
+mod builder;
 mod camera;
 pub mod chunk;
 mod entity_renderer;
 mod frame_ctx;
+#[cfg(feature = "gamepad")]
+mod gamepad;
 mod hiz;
 mod mesh;
+mod post_process;
 mod render_targets;
 mod texture_manager;
 mod timings;
@@ -50,10 +67,32 @@ mod utils;
 pub mod vulkan;
 pub mod world_renderer;
 
+/// Frames taking longer than this are logged with a breakdown of their gpu
+/// time.
+const SLOW_FRAME_THRESHOLD: Duration = Duration::from_millis(100);
+/// Far plane offered when switching away from an infinite one, in blocks.
+const DEFAULT_FAR_PLANE: f32 = 1024.0;
+/// Where the camera starts, and goes back to on a reset without a bot.
+const DEFAULT_CAMERA_POSITION: glam::Vec3 = glam::vec3(0.0, 250.0, 2.0);
+/// How far to look for the block the camera points at.
+const TARGET_BLOCK_DISTANCE: f32 = 64.0;
+
+/// Maximum number of queued world updates applied per frame, so a large backlog
+/// (e.g. after unpausing) is spread over several frames.
+const MAX_WORLD_UPDATES_PER_FRAME: usize = 4096;
+
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct Uniform {
     pub view_proj: glam::Mat4,
+    pub ao_strength: f32,
+    pub ambient_light: f32,
+    pub state_colors: u32,
+    pub _padding: f32,
+    pub sky_zenith: [f32; 4],
+    pub sky_horizon: [f32; 4],
+    pub sunrise: [f32; 4],
+    pub sun_direction: [f32; 4],
 }
 
 pub struct Renderer {
@@ -65,25 +104,49 @@ pub struct Renderer {
 
     renderer_config: WorldRendererConfig,
     command_pool: vk::CommandPool,
-    command_buffers: [vk::CommandBuffer; MAX_FRAMES_IN_FLIGHT],
-    timestamp_pools: Option<[TimestampQueryPool; MAX_FRAMES_IN_FLIGHT]>,
+    command_buffers: Vec<vk::CommandBuffer>,
+    timestamp_pools: Option<Vec<TimestampQueryPool>>,
 
-    uniforms: [Buffer; MAX_FRAMES_IN_FLIGHT],
+    uniforms: Vec<Buffer>,
 
     sync: FrameSync,
 
     world: WorldRenderer,
     entity_renderer: EntityRenderer,
+    post_process: PostProcess,
     texture_manager: TextureManager,
 
     camera: Camera,
     projection: Projection,
     camera_controller: CameraController,
-
-    egui: EguiVulkan,
+    /// `None` if no gamepad backend is available.
+    #[cfg(feature = "gamepad")]
+    gamepads: Option<gamepad::Gamepads>,
+
+    /// `None` when rendering offscreen.
+    egui: Option<EguiVulkan>,
+    /// Hides the debug UI and minimap for screenshots, toggled with F1.
+    ui_hidden: bool,
+    /// Image the last submitted frame was rendered to, which also selects its
+    /// depth pyramid.
+    last_image_index: u32,
+    /// Depth pyramid level saved by the debug UI.
+    hiz_dump_level: u32,
+    /// Distances in blocks drawn black and white by depth dumps.
+    depth_dump_range: [f32; 2],
+    /// Where to save the depth of the next frame, see [`Self::dump_depth`].
+    depth_dump: Option<PathBuf>,
+    /// Coordinates entered in the debug UI to teleport the camera to.
+    teleport_target: glam::Vec3,
+    /// SPIR-V file the shaders are loaded from, `None` if they are built into
+    /// the binary.
+    shader_path: Option<PathBuf>,
 
     tick_accumulator: Duration,
     tick_interval: Duration,
+    adaptive_distance: AdaptiveDistance,
+    /// Draws recorded in the last frame, see [`FrameCtx::stats`].
+    draw_stats: DrawStats,
 }
 
 impl Renderer {
@@ -95,36 +158,75 @@ impl Renderer {
         args: &RendererArgs,
         entities: Arc<Mutex<Vec<RenderState>>>,
     ) -> anyhow::Result<Self> {
-        let context = VkContext::new(window_handle, display_handle, args);
-        let render_targets = RenderTargets::new(&context, size.width, size.height);
-
-        let max_tex = unsafe {
-            let props = context
-                .instance()
-                .get_physical_device_properties(context.physical_device());
-            props.limits.max_image_dimension2_d
-        };
-
-        let assets = Arc::new(azalea_assets::load_assets("assets/minecraft", max_tex));
+        RendererBuilder::from_args(args.clone()).build(
+            window_handle,
+            display_handle,
+            size,
+            event_loop,
+            entities,
+        )
+    }
 
-        let texture_manager = TextureManager::new(&context, assets.clone());
+    /// Creates a renderer without a window that draws into offscreen images,
+    /// there is no debug ui and nothing is presented.
+    pub fn new_headless(
+        size: PhysicalSize<u32>,
+        args: &RendererArgs,
+        entities: Arc<Mutex<Vec<RenderState>>>,
+    ) -> anyhow::Result<Self> {
+        RendererBuilder::from_args(args.clone()).build_headless(size, entities)
+    }
 
-        let spirv = read_spv(&mut Cursor::new(include_bytes!(env!("SHADERS")))).unwrap();
-        let module = unsafe {
+    fn with_targets(
+        context: VkContext,
+        mut render_targets: RenderTargets,
+        size: PhysicalSize<u32>,
+        event_loop: Option<&ActiveEventLoop>,
+        args: &RendererArgs,
+        renderer_config: WorldRendererConfig,
+        entities: Arc<Mutex<Vec<RenderState>>>,
+    ) -> anyhow::Result<Self> {
+        let limits = unsafe {
             context
-                .device()
-                .create_shader_module(&vk::ShaderModuleCreateInfo::default().code(&spirv), None)
-                .unwrap()
+                .instance()
+                .get_physical_device_properties(context.physical_device())
+                .limits
         };
-        let uniforms: [_; MAX_FRAMES_IN_FLIGHT] = from_fn(|i| {
-            Buffer::new(
-                &context,
-                size_of::<Uniform>() as u64,
-                vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
-                MemoryUsage::AutoPreferDevice,
-                false,
-            )
-        });
+
+        let assets_path = args.assets.display();
+        let missing = azalea_assets::missing_entries(&args.assets);
+        if !missing.is_empty() {
+            let missing: Vec<_> = missing.iter().map(|p| p.display().to_string()).collect();
+            anyhow::bail!(
+                "the Minecraft assets under {assets_path} are incomplete, missing {}. Run \
+                 `python genassets.py` in the codegen directory to extract them, it should \
+                 create {assets_path}/blockstates, models, textures, atlases and \
+                 entity_models.json",
+                missing.join(", ")
+            );
+        }
+        let assets = Arc::new(azalea_assets::load_assets(
+            &args.assets,
+            limits.max_image_dimension2_d,
+            args.texture_scale,
+            args.texture_array.then_some(limits.max_image_array_layers),
+        ));
+
+        let texture_manager =
+            TextureManager::new(&context, assets.clone(), renderer_config.texture_filter());
+
+        let module = create_shader_module(&context, args.shader_path.as_deref())?;
+        let uniforms: Vec<_> = (0..context.frames_in_flight())
+            .map(|_| {
+                Buffer::new(
+                    &context,
+                    size_of::<Uniform>() as u64,
+                    vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+                    MemoryUsage::AutoPreferDevice,
+                    false,
+                )
+            })
+            .collect();
 
         let entity_renderer = EntityRenderer::new(
             &context,
@@ -134,8 +236,10 @@ impl Renderer {
             &texture_manager,
             entities,
             &uniforms,
+            args.skin.as_deref(),
         );
 
+        render_targets.set_oit(&context, renderer_config.oit_translucency);
         let world = WorldRenderer::new(
             assets.clone(),
             &context,
@@ -144,33 +248,44 @@ impl Renderer {
             &uniforms,
             WorldRendererFeatures {
                 fill_mode_non_solid: context.features().fill_mode_non_solid,
+                dynamic_cull_mode: context.features().dynamic_cull_mode,
             },
+            &renderer_config,
         );
 
+        let post_process = PostProcess::new(&context, module, &render_targets);
+
         let command_pool = create_command_pool(&context);
         let command_buffers = allocate_command_buffers(&context, command_pool);
 
-        let sync = FrameSync::new(context.device(), render_targets.swapchain.images.len());
+        let sync = FrameSync::new(
+            context.device(),
+            context.frames_in_flight(),
+            render_targets.image_count(),
+        );
 
-        let camera = Camera::new(glam::vec3(0.0, 250.0, 2.0), 0.0, 90.0);
-        let projection = Projection::new(size.width, size.height, 90.0, 0.1);
-        let camera_controller = CameraController::new(4.0, 1.0);
+        let camera = Camera::new(DEFAULT_CAMERA_POSITION, 0.0, 90.0);
+        let projection = Projection::new(size.width, size.height, args.fov, 0.1);
+        let camera_controller = CameraController::new(4.0, CameraConfig::default());
 
-        let egui = EguiVulkan::new(
-            event_loop,
-            &context,
-            module,
-            &render_targets.swapchain,
-            None,
-        )?;
+        let egui = match (event_loop, render_targets.swapchain()) {
+            (Some(event_loop), Some(swapchain)) => Some(EguiVulkan::new(
+                event_loop, &context, module, swapchain, None,
+            )?),
+            _ => None,
+        };
 
         let module = unsafe { context.device().destroy_shader_module(module, None) };
 
         let timestamp_pools = if context.features().timestamp_queries && args.timestamps {
-            Some([(); MAX_FRAMES_IN_FLIGHT].map(|_| {
-                TimestampQueryPool::new(context.device(), timings::TIMESTAMP_COUNT as u32)
-                    .expect("Failed creating timestamp query pool")
-            }))
+            Some(
+                (0..context.frames_in_flight())
+                    .map(|_| {
+                        TimestampQueryPool::new(context.device(), timings::TIMESTAMP_COUNT as u32)
+                            .expect("Failed creating timestamp query pool")
+                    })
+                    .collect(),
+            )
         } else {
             None
         };
@@ -181,7 +296,7 @@ impl Renderer {
             should_recreate: false,
             width: size.width,
             height: size.height,
-            renderer_config: Default::default(),
+            renderer_config,
             uniforms,
 
             command_pool,
@@ -193,13 +308,25 @@ impl Renderer {
             camera,
             projection,
             camera_controller,
+            #[cfg(feature = "gamepad")]
+            gamepads: gamepad::Gamepads::new(),
             entity_renderer,
+            post_process,
             texture_manager,
 
             egui,
+            ui_hidden: false,
+            last_image_index: 0,
+            hiz_dump_level: 0,
+            depth_dump_range: [0.1, 256.0],
+            depth_dump: None,
+            teleport_target: glam::Vec3::ZERO,
+            shader_path: args.shader_path.clone(),
 
             tick_accumulator: Duration::ZERO,
             tick_interval: Duration::from_millis(50),
+            adaptive_distance: AdaptiveDistance::default(),
+            draw_stats: DrawStats::default(),
         })
     }
 
@@ -224,11 +351,48 @@ impl Renderer {
         }
     }
 
+    /// Warns about frames slower than [`SLOW_FRAME_THRESHOLD`], with the gpu
+    /// timings of the last frame when timestamps are enabled.
+    pub fn check_frame_time(&self, frame_time: Duration) {
+        if frame_time < SLOW_FRAME_THRESHOLD {
+            return;
+        }
+        let ms = frame_time.as_secs_f64() * 1000.0;
+        match self.collect_timings(self.sync.current_frame) {
+            Some(timings) => log::warn!("Slow frame took {ms:.1}ms, gpu: {timings}"),
+            None => log::warn!("Slow frame took {ms:.1}ms, run with --timestamps for gpu timings"),
+        }
+    }
+
     pub fn run_debug_ui(&mut self, window: &Window, frame_time_ms: f64) {
         let wireframe_available = self.context.features().fill_mode_non_solid;
         let timings = self.collect_timings(self.sync.current_frame);
+        let mut filter_changed = false;
+        let mut uv_inset_changed = false;
+        let mut oit_changed = false;
+        let mut dump_hiz = false;
+        let mut dump_depth = false;
+        let mut teleport = None;
+        let mut reset_camera = false;
+        let mut reload_shaders = false;
+        let shaders_reloadable = self.shader_path.is_some();
+        let hiz_levels = self.render_targets.mip_levels;
+        let target_block = self.raycast_block(TARGET_BLOCK_DISTANCE);
+        let Some(egui_vulkan) = &mut self.egui else {
+            return;
+        };
+        let style = UiStyle {
+            opacity: self.renderer_config.ui_opacity,
+            backdrop: self.renderer_config.ui_backdrop && !self.ui_hidden,
+        };
+        // still run egui, otherwise the last ui would keep being painted
+        if self.ui_hidden {
+            egui_vulkan.run(window, style, |_| {});
+            return;
+        }
 
-        self.egui.run(window, |ctx| {
+        let mut hide_ui = false;
+        egui_vulkan.run(window, style, |ctx| {
             egui::Window::new("Debug Info").show(ctx, |ui| {
                 ui.label(format!("Frame time: {:.2}ms", frame_time_ms));
                 ui.label("Azalea Graphics Renderer");
@@ -250,6 +414,7 @@ impl Renderer {
                             "Visibility Compute: {:.2}ms",
                             timings.visibility_compute_time()
                         ));
+                        ui.label(format!("GPU Meshing: {:.2}ms", timings.gpu_meshing_time()));
                         ui.label(format!("UI Pass: {:.2}ms", timings.ui_time()));
                         ui.label(format!("Total GPU: {:.2}ms", timings.frame_time()));
                     });
@@ -266,19 +431,192 @@ impl Renderer {
                         "Wireframe mode (F3)",
                     ),
                 );
-
-                ui.add_enabled(
-                    wireframe_available,
-                    egui::Checkbox::new(
-                        &mut self.renderer_config.render_aabbs,
-                        "Render aabbs (F2)",
-                    ),
+                ui.checkbox(
+                    &mut self.renderer_config.backface_culling,
+                    "Backface culling",
+                );
+                oit_changed = ui
+                    .checkbox(
+                        &mut self.renderer_config.oit_translucency,
+                        "Order independent transparency",
+                    )
+                    .on_hover_text(
+                        "Blends water and translucent blocks without sorting, not used in \
+                         wireframe mode",
+                    )
+                    .changed();
+
+                ui.checkbox(&mut self.renderer_config.render_aabbs, "Render aabbs (F2)");
+                ui.add_enabled_ui(self.renderer_config.render_aabbs, |ui| {
+                    ui.checkbox(
+                        &mut self.renderer_config.aabb_depth_test,
+                        "Depth test aabbs",
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut self.renderer_config.aabb_line_width, 1.0..=8.0)
+                            .text("Aabb line width"),
+                    );
+                });
+                ui.checkbox(
+                    &mut self.renderer_config.render_gizmo,
+                    "Axis gizmo and chunk grid",
+                );
+                ui.checkbox(
+                    &mut self.renderer_config.render_selection_outline,
+                    "Selection outline",
                 );
+                ui.add_enabled_ui(self.renderer_config.render_gizmo, |ui| {
+                    ui.horizontal(|ui| {
+                        let origin = &mut self.renderer_config.gizmo_origin;
+                        ui.label("Gizmo origin");
+                        ui.add(egui::DragValue::new(&mut origin.x).prefix("x: "));
+                        ui.add(egui::DragValue::new(&mut origin.y).prefix("y: "));
+                        ui.add(egui::DragValue::new(&mut origin.z).prefix("z: "));
+                        if ui.button("At camera").clicked() {
+                            *origin = self.camera.position.floor().as_ivec3();
+                        }
+                    });
+                });
+                ui.checkbox(
+                    &mut self.renderer_config.render_chunk_borders,
+                    "Chunk borders (F7)",
+                );
+                ui.checkbox(&mut self.renderer_config.show_minimap, "Minimap");
+                ui.add(
+                    egui::Slider::new(&mut self.renderer_config.ui_opacity, 0.0..=1.0)
+                        .text("UI opacity"),
+                );
+                ui.checkbox(
+                    &mut self.renderer_config.ui_backdrop,
+                    "Darken scene behind UI",
+                );
+                hide_ui = ui.button("Hide UI (F1)").clicked();
                 ui.checkbox(
                     &mut self.renderer_config.disable_visibilty,
                     "Disable visibility calculation (F4)",
                 );
-                let response = ui.add(
+                ui.checkbox(
+                    &mut self.renderer_config.cull_empty_sections,
+                    "Skip empty sections in visibility pass",
+                );
+                ui.checkbox(
+                    &mut self.renderer_config.render_everything,
+                    "Render everything (no culling)",
+                )
+                .on_hover_text(
+                    "Draws every loaded section and entity to measure the raw draw cost",
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.renderer_config.debug_section_limit, 0..=256)
+                        .text("Nearest sections only (0 = all)"),
+                );
+                if ui
+                    .checkbox(
+                        &mut self.renderer_config.linear_filtering,
+                        "Linear texture filtering",
+                    )
+                    .changed()
+                {
+                    filter_changed = true;
+                }
+                let max_anisotropy = self.context.features().max_anisotropy;
+                ui.add_enabled_ui(max_anisotropy > 1.0, |ui| {
+                    egui::ComboBox::from_label("Anisotropic filtering")
+                        .selected_text(format!("{}x", self.renderer_config.anisotropy))
+                        .show_ui(ui, |ui| {
+                            for level in [1, 2, 4, 8, 16] {
+                                if level as f32 > max_anisotropy {
+                                    break;
+                                }
+                                filter_changed |= ui
+                                    .selectable_value(
+                                        &mut self.renderer_config.anisotropy,
+                                        level,
+                                        format!("{level}x"),
+                                    )
+                                    .changed();
+                            }
+                        });
+                })
+                .response
+                .on_disabled_hover_text("samplerAnisotropy isn't supported by this device");
+                // applying it waits for the device and re-meshes everything, so
+                // not on every step of a drag
+                let uv_inset = ui.add(
+                    egui::Slider::new(&mut self.renderer_config.uv_inset, 0.0..=1.0)
+                        .text("UV inset (texels)"),
+                );
+                uv_inset_changed =
+                    uv_inset.drag_stopped() || (uv_inset.changed() && !uv_inset.dragged());
+                ui.checkbox(&mut self.renderer_config.cull_entities, "Frustum cull entities");
+                ui.add(
+                    egui::Slider::new(
+                        &mut self.renderer_config.entity_render_distance,
+                        8.0..=512.0,
+                    )
+                    .logarithmic(true)
+                    .text("Entity render distance (blocks)"),
+                );
+                ui.checkbox(
+                    &mut self.renderer_config.render_block_entities,
+                    "Render block entities",
+                );
+                ui.checkbox(
+                    &mut self.renderer_config.render_entity_shadows,
+                    "Render entity shadows",
+                );
+                ui.checkbox(
+                    &mut self.renderer_config.flat_entity_color,
+                    "Flat entity color (debug)",
+                );
+                ui.checkbox(
+                    &mut self.renderer_config.pause_world_updates,
+                    "Pause world updates (F5)",
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.renderer_config.ao_strength, 0.0..=1.0)
+                        .text("Ambient occlusion strength"),
+                );
+                if ui
+                    .checkbox(
+                        &mut self.renderer_config.gpu_meshing,
+                        "GPU meshing (experimental)",
+                    )
+                    .changed()
+                {
+                    self.world.set_gpu_meshing(self.renderer_config.gpu_meshing);
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Leaves");
+                    let fast = ui.radio_value(
+                        &mut self.renderer_config.leaves_mode,
+                        LeavesMode::Fast,
+                        "Fast",
+                    );
+                    let fancy = ui.radio_value(
+                        &mut self.renderer_config.leaves_mode,
+                        LeavesMode::Fancy,
+                        "Fancy",
+                    );
+                    if fast.changed() || fancy.changed() {
+                        self.world.set_leaves_mode(self.renderer_config.leaves_mode);
+                    }
+                });
+                if ui
+                    .checkbox(
+                        &mut self.renderer_config.state_colors,
+                        "Color blocks by state",
+                    )
+                    .changed()
+                {
+                    self.world
+                        .set_state_colors(self.renderer_config.state_colors);
+                }
+                if ui.button("Remesh all sections").clicked() {
+                    self.world.remesh_all();
+                }
+                let response = ui.add_enabled(
+                    !self.renderer_config.adaptive_render_distance,
                     egui::Slider::new(&mut self.renderer_config.render_distance, 0..=64)
                         .text("Render distance"),
                 );
@@ -287,6 +625,39 @@ impl Renderer {
                     self.world
                         .set_render_distance(&self.context, self.renderer_config.render_distance);
                 }
+                ui.checkbox(
+                    &mut self.renderer_config.adaptive_render_distance,
+                    "Adaptive render distance",
+                );
+                ui.add_enabled_ui(self.renderer_config.adaptive_render_distance, |ui| {
+                    ui.add(
+                        egui::Slider::new(
+                            &mut self.renderer_config.target_frame_time_ms,
+                            4.0..=50.0,
+                        )
+                        .text("Target frame time (ms)"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut self.renderer_config.min_render_distance, 0..=64)
+                            .text("Min render distance"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut self.renderer_config.max_render_distance, 0..=64)
+                            .text("Max render distance"),
+                    );
+                });
+                let response = ui.add(
+                    egui::Slider::new(&mut self.renderer_config.lod_distance, 0..=64)
+                        .text("Lod distance (0 = off)"),
+                );
+                if response.changed() {
+                    self.world
+                        .set_lod_distance(self.renderer_config.lod_distance);
+                }
+                ui.add(
+                    egui::Slider::new(&mut self.renderer_config.view_priority, 0.0..=8.0)
+                        .text("Mesh priority toward the view"),
+                );
                 let worker_threads = self.renderer_config.worker_threads;
                 let response = ui.add(
                     egui::Slider::new(
@@ -300,13 +671,416 @@ impl Renderer {
                     self.world
                         .set_worker_threads(&self.context, self.renderer_config.worker_threads);
                 }
+                if ui
+                    .checkbox(
+                        &mut self.renderer_config.low_priority_workers,
+                        "Low priority worker threads",
+                    )
+                    .changed()
+                {
+                    self.world
+                        .set_low_priority_workers(self.renderer_config.low_priority_workers);
+                }
+                let response = ui.add(
+                    egui::Slider::new(
+                        &mut self.renderer_config.slow_mesh_threshold_ms,
+                        0.0..=100.0,
+                    )
+                    .text("Log meshes slower than (ms, 0 = off)"),
+                );
+                if response.changed() {
+                    self.world
+                        .set_slow_mesh_threshold(self.renderer_config.slow_mesh_threshold_ms);
+                }
+                ui.add(
+                    egui::Slider::new(&mut self.renderer_config.max_uploads_per_frame, 0..=512)
+                        .text("Mesh uploads per frame (0 = unlimited)"),
+                );
+
+                ui.checkbox(&mut self.renderer_config.render_sky, "Render sky");
+                ui.checkbox(
+                    &mut self.renderer_config.render_sun_and_moon,
+                    "Render sun and moon",
+                );
+                ui.checkbox(
+                    &mut self.renderer_config.override_time,
+                    "Override time of day",
+                );
+                ui.add_enabled(
+                    self.renderer_config.override_time,
+                    egui::Slider::new(&mut self.renderer_config.override_day_time, 0..=24000)
+                        .text("Time of day"),
+                );
+                ui.checkbox(&mut self.renderer_config.render_clouds, "Render clouds");
+                ui.add_enabled(
+                    self.renderer_config.render_clouds,
+                    egui::Checkbox::new(&mut self.renderer_config.fancy_clouds, "Fancy clouds"),
+                );
+                ui.add_enabled(
+                    self.renderer_config.render_clouds,
+                    egui::Slider::new(&mut self.renderer_config.cloud_height, -64.0..=320.0)
+                        .text("Cloud height"),
+                );
+                ui.checkbox(&mut self.renderer_config.render_weather, "Render weather");
+                ui.add_enabled(
+                    self.renderer_config.render_weather,
+                    egui::Slider::new(&mut self.renderer_config.weather_particles, 0..=16384)
+                        .text("Weather particles"),
+                );
+                ui.checkbox(
+                    &mut self.renderer_config.render_beacon_beams,
+                    "Render beacon beams",
+                );
+                ui.checkbox(
+                    &mut self.renderer_config.submerged_fog,
+                    "Underwater and lava fog",
+                );
+                ui.add_enabled(
+                    self.renderer_config.submerged_fog,
+                    egui::Slider::new(&mut self.renderer_config.water_fog_density, 0.0..=0.5)
+                        .text("Water fog density"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.renderer_config.water_depth_bias, 0.0..=16.0)
+                        .text("Water depth bias"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.renderer_config.water_depth_bias_slope, 0.0..=8.0)
+                        .text("Water depth bias slope"),
+                );
+
+                ui.collapsing("Camera", |ui| {
+                    match target_block {
+                        Some((pos, face)) => ui.label(format!(
+                            "Looking at: {} {} {} ({face:?})",
+                            pos.x, pos.y, pos.z
+                        )),
+                        None => ui.label("Looking at: nothing"),
+                    };
+                    ui.horizontal(|ui| {
+                        let target = &mut self.teleport_target;
+                        ui.add(egui::DragValue::new(&mut target.x).prefix("x: "));
+                        ui.add(egui::DragValue::new(&mut target.y).prefix("y: "));
+                        ui.add(egui::DragValue::new(&mut target.z).prefix("z: "));
+                        if ui.button("Teleport camera").clicked() {
+                            teleport = Some(*target);
+                        }
+                    });
+                    let player_position = self.world.player_position();
+                    if ui
+                        .add_enabled(
+                            player_position.is_some(),
+                            egui::Button::new("Teleport to bot"),
+                        )
+                        .clicked()
+                    {
+                        teleport = player_position;
+                    }
+                    reset_camera = ui.button("Reset camera (F8)").clicked();
+                    let camera = &mut self.camera_controller;
+                    ui.label(format!(
+                        "Speed: {:.2} ({:.2} with modifiers)",
+                        camera.speed,
+                        camera.movement_speed()
+                    ));
+                    ui.add(
+                        egui::Slider::new(&mut camera.config.sensitivity, 0.1..=5.0)
+                            .text("Mouse sensitivity"),
+                    );
+                    ui.checkbox(&mut camera.config.smooth_mouse, "Smooth mouse look");
+                    ui.add_enabled(
+                        camera.config.smooth_mouse,
+                        egui::Slider::new(&mut camera.config.mouse_smoothing, 0.01..=0.5)
+                            .logarithmic(true)
+                            .text("Mouse smoothing (s)"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut camera.config.scroll_factor, 1.01..=2.0)
+                            .text("Scroll speed factor"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut camera.config.min_speed, 0.01..=10.0)
+                            .logarithmic(true)
+                            .text("Min speed"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut camera.config.max_speed, 10.0..=1000.0)
+                            .logarithmic(true)
+                            .text("Max speed"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut camera.config.sprint_factor, 1.0..=20.0)
+                            .text("Sprint factor (Ctrl)"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut camera.config.slow_factor, 0.01..=1.0)
+                            .logarithmic(true)
+                            .text("Slow factor (Alt)"),
+                    );
+                    ui.checkbox(&mut camera.config.invert_scroll, "Invert scroll");
+                    ui.checkbox(
+                        &mut camera.config.grab_on_click,
+                        "Grab cursor on click (Tab toggles, Esc releases)",
+                    );
+                    if cfg!(feature = "gamepad") {
+                        ui.add(
+                            egui::Slider::new(&mut camera.config.gamepad_deadzone, 0.0..=0.5)
+                                .text("Gamepad deadzone"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut camera.config.gamepad_look_speed, 0.5..=10.0)
+                                .text("Gamepad look speed"),
+                        );
+                    }
+
+                    let (mut near, far) = self.projection.near_far();
+                    let mut infinite_far = far.is_none();
+                    let mut far = far.unwrap_or(DEFAULT_FAR_PLANE);
+                    let mut changed = ui
+                        .add(
+                            egui::Slider::new(&mut near, 0.01..=1.0)
+                                .logarithmic(true)
+                                .text("Near plane"),
+                        )
+                        .changed();
+                    changed |= ui.checkbox(&mut infinite_far, "Infinite far plane").changed();
+                    changed |= ui
+                        .add_enabled(
+                            !infinite_far,
+                            egui::Slider::new(&mut far, 16.0..=8192.0)
+                                .logarithmic(true)
+                                .text("Far plane"),
+                        )
+                        .changed();
+                    if changed {
+                        self.projection
+                            .set_near_far(near, (!infinite_far).then_some(far));
+                    }
+                    let render_distance = (self.renderer_config.render_distance * 16) as f32;
+                    if !infinite_far && far < render_distance {
+                        ui.label("Far plane clips terrain within the render distance");
+                    }
+                });
 
                 ui.label(format!(
                     "Average mesh time: {}ms",
                     self.world.average_mesh_time_ms()
-                ))
+                ));
+
+                let stats = self.world.mesher_stats();
+                ui.label(format!("Dirty sections: {}", stats.dirty));
+                ui.label(format!("Pending mesh jobs: {}", stats.pending_jobs));
+                ui.label(format!("Meshes completed: {}", stats.completed));
+                ui.label(format!(
+                    "Sections with a block mesh: {}",
+                    self.world.loaded_section_count()
+                ));
+                ui.label(format!(
+                    "Empty meshes: {} ({} with geometry)",
+                    stats.empty,
+                    stats.completed.saturating_sub(stats.empty)
+                ));
+                let quads = stats.block_quads + stats.water_quads;
+                ui.label(format!(
+                    "Quads generated: {} ({:.1}% water)",
+                    quads,
+                    stats.water_quads as f64 / quads.max(1) as f64 * 100.0
+                ));
+                if self.renderer_config.gpu_meshing {
+                    ui.label(format!("Pending GPU meshes: {}", stats.gpu_pending));
+                }
+                let (transforms, max_transforms) = self.entity_renderer.transform_counts();
+                ui.label(format!(
+                    "Entity transforms: {transforms} (max {max_transforms})"
+                ));
+                let draw_stats = [
+                    ("Terrain", self.draw_stats.terrain),
+                    ("Entities", self.draw_stats.entities),
+                ];
+                for (name, counts) in draw_stats {
+                    ui.label(format!(
+                        "{name}: {} draws, {} vertices, {} triangles",
+                        counts.draws, counts.vertices, counts.triangles
+                    ));
+                }
+
+                if cfg!(debug_assertions) {
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::Slider::new(&mut self.hiz_dump_level, 0..=hiz_levels - 1)
+                                .text("HiZ level"),
+                        );
+                        dump_hiz = ui.button("Save HiZ level").clicked();
+                    });
+                }
+                ui.horizontal(|ui| {
+                    let [near, far] = &mut self.depth_dump_range;
+                    ui.add(egui::DragValue::new(near).range(0.0..=*far).prefix("near "));
+                    ui.add(
+                        egui::DragValue::new(far)
+                            .range(*near..=4096.0)
+                            .prefix("far "),
+                    );
+                    dump_depth = ui.button("Save depth").clicked();
+                });
+
+                ui.separator();
+                reload_shaders = ui
+                    .add_enabled(shaders_reloadable, egui::Button::new("Reload shaders (F6)"))
+                    .on_disabled_hover_text("Start with --shader-path to load shaders from a file")
+                    .clicked();
             });
+
+            if self.renderer_config.show_minimap {
+                self.world
+                    .minimap()
+                    .show(ctx, self.camera.position, self.camera.forward());
+            }
         });
+
+        if hide_ui {
+            self.ui_hidden = true;
+        }
+        if filter_changed {
+            self.apply_texture_filter();
+        }
+        if uv_inset_changed {
+            // the cube face table is read by gpu meshing of frames in flight
+            unsafe { self.context.device().device_wait_idle().unwrap() };
+            self.world
+                .set_uv_inset(&self.context, self.renderer_config.uv_inset);
+        }
+        if oit_changed {
+            self.apply_oit();
+        }
+        if dump_hiz {
+            self.dump_hiz();
+        }
+        if dump_depth {
+            self.dump_depth(PathBuf::from("depth.png"));
+        }
+        if let Some(position) = teleport {
+            self.teleport_camera(position);
+        }
+        if reset_camera {
+            self.reset_camera();
+        }
+        if reload_shaders {
+            self.reload_shaders();
+        }
+    }
+
+    /// Reads the SPIR-V file passed with `--shader-path` again and rebuilds
+    /// every pipeline from it. If the file can't be loaded the old shaders are
+    /// kept.
+    pub fn reload_shaders(&mut self) {
+        let Some(path) = &self.shader_path else {
+            log::warn!("Start with --shader-path to reload the shaders");
+            return;
+        };
+        let module = match create_shader_module(&self.context, Some(path)) {
+            Ok(module) => module,
+            Err(e) => {
+                log::error!("Failed to reload shaders: {e:#}");
+                return;
+            }
+        };
+
+        // pipelines are referenced by the command buffers of frames in flight
+        unsafe { self.context.device().device_wait_idle().unwrap() };
+
+        self.world.reload_shaders(&self.context, module);
+        self.entity_renderer
+            .reload_shaders(&self.context, module, &self.texture_manager);
+        self.post_process.reload_shaders(&self.context, module);
+        let egui_result = self
+            .egui
+            .as_mut()
+            .map(|egui| egui.reload_shaders(&self.context, module));
+        if let Some(Err(e)) = egui_result {
+            log::error!("Failed to rebuild the egui pipeline: {e:#}");
+        }
+
+        unsafe { self.context.device().destroy_shader_module(module, None) };
+        log::info!("Reloaded shaders from {}", path.display());
+    }
+
+    /// Saves the selected level of the last frame's depth pyramid to
+    /// `hiz_level_<level>.png` in the working directory.
+    fn dump_hiz(&self) {
+        // the pyramid is only complete once the frame finished
+        unsafe { self.context.device().device_wait_idle().unwrap() };
+
+        let level = self.hiz_dump_level;
+        let path = PathBuf::from(format!("hiz_level_{level}.png"));
+        if let Err(e) = self.render_targets.dump_hiz_level(
+            &self.context,
+            self.last_image_index as usize,
+            level,
+            &path,
+        ) {
+            log::error!("Failed to save HiZ level {level}: {e:#}");
+        }
+    }
+
+    /// Saves the terrain depth of the next frame to `path` as a grayscale png,
+    /// normalized to `depth_dump_range`. Entities are drawn after the copy.
+    pub fn dump_depth(&mut self, path: PathBuf) {
+        self.depth_dump = Some(path);
+    }
+
+    fn save_depth_dump(&mut self, mut readback: Buffer, inverse_proj: glam::Mat4) {
+        // the copy is only done once the frame finished
+        unsafe { self.context.device().device_wait_idle().unwrap() };
+
+        let Some(path) = self.depth_dump.take() else {
+            readback.destroy(&self.context);
+            return;
+        };
+        let [near, far] = self.depth_dump_range;
+        if let Err(e) = render_targets::save_depth_readback(
+            &self.context,
+            readback,
+            self.render_targets.extent(),
+            inverse_proj,
+            near,
+            far,
+            &path,
+        ) {
+            log::error!("Failed to save the depth to {}: {e:#}", path.display());
+        }
+    }
+
+    /// Recreates the world atlas and entity texture samplers to match
+    /// `renderer_config.linear_filtering`.
+    fn apply_texture_filter(&mut self) {
+        let filter = self.renderer_config.texture_filter();
+        let anisotropy = self.renderer_config.anisotropy(&self.context.features());
+        log::info!("Sampling the block atlas with {anisotropy}x anisotropy");
+
+        self.world
+            .set_texture_filter(&self.context, &mut self.sync, filter, anisotropy);
+        self.texture_manager
+            .set_filter(&self.context, &mut self.sync, filter);
+    }
+
+    /// Allocates the OIT attachments when `renderer_config.oit_translucency`
+    /// was turned on and frees them when it was turned off.
+    fn apply_oit(&mut self) {
+        // the attachments are used by frames in flight
+        unsafe { self.context.device().device_wait_idle().unwrap() };
+
+        self.render_targets
+            .set_oit(&self.context, self.renderer_config.oit_translucency);
+        self.world.recreate_oit(&self.context, &self.render_targets);
+    }
+
+    /// The first non-air block within `max_dist` of the camera along its view
+    /// direction, and the face that was hit.
+    pub fn raycast_block(&self, max_dist: f32) -> Option<(BlockPos, Direction)> {
+        self.world
+            .raycast_block(self.camera.position, self.camera.forward(), max_dist)
     }
 
     pub fn update_world(&mut self, update: WorldUpdate) {
@@ -315,7 +1089,22 @@ impl Renderer {
     }
 
     pub fn update(&mut self, dt: Duration) {
-        self.camera_controller.update_camera(&mut self.camera, dt);
+        #[cfg(feature = "gamepad")]
+        self.poll_gamepads();
+        self.camera_controller
+            .update_camera(&mut self.camera, Instant::now());
+
+        if self.renderer_config.adaptive_render_distance
+            && let Some(distance) = self.adaptive_distance.update(dt, &self.renderer_config)
+        {
+            log::debug!("Adapting render distance to {distance}");
+            self.renderer_config.render_distance = distance;
+            self.world.set_render_distance(&self.context, distance);
+        }
+
+        if self.renderer_config.pause_world_updates {
+            return;
+        }
 
         self.tick_accumulator += dt;
         while self.tick_accumulator >= self.tick_interval {
@@ -324,6 +1113,21 @@ impl Renderer {
         }
     }
 
+    /// Feeds the gamepad sticks to the camera controller and handles its
+    /// buttons like the keys they are mapped to.
+    #[cfg(feature = "gamepad")]
+    fn poll_gamepads(&mut self) {
+        let Some(gamepads) = &mut self.gamepads else {
+            return;
+        };
+        let input = gamepads.poll();
+        for (key, state) in input.keys {
+            self.process_keyboard(key, state);
+        }
+        self.camera_controller
+            .set_gamepad_sticks(input.movement, input.look);
+    }
+
     pub fn process_keyboard(&mut self, key: KeyCode, state: ElementState) -> bool {
         if self.camera_controller.process_keyboard(key, state) {
             return true;
@@ -342,6 +1146,26 @@ impl Renderer {
                     self.renderer_config.render_aabbs ^= true;
                     true
                 }
+                KeyCode::F5 => {
+                    self.renderer_config.pause_world_updates ^= true;
+                    true
+                }
+                KeyCode::F6 => {
+                    self.reload_shaders();
+                    true
+                }
+                KeyCode::F7 => {
+                    self.renderer_config.render_chunk_borders ^= true;
+                    true
+                }
+                KeyCode::F1 => {
+                    self.ui_hidden ^= true;
+                    true
+                }
+                KeyCode::F8 => {
+                    self.reset_camera();
+                    true
+                }
                 _ => false,
             }
         } else {
@@ -349,6 +1173,10 @@ impl Renderer {
         }
     }
 
+    pub fn camera_config(&self) -> &CameraConfig {
+        &self.camera_controller.config
+    }
+
     pub fn handle_mouse_scroll(&mut self, delta: &MouseScrollDelta) {
         self.camera_controller.handle_mouse_scroll(delta);
     }
@@ -357,31 +1185,68 @@ impl Renderer {
         self.camera_controller.handle_mouse(dx, dy);
     }
 
-    pub fn draw_frame(&mut self, cmd_rx: &Receiver<WorldUpdate>) {
-        while let Ok(spos) = cmd_rx.try_recv() {
-            self.update_world(spos);
+    /// Moves the camera to `position` without changing where it looks.
+    pub fn teleport_camera(&mut self, position: glam::Vec3) {
+        self.camera.position = position;
+    }
+
+    /// Moves the camera to the bot, or to where it started if there is no bot
+    /// yet, looking level along +x with no motion left to apply.
+    pub fn reset_camera(&mut self) {
+        let position = self
+            .world
+            .player_position()
+            .unwrap_or(DEFAULT_CAMERA_POSITION);
+        self.camera = Camera::new(position, 0.0, 0.0);
+        self.camera_controller.stop(Instant::now());
+    }
+
+    /// See [`WorldRenderer::take_world_meshed`].
+    pub fn take_world_meshed(&mut self) -> Option<u64> {
+        self.world.take_world_meshed()
+    }
+
+    /// Returns whether a frame was submitted. Once the device is lost, nothing
+    /// can be drawn anymore and the renderer should be shut down.
+    pub fn draw_frame(&mut self, cmd_rx: &Receiver<WorldUpdate>) -> Result<bool, DeviceLost> {
+        if !self.renderer_config.pause_world_updates {
+            for update in cmd_rx.try_iter().take(MAX_WORLD_UPDATES_PER_FRAME) {
+                self.update_world(update);
+            }
         }
         let device = self.context.device();
         let frame = self.sync.next_frame();
 
-        self.sync.wait_for_fence(device, frame);
+        self.sync.wait_for_fence(device, frame)?;
         self.sync.process_deletion_queue(&self.context, frame);
-        self.world
-            .update_visibility(&self.context, frame, self.camera.position);
+        if !self.renderer_config.pause_world_updates {
+            self.world.update_visibility(
+                &self.context,
+                frame,
+                self.camera.position,
+                self.camera.forward(),
+                &self.renderer_config,
+            );
+        }
+        let selected = if self.renderer_config.render_selection_outline {
+            self.raycast_block(TARGET_BLOCK_DISTANCE)
+        } else {
+            None
+        };
+        self.world.select_block(selected.map(|(pos, _)| pos));
 
         let device = self.context.device();
 
-        let image_index = match self
-            .render_targets
-            .swapchain
-            .acquire_next_image(&self.sync, frame)
-        {
-            Ok(idx) => idx,
-            Err(true) => {
-                self.should_recreate = true;
-                return;
-            }
-            Err(false) => panic!("Failed to acquire swapchain image"),
+        let image_index = match self.render_targets.swapchain() {
+            Some(swapchain) => match swapchain.acquire_next_image(&self.sync, frame)? {
+                Some(idx) => idx,
+                None => {
+                    self.should_recreate = true;
+                    return Ok(false);
+                }
+            },
+            // offscreen targets have one image per frame in flight
+            None => frame as u32,
         };
 
         let cmd = self.command_buffers[frame];
@@ -405,34 +1270,72 @@ impl Renderer {
             view_proj: self.projection.calc_proj() * self.camera.calc_view(),
             camera_pos: self.camera.position,
             frame_index: frame,
+            partial_tick: self.tick_accumulator.as_secs_f32() / self.tick_interval.as_secs_f32(),
             config: self.renderer_config,
             timestamps: self.timestamp_pools.as_ref().map(|arr| &arr[frame]),
             frame_sync: &mut self.sync,
             render_targets: &self.render_targets,
+            stats: DrawStats::default(),
         };
+        let sky = self
+            .world
+            .sky_light(&frame_ctx.config, frame_ctx.partial_tick);
         frame_ctx.upload_to(
             &[Uniform {
                 view_proj: frame_ctx.view_proj,
+                ao_strength: frame_ctx.config.ao_strength,
+                ambient_light: sky.ambient,
+                state_colors: frame_ctx.config.state_colors as u32,
+                _padding: 0.0,
+                sky_zenith: sky.zenith.extend(1.0).to_array(),
+                sky_horizon: sky.horizon.extend(1.0).to_array(),
+                sunrise: sky.sunrise.to_array(),
+                sun_direction: sky.sun_direction.extend(0.0).to_array(),
             }],
             &self.uniforms[frame_ctx.frame_index],
         );
         frame_ctx.begin_timestamp(timings::START_FRAME);
 
+        let falling_blocks = self.entity_renderer.falling_blocks(
+            frame_ctx.camera_pos,
+            frame_ctx.config.entity_render_distance,
+        );
+        self.world.set_falling_blocks(falling_blocks);
         self.world.render(&mut frame_ctx);
-        self.entity_renderer.render(&mut frame_ctx, &mut self.texture_manager);
+        let depth_readback = self.depth_dump.is_some().then(|| {
+            let readback =
+                self.render_targets
+                    .record_depth_readback(&self.context, cmd, image_index as usize);
+            (readback, self.projection.calc_proj().inverse())
+        });
+        self.entity_renderer.render(
+            &mut frame_ctx,
+            &mut self.texture_manager,
+            self.world.block_entities(),
+            self.world.banner_patterns(),
+            self.world.ticks(),
+        );
+        if frame_ctx.config.submerged_fog
+            && let Some(submersion) = self.world.submersion(frame_ctx.camera_pos)
+        {
+            self.post_process.draw_fog(&frame_ctx, submersion);
+        }
+        self.draw_stats = frame_ctx.stats;
+        self.render_targets
+            .copy_to_swapchain(&self.context, cmd, image_index as usize);
         frame_ctx.begin_timestamp(timings::START_UI_PASS);
-        let dimensions = [
-            self.render_targets.swapchain.extent.width,
-            self.render_targets.swapchain.extent.height,
-        ];
-
-        if let Err(e) = self.egui.paint(
-            &self.context,
-            cmd,
-            dimensions,
-            image_index,
-            frame_ctx.frame_index,
-        ) {
+        let extent = self.render_targets.extent();
+        let dimensions = [extent.width, extent.height];
+
+        if let Some(egui) = &mut self.egui
+            && let Err(e) = egui.paint(
+                &self.context,
+                cmd,
+                dimensions,
+                image_index,
+                frame_ctx.frame_index,
+            )
+        {
             log::warn!("Failed to render egui: {}", e);
         }
 
@@ -450,31 +1353,37 @@ impl Renderer {
         let signal_semaphores = [self.sync.render_finished[image_index as usize]];
         let wait_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
 
-        let submit_info = vk::SubmitInfo::default()
-            .wait_semaphores(&wait_semaphores)
-            .wait_dst_stage_mask(&wait_stages)
-            .command_buffers(std::slice::from_ref(&cmd))
-            .signal_semaphores(&signal_semaphores);
+        let mut submit_info = vk::SubmitInfo::default().command_buffers(std::slice::from_ref(&cmd));
+        // offscreen images are not acquired or presented, the fence is enough
+        if self.render_targets.swapchain().is_some() {
+            submit_info = submit_info
+                .wait_semaphores(&wait_semaphores)
+                .wait_dst_stage_mask(&wait_stages)
+                .signal_semaphores(&signal_semaphores);
+        }
 
         unsafe {
-            device
-                .queue_submit(
+            check_device_lost(
+                device.queue_submit(
                     self.context.graphics_queue(),
                     &[submit_info],
                     self.sync.in_flight[frame],
-                )
-                .unwrap();
+                ),
+                "Submitting the frame",
+            )?;
+        }
+        self.last_image_index = image_index;
+        if let Some((readback, inverse_proj)) = depth_readback {
+            self.save_depth_dump(readback, inverse_proj);
         }
 
-        match self.render_targets.swapchain.present(
-            self.context.present_queue(),
-            &self.sync,
-            image_index,
-        ) {
-            Ok(true) => {}
-            Ok(false) => self.should_recreate = true,
-            Err(e) => panic!("Present failed: {:?}", e),
+        let Some(swapchain) = self.render_targets.swapchain() else {
+            return Ok(true);
+        };
+        if !swapchain.present(self.context.present_queue(), &self.sync, image_index)? {
+            self.should_recreate = true;
         }
+        Ok(true)
     }
 
     /// Mark swapchain as invalid, to be recreated later.
@@ -488,17 +1397,18 @@ impl Renderer {
     }
 
     /// Actually recreate swapchain if marked.
-    pub fn maybe_recreate(&mut self) {
+    pub fn maybe_recreate(&mut self) -> Result<(), DeviceLost> {
         if self.should_recreate {
+            let device = self.context.device();
             unsafe {
-                self.context
-                    .device()
-                    .queue_wait_idle(self.context.present_queue())
-                    .unwrap();
-                self.context
-                    .device()
-                    .queue_wait_idle(self.context.graphics_queue())
-                    .unwrap();
+                check_device_lost(
+                    device.queue_wait_idle(self.context.present_queue()),
+                    "Waiting for the present queue",
+                )?;
+                check_device_lost(
+                    device.queue_wait_idle(self.context.graphics_queue()),
+                    "Waiting for the graphics queue",
+                )?;
             }
             self.render_targets
                 .recreate(&self.context, self.width, self.height);
@@ -507,20 +1417,28 @@ impl Renderer {
             self.world
                 .recreate_swapchain(&self.context, &self.render_targets);
             self.entity_renderer.recreate_swapchain(&self.context, &self.render_targets);
+            self.post_process
+                .recreate_swapchain(&self.context, &self.render_targets);
 
             // Resize egui
-            self.egui
-                .resize(&self.context, &self.render_targets.swapchain);
+            if let (Some(egui), Some(swapchain)) = (&mut self.egui, self.render_targets.swapchain())
+            {
+                egui.resize(&self.context, swapchain);
+            }
 
             self.should_recreate = false;
         }
+        Ok(())
     }
 
     pub fn destroy(&mut self) {
         let device = self.context.device();
 
         unsafe {
-            device.device_wait_idle().unwrap();
+            // a lost device never becomes idle, its objects can still be destroyed
+            if let Err(e) = device.device_wait_idle() {
+                log::error!("Failed to wait for the device before destroying: {e}");
+            }
 
             self.timestamp_pools.as_ref().inspect(|pools| {
                 pools.iter().for_each(|pool| {
@@ -538,17 +1456,23 @@ impl Renderer {
 
         self.world.destroy(&self.context);
         self.entity_renderer.destroy(&self.context);
+        self.post_process.destroy(&self.context);
 
-        self.egui.destroy(&self.context);
+        if let Some(egui) = &mut self.egui {
+            egui.destroy(&self.context);
+        }
 
         self.render_targets.destroy(&self.context);
         self.sync.destroy(&self.context);
+
+        self.context.report_leaks();
     }
 
     /// Handle window events for egui.
     pub fn handle_egui_event(&mut self, window: &Window, event: &WindowEvent) -> bool {
-        let response = self.egui.on_window_event(window, event);
-        response.consumed
+        self.egui
+            .as_mut()
+            .is_some_and(|egui| egui.on_window_event(window, event).consumed)
     }
 }
 
@@ -563,28 +1487,27 @@ pub fn create_command_pool(ctx: &VkContext) -> vk::CommandPool {
     unsafe { device.create_command_pool(&info, None).unwrap() }
 }
 
-pub fn allocate_command_buffers(
-    ctx: &VkContext,
-    pool: vk::CommandPool,
-) -> [vk::CommandBuffer; MAX_FRAMES_IN_FLIGHT] {
-    let device = ctx.device();
-
+pub fn allocate_command_buffers(ctx: &VkContext, pool: vk::CommandPool) -> Vec<vk::CommandBuffer> {
     let alloc_info = vk::CommandBufferAllocateInfo::default()
         .command_pool(pool)
         .level(vk::CommandBufferLevel::PRIMARY)
-        .command_buffer_count(MAX_FRAMES_IN_FLIGHT as u32);
+        .command_buffer_count(ctx.frames_in_flight() as u32);
 
-    let mut buffers = [vk::CommandBuffer::null(); MAX_FRAMES_IN_FLIGHT];
+    unsafe { ctx.device().allocate_command_buffers(&alloc_info).unwrap() }
+}
 
-    unsafe {
-        (device.fp_v1_0().allocate_command_buffers)(
-            device.handle(),
-            &alloc_info,
-            buffers.as_mut_ptr(),
-        )
-        .result()
-        .unwrap()
+/// Creates a shader module from the SPIR-V file at `path`, or from the shaders
+/// built into the binary without one.
+fn create_shader_module(ctx: &VkContext, path: Option<&Path>) -> anyhow::Result<vk::ShaderModule> {
+    let spirv = match path {
+        Some(path) => File::open(path)
+            .and_then(|mut file| read_spv(&mut file))
+            .with_context(|| format!("reading {}", path.display()))?,
+        None => read_spv(&mut Cursor::new(include_bytes!(env!("SHADERS"))))?,
     };
-
-    buffers
+    let module = unsafe {
+        ctx.device()
+            .create_shader_module(&vk::ShaderModuleCreateInfo::default().code(&spirv), None)?
+    };
+    Ok(module)
 }
@@ -0,0 +1,418 @@
+//! Fullscreen effects drawn over the finished world and entities, before the
+//! ui. Reads the depth of the frame as an input attachment.
+
+use std::ffi::CString;
+
+use ash::vk;
+use glam::{Mat4, Vec4};
+
+use crate::renderer::{
+    frame_ctx::FrameCtx, render_targets::RenderTargets, vulkan::context::VkContext,
+};
+
+/// Fog color while in water, the alpha is how much even the nearest pixels are
+/// tinted.
+const WATER_FOG_COLOR: [f32; 4] = [0.05, 0.2, 0.45, 0.15];
+const LAVA_FOG_COLOR: [f32; 4] = [0.6, 0.1, 0.0, 0.4];
+/// Lava fog per block, thick enough to only see a block or two.
+const LAVA_FOG_DENSITY: f32 = 1.5;
+
+/// Fluid the camera is inside of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Submersion {
+    Water,
+    Lava,
+}
+
+#[repr(C)]
+struct FogPushConstants {
+    inv_view_proj: Mat4,
+    /// Camera position, w is the fog density per block.
+    camera: Vec4,
+    /// Fog color, w is how much even the nearest pixels are tinted.
+    color: Vec4,
+}
+
+pub struct PostProcess {
+    render_pass: vk::RenderPass,
+    framebuffers: Vec<vk::Framebuffer>,
+    set_layout: vk::DescriptorSetLayout,
+    fog_layout: vk::PipelineLayout,
+    fog_pipeline: vk::Pipeline,
+    pool: vk::DescriptorPool,
+    /// One set per render target image, reading its depth.
+    sets: Vec<vk::DescriptorSet>,
+}
+
+impl PostProcess {
+    pub fn new(ctx: &VkContext, module: vk::ShaderModule, render_targets: &RenderTargets) -> Self {
+        let device = ctx.device();
+        let render_pass = create_post_process_render_pass(ctx, render_targets);
+
+        let binding = vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::INPUT_ATTACHMENT)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT);
+        let set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::default()
+                        .bindings(std::slice::from_ref(&binding)),
+                    None,
+                )
+                .unwrap()
+        };
+        let push_constant_range = vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(size_of::<FogPushConstants>() as u32);
+        let fog_layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::default()
+                        .set_layouts(&[set_layout])
+                        .push_constant_ranges(std::slice::from_ref(&push_constant_range)),
+                    None,
+                )
+                .unwrap()
+        };
+        let fog_pipeline = create_fog_pipeline(ctx, module, render_pass, fog_layout);
+
+        let mut post_process = Self {
+            render_pass,
+            framebuffers: Vec::new(),
+            set_layout,
+            fog_layout,
+            fog_pipeline,
+            pool: vk::DescriptorPool::null(),
+            sets: Vec::new(),
+        };
+        post_process.create_frame_resources(ctx, render_targets);
+        post_process
+    }
+
+    /// Rebuilds the pipeline from a new shader module, the device must be idle.
+    pub fn reload_shaders(&mut self, ctx: &VkContext, module: vk::ShaderModule) {
+        ctx.untrack_object(self.fog_pipeline);
+        unsafe { ctx.device().destroy_pipeline(self.fog_pipeline, None) };
+        self.fog_pipeline = create_fog_pipeline(ctx, module, self.render_pass, self.fog_layout);
+    }
+
+    fn create_frame_resources(&mut self, ctx: &VkContext, render_targets: &RenderTargets) {
+        let device = ctx.device();
+        let extent = render_targets.extent();
+        let count = render_targets.image_count();
+
+        self.framebuffers = (0..count)
+            .map(|i| {
+                let attachments = [
+                    render_targets.image_view(i),
+                    render_targets.depth_images[i].default_view,
+                ];
+                let info = vk::FramebufferCreateInfo::default()
+                    .render_pass(self.render_pass)
+                    .attachments(&attachments)
+                    .width(extent.width)
+                    .height(extent.height)
+                    .layers(1);
+                unsafe { device.create_framebuffer(&info, None).unwrap() }
+            })
+            .collect();
+
+        self.pool = unsafe {
+            device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::default()
+                        .pool_sizes(&[vk::DescriptorPoolSize {
+                            ty: vk::DescriptorType::INPUT_ATTACHMENT,
+                            descriptor_count: count as u32,
+                        }])
+                        .max_sets(count as u32),
+                    None,
+                )
+                .unwrap()
+        };
+        ctx.track_object(self.pool);
+        self.sets = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::default()
+                        .descriptor_pool(self.pool)
+                        .set_layouts(&vec![self.set_layout; count]),
+                )
+                .unwrap()
+        };
+
+        for (i, &set) in self.sets.iter().enumerate() {
+            let info = vk::DescriptorImageInfo::default()
+                .image_view(render_targets.depth_images[i].default_view)
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+            let write = vk::WriteDescriptorSet::default()
+                .dst_set(set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::INPUT_ATTACHMENT)
+                .image_info(std::slice::from_ref(&info));
+            unsafe { device.update_descriptor_sets(&[write], &[]) };
+        }
+    }
+
+    fn destroy_frame_resources(&mut self, ctx: &VkContext) {
+        let device = ctx.device();
+        ctx.untrack_object(self.pool);
+        unsafe {
+            for fb in self.framebuffers.drain(..) {
+                device.destroy_framebuffer(fb, None);
+            }
+            // frees the sets with it
+            device.destroy_descriptor_pool(self.pool, None);
+        }
+        self.sets.clear();
+    }
+
+    pub fn recreate_swapchain(&mut self, ctx: &VkContext, render_targets: &RenderTargets) {
+        self.destroy_frame_resources(ctx);
+        self.create_frame_resources(ctx, render_targets);
+    }
+
+    /// Fogs and tints the frame as seen from inside `submersion`.
+    pub fn draw_fog(&self, frame_ctx: &FrameCtx, submersion: Submersion) {
+        let ctx = frame_ctx.ctx;
+        let device = ctx.device();
+        let cmd = frame_ctx.cmd;
+        let extent = frame_ctx.render_targets.extent();
+
+        let (color, density) = match submersion {
+            Submersion::Water => (WATER_FOG_COLOR, frame_ctx.config.water_fog_density),
+            Submersion::Lava => (LAVA_FOG_COLOR, LAVA_FOG_DENSITY),
+        };
+        let pc = FogPushConstants {
+            inv_view_proj: frame_ctx.view_proj.inverse(),
+            camera: frame_ctx.camera_pos.extend(density),
+            color: Vec4::from_array(color),
+        };
+
+        let rp_info = vk::RenderPassBeginInfo::default()
+            .render_pass(self.render_pass)
+            .framebuffer(self.framebuffers[frame_ctx.image_index as usize])
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            });
+
+        ctx.cmd_begin_debug_label(cmd, "Submerged fog");
+        unsafe {
+            device.cmd_begin_render_pass(cmd, &rp_info, vk::SubpassContents::INLINE);
+            device.cmd_set_viewport(
+                cmd,
+                0,
+                &[vk::Viewport {
+                    x: 0.0,
+                    y: 0.0,
+                    width: extent.width as f32,
+                    height: extent.height as f32,
+                    min_depth: 0.0,
+                    max_depth: 1.0,
+                }],
+            );
+            device.cmd_set_scissor(
+                cmd,
+                0,
+                &[vk::Rect2D {
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                    extent,
+                }],
+            );
+            device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, self.fog_pipeline);
+            device.cmd_bind_descriptor_sets(
+                cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.fog_layout,
+                0,
+                &[self.sets[frame_ctx.image_index as usize]],
+                &[],
+            );
+            device.cmd_push_constants(
+                cmd,
+                self.fog_layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                std::slice::from_raw_parts(
+                    &pc as *const FogPushConstants as *const u8,
+                    size_of::<FogPushConstants>(),
+                ),
+            );
+            device.cmd_draw(cmd, 3, 1, 0, 0);
+            device.cmd_end_render_pass(cmd);
+        }
+        ctx.cmd_end_debug_label(cmd);
+    }
+
+    pub fn destroy(&mut self, ctx: &VkContext) {
+        self.destroy_frame_resources(ctx);
+        ctx.untrack_object(self.fog_pipeline);
+        let device = ctx.device();
+        unsafe {
+            device.destroy_pipeline(self.fog_pipeline, None);
+            device.destroy_pipeline_layout(self.fog_layout, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+            device.destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
+
+fn create_post_process_render_pass(
+    ctx: &VkContext,
+    render_targets: &RenderTargets,
+) -> vk::RenderPass {
+    let color_attachment = vk::AttachmentDescription::default()
+        .format(render_targets.format())
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .load_op(vk::AttachmentLoadOp::LOAD)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .initial_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+
+    // left as the entity render pass leaves it, the next frame clears it anyway
+    let depth_attachment = vk::AttachmentDescription::default()
+        .format(vk::Format::D32_SFLOAT)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .load_op(vk::AttachmentLoadOp::LOAD)
+        .store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+    let attachments = [color_attachment, depth_attachment];
+
+    let color_ref = vk::AttachmentReference {
+        attachment: 0,
+        layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+    };
+    let depth_ref = vk::AttachmentReference {
+        attachment: 1,
+        layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    };
+
+    let subpass = vk::SubpassDescription::default()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .input_attachments(std::slice::from_ref(&depth_ref))
+        .color_attachments(std::slice::from_ref(&color_ref));
+
+    let dependencies = [
+        // the entity render pass' color and depth writes
+        vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            dst_subpass: 0,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::FRAGMENT_SHADER,
+            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ
+                | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::INPUT_ATTACHMENT_READ,
+            dependency_flags: vk::DependencyFlags::empty(),
+        },
+        // the ui draws over the color
+        vk::SubpassDependency {
+            src_subpass: 0,
+            dst_subpass: vk::SUBPASS_EXTERNAL,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ
+                | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dependency_flags: vk::DependencyFlags::empty(),
+        },
+    ];
+
+    let info = vk::RenderPassCreateInfo::default()
+        .attachments(&attachments)
+        .subpasses(std::slice::from_ref(&subpass))
+        .dependencies(&dependencies);
+
+    unsafe { ctx.device().create_render_pass(&info, None).unwrap() }
+}
+
+fn create_fog_pipeline(
+    ctx: &VkContext,
+    module: vk::ShaderModule,
+    render_pass: vk::RenderPass,
+    layout: vk::PipelineLayout,
+) -> vk::Pipeline {
+    let vert_entry = CString::new("post_process::fog_vert").unwrap();
+    let frag_entry = CString::new("post_process::fog_frag").unwrap();
+    let stages = [
+        vk::PipelineShaderStageCreateInfo::default()
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .module(module)
+            .name(&vert_entry),
+        vk::PipelineShaderStageCreateInfo::default()
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .module(module)
+            .name(&frag_entry),
+    ];
+
+    // the fullscreen triangle is generated in the shader
+    let vertex_input = vk::PipelineVertexInputStateCreateInfo::default();
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+    let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+        .viewport_count(1)
+        .scissor_count(1);
+    let rasterizer = vk::PipelineRasterizationStateCreateInfo::default()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .line_width(1.0);
+    let multisampling = vk::PipelineMultisampleStateCreateInfo::default()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+    let color_blend_attachment = vk::PipelineColorBlendAttachmentState::default()
+        .color_write_mask(
+            vk::ColorComponentFlags::R
+                | vk::ColorComponentFlags::G
+                | vk::ColorComponentFlags::B
+                | vk::ColorComponentFlags::A,
+        )
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ONE)
+        .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+        .alpha_blend_op(vk::BlendOp::ADD);
+    let color_blending = vk::PipelineColorBlendStateCreateInfo::default()
+        .attachments(std::slice::from_ref(&color_blend_attachment));
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state =
+        vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+    let pipeline_info = vk::GraphicsPipelineCreateInfo::default()
+        .stages(&stages)
+        .vertex_input_state(&vertex_input)
+        .input_assembly_state(&input_assembly)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterizer)
+        .multisample_state(&multisampling)
+        .color_blend_state(&color_blending)
+        .dynamic_state(&dynamic_state)
+        .layout(layout)
+        .render_pass(render_pass)
+        .subpass(0);
+
+    let pipeline = unsafe {
+        ctx.device()
+            .create_graphics_pipelines(
+                vk::PipelineCache::null(),
+                std::slice::from_ref(&pipeline_info),
+                None,
+            )
+            .unwrap()[0]
+    };
+    ctx.track_object(pipeline);
+    pipeline
+}
@@ -1,58 +1,471 @@
+use std::path::Path;
+
+use anyhow::Context;
 use ash::vk;
+use glam::{Mat4, Vec3};
+use vk_mem::MemoryUsage;
 
 use crate::renderer::{
     hiz::HiZPyramid,
-    vulkan::{context::VkContext, image::AllocatedImage, swapchain::Swapchain},
+    vulkan::{
+        buffer::Buffer,
+        context::VkContext,
+        image::AllocatedImage,
+        swapchain::{self, PresentMode, Swapchain, SwapchainColorSpace},
+    },
 };
 
+/// Format of offscreen color targets, the same one preferred for the swapchain.
+const OFFSCREEN_FORMAT: vk::Format = vk::Format::B8G8R8A8_SRGB;
+/// Weighted color sums of the order independent transparency pass.
+pub const OIT_ACCUM_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+/// How much of the background the translucent fragments let through.
+pub const OIT_REVEALAGE_FORMAT: vk::Format = vk::Format::R16_SFLOAT;
+/// Depth is reversed, 1 at the near plane and 0 at the far plane or infinity,
+/// see [`crate::renderer::camera::Projection::calc_proj`]. Nearer fragments
+/// have greater depths, and the HiZ pyramid keeps the smallest depth of each
+/// texel as the farthest occluder.
+pub const DEPTH_COMPARE_OP: vk::CompareOp = vk::CompareOp::GREATER_OR_EQUAL;
+/// Sign of a depth bias that moves fragments towards the camera, positive
+/// since nearer fragments have greater depths.
+pub const DEPTH_BIAS_SIGN: f32 = 1.0;
+/// The depth the depth buffer is cleared to, the far plane.
+pub const CLEAR_DEPTH: f32 = 0.0;
+
+/// The color images frames are rendered into.
+pub enum ColorTarget {
+    Swapchain(Swapchain),
+    /// Plain images without a window, one per frame in flight.
+    Offscreen {
+        images: Vec<AllocatedImage>,
+        extent: vk::Extent2D,
+    },
+}
+
+impl ColorTarget {
+    fn offscreen(ctx: &VkContext, width: u32, height: u32) -> Self {
+        let images = (0..ctx.frames_in_flight())
+            .map(|_| {
+                AllocatedImage::new_2d_with_view(
+                    ctx,
+                    OFFSCREEN_FORMAT,
+                    width,
+                    height,
+                    1,
+                    1,
+                    vk::SampleCountFlags::TYPE_1,
+                    vk::ImageTiling::OPTIMAL,
+                    vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+                    MemoryUsage::AutoPreferDevice,
+                    vk::ImageAspectFlags::COLOR,
+                )
+            })
+            .collect();
+
+        Self::Offscreen {
+            images,
+            extent: vk::Extent2D { width, height },
+        }
+    }
+
+    fn destroy(&mut self, ctx: &VkContext) {
+        match self {
+            Self::Swapchain(swapchain) => swapchain.destroy(ctx.device()),
+            Self::Offscreen { images, .. } => {
+                for img in images.iter_mut() {
+                    img.destroy(ctx);
+                }
+                images.clear();
+            }
+        }
+    }
+}
+
 pub struct RenderTargets {
     pub depth_images: Vec<AllocatedImage>,
     pub depth_pyramids: Vec<HiZPyramid>,
     pub mip_levels: u32,
-    pub swapchain: Swapchain,
+    pub color: ColorTarget,
+    /// sRGB images frames are rendered into when the swapchain format isn't
+    /// sRGB, copied into the swapchain by [`Self::copy_to_swapchain`].
+    pub srgb_images: Vec<AllocatedImage>,
+    /// Attachments of the OIT pass, only allocated while it is turned on with
+    /// [`Self::set_oit`].
+    pub oit_accum: Vec<AllocatedImage>,
+    pub oit_revealage: Vec<AllocatedImage>,
+    oit: bool,
 }
 
 impl RenderTargets {
-    pub fn new(ctx: &VkContext, width: u32, height: u32) -> Self {
-        let swapchain = Swapchain::new(ctx, width, height);
-        let depth_images = create_depth_resources(ctx, &swapchain);
+    pub fn new(
+        ctx: &VkContext,
+        width: u32,
+        height: u32,
+        color_space: SwapchainColorSpace,
+        image_count: Option<u32>,
+        present_mode: PresentMode,
+    ) -> Self {
+        let swapchain = Swapchain::new(ctx, width, height, color_space, image_count, present_mode);
+        Self::from_color(ctx, ColorTarget::Swapchain(swapchain))
+    }
 
-        let depth_pyramids: Vec<_> = (0..swapchain.image_views.len())
-            .map(|_| HiZPyramid::new(ctx, swapchain.extent.width, swapchain.extent.height))
-            .collect();
+    /// Render targets that are never presented, for rendering without a window.
+    pub fn new_offscreen(ctx: &VkContext, width: u32, height: u32) -> Self {
+        Self::from_color(ctx, ColorTarget::offscreen(ctx, width, height))
+    }
 
-        let mip_levels = depth_pyramids.first().map(|p| p.mip_levels).unwrap_or(1);
+    fn from_color(ctx: &VkContext, color: ColorTarget) -> Self {
+        let mut targets = Self {
+            depth_images: Vec::new(),
+            depth_pyramids: Vec::new(),
+            mip_levels: 1,
+            color,
+            srgb_images: Vec::new(),
+            oit_accum: Vec::new(),
+            oit_revealage: Vec::new(),
+            oit: false,
+        };
+        targets.create_frame_resources(ctx);
+        targets
+    }
 
-        Self {
-            depth_images,
-            depth_pyramids,
-            mip_levels,
-            swapchain,
+    pub fn swapchain(&self) -> Option<&Swapchain> {
+        match &self.color {
+            ColorTarget::Swapchain(swapchain) => Some(swapchain),
+            ColorTarget::Offscreen { .. } => None,
         }
     }
 
     pub fn extent(&self) -> vk::Extent2D {
-        self.swapchain.extent
+        match &self.color {
+            ColorTarget::Swapchain(swapchain) => swapchain.extent,
+            ColorTarget::Offscreen { extent, .. } => *extent,
+        }
+    }
+
+    /// Format of the images the world is rendered into, the ui is drawn
+    /// straight into the swapchain in its own format.
+    pub fn format(&self) -> vk::Format {
+        match &self.color {
+            ColorTarget::Swapchain(swapchain) => {
+                srgb_target_format(swapchain).unwrap_or(swapchain.format)
+            }
+            ColorTarget::Offscreen { .. } => OFFSCREEN_FORMAT,
+        }
+    }
+
+    pub fn image_count(&self) -> usize {
+        match &self.color {
+            ColorTarget::Swapchain(swapchain) => swapchain.images.len(),
+            ColorTarget::Offscreen { images, .. } => images.len(),
+        }
+    }
+
+    pub fn image_view(&self, index: usize) -> vk::ImageView {
+        if let Some(image) = self.srgb_images.get(index) {
+            return image.default_view;
+        }
+        match &self.color {
+            ColorTarget::Swapchain(swapchain) => swapchain.image_views[index],
+            ColorTarget::Offscreen { images, .. } => images[index].default_view,
+        }
+    }
+
+    /// Copies the sRGB image of `image_index` into the swapchain image, leaving
+    /// it ready for the ui pass. Does nothing if the world is drawn straight
+    /// into the swapchain.
+    pub fn copy_to_swapchain(&self, ctx: &VkContext, cmd: vk::CommandBuffer, image_index: usize) {
+        let (Some(swapchain), Some(src)) = (self.swapchain(), self.srgb_images.get(image_index))
+        else {
+            return;
+        };
+        let dst = swapchain.images[image_index];
+
+        let range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let barrier = |image, old_layout, new_layout, src_access, dst_access| {
+            vk::ImageMemoryBarrier::default()
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .image(image)
+                .subresource_range(range)
+        };
+        let layers = vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+
+        let device = ctx.device();
+        unsafe {
+            device.cmd_pipeline_barrier(
+                cmd,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[
+                    barrier(
+                        src.image,
+                        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                        vk::AccessFlags::TRANSFER_READ,
+                    ),
+                    barrier(
+                        dst,
+                        vk::ImageLayout::UNDEFINED,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::AccessFlags::empty(),
+                        vk::AccessFlags::TRANSFER_WRITE,
+                    ),
+                ],
+            );
+            // both formats have the same layout, so copying the raw bytes keeps
+            // the sRGB encoding the swapchain format lacks
+            device.cmd_copy_image(
+                cmd,
+                src.image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                dst,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[vk::ImageCopy::default()
+                    .src_subresource(layers)
+                    .dst_subresource(layers)
+                    .extent(src.extent)],
+            );
+            device.cmd_pipeline_barrier(
+                cmd,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier(
+                    dst,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::COLOR_ATTACHMENT_READ
+                        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                )],
+            );
+        }
+    }
+
+    /// Saves mip `level` of the depth pyramid of `frame` as a png, see
+    /// [`HiZPyramid::dump_level`].
+    pub fn dump_hiz_level(
+        &self,
+        ctx: &VkContext,
+        frame: usize,
+        level: u32,
+        path: &Path,
+    ) -> anyhow::Result<()> {
+        self.depth_pyramids
+            .get(frame)
+            .context("no depth pyramid for this frame")?
+            .dump_level(ctx, level, path)
+    }
+
+    /// Records a copy of the depth image of `index` into a new host visible
+    /// buffer, for [`save_depth_readback`] once the frame finished. Recorded
+    /// after the world passes, which leave the depth shader readable.
+    pub fn record_depth_readback(
+        &self,
+        ctx: &VkContext,
+        cmd: vk::CommandBuffer,
+        index: usize,
+    ) -> Buffer {
+        let extent = self.extent();
+        let image = self.depth_images[index].image;
+        let readback = Buffer::new(
+            ctx,
+            (extent.width * extent.height) as vk::DeviceSize * size_of::<f32>() as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryUsage::AutoPreferHost,
+            true,
+        );
+
+        let range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::DEPTH,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let barrier = |old_layout, new_layout, src_access, dst_access| {
+            vk::ImageMemoryBarrier::default()
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .image(image)
+                .subresource_range(range)
+        };
+        let depth_stages = vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+            | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+            | vk::PipelineStageFlags::FRAGMENT_SHADER
+            | vk::PipelineStageFlags::COMPUTE_SHADER;
+
+        unsafe {
+            let device = ctx.device();
+            device.cmd_pipeline_barrier(
+                cmd,
+                depth_stages,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier(
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    vk::AccessFlags::TRANSFER_READ,
+                )],
+            );
+            device.cmd_copy_image_to_buffer(
+                cmd,
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                readback.buffer,
+                &[vk::BufferImageCopy::default()
+                    .image_subresource(vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::DEPTH,
+                        mip_level: 0,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    })
+                    .image_extent(vk::Extent3D {
+                        width: extent.width,
+                        height: extent.height,
+                        depth: 1,
+                    })],
+            );
+            device.cmd_pipeline_barrier(
+                cmd,
+                vk::PipelineStageFlags::TRANSFER,
+                depth_stages,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier(
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::AccessFlags::TRANSFER_READ,
+                    vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+                        | vk::AccessFlags::SHADER_READ,
+                )],
+            );
+        }
+
+        readback
     }
 
     pub fn recreate(&mut self, ctx: &VkContext, width: u32, height: u32) {
-        self.swapchain.recreate(ctx, width, height);
+        match &mut self.color {
+            ColorTarget::Swapchain(swapchain) => swapchain.recreate(ctx, width, height),
+            offscreen @ ColorTarget::Offscreen { .. } => {
+                offscreen.destroy(ctx);
+                *offscreen = ColorTarget::offscreen(ctx, width, height);
+            }
+        }
         self.destory_frame_resources(ctx);
+        self.create_frame_resources(ctx);
+    }
+
+    fn create_frame_resources(&mut self, ctx: &VkContext) {
+        let extent = self.extent();
+
+        if let Some(swapchain) = self.swapchain()
+            && !swapchain::is_srgb(swapchain.format)
+            && swapchain::srgb_variant(swapchain.format).is_none()
+        {
+            log::warn!(
+                "Swapchain format {:?} has no sRGB variant, colors will be too dark",
+                swapchain.format
+            );
+        }
+        if let Some(format) = self.swapchain().and_then(srgb_target_format) {
+            self.srgb_images = (0..self.image_count())
+                .map(|_| {
+                    AllocatedImage::color_2d_device(
+                        ctx,
+                        format,
+                        extent.width,
+                        extent.height,
+                        1,
+                        vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+                    )
+                })
+                .collect();
+        }
 
-        self.depth_images = create_depth_resources(ctx, &self.swapchain);
-        self.depth_pyramids = (0..self.swapchain.image_views.len())
-            .map(|_| HiZPyramid::new(ctx, width, height))
+        self.depth_images = create_depth_resources(ctx, extent, self.image_count());
+        self.depth_pyramids = (0..self.image_count())
+            .map(|_| HiZPyramid::new(ctx, extent.width, extent.height))
             .collect();
         self.mip_levels = self
             .depth_pyramids
             .first()
             .map(|p| p.mip_levels)
             .unwrap_or(1);
+
+        if self.oit {
+            self.create_oit_resources(ctx);
+        }
+    }
+
+    /// Allocates the OIT attachments or frees them, the device must be idle.
+    pub fn set_oit(&mut self, ctx: &VkContext, enabled: bool) {
+        if self.oit == enabled {
+            return;
+        }
+        self.oit = enabled;
+        if enabled {
+            self.create_oit_resources(ctx);
+        } else {
+            self.destroy_oit_resources(ctx);
+        }
+    }
+
+    pub fn has_oit(&self) -> bool {
+        self.oit
+    }
+
+    fn create_oit_resources(&mut self, ctx: &VkContext) {
+        let extent = self.extent();
+        self.oit_accum = create_oit_resources(ctx, OIT_ACCUM_FORMAT, extent, self.image_count());
+        self.oit_revealage =
+            create_oit_resources(ctx, OIT_REVEALAGE_FORMAT, extent, self.image_count());
+    }
+
+    fn destroy_oit_resources(&mut self, ctx: &VkContext) {
+        for img in self.oit_accum.iter_mut().chain(&mut self.oit_revealage) {
+            img.destroy(ctx);
+        }
+        self.oit_accum.clear();
+        self.oit_revealage.clear();
     }
 
     pub fn destory_frame_resources(&mut self, ctx: &VkContext) {
         let device = ctx.device();
 
+        for img in &mut self.srgb_images {
+            img.destroy(ctx);
+        }
+        self.srgb_images.clear();
+
         for pyramid in &mut self.depth_pyramids {
             pyramid.destroy(ctx);
         }
@@ -62,25 +475,118 @@ impl RenderTargets {
             img.destroy(ctx);
         }
         self.depth_images.clear();
+
+        self.destroy_oit_resources(ctx);
     }
 
     pub fn destroy(&mut self, ctx: &VkContext) {
-        self.swapchain.destroy(&ctx.device());
+        self.color.destroy(ctx);
         self.destory_frame_resources(ctx);
     }
 }
 
-pub fn create_depth_resources(ctx: &VkContext, swapchain: &Swapchain) -> Vec<AllocatedImage> {
+/// Format of the sRGB images rendered into if `swapchain` isn't sRGB but has an
+/// sRGB variant, without one the world is drawn into the swapchain unencoded.
+fn srgb_target_format(swapchain: &Swapchain) -> Option<vk::Format> {
+    if swapchain::is_srgb(swapchain.format) {
+        None
+    } else {
+        swapchain::srgb_variant(swapchain.format)
+    }
+}
+
+/// Saves a depth image read back by [`RenderTargets::record_depth_readback`]
+/// as a grayscale png and destroys the buffer. Depths are turned back into
+/// distances from the camera with `inverse_proj`, `near` is black and `far`
+/// white, so the reversed depth doesn't squeeze everything into the bright end.
+pub fn save_depth_readback(
+    ctx: &VkContext,
+    mut readback: Buffer,
+    extent: vk::Extent2D,
+    inverse_proj: Mat4,
+    near: f32,
+    far: f32,
+    path: &Path,
+) -> anyhow::Result<()> {
+    let texels = (extent.width * extent.height) as usize;
+    let depths = unsafe {
+        let allocator = ctx.allocator();
+        allocator.invalidate_allocation(&readback.allocation, 0, vk::WHOLE_SIZE)?;
+        let ptr = allocator.map_memory(&mut readback.allocation)?;
+        let depths = std::slice::from_raw_parts(ptr as *const f32, texels).to_vec();
+        allocator.unmap_memory(&mut readback.allocation);
+        depths
+    };
+    readback.destroy(ctx);
+
+    let range = (far - near).max(f32::EPSILON);
+    let pixels = depths
+        .iter()
+        .map(|&depth| {
+            // reversed depth is 0 at the far plane, which may be at infinity
+            let distance = if depth > 0.0 {
+                -inverse_proj.project_point3(Vec3::new(0.0, 0.0, depth)).z
+            } else {
+                far
+            };
+            let t = (distance - near) / range;
+            (t.clamp(0.0, 1.0) * 255.0) as u8
+        })
+        .collect();
+
+    image::GrayImage::from_raw(extent.width, extent.height, pixels)
+        .context("readback size does not match the depth image")?
+        .save(path)
+        .with_context(|| format!("saving {}", path.display()))?;
+
+    log::info!(
+        "Saved depth ({}x{}, {near}..{far} blocks) to {}",
+        extent.width,
+        extent.height,
+        path.display()
+    );
+    Ok(())
+}
+
+pub fn create_depth_resources(
+    ctx: &VkContext,
+    extent: vk::Extent2D,
+    count: usize,
+) -> Vec<AllocatedImage> {
     let format = vk::Format::D32_SFLOAT;
-    (0..swapchain.image_views.len())
+    (0..count)
         .map(|_| {
             AllocatedImage::depth_2d_device(
                 ctx,
                 format,
-                swapchain.extent.width,
-                swapchain.extent.height,
+                extent.width,
+                extent.height,
                 vk::SampleCountFlags::TYPE_1,
-                vk::ImageUsageFlags::SAMPLED,
+                vk::ImageUsageFlags::SAMPLED
+                    | vk::ImageUsageFlags::INPUT_ATTACHMENT
+                    | vk::ImageUsageFlags::TRANSFER_SRC,
+            )
+        })
+        .collect()
+}
+
+/// Attachments of the order independent transparency pass, only read within
+/// the pass.
+fn create_oit_resources(
+    ctx: &VkContext,
+    format: vk::Format,
+    extent: vk::Extent2D,
+    count: usize,
+) -> Vec<AllocatedImage> {
+    (0..count)
+        .map(|_| {
+            AllocatedImage::color_2d_device(
+                ctx,
+                format,
+                extent.width,
+                extent.height,
+                1,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::INPUT_ATTACHMENT,
             )
         })
         .collect()
@@ -5,7 +5,7 @@ use azalea_assets::Assets;
 
 use crate::renderer::{
     frame_ctx::FrameCtx,
-    vulkan::{context::VkContext, frame_sync::MAX_FRAMES_IN_FLIGHT, texture::Texture},
+    vulkan::{context::VkContext, frame_sync::FrameSync, texture::Texture},
 };
 
 const MAX_TEXTURES: u32 = 1024;
@@ -16,16 +16,23 @@ pub struct TextureManager {
     name_to_index: HashMap<String, u32>,
     descriptor_set_layout: vk::DescriptorSetLayout,
     descriptor_pool: vk::DescriptorPool,
-    descriptor_sets: [vk::DescriptorSet; MAX_FRAMES_IN_FLIGHT],
-    dirty_descriptor_sets: [bool; MAX_FRAMES_IN_FLIGHT],
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    dirty_descriptor_sets: Vec<bool>,
+    filter: vk::Filter,
 }
 
 impl TextureManager {
-    pub fn new(ctx: &VkContext, assets: Arc<Assets>) -> Self {
+    pub fn new(ctx: &VkContext, assets: Arc<Assets>, filter: vk::Filter) -> Self {
         let descriptor_set_layout = Self::create_descriptor_set_layout(ctx.device());
-        let descriptor_pool = Self::create_descriptor_pool(ctx.device());
-        let descriptor_sets =
-            Self::allocate_descriptor_sets(ctx.device(), descriptor_pool, descriptor_set_layout);
+        let frames = ctx.frames_in_flight();
+        let descriptor_pool = Self::create_descriptor_pool(ctx.device(), frames);
+        ctx.track_object(descriptor_pool);
+        let descriptor_sets = Self::allocate_descriptor_sets(
+            ctx.device(),
+            descriptor_pool,
+            descriptor_set_layout,
+            frames,
+        );
 
         Self {
             assets,
@@ -34,7 +41,8 @@ impl TextureManager {
             descriptor_set_layout,
             descriptor_pool,
             descriptor_sets,
-            dirty_descriptor_sets: [true; MAX_FRAMES_IN_FLIGHT],
+            dirty_descriptor_sets: vec![true; frames],
+            filter,
         }
     }
 
@@ -50,14 +58,14 @@ impl TextureManager {
         unsafe { device.create_descriptor_set_layout(&info, None).unwrap() }
     }
 
-    fn create_descriptor_pool(device: &Device) -> vk::DescriptorPool {
+    fn create_descriptor_pool(device: &Device, frames: usize) -> vk::DescriptorPool {
         let pool_sizes = [vk::DescriptorPoolSize::default()
             .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-            .descriptor_count(MAX_TEXTURES * MAX_FRAMES_IN_FLIGHT as u32)];
+            .descriptor_count(MAX_TEXTURES * frames as u32)];
 
         let info = vk::DescriptorPoolCreateInfo::default()
             .pool_sizes(&pool_sizes)
-            .max_sets(MAX_FRAMES_IN_FLIGHT as u32);
+            .max_sets(frames as u32);
 
         unsafe { device.create_descriptor_pool(&info, None).unwrap() }
     }
@@ -66,15 +74,14 @@ impl TextureManager {
         device: &Device,
         pool: vk::DescriptorPool,
         layout: vk::DescriptorSetLayout,
-    ) -> [vk::DescriptorSet; MAX_FRAMES_IN_FLIGHT] {
-        let layouts = [layout; MAX_FRAMES_IN_FLIGHT];
+        frames: usize,
+    ) -> Vec<vk::DescriptorSet> {
+        let layouts = vec![layout; frames];
         let alloc_info = vk::DescriptorSetAllocateInfo::default()
             .descriptor_pool(pool)
             .set_layouts(&layouts);
 
-        let sets = unsafe { device.allocate_descriptor_sets(&alloc_info).unwrap() };
-
-        sets.try_into().unwrap()
+        unsafe { device.allocate_descriptor_sets(&alloc_info).unwrap() }
     }
 
     pub fn descriptor_set_layout(&self) -> vk::DescriptorSetLayout {
@@ -108,13 +115,7 @@ impl TextureManager {
             };
 
             let (width, height) = image.dimensions();
-            let mut texture = Texture::new(
-                ctx.ctx,
-                width,
-                height,
-                vk::Filter::NEAREST,
-                vk::Filter::NEAREST,
-            );
+            let mut texture = Texture::new(ctx.ctx, width, height, self.filter, self.filter);
             texture.upload_data(ctx, image.as_raw(), width, height);
 
             let texture_id = self.textures.len() as u32;
@@ -130,6 +131,22 @@ impl TextureManager {
         }
     }
 
+    /// Switches the filter of all loaded and future textures. The old samplers
+    /// are destroyed once the frames in flight are done with them, each
+    /// frame's set is rewritten the next time it is used.
+    pub fn set_filter(&mut self, ctx: &VkContext, sync: &mut FrameSync, filter: vk::Filter) {
+        self.filter = filter;
+        let frame = sync.last_frame();
+        for texture in &mut self.textures {
+            let old = texture.set_filter(ctx, filter);
+            sync.add_to_deletion_queue(frame, Box::new(old));
+        }
+
+        for dirty in &mut self.dirty_descriptor_sets {
+            *dirty = true;
+        }
+    }
+
     fn update_descriptor_set(&self, device: &Device, frame_index: usize) {
         if self.textures.is_empty() {
             return;
@@ -164,6 +181,7 @@ impl TextureManager {
         
         // Destroy descriptor resources
         let device = ctx.device();
+        ctx.untrack_object(self.descriptor_pool);
         unsafe {
             device.destroy_descriptor_pool(self.descriptor_pool, None);
             device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
@@ -1,4 +1,6 @@
-pub const TIMESTAMP_COUNT: usize = 12;
+use std::fmt;
+
+pub const TIMESTAMP_COUNT: usize = 14;
 
 // Frame
 pub const START_FRAME: usize = 0;
@@ -24,6 +26,10 @@ pub const END_VISIBILITY_COMPUTE: usize = 9;
 pub const START_UI_PASS: usize = 10;
 pub const END_UI_PASS: usize = 11;
 
+// GPU meshing
+pub const START_GPU_MESHING: usize = 12;
+pub const END_GPU_MESHING: usize = 13;
+
 #[derive(Debug, Clone, Copy)]
 pub struct Timings {
     ticks: [u64; TIMESTAMP_COUNT],
@@ -66,4 +72,25 @@ impl Timings {
     pub fn ui_time(&self) -> f32 {
         self.delta_ms(START_UI_PASS, END_UI_PASS)
     }
+
+    pub fn gpu_meshing_time(&self) -> f32 {
+        self.delta_ms(START_GPU_MESHING, END_GPU_MESHING)
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "frame {:.2}ms (uploads {:.2}ms, gpu meshing {:.2}ms, terrain {:.2}ms, HiZ {:.2}ms, \
+             visibility {:.2}ms, ui {:.2}ms)",
+            self.frame_time(),
+            self.upload_dirty_time(),
+            self.gpu_meshing_time(),
+            self.terrain_pass_time(),
+            self.hiz_compute_time(),
+            self.visibility_compute_time(),
+            self.ui_time(),
+        )
+    }
 }
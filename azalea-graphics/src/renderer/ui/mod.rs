@@ -12,6 +12,19 @@ mod painter;
 mod passes;
 mod pipelines;
 
+/// Alpha of the layer darkening the scene behind the windows.
+const BACKDROP_ALPHA: u8 = 96;
+
+/// How the windows are drawn over the scene.
+#[derive(Clone, Copy, Debug)]
+pub struct UiStyle {
+    /// Opacity of the window and panel backgrounds, from 0 to 1.
+    pub opacity: f32,
+    /// Darkens the whole scene behind the windows, so text stays readable over
+    /// bright terrain and sky.
+    pub backdrop: bool,
+}
+
 /// Use [`egui`] from a Vulkan app based on [`winit`].
 pub struct EguiVulkan {
     egui_ctx: egui::Context,
@@ -67,16 +80,36 @@ impl EguiVulkan {
     }
 
     /// Call [`Self::paint`] later to paint.
-    pub fn run(&mut self, window: &winit::window::Window, run_ui: impl FnMut(&egui::Context)) {
+    pub fn run(
+        &mut self,
+        window: &winit::window::Window,
+        style: UiStyle,
+        mut run_ui: impl FnMut(&egui::Context),
+    ) {
         let raw_input = self.egui_winit.take_egui_input(window);
 
+        let visuals = self.egui_ctx.theme().default_visuals();
+        self.egui_ctx.style_mut(|egui_style| {
+            egui_style.visuals.window_fill = visuals.window_fill.gamma_multiply(style.opacity);
+            egui_style.visuals.panel_fill = visuals.panel_fill.gamma_multiply(style.opacity);
+        });
+
         let egui::FullOutput {
             platform_output,
             textures_delta,
             shapes,
             pixels_per_point,
             viewport_output,
-        } = self.egui_ctx.run(raw_input, run_ui);
+        } = self.egui_ctx.run(raw_input, |ctx| {
+            if style.backdrop {
+                ctx.layer_painter(egui::LayerId::background()).rect_filled(
+                    ctx.screen_rect(),
+                    0.0,
+                    egui::Color32::from_black_alpha(BACKDROP_ALPHA),
+                );
+            }
+            run_ui(ctx);
+        });
 
         if viewport_output.len() > 1 {
             log::warn!("Multiple viewports not yet supported by EguiVulkan");
@@ -145,6 +178,15 @@ impl EguiVulkan {
         self.painter.resize(ctx, swapchain);
     }
 
+    /// Rebuild the egui pipeline after the shaders were reloaded.
+    pub fn reload_shaders(
+        &mut self,
+        ctx: &VkContext,
+        module: vk::ShaderModule,
+    ) -> anyhow::Result<()> {
+        self.painter.reload_shaders(ctx, module)
+    }
+
     /// Call to release the allocated graphics resources.
     pub fn destroy(&mut self, ctx: &VkContext) {
         self.painter.destroy(ctx);
@@ -11,7 +11,8 @@ use crate::renderer::{
     mesh::Mesh as GpuMesh,
     ui::{passes::create_egui_render_pass, pipelines::create_egui_pipeline},
     vulkan::{
-        context::VkContext, frame_sync::MAX_FRAMES_IN_FLIGHT, swapchain::Swapchain,
+        context::VkContext,
+        swapchain::{Swapchain, is_srgb},
         texture::Texture,
     },
 };
@@ -65,7 +66,7 @@ pub struct Painter {
     extent: vk::Extent2D,
 
     // Per-frame data
-    frame_data: [FrameData; MAX_FRAMES_IN_FLIGHT],
+    frame_data: Vec<FrameData>,
 
     // Textures
     textures: HashMap<TextureId, Texture>,
@@ -119,9 +120,10 @@ impl Painter {
                 .create_descriptor_pool(&descriptor_pool_info, None)
                 .map_err(|e| anyhow::anyhow!("Failed to create descriptor pool: {:?}", e))?
         };
+        ctx.track_object(descriptor_pool);
 
         let push_constant_range = vk::PushConstantRange::default()
-            .stage_flags(vk::ShaderStageFlags::VERTEX)
+            .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
             .offset(0)
             .size(std::mem::size_of::<EguiPushConstants>() as u32);
 
@@ -135,7 +137,7 @@ impl Painter {
                 .map_err(|e| anyhow::anyhow!("Failed to create pipeline layout: {:?}", e))?
         };
 
-        let pipeline = create_egui_pipeline(device, module, render_pass, pipeline_layout)?;
+        let pipeline = create_egui_pipeline(ctx, module, render_pass, pipeline_layout)?;
 
         Ok(Self {
             render_pass,
@@ -146,7 +148,9 @@ impl Painter {
             framebuffers,
             swapchain_format: swapchain.format,
             extent: swapchain.extent,
-            frame_data: Default::default(),
+            frame_data: (0..ctx.frames_in_flight())
+                .map(|_| FrameData::default())
+                .collect(),
             textures: HashMap::new(),
             texture_descriptor_sets: HashMap::new(),
             next_native_tex_id: 1 << 32,
@@ -204,6 +208,20 @@ impl Painter {
         self.framebuffers = Self::create_framebuffers(ctx, swapchain, self.render_pass);
     }
 
+    /// Recreate the pipeline from a new shader module, the device must be idle.
+    pub fn reload_shaders(
+        &mut self,
+        ctx: &VkContext,
+        module: vk::ShaderModule,
+    ) -> anyhow::Result<()> {
+        self.assert_not_destroyed();
+        let pipeline = create_egui_pipeline(ctx, module, self.render_pass, self.pipeline_layout)?;
+        ctx.untrack_object(self.pipeline);
+        unsafe { ctx.device().destroy_pipeline(self.pipeline, None) };
+        self.pipeline = pipeline;
+        Ok(())
+    }
+
     /// Paint egui primitives to the current command buffer with its own render
     /// pass.
     pub fn paint_primitives(
@@ -241,13 +259,14 @@ impl Painter {
 
         let push_constants = EguiPushConstants {
             screen_size: [screen_size_px[0] as f32, screen_size_px[1] as f32],
+            srgb_target: is_srgb(self.swapchain_format) as u32,
         };
 
         unsafe {
             ctx.device().cmd_push_constants(
                 cmd,
                 self.pipeline_layout,
-                vk::ShaderStageFlags::VERTEX,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
                 0,
                 std::slice::from_raw_parts(
                     &push_constants as *const _ as *const u8,
@@ -487,6 +506,8 @@ impl Painter {
                 }
 
                 // Clean up Vulkan objects
+                ctx.untrack_object(self.pipeline);
+                ctx.untrack_object(self.descriptor_pool);
                 device.destroy_pipeline(self.pipeline, None);
                 device.destroy_pipeline_layout(self.pipeline_layout, None);
                 device.destroy_descriptor_pool(self.descriptor_pool, None);
@@ -517,4 +538,5 @@ impl Drop for Painter {
 #[derive(Copy, Clone, Debug)]
 struct EguiPushConstants {
     screen_size: [f32; 2],
+    srgb_target: u32,
 }
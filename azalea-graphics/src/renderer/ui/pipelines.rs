@@ -2,6 +2,8 @@ use std::ffi::CString;
 
 use ash::{Device, vk};
 
+use crate::renderer::vulkan::context::VkContext;
+
 fn create_shader_module(device: &Device, code: &[u8]) -> vk::ShaderModule {
     let code_aligned = ash::util::read_spv(&mut std::io::Cursor::new(code)).unwrap();
     let info = vk::ShaderModuleCreateInfo::default().code(&code_aligned);
@@ -9,11 +11,13 @@ fn create_shader_module(device: &Device, code: &[u8]) -> vk::ShaderModule {
 }
 
 pub fn create_egui_pipeline(
-    device: &Device,
+    ctx: &VkContext,
     module: vk::ShaderModule,
     render_pass: vk::RenderPass,
     pipeline_layout: vk::PipelineLayout,
 ) -> anyhow::Result<vk::Pipeline> {
+    let device = ctx.device();
+
     // Vertex input state for egui vertices
     let binding_desc = vk::VertexInputBindingDescription::default()
         .binding(0)
@@ -116,6 +120,7 @@ pub fn create_egui_pipeline(
             .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info], None)
             .map_err(|e| anyhow::anyhow!("Failed to create egui pipeline: {:?}", e))?
     };
+    ctx.track_object(pipelines[0]);
 
     Ok(pipelines[0])
 }
@@ -8,17 +8,18 @@ pub fn create_framebuffers(
     render_pass: vk::RenderPass,
 ) -> Vec<vk::Framebuffer> {
     let device = ctx.device();
-    let mut fbs = Vec::with_capacity(render_targets.swapchain.image_views.len());
+    let extent = render_targets.extent();
+    let mut fbs = Vec::with_capacity(render_targets.image_count());
 
-    for (i, &color_view) in render_targets.swapchain.image_views.iter().enumerate() {
+    for i in 0..render_targets.image_count() {
         let depth_view = render_targets.depth_images[i].default_view;
-        let attachments = [color_view, depth_view];
+        let attachments = [render_targets.image_view(i), depth_view];
 
         let info = vk::FramebufferCreateInfo::default()
             .render_pass(render_pass)
             .attachments(&attachments)
-            .width(render_targets.swapchain.extent.width)
-            .height(render_targets.swapchain.extent.height)
+            .width(extent.width)
+            .height(extent.height)
             .layers(1);
 
         let fb = unsafe { device.create_framebuffer(&info, None).unwrap() };
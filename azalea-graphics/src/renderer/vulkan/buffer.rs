@@ -22,6 +22,7 @@ impl Buffer {
         mapped: bool,
     ) -> Self {
         let (buffer, allocation) = create_buffer(ctx.allocator(), size, usage, memory, mapped);
+        ctx.track_object(buffer);
         Self {
             buffer,
             allocation,
@@ -42,6 +43,7 @@ impl Buffer {
     /// Destroy the buffer
     pub fn destroy(&mut self, ctx: &VkContext) {
         ctx.label_object(self.buffer, format!("to_destory_manual: {}", self.size));
+        ctx.untrack_object(self.buffer);
         unsafe {
             ctx.allocator()
                 .destroy_buffer(self.buffer, &mut self.allocation);
@@ -119,6 +121,7 @@ impl VkObject for Buffer {
     fn destroy(&self, ctx: &VkContext) {
         unsafe {
             ctx.label_object(self.buffer, "to_destory");
+            ctx.untrack_object(self.buffer);
             ctx.allocator()
                 .destroy_buffer(self.buffer, &mut self.allocation.clone());
         }
@@ -11,9 +11,13 @@ use ash::{
     vk::{self, Handle},
 };
 use raw_window_handle::{DisplayHandle, WindowHandle};
+use thiserror::Error;
 use vk_mem::{Allocator, AllocatorCreateInfo};
 
-use crate::app::RendererArgs;
+use crate::{
+    app::RendererArgs,
+    renderer::vulkan::{frame_sync::MAX_FRAMES_IN_FLIGHT, tracker::ObjectTracker},
+};
 
 #[derive(Clone, Copy)]
 pub struct QueueFamiliesIndices {
@@ -24,7 +28,18 @@ pub struct QueueFamiliesIndices {
 #[derive(Clone, Copy, Debug)]
 pub struct DeviceFeatures {
     pub fill_mode_non_solid: bool,
+    pub wide_lines: bool,
+    /// Widest line the device rasterizes, the upper end of `lineWidthRange`
+    /// and 1 without `wideLines`.
+    pub max_line_width: f32,
     pub timestamp_queries: bool,
+    /// The cull mode can be set while recording instead of being baked into
+    /// pipelines, core since Vulkan 1.3.
+    pub dynamic_cull_mode: bool,
+    /// Largest sampler anisotropy, 1 without the `samplerAnisotropy` feature.
+    pub max_anisotropy: f32,
+    /// Offsets into uniform buffers must be a multiple of this.
+    pub min_uniform_buffer_offset_alignment: vk::DeviceSize,
 }
 
 pub struct Debug {
@@ -33,12 +48,36 @@ pub struct Debug {
     messenger: vk::DebugUtilsMessengerEXT,
 }
 
+struct Surface {
+    loader: surface::Instance,
+    surface_khr: vk::SurfaceKHR,
+}
+
+/// The gpu was reset or removed, e.g. by a driver update or timeout. Nothing
+/// can be rendered with the device anymore.
+#[derive(Error, Debug, Clone, Copy)]
+#[error("device lost, restart required")]
+pub struct DeviceLost;
+
+/// Turns `ERROR_DEVICE_LOST` into [`DeviceLost`], any other error is a bug and
+/// panics.
+pub fn check_device_lost<T>(
+    result: ash::prelude::VkResult<T>,
+    what: &str,
+) -> Result<T, DeviceLost> {
+    match result {
+        Ok(value) => Ok(value),
+        Err(vk::Result::ERROR_DEVICE_LOST) => Err(DeviceLost),
+        Err(e) => panic!("{what} failed: {e:?}"),
+    }
+}
+
 pub struct VkContext {
     _entry: Entry,
     instance: Instance,
     debug: Option<Debug>,
-    surface: surface::Instance,
-    surface_khr: vk::SurfaceKHR,
+    /// `None` when rendering offscreen.
+    surface: Option<Surface>,
 
     physical_device: vk::PhysicalDevice,
     device: Device,
@@ -49,17 +88,40 @@ pub struct VkContext {
     graphics_queue: vk::Queue,
     present_queue: vk::Queue,
     command_pool: vk::CommandPool,
+    frames_in_flight: usize,
+    /// Live buffers, images, pipelines and descriptor pools, only kept in
+    /// debug builds.
+    objects: Option<ObjectTracker>,
 }
 
 impl VkContext {
     pub fn new(window: &WindowHandle, display: &DisplayHandle, args: &RendererArgs) -> Self {
+        Self::create(Some((window, display)), args)
+    }
+
+    /// Creates a context without a surface, for rendering to offscreen targets
+    /// only.
+    pub fn new_headless(args: &RendererArgs) -> Self {
+        Self::create(None, args)
+    }
+
+    fn create(window: Option<(&WindowHandle, &DisplayHandle)>, args: &RendererArgs) -> Self {
         let entry = unsafe { Entry::load().expect("Failed to load Vulkan entry.") };
-        let instance = Self::create_instance(&entry, display, args.debug);
-        let surface = surface::Instance::new(&entry, &instance);
-        let surface_khr = unsafe {
-            ash_window::create_surface(&entry, &instance, display.as_raw(), window.as_raw(), None)
+        let instance =
+            Self::create_instance(&entry, window.map(|(_, display)| display), args.debug);
+        let surface = window.map(|(window, display)| Surface {
+            loader: surface::Instance::new(&entry, &instance),
+            surface_khr: unsafe {
+                ash_window::create_surface(
+                    &entry,
+                    &instance,
+                    display.as_raw(),
+                    window.as_raw(),
+                    None,
+                )
                 .expect("Failed to create surface.")
-        };
+            },
+        });
 
         // Instance-level messenger first
         let debug_utils = if args.debug {
@@ -72,12 +134,13 @@ impl VkContext {
             .map(|utils| setup_debug_messenger(utils));
 
         let (physical_device, queue_families) =
-            Self::pick_physical_device(&instance, &surface, surface_khr);
+            Self::pick_physical_device(&instance, surface.as_ref());
         let (device, graphics_queue, present_queue, features) = Self::create_logical_device(
             &instance,
             physical_device,
             queue_families,
             args.timestamps,
+            surface.is_some(),
         );
 
         let allocator = ManuallyDrop::new(unsafe {
@@ -116,7 +179,6 @@ impl VkContext {
             instance,
             debug,
             surface,
-            surface_khr,
             physical_device,
             device,
             allocator,
@@ -125,6 +187,8 @@ impl VkContext {
             graphics_queue,
             present_queue,
             command_pool,
+            frames_in_flight: args.frames_in_flight.clamp(1, MAX_FRAMES_IN_FLIGHT),
+            objects: cfg!(debug_assertions).then(ObjectTracker::default),
         }
     }
 
@@ -138,10 +202,20 @@ impl VkContext {
         &self.instance
     }
     pub fn surface(&self) -> &surface::Instance {
-        &self.surface
+        &self
+            .surface
+            .as_ref()
+            .expect("headless context has no surface")
+            .loader
     }
     pub fn surface_khr(&self) -> vk::SurfaceKHR {
-        self.surface_khr
+        self.surface
+            .as_ref()
+            .expect("headless context has no surface")
+            .surface_khr
+    }
+    pub fn is_headless(&self) -> bool {
+        self.surface.is_none()
     }
     pub fn physical_device(&self) -> vk::PhysicalDevice {
         self.physical_device
@@ -158,11 +232,20 @@ impl VkContext {
     pub fn features(&self) -> DeviceFeatures {
         self.features
     }
+    /// Number of frames the cpu may record ahead of the gpu, between 1 and
+    /// [`MAX_FRAMES_IN_FLIGHT`].
+    pub fn frames_in_flight(&self) -> usize {
+        self.frames_in_flight
+    }
 
     pub fn label_object<H>(&self, object: H, name: impl AsRef<str>)
     where
         H: Handle,
     {
+        if let Some(objects) = &self.objects {
+            objects.set_name(object, name.as_ref());
+        }
+
         // Only try if debug_messenger is active
         if let Some(debug) = &self.debug {
             let cname = CString::new(name.as_ref()).expect("object name contained a null byte");
@@ -180,6 +263,28 @@ impl VkContext {
         }
     }
 
+    /// Records a newly created buffer, image, pipeline or descriptor pool for
+    /// [`Self::report_leaks`].
+    pub fn track_object<H: Handle>(&self, object: H) {
+        if let Some(objects) = &self.objects {
+            objects.created(object);
+        }
+    }
+
+    pub fn untrack_object<H: Handle>(&self, object: H) {
+        if let Some(objects) = &self.objects {
+            objects.destroyed(object);
+        }
+    }
+
+    /// Logs the tracked objects that weren't destroyed, with their debug
+    /// names. Does nothing in release builds.
+    pub fn report_leaks(&self) {
+        if let Some(objects) = &self.objects {
+            objects.report_leaks();
+        }
+    }
+
     pub fn cmd_begin_debug_label(&self, cmd: vk::CommandBuffer, name: impl AsRef<str>) {
         if let Some(debug) = &self.debug {
             let cname = CString::new(name.as_ref()).expect("label name contained a null byte");
@@ -249,7 +354,7 @@ impl VkContext {
         }
     }
 
-    fn create_instance(entry: &Entry, display: &DisplayHandle, debug: bool) -> Instance {
+    fn create_instance(entry: &Entry, display: Option<&DisplayHandle>, debug: bool) -> Instance {
         let app_name = CString::new("Azalea Renderer").unwrap();
         let engine_name = CString::new("Custom").unwrap();
 
@@ -260,9 +365,12 @@ impl VkContext {
             .engine_version(vk::make_api_version(0, 0, 1, 0))
             .api_version(vk::make_api_version(0, 1, 3, 0));
 
-        let mut extensions = ash_window::enumerate_required_extensions(display.as_raw())
-            .unwrap()
-            .to_vec();
+        let mut extensions = match display {
+            Some(display) => ash_window::enumerate_required_extensions(display.as_raw())
+                .unwrap()
+                .to_vec(),
+            None => Vec::new(),
+        };
         if debug {
             extensions.push(debug_utils::NAME.as_ptr());
         }
@@ -282,20 +390,19 @@ impl VkContext {
 
     fn pick_physical_device(
         instance: &Instance,
-        surface: &surface::Instance,
-        surface_khr: vk::SurfaceKHR,
+        surface: Option<&Surface>,
     ) -> (vk::PhysicalDevice, QueueFamiliesIndices) {
         let devices =
             unsafe { instance.enumerate_physical_devices() }.expect("Failed to enumerate devices.");
         let device = devices
             .into_iter()
             .find(|&dev| {
-                let (gfx, pres) = Self::find_queue_families(instance, surface, surface_khr, dev);
+                let (gfx, pres) = Self::find_queue_families(instance, surface, dev);
                 gfx.is_some() && pres.is_some()
             })
             .expect("No suitable GPU found.");
 
-        let (graphics, present) = Self::find_queue_families(instance, surface, surface_khr, device);
+        let (graphics, present) = Self::find_queue_families(instance, surface, device);
         let indices = QueueFamiliesIndices {
             graphics_index: graphics.unwrap(),
             present_index: present.unwrap(),
@@ -304,10 +411,11 @@ impl VkContext {
         (device, indices)
     }
 
+    /// Without a surface nothing is presented, so the present family is the
+    /// graphics family.
     fn find_queue_families(
         instance: &Instance,
-        surface: &surface::Instance,
-        surface_khr: vk::SurfaceKHR,
+        surface: Option<&Surface>,
         device: vk::PhysicalDevice,
     ) -> (Option<u32>, Option<u32>) {
         let mut graphics = None;
@@ -319,15 +427,22 @@ impl VkContext {
             if fam.queue_flags.contains(vk::QueueFlags::GRAPHICS) && graphics.is_none() {
                 graphics = Some(idx);
             }
+            let Some(surface) = surface else {
+                continue;
+            };
             let supports_present = unsafe {
                 surface
-                    .get_physical_device_surface_support(device, idx, surface_khr)
+                    .loader
+                    .get_physical_device_surface_support(device, idx, surface.surface_khr)
                     .unwrap()
             };
             if supports_present && present.is_none() {
                 present = Some(idx);
             }
         }
+        if surface.is_none() {
+            present = graphics;
+        }
         (graphics, present)
     }
 
@@ -336,6 +451,7 @@ impl VkContext {
         physical: vk::PhysicalDevice,
         families: QueueFamiliesIndices,
         use_timestamps: bool,
+        use_swapchain: bool,
     ) -> (Device, vk::Queue, vk::Queue, DeviceFeatures) {
         let priorities = [1.0f32];
         let mut unique_indices = vec![families.graphics_index, families.present_index];
@@ -357,6 +473,19 @@ impl VkContext {
         let graphics_family_props = family_props[families.graphics_index as usize];
 
         let fill_mode_non_solid = base_features.fill_mode_non_solid == vk::TRUE;
+        let wide_lines = base_features.wide_lines == vk::TRUE;
+        let max_line_width = if wide_lines {
+            properties.limits.line_width_range[1].max(1.0)
+        } else {
+            1.0
+        };
+        let dynamic_cull_mode = properties.api_version >= vk::API_VERSION_1_3;
+        let sampler_anisotropy = base_features.sampler_anisotropy == vk::TRUE;
+        let max_anisotropy = if sampler_anisotropy {
+            properties.limits.max_sampler_anisotropy.max(1.0)
+        } else {
+            1.0
+        };
         let queue_supports_timestamps = graphics_family_props.timestamp_valid_bits > 0;
         let timestamp_queries = properties.limits.timestamp_compute_and_graphics == vk::TRUE
             && properties.limits.timestamp_period > 0.0
@@ -390,9 +519,30 @@ impl VkContext {
             }
         }
 
+        if !wide_lines {
+            log::warn!("wideLines not supported, thick debug lines are drawn as quads");
+        }
+
+        if sampler_anisotropy {
+            log::info!("samplerAnisotropy supported, up to {max_anisotropy}x");
+        } else {
+            log::warn!("samplerAnisotropy not supported, anisotropic filtering disabled");
+        }
+
+        if !dynamic_cull_mode {
+            log::warn!("Vulkan 1.3 not supported, using extra pipelines to disable culling");
+        }
+
         let device_features = DeviceFeatures {
             fill_mode_non_solid,
+            wide_lines,
+            max_line_width,
             timestamp_queries,
+            dynamic_cull_mode,
+            max_anisotropy,
+            min_uniform_buffer_offset_alignment: properties
+                .limits
+                .min_uniform_buffer_offset_alignment,
         };
 
         let mut vulkan_memory_model_features =
@@ -402,8 +552,18 @@ impl VkContext {
         if fill_mode_non_solid {
             enabled_features.fill_mode_non_solid = vk::TRUE;
         }
+        if wide_lines {
+            enabled_features.wide_lines = vk::TRUE;
+        }
+        if sampler_anisotropy {
+            enabled_features.sampler_anisotropy = vk::TRUE;
+        }
 
-        let extensions = [khr_swapchain::NAME.as_ptr()];
+        let extensions = if use_swapchain {
+            vec![khr_swapchain::NAME.as_ptr()]
+        } else {
+            Vec::new()
+        };
 
         let mut indexing = vk::PhysicalDeviceDescriptorIndexingFeatures::default()
             //.shader_sampled_image_array_non_uniform_indexing(true)
@@ -436,7 +596,9 @@ impl Drop for VkContext {
             self.device.destroy_command_pool(self.command_pool, None);
             ManuallyDrop::drop(&mut self.allocator);
             self.device.destroy_device(None);
-            self.surface.destroy_surface(self.surface_khr, None);
+            if let Some(surface) = self.surface.take() {
+                surface.loader.destroy_surface(surface.surface_khr, None);
+            }
             if let Some(debug) = self.debug.take() {
                 debug
                     .utils
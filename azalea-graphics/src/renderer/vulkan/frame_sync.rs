@@ -1,28 +1,32 @@
 use ash::vk;
 
-use crate::renderer::vulkan::{context::VkContext, object::VkObject};
+use crate::renderer::vulkan::{
+    context::{DeviceLost, VkContext, check_device_lost},
+    object::VkObject,
+};
 
-pub const MAX_FRAMES_IN_FLIGHT: usize = 2;
+/// Upper bound for [`VkContext::frames_in_flight`], allows triple buffering.
+pub const MAX_FRAMES_IN_FLIGHT: usize = 3;
 
 pub struct FrameSync {
-    pub image_available: [vk::Semaphore; MAX_FRAMES_IN_FLIGHT],
-    pub in_flight: [vk::Fence; MAX_FRAMES_IN_FLIGHT],
+    pub image_available: Vec<vk::Semaphore>,
+    pub in_flight: Vec<vk::Fence>,
     pub render_finished: Vec<vk::Semaphore>,
-    pub deletion_queues: [Vec<Box<dyn VkObject>>; MAX_FRAMES_IN_FLIGHT],
+    pub deletion_queues: Vec<Vec<Box<dyn VkObject>>>,
     pub current_frame: usize,
 }
 
 impl FrameSync {
-    pub fn new(device: &ash::Device, image_count: usize) -> Self {
+    pub fn new(device: &ash::Device, frames_in_flight: usize, image_count: usize) -> Self {
         let semaphore_info = vk::SemaphoreCreateInfo::default();
         let fence_info = vk::FenceCreateInfo::default().flags(vk::FenceCreateFlags::SIGNALED);
 
-        let mut image_available = [vk::Semaphore::null(); MAX_FRAMES_IN_FLIGHT];
-        let mut in_flight = [vk::Fence::null(); MAX_FRAMES_IN_FLIGHT];
-        for i in 0..MAX_FRAMES_IN_FLIGHT {
+        let mut image_available = Vec::with_capacity(frames_in_flight);
+        let mut in_flight = Vec::with_capacity(frames_in_flight);
+        for _ in 0..frames_in_flight {
             unsafe {
-                image_available[i] = device.create_semaphore(&semaphore_info, None).unwrap();
-                in_flight[i] = device.create_fence(&fence_info, None).unwrap();
+                image_available.push(device.create_semaphore(&semaphore_info, None).unwrap());
+                in_flight.push(device.create_fence(&fence_info, None).unwrap());
             }
         }
 
@@ -32,7 +36,7 @@ impl FrameSync {
             render_finished.push(sem);
         }
 
-        let deletion_queues = [(); MAX_FRAMES_IN_FLIGHT].map(|_| Vec::new());
+        let deletion_queues = (0..frames_in_flight).map(|_| Vec::new()).collect();
 
         Self {
             image_available,
@@ -43,19 +47,31 @@ impl FrameSync {
         }
     }
 
+    pub fn frames_in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
     pub fn next_frame(&mut self) -> usize {
         let frame = self.current_frame;
-        self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
+        self.current_frame = (self.current_frame + 1) % self.frames_in_flight();
         frame
     }
 
-    pub fn wait_for_fence(&self, device: &ash::Device, frame: usize) {
+    /// The frame started last, objects it may still use can be queued for
+    /// deletion on it between frames.
+    pub fn last_frame(&self) -> usize {
+        (self.current_frame + self.frames_in_flight() - 1) % self.frames_in_flight()
+    }
+
+    pub fn wait_for_fence(&self, device: &ash::Device, frame: usize) -> Result<(), DeviceLost> {
         unsafe {
-            device
-                .wait_for_fences(&[self.in_flight[frame]], true, u64::MAX)
-                .unwrap();
+            check_device_lost(
+                device.wait_for_fences(&[self.in_flight[frame]], true, u64::MAX),
+                "Waiting for the frame fence",
+            )?;
             device.reset_fences(&[self.in_flight[frame]]).unwrap();
         }
+        Ok(())
     }
 
     pub fn add_to_deletion_queue(&mut self, frame: usize, object: Box<dyn VkObject>) {
@@ -75,10 +91,9 @@ impl FrameSync {
                     object.destroy(ctx);
                 }
             }
-            for i in 0..MAX_FRAMES_IN_FLIGHT {
-                ctx.device()
-                    .destroy_semaphore(self.image_available[i], None);
-                ctx.device().destroy_fence(self.in_flight[i], None);
+            for (&semaphore, &fence) in self.image_available.iter().zip(&self.in_flight) {
+                ctx.device().destroy_semaphore(semaphore, None);
+                ctx.device().destroy_fence(fence, None);
             }
             for sempahore in &self.render_finished {
                 ctx.device().destroy_semaphore(*sempahore, None);
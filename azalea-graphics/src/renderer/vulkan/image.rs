@@ -58,6 +58,7 @@ impl AllocatedImage {
                 .create_image(&image_info, &alloc_info)
                 .expect("Failed to create VMA image")
         };
+        ctx.track_object(image);
 
         let view_info = vk::ImageViewCreateInfo::default()
             .image(image)
@@ -190,6 +191,7 @@ impl AllocatedImage {
     pub fn destroy(&mut self, ctx: &VkContext) {
         unsafe {
             let device = ctx.device();
+            ctx.untrack_object(self.image);
             device.destroy_image_view(self.default_view, None);
             ctx.allocator()
                 .destroy_image(self.image, &mut self.allocation);
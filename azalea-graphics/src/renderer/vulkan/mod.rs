@@ -6,3 +6,4 @@ pub mod object;
 pub mod swapchain;
 pub mod texture;
 pub mod timestamp;
+pub mod tracker;
@@ -1,5 +1,15 @@
+use ash::vk;
+
 use crate::renderer::vulkan::context::VkContext;
 
 pub trait VkObject {
     fn destroy(&self, ctx: &VkContext);
 }
+
+/// Samplers replaced by
+/// [`Texture::set_sampler`](super::texture::Texture::set_sampler).
+impl VkObject for vk::Sampler {
+    fn destroy(&self, ctx: &VkContext) {
+        unsafe { ctx.device().destroy_sampler(*self, None) };
+    }
+}
@@ -1,6 +1,34 @@
 use ash::{khr::swapchain as khr_swapchain, vk};
 
-use super::{context::VkContext, frame_sync::FrameSync};
+use super::{
+    context::{DeviceLost, VkContext, check_device_lost},
+    frame_sync::FrameSync,
+};
+
+/// The kind of surface format preferred for the swapchain.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SwapchainColorSpace {
+    /// An `_SRGB` format, the hardware encodes the linear colors the shaders
+    /// write.
+    #[default]
+    Srgb,
+    /// A `_UNORM` format, frames are rendered into sRGB images and copied over
+    /// before the ui is drawn.
+    Unorm,
+}
+
+/// The present mode preferred for the swapchain, FIFO is used if the surface
+/// doesn't support it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PresentMode {
+    /// Shows the newest frame at the next vblank without tearing or waiting.
+    #[default]
+    Mailbox,
+    /// Vsync, waits for the display to show a queued frame.
+    Fifo,
+    /// Shows frames as soon as they are presented, may tear.
+    Immediate,
+}
 
 pub struct Swapchain {
     loader: khr_swapchain::Device,
@@ -9,10 +37,20 @@ pub struct Swapchain {
     pub extent: vk::Extent2D,
     pub images: Vec<vk::Image>,
     pub image_views: Vec<vk::ImageView>,
+    /// Minimum number of images asked for, the driver may create more.
+    image_count: u32,
+    present_mode: PresentMode,
 }
 
 impl Swapchain {
-    pub fn new(context: &VkContext, width: u32, height: u32) -> Self {
+    pub fn new(
+        context: &VkContext,
+        width: u32,
+        height: u32,
+        color_space: SwapchainColorSpace,
+        image_count: Option<u32>,
+        present_mode: PresentMode,
+    ) -> Self {
         let formats = unsafe {
             context
                 .surface()
@@ -22,9 +60,13 @@ impl Swapchain {
                 )
                 .expect("Failed to query surface formats")
         };
-        let surface_format = choose_surface_format(&formats);
+        let surface_format = choose_surface_format(&formats, color_space);
+        log::info!(
+            "Swapchain format {:?} in {:?}",
+            surface_format.format,
+            surface_format.color_space
+        );
 
-        // compute image count once
         let capabilities = unsafe {
             context
                 .surface()
@@ -34,19 +76,22 @@ impl Swapchain {
                 )
                 .unwrap()
         };
-        let mut image_count = capabilities.min_image_count + 1;
-        if capabilities.max_image_count > 0 && image_count > capabilities.max_image_count {
-            image_count = capabilities.max_image_count;
-        }
+        let image_count = choose_image_count(&capabilities, image_count);
 
-        Self::from_old(
+        let swapchain = Self::from_old(
             context,
             width,
             height,
             vk::SwapchainKHR::null(),
             surface_format.format,
             image_count,
-        )
+            present_mode,
+        );
+        log::info!(
+            "Asked for {image_count} swapchain images, got {}",
+            swapchain.images.len()
+        );
+        swapchain
     }
 
     pub fn from_old(
@@ -56,6 +101,7 @@ impl Swapchain {
         old_swapchain: vk::SwapchainKHR,
         format: vk::Format,
         image_count: u32,
+        present_mode: PresentMode,
     ) -> Self {
         let loader = khr_swapchain::Device::new(context.instance(), context.device());
 
@@ -79,7 +125,7 @@ impl Swapchain {
                 .unwrap()
         };
 
-        let present_mode = choose_present_mode(&present_modes);
+        let vk_present_mode = choose_present_mode(&present_modes, present_mode);
         let extent = choose_extent(&capabilities, width, height);
 
         let indices = context.queue_families();
@@ -96,12 +142,12 @@ impl Swapchain {
             .image_format(format)
             .image_extent(extent)
             .image_array_layers(1)
-            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+            .image_usage(image_usage(format))
             .image_sharing_mode(sharing_mode)
             .queue_family_indices(indices)
             .pre_transform(capabilities.current_transform)
             .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-            .present_mode(present_mode)
+            .present_mode(vk_present_mode)
             .clipped(true)
             .old_swapchain(old_swapchain);
 
@@ -141,6 +187,8 @@ impl Swapchain {
             extent,
             images,
             image_views,
+            image_count,
+            present_mode,
         }
     }
 
@@ -156,11 +204,17 @@ impl Swapchain {
             height,
             self.swapchain_khr,
             self.format,
-            self.images.len() as u32,
+            self.image_count,
+            self.present_mode,
         );
     }
 
-    pub fn acquire_next_image(&self, sync: &FrameSync, frame: usize) -> Result<u32, bool> {
+    /// The index of the next image, `None` if the swapchain is out of date.
+    pub fn acquire_next_image(
+        &self,
+        sync: &FrameSync,
+        frame: usize,
+    ) -> Result<Option<u32>, DeviceLost> {
         match unsafe {
             self.loader.acquire_next_image(
                 self.swapchain_khr,
@@ -169,9 +223,9 @@ impl Swapchain {
                 vk::Fence::null(),
             )
         } {
-            Ok((index, _)) => Ok(index),
-            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Err(true),
-            Err(_) => Err(false),
+            Ok((index, _)) => Ok(Some(index)),
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(None),
+            Err(e) => check_device_lost(Err(e), "Acquiring a swapchain image"),
         }
     }
 
@@ -180,7 +234,7 @@ impl Swapchain {
         queue: vk::Queue,
         sync: &FrameSync,
         image_index: u32,
-    ) -> Result<bool, vk::Result> {
+    ) -> Result<bool, DeviceLost> {
         let wait_semaphores = [sync.render_finished[image_index as usize]];
         let swapchains = [self.swapchain_khr];
         let indices = [image_index];
@@ -194,7 +248,7 @@ impl Swapchain {
         match result {
             Ok(_) => Ok(true),
             Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(false),
-            Err(e) => Err(e),
+            Err(e) => check_device_lost(Err(e), "Presenting"),
         }
     }
 
@@ -208,21 +262,98 @@ impl Swapchain {
     }
 }
 
-fn choose_surface_format(available: &[vk::SurfaceFormatKHR]) -> vk::SurfaceFormatKHR {
-    available
+fn choose_surface_format(
+    available: &[vk::SurfaceFormatKHR],
+    color_space: SwapchainColorSpace,
+) -> vk::SurfaceFormatKHR {
+    let preferred = match color_space {
+        SwapchainColorSpace::Srgb => [vk::Format::B8G8R8A8_SRGB, vk::Format::R8G8B8A8_SRGB],
+        SwapchainColorSpace::Unorm => [vk::Format::B8G8R8A8_UNORM, vk::Format::R8G8B8A8_UNORM],
+    };
+    let nonlinear =
+        |fmt: &&vk::SurfaceFormatKHR| fmt.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR;
+
+    // any format that ends up sRGB encoded is better than the first one, which
+    // may be displayed too dark or washed out
+    preferred
         .iter()
-        .cloned()
-        .find(|fmt| {
-            fmt.format == vk::Format::B8G8R8A8_SRGB
-                && fmt.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+        .find_map(|&format| {
+            available
+                .iter()
+                .filter(nonlinear)
+                .find(|fmt| fmt.format == format)
         })
+        .or_else(|| {
+            available
+                .iter()
+                .filter(nonlinear)
+                .find(|fmt| is_srgb(fmt.format) || srgb_variant(fmt.format).is_some())
+        })
+        .copied()
         .unwrap_or(available[0])
 }
 
-fn choose_present_mode(available: &[vk::PresentModeKHR]) -> vk::PresentModeKHR {
-    if available.contains(&vk::PresentModeKHR::MAILBOX) {
-        vk::PresentModeKHR::MAILBOX
+/// Copying from the sRGB images rendered into needs `TRANSFER_DST` on swapchain
+/// images that aren't sRGB themselves.
+fn image_usage(format: vk::Format) -> vk::ImageUsageFlags {
+    if is_srgb(format) {
+        vk::ImageUsageFlags::COLOR_ATTACHMENT
+    } else {
+        vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST
+    }
+}
+
+/// Whether writes to `format` are encoded to sRGB by the hardware.
+pub fn is_srgb(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::B8G8R8A8_SRGB | vk::Format::R8G8B8A8_SRGB | vk::Format::A8B8G8R8_SRGB_PACK32
+    )
+}
+
+/// The sRGB format with the same memory layout as the `_UNORM` `format`, so
+/// images can be copied between them.
+pub fn srgb_variant(format: vk::Format) -> Option<vk::Format> {
+    match format {
+        vk::Format::B8G8R8A8_UNORM => Some(vk::Format::B8G8R8A8_SRGB),
+        vk::Format::R8G8B8A8_UNORM => Some(vk::Format::R8G8B8A8_SRGB),
+        vk::Format::A8B8G8R8_UNORM_PACK32 => Some(vk::Format::A8B8G8R8_SRGB_PACK32),
+        _ => None,
+    }
+}
+
+/// `requested` or one more than the minimum, within the limits of the surface.
+/// A `max_image_count` of 0 means there is no maximum.
+fn choose_image_count(capabilities: &vk::SurfaceCapabilitiesKHR, requested: Option<u32>) -> u32 {
+    let min = capabilities.min_image_count;
+    let max = match capabilities.max_image_count {
+        0 => u32::MAX,
+        max => max,
+    };
+    let count = requested.unwrap_or(min + 1).clamp(min, max);
+    if let Some(requested) = requested
+        && requested != count
+    {
+        log::warn!("Swapchain image count {requested} is outside of {min}..={max}, using {count}");
+    }
+    count
+}
+
+/// `preferred` if the surface supports it, otherwise FIFO which every surface
+/// supports.
+fn choose_present_mode(
+    available: &[vk::PresentModeKHR],
+    preferred: PresentMode,
+) -> vk::PresentModeKHR {
+    let preferred = match preferred {
+        PresentMode::Mailbox => vk::PresentModeKHR::MAILBOX,
+        PresentMode::Fifo => vk::PresentModeKHR::FIFO,
+        PresentMode::Immediate => vk::PresentModeKHR::IMMEDIATE,
+    };
+    if available.contains(&preferred) {
+        preferred
     } else {
+        log::info!("Present mode {preferred:?} is not supported, using FIFO");
         vk::PresentModeKHR::FIFO
     }
 }
@@ -22,6 +22,33 @@ impl Texture {
         tex
     }
 
+    /// A texture array with one layer per image, viewed as an array even if
+    /// there is only one. All images must have the same size.
+    pub fn from_layers(ctx: &VkContext, images: &[image::RgbaImage]) -> Self {
+        let (width, height) = images[0].dimensions();
+        let layers = images.len() as u32;
+        let mut tex = Self::create(
+            ctx,
+            width,
+            height,
+            layers,
+            vk::ImageViewType::TYPE_2D_ARRAY,
+            vk::Filter::NEAREST,
+            vk::Filter::NEAREST,
+        );
+
+        let rgba_data: Vec<u8> = images
+            .iter()
+            .flat_map(|image| {
+                debug_assert_eq!(image.dimensions(), (width, height));
+                image.as_raw()
+            })
+            .copied()
+            .collect();
+        tex.upload_layers_one_time(ctx, &rgba_data, width, height, layers);
+        tex
+    }
+
     pub fn from_egui_image(
         ctx: &VkContext,
         image: &egui::ColorImage,
@@ -56,6 +83,26 @@ impl Texture {
         height: u32,
         mag_filter: vk::Filter,
         min_filter: vk::Filter,
+    ) -> Self {
+        Self::create(
+            ctx,
+            width,
+            height,
+            1,
+            vk::ImageViewType::TYPE_2D,
+            mag_filter,
+            min_filter,
+        )
+    }
+
+    fn create(
+        ctx: &VkContext,
+        width: u32,
+        height: u32,
+        layers: u32,
+        view_type: vk::ImageViewType,
+        mag_filter: vk::Filter,
+        min_filter: vk::Filter,
     ) -> Self {
         let allocator = ctx.allocator();
         let extent = vk::Extent3D {
@@ -69,7 +116,7 @@ impl Texture {
             .format(vk::Format::R8G8B8A8_SRGB)
             .extent(extent)
             .mip_levels(1)
-            .array_layers(1)
+            .array_layers(layers)
             .samples(vk::SampleCountFlags::TYPE_1)
             .tiling(vk::ImageTiling::OPTIMAL)
             .usage(vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED)
@@ -87,31 +134,25 @@ impl Texture {
                 )
                 .expect("create image")
         };
+        ctx.track_object(image);
 
         let subresource = vk::ImageSubresourceRange {
             aspect_mask: vk::ImageAspectFlags::COLOR,
             base_mip_level: 0,
             level_count: 1,
             base_array_layer: 0,
-            layer_count: 1,
+            layer_count: layers,
         };
 
         let view_info = vk::ImageViewCreateInfo::default()
             .image(image)
-            .view_type(vk::ImageViewType::TYPE_2D)
+            .view_type(view_type)
             .format(vk::Format::R8G8B8A8_SRGB)
             .subresource_range(subresource);
 
         let view = unsafe { ctx.device().create_image_view(&view_info, None).unwrap() };
 
-        let sampler_info = vk::SamplerCreateInfo::default()
-            .mag_filter(mag_filter)
-            .min_filter(min_filter)
-            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
-            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
-            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
-
-        let sampler = unsafe { ctx.device().create_sampler(&sampler_info, None).unwrap() };
+        let sampler = Self::create_sampler(ctx, mag_filter, min_filter, 1.0);
 
         Self {
             image,
@@ -121,12 +162,68 @@ impl Texture {
         }
     }
 
+    /// `anisotropy` above 1 needs the `samplerAnisotropy` feature and must not
+    /// be above [`super::context::DeviceFeatures::max_anisotropy`].
+    fn create_sampler(
+        ctx: &VkContext,
+        mag_filter: vk::Filter,
+        min_filter: vk::Filter,
+        anisotropy: f32,
+    ) -> vk::Sampler {
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(mag_filter)
+            .min_filter(min_filter)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .anisotropy_enable(anisotropy > 1.0)
+            .max_anisotropy(anisotropy);
+
+        unsafe { ctx.device().create_sampler(&sampler_info, None).unwrap() }
+    }
+
+    /// Replaces the sampler with one using `filter`, the image is left
+    /// untouched. Returns the old sampler, frames in flight may still use it
+    /// so it should go through the deletion queue, and descriptors referencing
+    /// it have to be rewritten.
+    #[must_use]
+    pub fn set_filter(&mut self, ctx: &VkContext, filter: vk::Filter) -> vk::Sampler {
+        self.set_sampler(ctx, filter, 1.0)
+    }
+
+    /// Like [`Self::set_filter`], also sampling with `anisotropy`, see
+    /// [`Self::create_sampler`].
+    #[must_use]
+    pub fn set_sampler(
+        &mut self,
+        ctx: &VkContext,
+        filter: vk::Filter,
+        anisotropy: f32,
+    ) -> vk::Sampler {
+        std::mem::replace(
+            &mut self.sampler,
+            Self::create_sampler(ctx, filter, filter, anisotropy),
+        )
+    }
+
     pub fn upload_data_one_time(
         &mut self,
         ctx: &VkContext,
         rgba_data: &[u8],
         width: u32,
         height: u32,
+    ) {
+        self.upload_layers_one_time(ctx, rgba_data, width, height, 1);
+    }
+
+    /// Uploads `layers` tightly packed images, starting at the first layer.
+    fn upload_layers_one_time(
+        &mut self,
+        ctx: &VkContext,
+        rgba_data: &[u8],
+        width: u32,
+        height: u32,
+        layers: u32,
     ) {
         let allocator = ctx.allocator();
         let image_size = rgba_data.len() as vk::DeviceSize;
@@ -136,7 +233,15 @@ impl Texture {
 
         let cmd = ctx.begin_one_time_commands();
 
-        Self::record_image_upload(ctx.device(), cmd, &staging_buf, self.image, width, height);
+        Self::record_image_upload(
+            ctx.device(),
+            cmd,
+            &staging_buf,
+            self.image,
+            width,
+            height,
+            layers,
+        );
 
         ctx.end_one_time_commands(cmd);
 
@@ -153,6 +258,7 @@ impl Texture {
             self.image,
             width,
             height,
+            1,
         );
 
         frame.delete(staging_buf);
@@ -165,13 +271,14 @@ impl Texture {
         image: vk::Image,
         width: u32,
         height: u32,
+        layers: u32,
     ) {
         let subresource_range = vk::ImageSubresourceRange {
             aspect_mask: vk::ImageAspectFlags::COLOR,
             base_mip_level: 0,
             level_count: 1,
             base_array_layer: 0,
-            layer_count: 1,
+            layer_count: layers,
         };
 
         let copy_region = vk::BufferImageCopy::default()
@@ -180,7 +287,7 @@ impl Texture {
                 aspect_mask: vk::ImageAspectFlags::COLOR,
                 mip_level: 0,
                 base_array_layer: 0,
-                layer_count: 1,
+                layer_count: layers,
             })
             .image_extent(vk::Extent3D {
                 width,
@@ -236,6 +343,7 @@ impl Texture {
     }
 
     pub fn destroy(&mut self, ctx: &VkContext) {
+        ctx.untrack_object(self.image);
         unsafe {
             ctx.device().destroy_sampler(self.sampler, None);
             ctx.device().destroy_image_view(self.view, None);
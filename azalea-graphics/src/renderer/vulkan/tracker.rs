@@ -0,0 +1,55 @@
+//! Bookkeeping of live buffers, images, pipelines and descriptor pools in
+//! debug builds, so the ones that are never destroyed show up when the
+//! renderer shuts down.
+
+use std::collections::HashMap;
+
+use ash::vk::{self, Handle};
+use parking_lot::Mutex;
+
+#[derive(Default)]
+pub struct ObjectTracker {
+    /// The debug name of each live object, if it was given one with
+    /// `VkContext::label_object`.
+    live: Mutex<HashMap<(vk::ObjectType, u64), Option<String>>>,
+}
+
+impl ObjectTracker {
+    pub fn created<H: Handle>(&self, handle: H) {
+        self.live.lock().insert((H::TYPE, handle.as_raw()), None);
+    }
+
+    pub fn destroyed<H: Handle>(&self, handle: H) {
+        self.live.lock().remove(&(H::TYPE, handle.as_raw()));
+    }
+
+    /// Names `handle` if it is tracked.
+    pub fn set_name<H: Handle>(&self, handle: H, name: &str) {
+        if let Some(entry) = self.live.lock().get_mut(&(H::TYPE, handle.as_raw())) {
+            *entry = Some(name.to_owned());
+        }
+    }
+
+    /// Logs every object that is still alive, grouped by type.
+    pub fn report_leaks(&self) {
+        let live = self.live.lock();
+        if live.is_empty() {
+            log::debug!("All tracked Vulkan objects were destroyed");
+            return;
+        }
+
+        let mut by_type: HashMap<vk::ObjectType, Vec<(u64, &Option<String>)>> = HashMap::new();
+        for ((ty, raw), name) in live.iter() {
+            by_type.entry(*ty).or_default().push((*raw, name));
+        }
+        for (ty, objects) in by_type {
+            log::warn!("{} {ty:?} objects were never destroyed:", objects.len());
+            for (raw, name) in objects {
+                match name {
+                    Some(name) => log::warn!("  {raw:#x} \"{name}\""),
+                    None => log::warn!("  {raw:#x} (unnamed)"),
+                }
+            }
+        }
+    }
+}
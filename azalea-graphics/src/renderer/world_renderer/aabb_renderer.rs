@@ -1,26 +1,116 @@
-use std::ffi::CString;
+use std::{ffi::CString, mem::offset_of};
 
 use ash::{Device, vk};
+use glam::IVec3;
+use vk_mem::MemoryUsage;
 
 use crate::renderer::{
-    vulkan::{buffer::Buffer, context::VkContext, frame_sync::MAX_FRAMES_IN_FLIGHT},
-    world_renderer::types::VisibilityUniform,
+    frame_ctx::FrameCtx,
+    render_targets::DEPTH_COMPARE_OP,
+    vulkan::{buffer::Buffer, context::VkContext},
+    world_renderer::types::{BlockHighlight, DebugBox, VisibilityUniform},
 };
 
+/// Vertices for the 12 edges of a box drawn as a line list.
+const LINE_VERTICES: u32 = 24;
+/// Vertices for the 12 edges of a box expanded into quads.
+const QUAD_VERTICES: u32 = 72;
+/// Chunks of the gizmo grid drawn in each direction around the camera.
+const GIZMO_GRID_RADIUS: i32 = 8;
+/// The 3 axes followed by the chunk borders of the grid along x and z.
+const GIZMO_VERTICES: u32 = 6 + 2 * 2 * (GIZMO_GRID_RADIUS as u32 * 2 + 2);
+/// Corner lines of the camera's chunk and its neighbours, then the lines every
+/// 2 blocks along the edges of the camera's chunk. The rings around it follow.
+const CHUNK_BORDER_VERTICES: u32 = 4 * 4 * 2 + 4 * 8 * 2;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct LinePushConstants {
+    pub viewport: [f32; 2],
+    pub line_width: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct BoxPushConstants {
+    pub min: [f32; 4],
+    pub max: [f32; 4],
+    pub color: [f32; 4],
+}
+
+/// Vertex attributes of one highlighted block, fed per instance.
+fn highlight_input() -> (
+    vk::VertexInputBindingDescription,
+    [vk::VertexInputAttributeDescription; 2],
+) {
+    let binding = vk::VertexInputBindingDescription::default()
+        .binding(0)
+        .stride(size_of::<BlockHighlight>() as u32)
+        .input_rate(vk::VertexInputRate::INSTANCE);
+    let attributes = [
+        vk::VertexInputAttributeDescription {
+            binding: 0,
+            location: 0,
+            format: vk::Format::R32G32B32A32_SFLOAT,
+            offset: offset_of!(BlockHighlight, pos) as u32,
+        },
+        vk::VertexInputAttributeDescription {
+            binding: 0,
+            location: 1,
+            format: vk::Format::R32G32B32A32_SFLOAT,
+            offset: offset_of!(BlockHighlight, color) as u32,
+        },
+    ];
+    (binding, attributes)
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct GizmoPushConstants {
+    pub origin: [f32; 4],
+    pub grid_center: [i32; 2],
+    pub grid_radius: i32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ChunkBorderPushConstants {
+    pub chunk: [i32; 2],
+    pub min_y: i32,
+    pub max_y: i32,
+}
+
 pub struct AabbRenderer {
     pub pipeline_layout: vk::PipelineLayout,
-    pub pipeline: vk::Pipeline,
+    /// Line list pipelines, indexed by whether they are depth tested.
+    pub line_pipelines: [vk::Pipeline; 2],
+    /// Screen space quad pipelines for lines wider than the device rasterizes,
+    /// indexed by whether they are depth tested.
+    pub quad_pipelines: [vk::Pipeline; 2],
+    pub box_pipeline_layout: vk::PipelineLayout,
+    /// Single box pipelines, indexed by whether they are depth tested.
+    pub box_pipelines: [vk::Pipeline; 2],
+    /// Axes and chunk grid, shares the box pipeline layout since its push
+    /// constants are smaller.
+    pub gizmo_pipeline: vk::Pipeline,
+    /// Depth tested chunk border lines, also on the box pipeline layout.
+    pub chunk_border_pipeline: vk::Pipeline,
+    /// Outlines of highlighted blocks, one instance per block, also on the box
+    /// pipeline layout.
+    pub highlight_pipeline: vk::Pipeline,
     pub descriptor_set_layout: vk::DescriptorSetLayout,
     pub descriptor_pool: vk::DescriptorPool,
-    pub descriptor_sets: [vk::DescriptorSet; MAX_FRAMES_IN_FLIGHT],
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+    max_line_width: f32,
 }
 
 impl AabbRenderer {
     pub fn new(
         ctx: &VkContext,
-        uniform_buffers: &[Buffer; MAX_FRAMES_IN_FLIGHT],
+        uniform_buffers: &[Buffer],
         module: vk::ShaderModule,
         render_pass: vk::RenderPass,
+        world_set_layout: vk::DescriptorSetLayout,
     ) -> Self {
         let device = ctx.device();
 
@@ -44,8 +134,14 @@ impl AabbRenderer {
                 .unwrap()
         };
 
+        let push_constant_range = vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(size_of::<LinePushConstants>() as u32);
+
         let pipeline_layout_info = vk::PipelineLayoutCreateInfo::default()
-            .set_layouts(std::slice::from_ref(&descriptor_set_layout));
+            .set_layouts(std::slice::from_ref(&descriptor_set_layout))
+            .push_constant_ranges(std::slice::from_ref(&push_constant_range));
 
         let pipeline_layout = unsafe {
             device
@@ -53,26 +149,38 @@ impl AabbRenderer {
                 .unwrap()
         };
 
-        let pipeline = Self::create_pipeline(ctx, module, render_pass, pipeline_layout);
-
+        // single boxes only need the camera, so they use the world set and push
+        // their bounds directly
+        let box_pipeline_layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::default()
+                        .set_layouts(&[world_set_layout])
+                        .push_constant_ranges(&[vk::PushConstantRange {
+                            stage_flags: vk::ShaderStageFlags::VERTEX,
+                            offset: 0,
+                            size: size_of::<BoxPushConstants>() as u32,
+                        }]),
+                    None,
+                )
+                .unwrap()
+        };
         let pool_size = vk::DescriptorPoolSize::default()
             .ty(vk::DescriptorType::STORAGE_BUFFER)
-            .descriptor_count(MAX_FRAMES_IN_FLIGHT as u32);
+            .descriptor_count(uniform_buffers.len() as u32);
         let pool_info = vk::DescriptorPoolCreateInfo::default()
             .pool_sizes(std::slice::from_ref(&pool_size))
-            .max_sets(MAX_FRAMES_IN_FLIGHT as u32);
+            .max_sets(uniform_buffers.len() as u32);
         let descriptor_pool = unsafe { device.create_descriptor_pool(&pool_info, None).unwrap() };
-        let layouts = vec![descriptor_set_layout; MAX_FRAMES_IN_FLIGHT];
+        ctx.track_object(descriptor_pool);
+        let layouts = vec![descriptor_set_layout; uniform_buffers.len()];
         let alloc_info = vk::DescriptorSetAllocateInfo::default()
             .descriptor_pool(descriptor_pool)
             .set_layouts(&layouts);
 
-        let descriptor_sets: [_; MAX_FRAMES_IN_FLIGHT] =
-            unsafe { device.allocate_descriptor_sets(&alloc_info).unwrap() }
-                .try_into()
-                .expect("size should match frames in flight");
+        let descriptor_sets = unsafe { device.allocate_descriptor_sets(&alloc_info).unwrap() };
 
-        for i in 0..MAX_FRAMES_IN_FLIGHT {
+        for i in 0..uniform_buffers.len() {
             unsafe {
                 device.update_descriptor_sets(
                     &[vk::WriteDescriptorSet::default()
@@ -87,12 +195,124 @@ impl AabbRenderer {
             }
         }
 
-        Self {
+        let mut renderer = Self {
             pipeline_layout,
-            pipeline,
+            line_pipelines: [vk::Pipeline::null(); 2],
+            quad_pipelines: [vk::Pipeline::null(); 2],
+            box_pipeline_layout,
+            box_pipelines: [vk::Pipeline::null(); 2],
+            gizmo_pipeline: vk::Pipeline::null(),
+            chunk_border_pipeline: vk::Pipeline::null(),
+            highlight_pipeline: vk::Pipeline::null(),
             descriptor_set_layout,
             descriptor_pool,
             descriptor_sets,
+            max_line_width: ctx.features().max_line_width,
+        };
+        renderer.create_pipelines(ctx, module, render_pass);
+        renderer
+    }
+
+    /// Rebuilds the pipelines from a new shader module, the device must be
+    /// idle.
+    pub fn reload_shaders(
+        &mut self,
+        ctx: &VkContext,
+        module: vk::ShaderModule,
+        render_pass: vk::RenderPass,
+    ) {
+        self.destroy_pipelines(ctx);
+        self.create_pipelines(ctx, module, render_pass);
+    }
+
+    fn create_pipelines(
+        &mut self,
+        ctx: &VkContext,
+        module: vk::ShaderModule,
+        render_pass: vk::RenderPass,
+    ) {
+        self.line_pipelines = [false, true].map(|depth_test| {
+            Self::create_pipeline(
+                ctx,
+                module,
+                render_pass,
+                self.pipeline_layout,
+                ("debug::aabb_vert", "debug::aabb_frag"),
+                vk::PrimitiveTopology::LINE_LIST,
+                depth_test,
+                false,
+            )
+        });
+        self.quad_pipelines = [false, true].map(|depth_test| {
+            Self::create_pipeline(
+                ctx,
+                module,
+                render_pass,
+                self.pipeline_layout,
+                ("debug::aabb_quad_vert", "debug::aabb_quad_frag"),
+                vk::PrimitiveTopology::TRIANGLE_LIST,
+                depth_test,
+                false,
+            )
+        });
+        self.box_pipelines = [false, true].map(|depth_test| {
+            Self::create_pipeline(
+                ctx,
+                module,
+                render_pass,
+                self.box_pipeline_layout,
+                ("debug::box_vert", "debug::aabb_frag"),
+                vk::PrimitiveTopology::LINE_LIST,
+                depth_test,
+                false,
+            )
+        });
+        self.gizmo_pipeline = Self::create_pipeline(
+            ctx,
+            module,
+            render_pass,
+            self.box_pipeline_layout,
+            ("debug::gizmo_vert", "debug::aabb_frag"),
+            vk::PrimitiveTopology::LINE_LIST,
+            false,
+            false,
+        );
+        self.chunk_border_pipeline = Self::create_pipeline(
+            ctx,
+            module,
+            render_pass,
+            self.box_pipeline_layout,
+            ("debug::chunk_border_vert", "debug::aabb_frag"),
+            vk::PrimitiveTopology::LINE_LIST,
+            true,
+            false,
+        );
+        self.highlight_pipeline = Self::create_pipeline(
+            ctx,
+            module,
+            render_pass,
+            self.box_pipeline_layout,
+            ("debug::highlight_vert", "debug::aabb_frag"),
+            vk::PrimitiveTopology::LINE_LIST,
+            false,
+            true,
+        );
+    }
+
+    fn destroy_pipelines(&self, ctx: &VkContext) {
+        for pipeline in self
+            .line_pipelines
+            .into_iter()
+            .chain(self.quad_pipelines)
+            .chain(self.box_pipelines)
+            .chain([
+                self.gizmo_pipeline,
+                self.chunk_border_pipeline,
+                self.highlight_pipeline,
+            ])
+        {
+            ctx.untrack_object(pipeline);
+            unsafe { ctx.device().destroy_pipeline(pipeline, None) };
         }
     }
 
@@ -101,11 +321,15 @@ impl AabbRenderer {
         module: vk::ShaderModule,
         render_pass: vk::RenderPass,
         pipeline_layout: vk::PipelineLayout,
+        (vert_entry, frag_entry): (&str, &str),
+        topology: vk::PrimitiveTopology,
+        depth_test: bool,
+        highlights: bool,
     ) -> vk::Pipeline {
         let device = ctx.device();
 
-        let vert_entry = CString::new("debug::aabb_vert").unwrap();
-        let frag_entry = CString::new("debug::aabb_frag").unwrap();
+        let vert_entry = CString::new(vert_entry).unwrap();
+        let frag_entry = CString::new(frag_entry).unwrap();
         let stages = [
             vk::PipelineShaderStageCreateInfo::default()
                 .stage(vk::ShaderStageFlags::VERTEX)
@@ -117,18 +341,26 @@ impl AabbRenderer {
                 .name(&frag_entry),
         ];
 
-        // No vertex input (geometry generated in shader)
-        let vertex_input = vk::PipelineVertexInputStateCreateInfo::default();
+        // geometry is generated in the shader, only highlights read their
+        // blocks from a vertex buffer
+        let (binding, attributes) = highlight_input();
+        let vertex_input = if highlights {
+            vk::PipelineVertexInputStateCreateInfo::default()
+                .vertex_binding_descriptions(std::slice::from_ref(&binding))
+                .vertex_attribute_descriptions(&attributes)
+        } else {
+            vk::PipelineVertexInputStateCreateInfo::default()
+        };
 
-        let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default()
-            .topology(vk::PrimitiveTopology::LINE_LIST);
+        let input_assembly =
+            vk::PipelineInputAssemblyStateCreateInfo::default().topology(topology);
 
         let viewport_state = vk::PipelineViewportStateCreateInfo::default()
             .viewport_count(1)
             .scissor_count(1);
 
         let rasterizer = vk::PipelineRasterizationStateCreateInfo::default()
-            .polygon_mode(vk::PolygonMode::LINE)
+            .polygon_mode(vk::PolygonMode::FILL)
             .cull_mode(vk::CullModeFlags::NONE)
             .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
             .line_width(1.0);
@@ -143,17 +375,27 @@ impl AabbRenderer {
                     | vk::ColorComponentFlags::B
                     | vk::ColorComponentFlags::A,
             )
-            .blend_enable(false);
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ONE)
+            .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+            .alpha_blend_op(vk::BlendOp::ADD);
 
         let color_blending = vk::PipelineColorBlendStateCreateInfo::default()
             .attachments(std::slice::from_ref(&color_blend_attachment));
 
         let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
-            .depth_test_enable(false)
+            .depth_test_enable(depth_test)
             .depth_write_enable(false)
-            .depth_compare_op(vk::CompareOp::GREATER_OR_EQUAL);
+            .depth_compare_op(DEPTH_COMPARE_OP);
 
-        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_states = [
+            vk::DynamicState::VIEWPORT,
+            vk::DynamicState::SCISSOR,
+            vk::DynamicState::LINE_WIDTH,
+        ];
         let dynamic_state =
             vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
 
@@ -180,15 +422,12 @@ impl AabbRenderer {
                 )
                 .unwrap()[0]
         };
+        ctx.track_object(pipeline);
 
         pipeline
     }
 
-    pub fn recreate_descriptor_sets(
-        &mut self,
-        device: &Device,
-        visibility_buffers: &[Buffer; MAX_FRAMES_IN_FLIGHT],
-    ) {
+    pub fn recreate_descriptor_sets(&mut self, device: &Device, visibility_buffers: &[Buffer]) {
         for (i, buffer) in visibility_buffers.iter().enumerate() {
             let buffer_info = vk::DescriptorBufferInfo::default()
                 .buffer(buffer.buffer)
@@ -207,33 +446,257 @@ impl AabbRenderer {
         }
     }
 
-    pub fn draw(
+    /// Line width that the line pipelines can rasterize directly.
+    fn supported_line_width(&self, line_width: f32) -> f32 {
+        line_width.min(self.max_line_width)
+    }
+
+    pub fn draw(&self, frame_ctx: &FrameCtx, instance_count: u32) {
+        let device = frame_ctx.ctx.device();
+        let cmd = frame_ctx.cmd;
+        let config = &frame_ctx.config;
+        let extent = frame_ctx.render_targets.extent();
+
+        let depth_test = config.aabb_depth_test as usize;
+        let use_quads = config.aabb_line_width > self.max_line_width;
+        let (pipeline, vertex_count) = if use_quads {
+            (self.quad_pipelines[depth_test], QUAD_VERTICES)
+        } else {
+            (self.line_pipelines[depth_test], LINE_VERTICES)
+        };
+
+        let push_constants = LinePushConstants {
+            viewport: [extent.width as f32, extent.height as f32],
+            line_width: config.aabb_line_width,
+        };
+
+        unsafe {
+            device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, pipeline);
+
+            device.cmd_bind_descriptor_sets(
+                cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                std::slice::from_ref(&self.descriptor_sets[frame_ctx.frame_index]),
+                &[],
+            );
+            device.cmd_push_constants(
+                cmd,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                0,
+                std::slice::from_raw_parts(
+                    &push_constants as *const _ as *const u8,
+                    std::mem::size_of::<LinePushConstants>(),
+                ),
+            );
+            device.cmd_set_line_width(cmd, self.supported_line_width(config.aabb_line_width));
+
+            device.cmd_draw(cmd, vertex_count, instance_count, 0, 0);
+        }
+    }
+
+    /// Draws the edges of a single box, must be called inside the world render
+    /// pass. Depth tested boxes are hidden behind terrain.
+    pub fn draw_box(
         &self,
-        device: &Device,
-        cmd: vk::CommandBuffer,
-        instance_count: u32,
-        buffer_index: usize,
+        frame_ctx: &FrameCtx,
+        world_set: vk::DescriptorSet,
+        debug_box: &DebugBox,
+        depth_test: bool,
     ) {
+        let device = frame_ctx.ctx.device();
+        let cmd = frame_ctx.cmd;
+
+        let push_constants = BoxPushConstants {
+            min: debug_box.min.extend(1.0).to_array(),
+            max: debug_box.max.extend(1.0).to_array(),
+            color: debug_box.color,
+        };
+
         unsafe {
-            device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, self.pipeline);
+            device.cmd_bind_pipeline(
+                cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.box_pipelines[depth_test as usize],
+            );
+            device.cmd_set_line_width(
+                cmd,
+                self.supported_line_width(frame_ctx.config.aabb_line_width),
+            );
+            device.cmd_bind_descriptor_sets(
+                cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.box_pipeline_layout,
+                0,
+                &[world_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                cmd,
+                self.box_pipeline_layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                std::slice::from_raw_parts(
+                    &push_constants as *const _ as *const u8,
+                    std::mem::size_of::<BoxPushConstants>(),
+                ),
+            );
+            device.cmd_draw(cmd, LINE_VERTICES, 1, 0, 0);
+        }
+    }
+
+    /// Draws colored axes at `origin` and the chunk borders around the camera
+    /// on its ground plane, must be called inside the world render pass.
+    pub fn draw_gizmo(&self, frame_ctx: &FrameCtx, world_set: vk::DescriptorSet, origin: IVec3) {
+        let device = frame_ctx.ctx.device();
+        let cmd = frame_ctx.cmd;
+
+        let camera_chunk = (frame_ctx.camera_pos / 16.0).floor().as_ivec3();
+        let push_constants = GizmoPushConstants {
+            origin: origin.as_vec3().extend(1.0).to_array(),
+            grid_center: [camera_chunk.x, camera_chunk.z],
+            grid_radius: GIZMO_GRID_RADIUS,
+        };
 
+        unsafe {
+            device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, self.gizmo_pipeline);
+            device.cmd_set_line_width(
+                cmd,
+                self.supported_line_width(frame_ctx.config.aabb_line_width),
+            );
             device.cmd_bind_descriptor_sets(
                 cmd,
                 vk::PipelineBindPoint::GRAPHICS,
-                self.pipeline_layout,
+                self.box_pipeline_layout,
+                0,
+                &[world_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                cmd,
+                self.box_pipeline_layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                std::slice::from_raw_parts(
+                    &push_constants as *const _ as *const u8,
+                    std::mem::size_of::<GizmoPushConstants>(),
+                ),
+            );
+            device.cmd_draw(cmd, GIZMO_VERTICES, 1, 0, 0);
+        }
+    }
+
+    /// Draws vanilla's chunk border lines around the camera's chunk from
+    /// `min_y` to `max_y`, must be called inside the world render pass.
+    pub fn draw_chunk_borders(
+        &self,
+        frame_ctx: &FrameCtx,
+        world_set: vk::DescriptorSet,
+        min_y: i32,
+        max_y: i32,
+    ) {
+        let device = frame_ctx.ctx.device();
+        let cmd = frame_ctx.cmd;
+
+        let camera_chunk = (frame_ctx.camera_pos / 16.0).floor().as_ivec3();
+        let push_constants = ChunkBorderPushConstants {
+            chunk: [camera_chunk.x, camera_chunk.z],
+            min_y,
+            max_y,
+        };
+        // one ring of 4 lines every 2 blocks, including the top
+        let rings = ((max_y - min_y) / 2 + 1).max(0) as u32;
+
+        unsafe {
+            device.cmd_bind_pipeline(
+                cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.chunk_border_pipeline,
+            );
+            device.cmd_set_line_width(
+                cmd,
+                self.supported_line_width(frame_ctx.config.aabb_line_width),
+            );
+            device.cmd_bind_descriptor_sets(
+                cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.box_pipeline_layout,
                 0,
-                std::slice::from_ref(&self.descriptor_sets[buffer_index]),
+                &[world_set],
                 &[],
             );
+            device.cmd_push_constants(
+                cmd,
+                self.box_pipeline_layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                std::slice::from_raw_parts(
+                    &push_constants as *const _ as *const u8,
+                    std::mem::size_of::<ChunkBorderPushConstants>(),
+                ),
+            );
+            device.cmd_draw(cmd, CHUNK_BORDER_VERTICES + rings * 8, 1, 0, 0);
+        }
+    }
+
+    /// Outlines every block in `highlights` with one instanced draw, must be
+    /// called inside the world render pass. Highlights show through terrain.
+    pub fn draw_highlights(
+        &self,
+        frame_ctx: &mut FrameCtx,
+        world_set: vk::DescriptorSet,
+        highlights: &[BlockHighlight],
+    ) {
+        if highlights.is_empty() {
+            return;
+        }
+        let ctx = frame_ctx.ctx;
+        let device = ctx.device();
+        let cmd = frame_ctx.cmd;
 
-            device.cmd_draw(cmd, 24, instance_count, 0, 0);
+        let mut buffer = Buffer::new(
+            ctx,
+            size_of_val(highlights) as vk::DeviceSize,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            MemoryUsage::AutoPreferHost,
+            true,
+        );
+        buffer.upload_data(ctx, 0, highlights);
+
+        unsafe {
+            device.cmd_bind_pipeline(
+                cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.highlight_pipeline,
+            );
+            device.cmd_set_line_width(
+                cmd,
+                self.supported_line_width(frame_ctx.config.aabb_line_width),
+            );
+            device.cmd_bind_descriptor_sets(
+                cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.box_pipeline_layout,
+                0,
+                &[world_set],
+                &[],
+            );
+            device.cmd_bind_vertex_buffers(cmd, 0, &[buffer.buffer], &[0]);
+            device.cmd_draw(cmd, LINE_VERTICES, highlights.len() as u32, 0, 0);
         }
+        // the buffer is rebuilt every frame, it lives until the frame is done
+        frame_ctx.delete(buffer);
     }
 
-    pub fn destroy(&mut self, device: &Device) {
+    pub fn destroy(&mut self, ctx: &VkContext) {
+        self.destroy_pipelines(ctx);
+        let device = ctx.device();
+        ctx.untrack_object(self.descriptor_pool);
         unsafe {
-            device.destroy_pipeline(self.pipeline, None);
             device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_pipeline_layout(self.box_pipeline_layout, None);
             device.destroy_descriptor_pool(self.descriptor_pool, None);
             device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
         }
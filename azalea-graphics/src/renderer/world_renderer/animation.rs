@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use azalea_assets::processed::{
     animation::Animation,
-    atlas::{Atlas, TextureEntry},
+    atlas::{Atlas, SpriteLayers, TextureEntry},
 };
 use image::{GenericImageView, RgbaImage};
 
@@ -37,18 +37,46 @@ impl AnimationManager {
         Self { block_states }
     }
 
+    /// Collects the sprites that need to be re-uploaded this frame.
+    /// Interpolated sprites are blended by `partial_tick` and stay dirty, since
+    /// their contents change every frame.
     pub fn dirty_textures<'a>(
         &'a mut self,
         textures: &'a HashMap<String, TextureEntry>,
-    ) -> Vec<(&'a str, &'a TextureEntry, (u32, u32), usize)> {
+        partial_tick: f32,
+    ) -> Vec<DirtyFrame<'a>> {
         let mut result = Vec::new();
 
         for (name, state) in self.block_states.iter_mut() {
-            if state.dirty {
-                if let Some(texture) = textures.get(name) {
-                    let size = texture.size();
-                    result.push((name.as_str(), texture, size, state.current_frame));
-                }
+            if !state.dirty {
+                continue;
+            }
+            let Some(texture) = textures.get(name) else {
+                continue;
+            };
+            let interpolate = texture
+                .animation
+                .as_ref()
+                .is_some_and(|animation| animation.interpolate());
+
+            let blend = if interpolate && state.next_frame != state.current_frame {
+                ((state.ticks_elapsed as f32 + partial_tick) / state.frame_time as f32)
+                    .clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+
+            result.push(DirtyFrame {
+                name: name.as_str(),
+                texture,
+                size: texture.size(),
+                frame: state.current_frame,
+                next_frame: state.next_frame,
+                blend,
+            });
+
+            if !interpolate {
+                state.clear_dirty();
             }
         }
 
@@ -66,6 +94,44 @@ impl AnimationManager {
     }
 }
 
+pub struct DirtyFrame<'a> {
+    pub name: &'a str,
+    pub texture: &'a TextureEntry,
+    pub size: (u32, u32),
+    pub frame: usize,
+    pub next_frame: usize,
+    /// How far to blend from `frame` towards `next_frame`, in `0.0..=1.0`.
+    pub blend: f32,
+}
+
+impl DirtyFrame<'_> {
+    /// Extracts the (possibly blended) frame image.
+    pub fn image(&self) -> RgbaImage {
+        let animation = self.texture.animation.as_ref().unwrap();
+        let image_size = self.texture.data.dimensions();
+        let (fw, fh) = self.size;
+
+        let (fx, fy) = animation.get_frame(self.frame, image_size);
+        let mut frame = self.texture.data.view(fx, fy, fw, fh).to_image();
+
+        if self.blend > 0.0 {
+            let (nx, ny) = animation.get_frame(self.next_frame, image_size);
+            let next = self.texture.data.view(nx, ny, fw, fh);
+
+            for (x, y, px) in frame.enumerate_pixels_mut() {
+                let other = next.get_pixel(x, y);
+                for c in 0..4 {
+                    let a = px[c] as f32;
+                    let b = other[c] as f32;
+                    px[c] = (a + (b - a) * self.blend).round() as u8;
+                }
+            }
+        }
+
+        frame
+    }
+}
+
 pub struct BlockAnimationState {
     pub current_frame: usize,
     pub next_frame: usize,
@@ -114,3 +180,21 @@ pub fn create_initial_atlas(atlas: &Atlas, textures: &HashMap<String, TextureEnt
 
     atlas_img
 }
+
+/// The first frame of every sprite, one image per texture array layer.
+pub fn create_initial_layers(
+    layers: &SpriteLayers,
+    textures: &HashMap<String, TextureEntry>,
+) -> Vec<RgbaImage> {
+    let mut images = vec![RgbaImage::new(layers.width, layers.height); layers.len() as usize];
+
+    for (name, &layer) in &layers.layers {
+        if let Some(tex) = textures.get(name) {
+            let (fw, fh) = tex.size();
+            let first_frame = tex.data.view(0, 0, fw, fh).to_image();
+            images[layer as usize] = layers.fit_frame(first_frame);
+        }
+    }
+
+    images
+}
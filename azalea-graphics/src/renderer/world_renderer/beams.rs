@@ -0,0 +1,229 @@
+use std::ffi::CString;
+
+use ash::vk;
+
+use crate::renderer::{
+    frame_ctx::FrameCtx, render_targets::DEPTH_COMPARE_OP, vulkan::context::VkContext,
+    world_renderer::types::BeaconBeam,
+};
+
+/// Vertices for the four sides of a beam segment.
+const SIDE_VERTICES: u32 = 24;
+/// Height of the last segment of a beam, like vanilla it ends far above any
+/// world.
+const TOP_SEGMENT_HEIGHT: f32 = 2048.0;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct BeamPushConstants {
+    pub base: [f32; 4],
+    pub color: [f32; 4],
+}
+
+pub struct BeamRenderer {
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+}
+
+impl BeamRenderer {
+    pub fn new(
+        ctx: &VkContext,
+        module: vk::ShaderModule,
+        render_pass: vk::RenderPass,
+        world_set_layout: vk::DescriptorSetLayout,
+    ) -> Self {
+        let pipeline_layout = unsafe {
+            ctx.device()
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::default()
+                        .set_layouts(&[world_set_layout])
+                        .push_constant_ranges(&[vk::PushConstantRange {
+                            stage_flags: vk::ShaderStageFlags::VERTEX,
+                            offset: 0,
+                            size: size_of::<BeamPushConstants>() as u32,
+                        }]),
+                    None,
+                )
+                .unwrap()
+        };
+        let pipeline = Self::create_pipeline(ctx, module, render_pass, pipeline_layout);
+
+        Self {
+            pipeline_layout,
+            pipeline,
+        }
+    }
+
+    /// Rebuilds the pipeline from a new shader module, the device must be idle.
+    pub fn reload_shaders(
+        &mut self,
+        ctx: &VkContext,
+        module: vk::ShaderModule,
+        render_pass: vk::RenderPass,
+    ) {
+        ctx.untrack_object(self.pipeline);
+        unsafe { ctx.device().destroy_pipeline(self.pipeline, None) };
+        self.pipeline = Self::create_pipeline(ctx, module, render_pass, self.pipeline_layout);
+    }
+
+    fn create_pipeline(
+        ctx: &VkContext,
+        module: vk::ShaderModule,
+        render_pass: vk::RenderPass,
+        pipeline_layout: vk::PipelineLayout,
+    ) -> vk::Pipeline {
+        let device = ctx.device();
+
+        let vert_entry = CString::new("beam::beam_vert").unwrap();
+        let frag_entry = CString::new("beam::beam_frag").unwrap();
+        let stages = [
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(module)
+                .name(&vert_entry),
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(module)
+                .name(&frag_entry),
+        ];
+
+        let vertex_input = vk::PipelineVertexInputStateCreateInfo::default();
+
+        let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+
+        // the sides are seen from inside the glow too
+        let rasterizer = vk::PipelineRasterizationStateCreateInfo::default()
+            .polygon_mode(vk::PolygonMode::FILL)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .line_width(1.0);
+
+        let multisampling = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+        // additive, so the order of overlapping beams doesn't matter
+        let color_blend_attachment = vk::PipelineColorBlendAttachmentState::default()
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+            .alpha_blend_op(vk::BlendOp::ADD);
+
+        let color_blending = vk::PipelineColorBlendStateCreateInfo::default()
+            .attachments(std::slice::from_ref(&color_blend_attachment));
+
+        // hidden by terrain but never written
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(true)
+            .depth_write_enable(false)
+            .depth_compare_op(DEPTH_COMPARE_OP);
+
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state =
+            vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&stages)
+            .vertex_input_state(&vertex_input)
+            .input_assembly_state(&input_assembly)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterizer)
+            .multisample_state(&multisampling)
+            .color_blend_state(&color_blending)
+            .depth_stencil_state(&depth_stencil)
+            .dynamic_state(&dynamic_state)
+            .layout(pipeline_layout)
+            .render_pass(render_pass)
+            .subpass(0);
+
+        let pipeline = unsafe {
+            device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    std::slice::from_ref(&pipeline_info),
+                    None,
+                )
+                .unwrap()[0]
+        };
+        ctx.track_object(pipeline);
+        pipeline
+    }
+
+    /// Draws the segments of the `beams` within the render distance, must be
+    /// called inside the world render pass.
+    pub fn draw(&self, frame_ctx: &FrameCtx, world_set: vk::DescriptorSet, beams: &[BeaconBeam]) {
+        let device = frame_ctx.ctx.device();
+        let cmd = frame_ctx.cmd;
+        let max_distance = frame_ctx.config.render_distance as f32 * 16.0;
+
+        unsafe {
+            device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, self.pipeline);
+            device.cmd_bind_descriptor_sets(
+                cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[world_set],
+                &[],
+            );
+        }
+
+        for beam in beams {
+            let center = beam.pos.as_vec3() + glam::Vec3::new(0.5, 0.0, 0.5);
+            let offset = center - frame_ctx.camera_pos;
+            if offset.x.abs() > max_distance || offset.z.abs() > max_distance {
+                continue;
+            }
+
+            let mut y = center.y;
+            for (i, segment) in beam.segments.iter().enumerate() {
+                let height = if i == beam.segments.len() - 1 {
+                    TOP_SEGMENT_HEIGHT
+                } else {
+                    segment.height as f32
+                };
+                let push_constants = BeamPushConstants {
+                    base: [center.x, y, center.z, height],
+                    color: [segment.color[0], segment.color[1], segment.color[2], 1.0],
+                };
+                y += height;
+
+                unsafe {
+                    device.cmd_push_constants(
+                        cmd,
+                        self.pipeline_layout,
+                        vk::ShaderStageFlags::VERTEX,
+                        0,
+                        std::slice::from_raw_parts(
+                            &push_constants as *const _ as *const u8,
+                            std::mem::size_of::<BeamPushConstants>(),
+                        ),
+                    );
+                    device.cmd_draw(cmd, SIDE_VERTICES, 2, 0, 0);
+                }
+            }
+        }
+    }
+
+    pub fn destroy(&mut self, ctx: &VkContext) {
+        let device = ctx.device();
+        ctx.untrack_object(self.pipeline);
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
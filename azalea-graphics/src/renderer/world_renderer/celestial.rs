@@ -0,0 +1,460 @@
+//! The sky gradient and the sun and moon on it, drawn before the terrain.
+
+use std::{f64::consts::PI, ffi::CString};
+
+use ash::vk;
+use azalea_assets::Assets;
+use glam::{Mat4, Vec3, Vec4};
+
+use crate::renderer::{
+    frame_ctx::FrameCtx,
+    vulkan::{context::VkContext, texture::Texture},
+    world_renderer::weather::load_texture,
+};
+
+/// Ticks in a minecraft day.
+const DAY_LENGTH: f64 = 24000.0;
+/// Half the side of the sun and moon quads 100 blocks away, like vanilla.
+const SUN_SIZE: f32 = 15.0;
+const MOON_SIZE: f32 = 10.0;
+/// Vertices for one quad.
+const QUAD_VERTICES: u32 = 6;
+/// Vanilla's sky and fog colors in the plains at noon, sRGB.
+const DAY_ZENITH: Vec3 = Vec3::new(120.0 / 255.0, 167.0 / 255.0, 1.0);
+const DAY_HORIZON: Vec3 = Vec3::new(192.0 / 255.0, 216.0 / 255.0, 1.0);
+/// Ambient light at midnight, blocks never go fully black.
+const NIGHT_AMBIENT: f32 = 0.2;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct CelestialPushConstants {
+    /// Direction from the camera to the body, w is half the side of its quad.
+    pub direction: [f32; 4],
+    /// Min and max uv of the sprite in its texture.
+    pub uv: [f32; 4],
+    /// Camera position, w is the alpha.
+    pub camera: [f32; 4],
+    /// 0 for the sun, 1 for the moon.
+    pub kind: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SkyPushConstants {
+    pub inv_view_proj: Mat4,
+}
+
+/// Colors of the sky and the light it gives at some time of day. The colors
+/// are sRGB like vanilla's and converted by the sky shader.
+#[derive(Clone, Copy, Debug)]
+pub struct SkyLight {
+    pub zenith: Vec3,
+    pub horizon: Vec3,
+    /// Glow at the horizon towards the sun at dawn and dusk, w is its
+    /// strength.
+    pub sunrise: Vec4,
+    pub sun_direction: Vec3,
+    /// Multiplier of the light of blocks that don't glow themselves.
+    pub ambient: f32,
+}
+
+impl SkyLight {
+    /// The sky at a [`celestial_angle`], interpolated across dawn, day, dusk
+    /// and night like vanilla.
+    pub fn at(angle: f32) -> Self {
+        let sun_height = (angle * std::f32::consts::TAU).cos();
+        let day = (sun_height * 2.0 + 0.5).clamp(0.0, 1.0);
+
+        let sunrise = if (-0.4..=0.4).contains(&sun_height) {
+            let t = sun_height / 0.4 * 0.5 + 0.5;
+            let alpha = 1.0 - (1.0 - (t * std::f32::consts::PI).sin()) * 0.99;
+            Vec4::new(t * 0.3 + 0.7, t * t * 0.7 + 0.2, 0.2, alpha * alpha)
+        } else {
+            Vec4::ZERO
+        };
+
+        Self {
+            zenith: DAY_ZENITH * day,
+            horizon: DAY_HORIZON * (day * 0.94 + 0.06),
+            sunrise,
+            sun_direction: sun_direction(angle),
+            ambient: NIGHT_AMBIENT + (1.0 - NIGHT_AMBIENT) * day,
+        }
+    }
+}
+
+/// Position of the sun in its daily cycle, 0 at noon and 0.5 at midnight. Eased
+/// like vanilla, so days last a bit longer than nights.
+pub fn celestial_angle(day_time: f64) -> f32 {
+    let d = (day_time / DAY_LENGTH - 0.25).rem_euclid(1.0);
+    let eased = 0.5 - (d * PI).cos() / 2.0;
+    ((d * 2.0 + eased) / 3.0) as f32
+}
+
+/// Direction towards the sun for a [`celestial_angle`], rising in the east
+/// (+x) and setting in the west. The moon is on the opposite side.
+pub fn sun_direction(angle: f32) -> Vec3 {
+    let angle = angle * std::f32::consts::TAU;
+    Vec3::new(-angle.sin(), angle.cos(), 0.0)
+}
+
+/// Which of the 8 moon phases is shown, starting at full moon.
+pub fn moon_phase(day_time: u64) -> u32 {
+    ((day_time / DAY_LENGTH as u64) % 8) as u32
+}
+
+pub struct CelestialRenderer {
+    set_layout: vk::DescriptorSetLayout,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+    sky_pipeline_layout: vk::PipelineLayout,
+    sky_pipeline: vk::Pipeline,
+    pool: vk::DescriptorPool,
+    set: vk::DescriptorSet,
+    sun: Texture,
+    /// The 8 phases of the moon in a 4x2 grid.
+    moon: Texture,
+}
+
+impl CelestialRenderer {
+    pub fn new(
+        ctx: &VkContext,
+        module: vk::ShaderModule,
+        render_pass: vk::RenderPass,
+        world_set_layout: vk::DescriptorSetLayout,
+        assets: &Assets,
+    ) -> Self {
+        let device = ctx.device();
+
+        let sun = load_texture(ctx, assets, "textures/environment/sun.png");
+        let moon = load_texture(ctx, assets, "textures/environment/moon_phases.png");
+
+        let bindings = [0, 1].map(|binding| {
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(binding)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+        });
+        let set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings),
+                    None,
+                )
+                .unwrap()
+        };
+
+        let pool_sizes = [vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(2)];
+        let pool = unsafe {
+            device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::default()
+                        .pool_sizes(&pool_sizes)
+                        .max_sets(1),
+                    None,
+                )
+                .unwrap()
+        };
+        ctx.track_object(pool);
+        // the textures never change, so one set serves every frame
+        let set = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::default()
+                        .descriptor_pool(pool)
+                        .set_layouts(&[set_layout]),
+                )
+                .unwrap()[0]
+        };
+
+        let image_infos = [&sun, &moon].map(|texture| vk::DescriptorImageInfo {
+            sampler: texture.sampler,
+            image_view: texture.view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        });
+        let writes = [0, 1].map(|binding| {
+            vk::WriteDescriptorSet::default()
+                .dst_set(set)
+                .dst_binding(binding)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(std::slice::from_ref(&image_infos[binding as usize]))
+        });
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+        let pipeline_layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::default()
+                        .set_layouts(&[world_set_layout, set_layout])
+                        .push_constant_ranges(&[vk::PushConstantRange {
+                            stage_flags: vk::ShaderStageFlags::VERTEX
+                                | vk::ShaderStageFlags::FRAGMENT,
+                            offset: 0,
+                            size: size_of::<CelestialPushConstants>() as u32,
+                        }]),
+                    None,
+                )
+                .unwrap()
+        };
+        let pipeline =
+            Self::create_pipeline(ctx, module, render_pass, pipeline_layout, "celestial");
+
+        let sky_pipeline_layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::default()
+                        .set_layouts(&[world_set_layout])
+                        .push_constant_ranges(&[vk::PushConstantRange {
+                            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                            offset: 0,
+                            size: size_of::<SkyPushConstants>() as u32,
+                        }]),
+                    None,
+                )
+                .unwrap()
+        };
+        let sky_pipeline =
+            Self::create_pipeline(ctx, module, render_pass, sky_pipeline_layout, "sky");
+
+        Self {
+            set_layout,
+            pipeline_layout,
+            pipeline,
+            sky_pipeline_layout,
+            sky_pipeline,
+            pool,
+            set,
+            sun,
+            moon,
+        }
+    }
+
+    /// Rebuilds the pipeline from a new shader module, the device must be idle.
+    pub fn reload_shaders(
+        &mut self,
+        ctx: &VkContext,
+        module: vk::ShaderModule,
+        render_pass: vk::RenderPass,
+    ) {
+        for pipeline in [self.pipeline, self.sky_pipeline] {
+            ctx.untrack_object(pipeline);
+            unsafe { ctx.device().destroy_pipeline(pipeline, None) };
+        }
+        self.pipeline =
+            Self::create_pipeline(ctx, module, render_pass, self.pipeline_layout, "celestial");
+        self.sky_pipeline =
+            Self::create_pipeline(ctx, module, render_pass, self.sky_pipeline_layout, "sky");
+    }
+
+    /// Pipeline of the `sky::{name}_vert` and `sky::{name}_frag` shaders. The
+    /// sky overwrites the cleared image, the sun and moon are added onto it.
+    fn create_pipeline(
+        ctx: &VkContext,
+        module: vk::ShaderModule,
+        render_pass: vk::RenderPass,
+        pipeline_layout: vk::PipelineLayout,
+        name: &str,
+    ) -> vk::Pipeline {
+        let device = ctx.device();
+
+        let vert_entry = CString::new(format!("sky::{name}_vert")).unwrap();
+        let frag_entry = CString::new(format!("sky::{name}_frag")).unwrap();
+        let stages = [
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(module)
+                .name(&vert_entry),
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(module)
+                .name(&frag_entry),
+        ];
+
+        let vertex_input = vk::PipelineVertexInputStateCreateInfo::default();
+
+        let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+
+        let rasterizer = vk::PipelineRasterizationStateCreateInfo::default()
+            .polygon_mode(vk::PolygonMode::FILL)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .line_width(1.0);
+
+        let multisampling = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+        // the sun and moon are added on top of the sky like vanilla, so the
+        // black around the moon doesn't show
+        let color_blend_attachment = vk::PipelineColorBlendAttachmentState::default()
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B,
+            )
+            .blend_enable(name != "sky")
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+            .alpha_blend_op(vk::BlendOp::ADD);
+
+        let color_blending = vk::PipelineColorBlendStateCreateInfo::default()
+            .attachments(std::slice::from_ref(&color_blend_attachment));
+
+        // drawn first and infinitely far away, everything else covers it
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state =
+            vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&stages)
+            .vertex_input_state(&vertex_input)
+            .input_assembly_state(&input_assembly)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterizer)
+            .multisample_state(&multisampling)
+            .color_blend_state(&color_blending)
+            .depth_stencil_state(&depth_stencil)
+            .dynamic_state(&dynamic_state)
+            .layout(pipeline_layout)
+            .render_pass(render_pass)
+            .subpass(0);
+
+        let pipeline = unsafe {
+            device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    std::slice::from_ref(&pipeline_info),
+                    None,
+                )
+                .unwrap()[0]
+        };
+        ctx.track_object(pipeline);
+        pipeline
+    }
+
+    /// Fills the background with the sky colors of the world uniform. Must be
+    /// called inside the world render pass before anything else is drawn.
+    pub fn draw_sky(&self, frame_ctx: &FrameCtx, world_set: vk::DescriptorSet) {
+        let push_constants = SkyPushConstants {
+            inv_view_proj: frame_ctx.view_proj.inverse(),
+        };
+
+        let device = frame_ctx.ctx.device();
+        let cmd = frame_ctx.cmd;
+        unsafe {
+            device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, self.sky_pipeline);
+            device.cmd_bind_descriptor_sets(
+                cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.sky_pipeline_layout,
+                0,
+                &[world_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                cmd,
+                self.sky_pipeline_layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                std::slice::from_raw_parts(
+                    &push_constants as *const _ as *const u8,
+                    std::mem::size_of::<SkyPushConstants>(),
+                ),
+            );
+            device.cmd_draw(cmd, 3, 1, 0, 0);
+        }
+    }
+
+    /// Draws the sun and moon for `day_time`, faded out by `alpha` when it
+    /// rains. Must be called inside the world render pass before the terrain.
+    pub fn draw(
+        &self,
+        frame_ctx: &FrameCtx,
+        world_set: vk::DescriptorSet,
+        day_time: f64,
+        alpha: f32,
+    ) {
+        let sun = sun_direction(celestial_angle(day_time));
+        let phase = moon_phase(day_time as u64);
+        let (column, row) = ((phase % 4) as f32, (phase / 4) as f32);
+        let camera = frame_ctx.camera_pos.extend(alpha).to_array();
+
+        let bodies = [
+            CelestialPushConstants {
+                direction: sun.extend(SUN_SIZE).to_array(),
+                uv: [0.0, 0.0, 1.0, 1.0],
+                camera,
+                kind: 0,
+            },
+            CelestialPushConstants {
+                direction: (-sun).extend(MOON_SIZE).to_array(),
+                uv: [
+                    column / 4.0,
+                    row / 2.0,
+                    (column + 1.0) / 4.0,
+                    (row + 1.0) / 2.0,
+                ],
+                camera,
+                kind: 1,
+            },
+        ];
+
+        let device = frame_ctx.ctx.device();
+        let cmd = frame_ctx.cmd;
+        unsafe {
+            device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, self.pipeline);
+            device.cmd_bind_descriptor_sets(
+                cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[world_set, self.set],
+                &[],
+            );
+            for push_constants in &bodies {
+                device.cmd_push_constants(
+                    cmd,
+                    self.pipeline_layout,
+                    vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    std::slice::from_raw_parts(
+                        push_constants as *const _ as *const u8,
+                        std::mem::size_of::<CelestialPushConstants>(),
+                    ),
+                );
+                device.cmd_draw(cmd, QUAD_VERTICES, 1, 0, 0);
+            }
+        }
+    }
+
+    pub fn destroy(&mut self, ctx: &VkContext) {
+        let device = ctx.device();
+        self.sun.destroy(ctx);
+        self.moon.destroy(ctx);
+        ctx.untrack_object(self.pipeline);
+        ctx.untrack_object(self.sky_pipeline);
+        ctx.untrack_object(self.pool);
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_pipeline(self.sky_pipeline, None);
+            device.destroy_pipeline_layout(self.sky_pipeline_layout, None);
+            device.destroy_descriptor_pool(self.pool, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+    }
+}
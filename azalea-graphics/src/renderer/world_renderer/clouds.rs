@@ -0,0 +1,272 @@
+use std::ffi::CString;
+
+use ash::vk;
+
+use crate::renderer::{
+    frame_ctx::FrameCtx, render_targets::DEPTH_COMPARE_OP, vulkan::context::VkContext,
+};
+
+/// How far clouds drift along +X per tick, in blocks.
+const CLOUD_SPEED: f64 = 0.03;
+/// Size of a single cloud cell in blocks, must match the shader.
+const CELL_SIZE: f32 = 12.0;
+/// Vertices for one fancy cloud box, 6 faces of 2 triangles.
+const BOX_VERTICES: u32 = 36;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct CloudPushConstants {
+    pub color: [f32; 4],
+    pub camera_x: f32,
+    pub camera_z: f32,
+    pub height: f32,
+    pub scroll: f32,
+    pub radius: i32,
+}
+
+pub struct CloudRenderer {
+    pipeline_layout: vk::PipelineLayout,
+    flat_pipeline: vk::Pipeline,
+    box_pipeline: vk::Pipeline,
+}
+
+impl CloudRenderer {
+    pub fn new(
+        ctx: &VkContext,
+        module: vk::ShaderModule,
+        render_pass: vk::RenderPass,
+        world_set_layout: vk::DescriptorSetLayout,
+    ) -> Self {
+        let device = ctx.device();
+
+        let pipeline_layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::default()
+                        .set_layouts(&[world_set_layout])
+                        .push_constant_ranges(&[vk::PushConstantRange {
+                            stage_flags: vk::ShaderStageFlags::VERTEX
+                                | vk::ShaderStageFlags::FRAGMENT,
+                            offset: 0,
+                            size: size_of::<CloudPushConstants>() as u32,
+                        }]),
+                    None,
+                )
+                .unwrap()
+        };
+
+        let (flat_pipeline, box_pipeline) =
+            Self::create_pipelines(ctx, module, render_pass, pipeline_layout);
+
+        Self {
+            pipeline_layout,
+            flat_pipeline,
+            box_pipeline,
+        }
+    }
+
+    /// Rebuilds the pipelines from a new shader module, the device must be
+    /// idle.
+    pub fn reload_shaders(
+        &mut self,
+        ctx: &VkContext,
+        module: vk::ShaderModule,
+        render_pass: vk::RenderPass,
+    ) {
+        for pipeline in [self.flat_pipeline, self.box_pipeline] {
+            ctx.untrack_object(pipeline);
+            unsafe { ctx.device().destroy_pipeline(pipeline, None) };
+        }
+        (self.flat_pipeline, self.box_pipeline) =
+            Self::create_pipelines(ctx, module, render_pass, self.pipeline_layout);
+    }
+
+    /// The flat and the box pipeline.
+    fn create_pipelines(
+        ctx: &VkContext,
+        module: vk::ShaderModule,
+        render_pass: vk::RenderPass,
+        pipeline_layout: vk::PipelineLayout,
+    ) -> (vk::Pipeline, vk::Pipeline) {
+        // the flat layer is visible from above and below, boxes are closed
+        let flat_pipeline = Self::create_pipeline(
+            ctx,
+            module,
+            render_pass,
+            pipeline_layout,
+            "sky::cloud_flat_vert",
+            "sky::cloud_flat_frag",
+            vk::CullModeFlags::NONE,
+        );
+        let box_pipeline = Self::create_pipeline(
+            ctx,
+            module,
+            render_pass,
+            pipeline_layout,
+            "sky::cloud_box_vert",
+            "sky::cloud_box_frag",
+            vk::CullModeFlags::BACK,
+        );
+        (flat_pipeline, box_pipeline)
+    }
+
+    fn create_pipeline(
+        ctx: &VkContext,
+        module: vk::ShaderModule,
+        render_pass: vk::RenderPass,
+        pipeline_layout: vk::PipelineLayout,
+        vert_entry: &str,
+        frag_entry: &str,
+        cull_mode: vk::CullModeFlags,
+    ) -> vk::Pipeline {
+        let device = ctx.device();
+
+        let vert_entry = CString::new(vert_entry).unwrap();
+        let frag_entry = CString::new(frag_entry).unwrap();
+        let stages = [
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(module)
+                .name(&vert_entry),
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(module)
+                .name(&frag_entry),
+        ];
+
+        // No vertex input (geometry generated in shader)
+        let vertex_input = vk::PipelineVertexInputStateCreateInfo::default();
+
+        let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+
+        let rasterizer = vk::PipelineRasterizationStateCreateInfo::default()
+            .polygon_mode(vk::PolygonMode::FILL)
+            .cull_mode(cull_mode)
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .line_width(1.0);
+
+        let multisampling = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+        let color_blend_attachment = vk::PipelineColorBlendAttachmentState::default()
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ONE)
+            .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+            .alpha_blend_op(vk::BlendOp::ADD);
+
+        let color_blending = vk::PipelineColorBlendStateCreateInfo::default()
+            .attachments(std::slice::from_ref(&color_blend_attachment));
+
+        // depth tested so terrain pokes through, but never written since clouds are
+        // translucent
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(true)
+            .depth_write_enable(false)
+            .depth_compare_op(DEPTH_COMPARE_OP);
+
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state =
+            vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&stages)
+            .vertex_input_state(&vertex_input)
+            .input_assembly_state(&input_assembly)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterizer)
+            .multisample_state(&multisampling)
+            .color_blend_state(&color_blending)
+            .depth_stencil_state(&depth_stencil)
+            .dynamic_state(&dynamic_state)
+            .layout(pipeline_layout)
+            .render_pass(render_pass)
+            .subpass(0);
+
+        let pipeline = unsafe {
+            device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    std::slice::from_ref(&pipeline_info),
+                    None,
+                )
+                .unwrap()[0]
+        };
+        ctx.track_object(pipeline);
+        pipeline
+    }
+
+    /// Draws the cloud layer, must be called inside the world render pass.
+    pub fn draw(&self, frame_ctx: &FrameCtx, world_set: vk::DescriptorSet, ticks: u64) {
+        let device = frame_ctx.ctx.device();
+        let cmd = frame_ctx.cmd;
+        let config = &frame_ctx.config;
+
+        let time = ticks as f64 + frame_ctx.partial_tick as f64;
+        let radius = (config.render_distance as f32 * 16.0 / CELL_SIZE).ceil() as i32;
+
+        let push_constants = CloudPushConstants {
+            color: [1.0, 1.0, 1.0, 0.8],
+            camera_x: frame_ctx.camera_pos.x,
+            camera_z: frame_ctx.camera_pos.z,
+            height: config.cloud_height,
+            // wrap the scroll to keep float precision over long sessions
+            scroll: ((time * CLOUD_SPEED) % (CELL_SIZE as f64 * 4096.0)) as f32,
+            radius,
+        };
+
+        let (pipeline, vertex_count, instance_count) = if config.fancy_clouds {
+            let side = (radius * 2 + 1) as u32;
+            (self.box_pipeline, BOX_VERTICES, side * side)
+        } else {
+            (self.flat_pipeline, 6, 1)
+        };
+
+        unsafe {
+            device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            device.cmd_bind_descriptor_sets(
+                cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[world_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                cmd,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                0,
+                std::slice::from_raw_parts(
+                    &push_constants as *const _ as *const u8,
+                    std::mem::size_of::<CloudPushConstants>(),
+                ),
+            );
+            device.cmd_draw(cmd, vertex_count, instance_count, 0, 0);
+        }
+    }
+
+    pub fn destroy(&mut self, ctx: &VkContext) {
+        let device = ctx.device();
+        ctx.untrack_object(self.flat_pipeline);
+        ctx.untrack_object(self.box_pipeline);
+        unsafe {
+            device.destroy_pipeline(self.flat_pipeline, None);
+            device.destroy_pipeline(self.box_pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
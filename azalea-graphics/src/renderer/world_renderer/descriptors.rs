@@ -1,8 +1,6 @@
-use std::array::from_fn;
-
 use ash::{Device, vk};
 
-use crate::renderer::vulkan::{buffer::Buffer, frame_sync::MAX_FRAMES_IN_FLIGHT, texture::Texture};
+use crate::renderer::vulkan::{buffer::Buffer, context::VkContext, texture::Texture};
 
 pub fn create_world_descriptor_set_layout(device: &Device) -> vk::DescriptorSetLayout {
     let sampler_bindings = [
@@ -15,7 +13,7 @@ pub fn create_world_descriptor_set_layout(device: &Device) -> vk::DescriptorSetL
             .binding(1)
             .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
             .descriptor_count(1)
-            .stage_flags(vk::ShaderStageFlags::VERTEX),
+            .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT),
     ];
 
     let info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&sampler_bindings);
@@ -23,19 +21,19 @@ pub fn create_world_descriptor_set_layout(device: &Device) -> vk::DescriptorSetL
     unsafe { device.create_descriptor_set_layout(&info, None).unwrap() }
 }
 
-pub fn create_world_descriptor_pool(device: &Device) -> vk::DescriptorPool {
+pub fn create_world_descriptor_pool(device: &Device, frames: usize) -> vk::DescriptorPool {
     let pool_sizes = [
         vk::DescriptorPoolSize::default()
             .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-            .descriptor_count(MAX_FRAMES_IN_FLIGHT as u32),
+            .descriptor_count(frames as u32),
         vk::DescriptorPoolSize::default()
             .ty(vk::DescriptorType::UNIFORM_BUFFER)
-            .descriptor_count(MAX_FRAMES_IN_FLIGHT as u32),
+            .descriptor_count(frames as u32),
     ];
 
     let info = vk::DescriptorPoolCreateInfo::default()
         .pool_sizes(&pool_sizes)
-        .max_sets(MAX_FRAMES_IN_FLIGHT as u32);
+        .max_sets(frames as u32);
 
     unsafe { device.create_descriptor_pool(&info, None).unwrap() }
 }
@@ -44,21 +42,20 @@ pub fn allocate_world_descriptor_sets(
     device: &Device,
     pool: vk::DescriptorPool,
     layout: vk::DescriptorSetLayout,
-) -> [vk::DescriptorSet; MAX_FRAMES_IN_FLIGHT] {
-    let layouts = [layout; MAX_FRAMES_IN_FLIGHT];
+    frames: usize,
+) -> Vec<vk::DescriptorSet> {
+    let layouts = vec![layout; frames];
     let alloc_info = vk::DescriptorSetAllocateInfo::default()
         .descriptor_pool(pool)
         .set_layouts(&layouts);
 
-    let sets = unsafe { device.allocate_descriptor_sets(&alloc_info).unwrap() };
-
-    sets.try_into().unwrap()
+    unsafe { device.allocate_descriptor_sets(&alloc_info).unwrap() }
 }
 
 pub fn update_world_texture_descriptor(
     device: &Device,
-    descriptor_sets: &[vk::DescriptorSet; MAX_FRAMES_IN_FLIGHT],
-    uniform_buffers: &[Buffer; MAX_FRAMES_IN_FLIGHT],
+    descriptor_sets: &[vk::DescriptorSet],
+    uniform_buffers: &[Buffer],
     tex: &Texture,
 ) {
     let image_info = vk::DescriptorImageInfo {
@@ -69,13 +66,16 @@ pub fn update_world_texture_descriptor(
 
     let mut writes = Vec::new();
 
-    let buffer_infos: [_; MAX_FRAMES_IN_FLIGHT] = from_fn(|i| {
-        vk::DescriptorBufferInfo::default()
-            .buffer(uniform_buffers[i].buffer)
-            .range(vk::WHOLE_SIZE)
-    });
+    let buffer_infos: Vec<_> = uniform_buffers
+        .iter()
+        .map(|uniform| {
+            vk::DescriptorBufferInfo::default()
+                .buffer(uniform.buffer)
+                .range(vk::WHOLE_SIZE)
+        })
+        .collect();
 
-    for i in 0..MAX_FRAMES_IN_FLIGHT {
+    for i in 0..descriptor_sets.len() {
         writes.push(
             vk::WriteDescriptorSet::default()
                 .dst_set(descriptor_sets[i])
@@ -101,23 +101,61 @@ pub fn update_world_texture_descriptor(
 pub struct Descriptors {
     pub layout: vk::DescriptorSetLayout,
     pub pool: vk::DescriptorPool,
-    pub sets: [vk::DescriptorSet; MAX_FRAMES_IN_FLIGHT],
+    pub sets: Vec<vk::DescriptorSet>,
+    /// Frames whose set still points at a replaced atlas sampler.
+    dirty_texture: Vec<bool>,
 }
 
 impl Descriptors {
-    pub fn new(
-        device: &Device,
-        uniform_buffers: &[Buffer; MAX_FRAMES_IN_FLIGHT],
-        texture: &Texture,
-    ) -> Self {
+    /// Creates one set per frame in flight, one for each of `uniform_buffers`.
+    pub fn new(ctx: &VkContext, uniform_buffers: &[Buffer], texture: &Texture) -> Self {
+        let device = ctx.device();
+        let frames = uniform_buffers.len();
         let layout = create_world_descriptor_set_layout(device);
-        let pool = create_world_descriptor_pool(device);
-        let sets = allocate_world_descriptor_sets(device, pool, layout);
+        let pool = create_world_descriptor_pool(device, frames);
+        ctx.track_object(pool);
+        let sets = allocate_world_descriptor_sets(device, pool, layout, frames);
         update_world_texture_descriptor(device, &sets, uniform_buffers, texture);
-        Self { layout, pool, sets }
+        Self {
+            layout,
+            pool,
+            sets,
+            dirty_texture: vec![false; frames],
+        }
+    }
+
+    /// Marks every frame's set as outdated, each one is rewritten by
+    /// [`Self::update_frame_texture`] once its frame is no longer in flight.
+    pub fn mark_texture_dirty(&mut self) {
+        self.dirty_texture.fill(true);
+    }
+
+    /// Points the atlas binding of `frame_index`'s set at `texture`'s sampler
+    /// and view if it is outdated.
+    pub fn update_frame_texture(&mut self, device: &Device, frame_index: usize, texture: &Texture) {
+        if !std::mem::take(&mut self.dirty_texture[frame_index]) {
+            return;
+        }
+
+        let image_info = vk::DescriptorImageInfo {
+            sampler: texture.sampler,
+            image_view: texture.view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        };
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(self.sets[frame_index])
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(std::slice::from_ref(&image_info));
+
+        unsafe {
+            device.update_descriptor_sets(std::slice::from_ref(&write), &[]);
+        }
     }
 
-    pub fn destroy(&mut self, device: &Device) {
+    pub fn destroy(&mut self, ctx: &VkContext) {
+        let device = ctx.device();
+        ctx.untrack_object(self.pool);
         unsafe {
             device.destroy_descriptor_pool(self.pool, None);
             device.destroy_descriptor_set_layout(self.layout, None);
@@ -0,0 +1,340 @@
+//! Experimental mesher that builds the faces of full cubes in a compute shader.
+//! The worker threads still cull the faces, the shader turns them into quads
+//! with ambient occlusion and texture uvs.
+
+use std::collections::HashMap;
+
+use ash::vk;
+use azalea::core::position::{ChunkPos, ChunkSectionPos};
+use vk_mem::MemoryUsage;
+
+use crate::renderer::{
+    frame_ctx::FrameCtx,
+    mesh::Mesh,
+    vulkan::{buffer::Buffer, context::VkContext},
+    world_renderer::{
+        mesher::{CubeFace, CubeFaces, CubeTable, OCCLUDER_WORDS},
+        types::BlockVertex,
+    },
+};
+
+/// Sections meshed per frame, the rest wait for the following frames.
+const MAX_SECTIONS_PER_FRAME: usize = 64;
+/// Workgroup size of `gpu_mesher::mesh_cubes`.
+const THREADS: u32 = 64;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GpuMesherPushConstants {
+    origin: [i32; 4],
+    face_count: u32,
+    index_offset: u32,
+}
+
+pub struct GpuMesher {
+    set_layout: vk::DescriptorSetLayout,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+    /// One pool per frame in flight, reset each time the frame comes around.
+    pools: Vec<vk::DescriptorPool>,
+    cube_faces: Buffer,
+    pending: HashMap<ChunkSectionPos, CubeFaces>,
+}
+
+impl GpuMesher {
+    pub fn new(ctx: &VkContext, module: vk::ShaderModule, cubes: &CubeTable) -> Self {
+        let d = ctx.device();
+
+        let bindings: Vec<_> = (0..3)
+            .map(|binding| {
+                vk::DescriptorSetLayoutBinding::default()
+                    .binding(binding)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .descriptor_count(1)
+                    .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            })
+            .collect();
+        let set_layout = unsafe {
+            d.create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings),
+                None,
+            )
+            .unwrap()
+        };
+
+        let push_constant_range = vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(size_of::<GpuMesherPushConstants>() as u32);
+        let pipeline_layout = unsafe {
+            d.create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo::default()
+                    .set_layouts(&[set_layout])
+                    .push_constant_ranges(&[push_constant_range]),
+                None,
+            )
+            .unwrap()
+        };
+
+        let pipeline = create_pipeline(ctx, module, pipeline_layout);
+
+        let pools = (0..ctx.frames_in_flight())
+            .map(|_| {
+                let pool = unsafe {
+                    d.create_descriptor_pool(
+                        &vk::DescriptorPoolCreateInfo::default()
+                            .pool_sizes(&[vk::DescriptorPoolSize {
+                                ty: vk::DescriptorType::STORAGE_BUFFER,
+                                descriptor_count: 3 * MAX_SECTIONS_PER_FRAME as u32,
+                            }])
+                            .max_sets(MAX_SECTIONS_PER_FRAME as u32),
+                        None,
+                    )
+                    .unwrap()
+                };
+                ctx.track_object(pool);
+                pool
+            })
+            .collect();
+
+        Self {
+            set_layout,
+            pipeline_layout,
+            pipeline,
+            pools,
+            cube_faces: create_cube_faces(ctx, cubes),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Replaces the cube face table, e.g. after the uv inset changed. The
+    /// device must be idle.
+    pub fn set_cubes(&mut self, ctx: &VkContext, cubes: &CubeTable) {
+        self.cube_faces.destroy(ctx);
+        self.cube_faces = create_cube_faces(ctx, cubes);
+    }
+
+    /// Queues the cube faces of `spos`, replacing older ones that weren't
+    /// meshed yet.
+    pub fn queue(&mut self, spos: ChunkSectionPos, faces: CubeFaces) {
+        self.pending.insert(spos, faces);
+    }
+
+    /// Drops the queued faces of `spos`, for when it no longer has any.
+    pub fn cancel(&mut self, spos: ChunkSectionPos) {
+        self.pending.remove(&spos);
+    }
+
+    /// Drops the queued faces of every section of an unloaded chunk.
+    pub fn cancel_chunk(&mut self, pos: ChunkPos) {
+        self.pending
+            .retain(|spos, _| spos.x != pos.x || spos.z != pos.z);
+    }
+
+    /// Drops every queued section, for when the world is replaced.
+    pub fn cancel_all(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Number of sections waiting to be meshed.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Records the meshing of up to [`MAX_SECTIONS_PER_FRAME`] queued sections.
+    /// The returned meshes can be drawn later in the same command buffer.
+    pub fn dispatch(
+        &mut self,
+        frame_ctx: &mut FrameCtx,
+    ) -> Vec<(ChunkSectionPos, Mesh<BlockVertex>)> {
+        let sections: Vec<ChunkSectionPos> = self
+            .pending
+            .keys()
+            .take(MAX_SECTIONS_PER_FRAME)
+            .copied()
+            .collect();
+        if sections.is_empty() {
+            return Vec::new();
+        }
+
+        let ctx = frame_ctx.ctx;
+        let d = ctx.device();
+        let pool = self.pools[frame_ctx.frame_index];
+        let sets = unsafe {
+            d.reset_descriptor_pool(pool, vk::DescriptorPoolResetFlags::empty())
+                .unwrap();
+            d.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::default()
+                    .descriptor_pool(pool)
+                    .set_layouts(&vec![self.set_layout; sections.len()]),
+            )
+            .unwrap()
+        };
+
+        unsafe {
+            d.cmd_bind_pipeline(frame_ctx.cmd, vk::PipelineBindPoint::COMPUTE, self.pipeline);
+        }
+
+        let mut meshes = Vec::with_capacity(sections.len());
+        for (spos, set) in sections.into_iter().zip(sets) {
+            let CubeFaces { faces, occluders } = self.pending.remove(&spos).unwrap();
+
+            let input_size = (size_of::<u32>() * (OCCLUDER_WORDS + faces.len())) as vk::DeviceSize;
+            let mut input = Buffer::new(
+                ctx,
+                input_size,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                MemoryUsage::AutoPreferHost,
+                true,
+            );
+            input.upload_data(ctx, 0, &occluders);
+            input.upload_data(
+                ctx,
+                (size_of::<u32>() * OCCLUDER_WORDS) as vk::DeviceSize,
+                &faces,
+            );
+
+            let mesh = Mesh::<BlockVertex>::new_storage(ctx, faces.len() * 4, faces.len() * 6);
+
+            let infos = [
+                vk::DescriptorBufferInfo::default()
+                    .buffer(self.cube_faces.buffer)
+                    .range(vk::WHOLE_SIZE),
+                vk::DescriptorBufferInfo::default()
+                    .buffer(input.buffer)
+                    .range(vk::WHOLE_SIZE),
+                vk::DescriptorBufferInfo::default()
+                    .buffer(mesh.buffer.buffer)
+                    .range(vk::WHOLE_SIZE),
+            ];
+            let writes: Vec<_> = infos
+                .iter()
+                .enumerate()
+                .map(|(binding, info)| {
+                    vk::WriteDescriptorSet::default()
+                        .dst_set(set)
+                        .dst_binding(binding as u32)
+                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                        .buffer_info(std::slice::from_ref(info))
+                })
+                .collect();
+
+            let push = GpuMesherPushConstants {
+                origin: [spos.x * 16, spos.y * 16, spos.z * 16, 0],
+                face_count: faces.len() as u32,
+                index_offset: (mesh.index_offset / size_of::<u32>() as vk::DeviceSize) as u32,
+            };
+
+            unsafe {
+                d.update_descriptor_sets(&writes, &[]);
+                d.cmd_bind_descriptor_sets(
+                    frame_ctx.cmd,
+                    vk::PipelineBindPoint::COMPUTE,
+                    self.pipeline_layout,
+                    0,
+                    &[set],
+                    &[],
+                );
+                d.cmd_push_constants(
+                    frame_ctx.cmd,
+                    self.pipeline_layout,
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    std::slice::from_raw_parts(
+                        &push as *const _ as *const u8,
+                        size_of::<GpuMesherPushConstants>(),
+                    ),
+                );
+                d.cmd_dispatch(frame_ctx.cmd, (faces.len() as u32).div_ceil(THREADS), 1, 1);
+            }
+
+            frame_ctx.delete(input);
+            meshes.push((spos, mesh));
+        }
+
+        let barriers: Vec<vk::BufferMemoryBarrier> = meshes
+            .iter()
+            .map(|(_, mesh)| {
+                vk::BufferMemoryBarrier::default()
+                    .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                    .dst_access_mask(
+                        vk::AccessFlags::VERTEX_ATTRIBUTE_READ | vk::AccessFlags::INDEX_READ,
+                    )
+                    .buffer(mesh.buffer.buffer)
+                    .offset(0)
+                    .size(vk::WHOLE_SIZE)
+            })
+            .collect();
+        frame_ctx.pipeline_barrier(
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            &barriers,
+            &[],
+        );
+
+        meshes
+    }
+
+    /// Rebuilds the pipeline from a new shader module, the device must be idle.
+    pub fn reload_shaders(&mut self, ctx: &VkContext, module: vk::ShaderModule) {
+        ctx.untrack_object(self.pipeline);
+        unsafe { ctx.device().destroy_pipeline(self.pipeline, None) };
+        self.pipeline = create_pipeline(ctx, module, self.pipeline_layout);
+    }
+
+    pub fn destroy(&mut self, ctx: &VkContext) {
+        let d = ctx.device();
+        ctx.untrack_object(self.pipeline);
+        unsafe {
+            d.destroy_pipeline(self.pipeline, None);
+            d.destroy_pipeline_layout(self.pipeline_layout, None);
+            for pool in self.pools.drain(..) {
+                ctx.untrack_object(pool);
+                d.destroy_descriptor_pool(pool, None);
+            }
+            d.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+        self.cube_faces.destroy(ctx);
+    }
+}
+
+fn create_cube_faces(ctx: &VkContext, cubes: &CubeTable) -> Buffer {
+    // an empty buffer can't be bound, keep room for one cube
+    let cube_faces_size = size_of::<CubeFace>() * 6 * cubes.len().max(1);
+    let mut cube_faces = Buffer::new(
+        ctx,
+        cube_faces_size as vk::DeviceSize,
+        vk::BufferUsageFlags::STORAGE_BUFFER,
+        MemoryUsage::AutoPreferHost,
+        true,
+    );
+    cube_faces.upload_data(ctx, 0, &cubes.faces);
+    ctx.label_object(cube_faces.buffer, "GPU Mesher Cube Faces");
+    cube_faces
+}
+
+fn create_pipeline(
+    ctx: &VkContext,
+    module: vk::ShaderModule,
+    pipeline_layout: vk::PipelineLayout,
+) -> vk::Pipeline {
+    let entry = std::ffi::CString::new("gpu_mesher::mesh_cubes").unwrap();
+    let stage = vk::PipelineShaderStageCreateInfo::default()
+        .stage(vk::ShaderStageFlags::COMPUTE)
+        .module(module)
+        .name(&entry);
+
+    let pipeline = unsafe {
+        ctx.device()
+            .create_compute_pipelines(
+                vk::PipelineCache::null(),
+                &[vk::ComputePipelineCreateInfo::default()
+                    .stage(stage)
+                    .layout(pipeline_layout)],
+                None,
+            )
+            .unwrap()[0]
+    };
+    ctx.track_object(pipeline);
+    pipeline
+}
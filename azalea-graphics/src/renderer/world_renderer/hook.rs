@@ -0,0 +1,62 @@
+//! Custom geometry drawn by users of the renderer, recorded at the end of the
+//! world pass.
+
+use ash::vk;
+use glam::{Mat4, Vec3};
+
+use crate::renderer::{
+    frame_ctx::FrameCtx,
+    world_renderer::{aabb_renderer::AabbRenderer, types::DebugBox},
+};
+
+/// Records extra draws into the world render pass every frame, after the
+/// terrain, entities and debug boxes but before the water resolve.
+pub trait CustomRenderHook: Send {
+    fn render(&mut self, ctx: &CustomRenderCtx);
+}
+
+/// What a [`CustomRenderHook`] gets to draw with. The world render pass is
+/// active and the world descriptor set is compatible with set 0 of
+/// [`Self::pipeline_layout`].
+pub struct CustomRenderCtx<'a> {
+    pub device: &'a ash::Device,
+    pub cmd: vk::CommandBuffer,
+    /// Layout of the line pipelines used by [`Self::draw_box`], for hooks that
+    /// bind their own pipelines with the same interface.
+    pub pipeline_layout: vk::PipelineLayout,
+    pub world_set: vk::DescriptorSet,
+    pub view_proj: Mat4,
+    pub camera_pos: Vec3,
+    frame_ctx: &'a FrameCtx<'a>,
+    aabb_renderer: &'a AabbRenderer,
+}
+
+impl<'a> CustomRenderCtx<'a> {
+    pub(super) fn new(
+        frame_ctx: &'a FrameCtx<'a>,
+        aabb_renderer: &'a AabbRenderer,
+        world_set: vk::DescriptorSet,
+    ) -> Self {
+        Self {
+            device: frame_ctx.ctx.device(),
+            cmd: frame_ctx.cmd,
+            pipeline_layout: aabb_renderer.box_pipeline_layout,
+            world_set,
+            view_proj: frame_ctx.view_proj,
+            camera_pos: frame_ctx.camera_pos,
+            frame_ctx,
+            aabb_renderer,
+        }
+    }
+
+    /// Draws the outline of the box between `min` and `max` in world space,
+    /// hidden behind terrain if `depth_test` is set.
+    pub fn draw_box(&self, min: Vec3, max: Vec3, color: [f32; 4], depth_test: bool) {
+        self.aabb_renderer.draw_box(
+            self.frame_ctx,
+            self.world_set,
+            &DebugBox { min, max, color },
+            depth_test,
+        );
+    }
+}
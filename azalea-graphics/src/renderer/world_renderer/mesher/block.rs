@@ -2,6 +2,7 @@ use azalea::{
     blocks::{BlockState, BlockTrait},
     core::direction::Direction,
     physics::collision::BlockWithShape,
+    registry::{Block, tags},
 };
 use azalea_assets::processed::{
     VariantDesc,
@@ -15,49 +16,85 @@ use crate::renderer::{
         BlockVertex,
         mesher::{
             MeshBuilder,
-            helpers::{FACES, compute_ao, generate_uv, offset_to_coord, remap_uv_to_atlas},
+            ctm::connected_sprite,
+            emission::is_emissive,
+            helpers::{
+                FACES, compute_ao, face_corners, remap_uv_to_atlas, rotate_direction,
+                rotate_offset, state_color,
+            },
         },
     },
 };
 
 pub fn mesh_block(block: BlockState, local: IVec3, builder: &mut MeshBuilder) {
-    for desc in builder.assets.get_variant_descs(block) {
+    let emissive = is_emissive(block);
+    let opaque = builder.fast_leaves && is_leaves(block);
+    let layer = builder.render_layer(block);
+    let spos = builder.section.spos;
+    let pos = IVec3::new(spos.x, spos.y, spos.z) * 16 + local - IVec3::ONE;
+
+    for desc in builder.assets.variant_descs_at(block, pos) {
         let model = desc.model.clone();
+        let (x_rot, y_rot) = (desc.x_rotation, desc.y_rotation);
 
         for element in &model.elements {
-            for face in FACES {
+            for face in &FACES {
                 if let Some(model_face) = face_for_direction(&element, face.dir) {
                     if let Some(cull_dir) = resolve_cullface(desc, model_face) {
-                        if face_is_occluded(local, cull_dir, builder.section) {
+                        if face_is_occluded(local, cull_dir, builder.section, builder.fast_leaves) {
                             continue;
                         }
                     }
 
-                    let uvs = generate_uv(face.dir, model_face.uv);
-
-                    let tint = builder.block_colors.get_color(
-                        block,
-                        builder.section,
-                        builder.biome_cache,
-                        local,
-                        model_face.tintindex,
-                        builder.assets,
-                    );
-
-                    let sprite_name = model
+                    let dir = rotate_direction(face.dir, x_rot, y_rot);
+                    let corners =
+                        face_corners(element, face, model_face.uv, x_rot, y_rot, desc.uvlock);
+
+                    // only faces with a tintindex are colored, e.g. the grass overlay
+                    // but not the dirt below it
+                    let tint = if builder.state_colors {
+                        state_color(block)
+                    } else if model_face.tintindex == -1 {
+                        [1.0; 3]
+                    } else {
+                        builder.block_colors.get_color(
+                            block,
+                            builder.section,
+                            builder.biome_cache,
+                            local,
+                            model_face.tintindex,
+                            builder.assets,
+                        )
+                    };
+
+                    let mut sprite_name = model
                         .resolve_texture(&model_face.texture)
                         .unwrap_or("empty");
+                    if let Some(connected) =
+                        builder.assets.get_connected_texture(block, sprite_name)
+                    {
+                        sprite_name = connected_sprite(
+                            connected,
+                            block,
+                            sprite_name,
+                            local,
+                            dir,
+                            builder.section,
+                            builder.assets,
+                        );
+                    }
 
-                    if let Some(spr) = builder.assets.get_sprite_rect(sprite_name) {
+                    if let Some(spr) = builder.assets.sprite_uv(sprite_name) {
                         let mut quad = [BlockVertex {
                             position: [0.0; 3],
                             ao: 3.0,
                             uv: [0.0; 2],
                             tint,
+                            layer: spr.layer,
                         }; 4];
 
                         for (i, &offset) in face.offsets.iter().enumerate() {
-                            let local_pos = offset_to_coord(offset, element) / 16.0;
+                            let (local_pos, uv) = corners[i];
 
                             let world_pos = Vec3::new(
                                 (local.x - 1) as f32 + builder.section.spos.x as f32 * 16.0,
@@ -65,26 +102,29 @@ pub fn mesh_block(block: BlockState, local: IVec3, builder: &mut MeshBuilder) {
                                 (local.z - 1) as f32 + builder.section.spos.z as f32 * 16.0,
                             );
 
-                            let uv = remap_uv_to_atlas(
-                                uvs[i],
-                                spr,
-                                builder.assets.block_atlas.width,
-                                builder.assets.block_atlas.height,
-                            );
+                            let uv = remap_uv_to_atlas(uv, &spr, builder.uv_inset);
 
                             quad[i] = BlockVertex {
                                 position: (local_pos + world_pos).into(),
-                                ao: if model.ambient_occlusion {
-                                    compute_ao(local, offset, face.dir, builder.section) as f32
+                                ao: if emissive {
+                                    BlockVertex::EMISSIVE_AO
+                                } else if model.ambient_occlusion {
+                                    let offset = rotate_offset(offset, x_rot, y_rot);
+                                    compute_ao(local, offset, dir, builder.section) as f32
                                 } else {
                                     3.0
                                 },
                                 uv,
                                 tint,
+                                layer: if opaque {
+                                    spr.layer | BlockVertex::OPAQUE_LAYER
+                                } else {
+                                    spr.layer
+                                },
                             };
                         }
 
-                        builder.push_block_quad(quad);
+                        builder.push_quad(layer, quad);
                     }
                 }
             }
@@ -92,8 +132,12 @@ pub fn mesh_block(block: BlockState, local: IVec3, builder: &mut MeshBuilder) {
     }
 }
 
+pub(super) fn is_leaves(state: BlockState) -> bool {
+    tags::blocks::LEAVES.contains(&Block::from(state))
+}
+
 /// Get the model face for a given direction (without applying rotations)
-fn face_for_direction<'a>(element: &'a Cube, dir: Direction) -> Option<&'a model::Face> {
+pub(super) fn face_for_direction<'a>(element: &'a Cube, dir: Direction) -> Option<&'a model::Face> {
     match dir {
         Direction::Up => element.faces.up.as_ref(),
         Direction::Down => element.faces.down.as_ref(),
@@ -116,11 +160,21 @@ fn resolve_cullface(desc: &VariantDesc, model_face: &model::Face) -> Option<Dire
             "west" => Direction::West,
             _ => return None,
         };
-        Some(dir)
+        Some(rotate_direction(dir, desc.x_rotation, desc.y_rotation))
     })
 }
 
-fn face_is_occluded(local: IVec3, cull_dir: Direction, section: &LocalSection) -> bool {
+/// With `fast_leaves` leaves hide the faces behind them like full blocks.
+///
+/// Blocks of chunks that aren't loaded never hide a face. Culling against them
+/// would leave holes if they load as air, and the section is re-meshed once
+/// they load anyway, see [`super::Mesher::submit_chunk`].
+pub(super) fn face_is_occluded(
+    local: IVec3,
+    cull_dir: Direction,
+    section: &LocalSection,
+    fast_leaves: bool,
+) -> bool {
     let offset = match cull_dir {
         Direction::Up => IVec3::new(0, 1, 0),
         Direction::Down => IVec3::new(0, -1, 0),
@@ -142,13 +196,18 @@ fn face_is_occluded(local: IVec3, cull_dir: Direction, section: &LocalSection) -
         return true;
     }
 
-    let neighbor_state = section.blocks[neighbor_pos.x as usize][neighbor_pos.y as usize]
-        [neighbor_pos.z as usize]
-        .unwrap_or(BlockState::AIR);
+    let Some(neighbor_state) =
+        section.blocks[neighbor_pos.x as usize][neighbor_pos.y as usize][neighbor_pos.z as usize]
+    else {
+        return false;
+    };
 
     if neighbor_state.is_air() {
         return false;
     }
+    if fast_leaves && is_leaves(neighbor_state) {
+        return true;
+    }
 
     let dyn_state = neighbor_state.to_trait();
     dyn_state.behavior().can_occlude && neighbor_state.is_collision_shape_full()
@@ -322,28 +322,28 @@ fn lily_pad_color_provider(
 
 /// Get grass color from biome data following Java logic
 fn get_biome_grass_color(biome: Biome, biome_cache: &BiomeCache, assets: &Assets) -> [f32; 3] {
-    let biome_index = biome.protocol_id() as usize;
+    if let Some(biome_data) = biome_cache.get(biome) {
+        // First check for grass color override (BiomeSpecialEffects.grassColorOverride)
+        if let Some(grass_color) = biome_data.effects.grass_color {
+            return int_color_to_rgb(grass_color);
+        }
 
-    let biome_data = &biome_cache.biomes[biome_index];
-    // First check for grass color override (BiomeSpecialEffects.grassColorOverride)
-    if let Some(grass_color) = biome_data.effects.grass_color {
-        return int_color_to_rgb(grass_color);
-    }
+        // If no override, use temperature/downfall to calculate from texture
+        // Java: Biome.getGrassColorFromTexture() -> GrassColor.get(temperature,
+        // downfall)
+        let temperature = biome_data.temperature.clamp(0.0, 1.0) as f64;
+        let downfall = biome_data.downfall.clamp(0.0, 1.0) as f64;
 
-    // If no override, use temperature/downfall to calculate from texture
-    // Java: Biome.getGrassColorFromTexture() -> GrassColor.get(temperature,
-    // downfall)
-    let temperature = biome_data.temperature.clamp(0.0, 1.0) as f64;
-    let downfall = biome_data.downfall.clamp(0.0, 1.0) as f64;
+        return get_grass_color_from_texture(temperature, downfall, assets);
+    }
 
-    return get_grass_color_from_texture(temperature, downfall, assets);
+    // Fallback (plains)
+    get_grass_color_from_texture(0.8, 0.4, assets)
 }
 
 /// Get foliage color from biome data following Java logic
 fn get_biome_foliage_color(biome: Biome, biome_cache: &BiomeCache, assets: &Assets) -> [f32; 3] {
-    let biome_index = biome.protocol_id() as usize;
-
-    if let Some(biome_data) = biome_cache.biomes.get(biome_index) {
+    if let Some(biome_data) = biome_cache.get(biome) {
         // First check for foliage color override
         // (BiomeSpecialEffects.foliageColorOverride)
         if let Some(foliage_color) = biome_data.effects.foliage_color {
@@ -365,9 +365,7 @@ fn get_biome_foliage_color(biome: Biome, biome_cache: &BiomeCache, assets: &Asse
 
 /// Get water color from biome data (always specified)
 fn get_biome_water_color(biome: Biome, biome_cache: &BiomeCache) -> [f32; 3] {
-    let biome_index = biome.protocol_id() as usize;
-
-    if let Some(biome_data) = biome_cache.biomes.get(biome_index) {
+    if let Some(biome_data) = biome_cache.get(biome) {
         return int_color_to_rgb(biome_data.effects.water_color);
     }
 
@@ -0,0 +1,223 @@
+//! Picks the tile of a connected texture from the blocks around a face.
+
+use azalea::{blocks::BlockState, core::direction::Direction, registry::Block};
+use azalea_assets::{
+    Assets,
+    processed::ctm::{Connect, ConnectedTexture},
+};
+use glam::IVec3;
+
+use crate::renderer::{chunk::LocalSection, world_renderer::mesher::block::face_for_direction};
+
+/// The sprite drawn on the `dir` face of the block at `local` in place of
+/// `sprite`.
+pub fn connected_sprite<'a>(
+    texture: &'a ConnectedTexture,
+    block: BlockState,
+    sprite: &str,
+    local: IVec3,
+    dir: Direction,
+    section: &LocalSection,
+    assets: &Assets,
+) -> &'a str {
+    let (right, up) = face_axes(dir);
+    let normal = dir.normal();
+    let normal = IVec3::new(normal.x, normal.y, normal.z);
+
+    let matches = |pos: IVec3| {
+        let Some(state) = get_block(section, pos) else {
+            return false;
+        };
+        match texture.connect {
+            Connect::Block => Block::from(state) == Block::from(block),
+            Connect::Tile => uses_sprite(assets, state, dir, sprite),
+        }
+    };
+    let connects = |dx: i32, dy: i32| {
+        let pos = local + right * dx + up * dy;
+        // a neighbour covered by a matching block has no visible face to connect to
+        matches(pos) && !matches(pos + normal)
+    };
+
+    &texture.tiles[ctm_index(connects)]
+}
+
+/// The directions of the texture's right and up on a face in world space,
+/// matching the default uvs of block models.
+fn face_axes(dir: Direction) -> (IVec3, IVec3) {
+    match dir {
+        Direction::Up => (IVec3::X, IVec3::NEG_Z),
+        Direction::Down => (IVec3::X, IVec3::Z),
+        Direction::North => (IVec3::NEG_X, IVec3::Y),
+        Direction::South => (IVec3::X, IVec3::Y),
+        Direction::West => (IVec3::Z, IVec3::Y),
+        Direction::East => (IVec3::NEG_Z, IVec3::Y),
+    }
+}
+
+fn get_block(section: &LocalSection, pos: IVec3) -> Option<BlockState> {
+    if pos.cmplt(IVec3::ZERO).any() || pos.cmpge(IVec3::splat(18)).any() {
+        return None;
+    }
+    section.blocks[pos.x as usize][pos.y as usize][pos.z as usize]
+}
+
+/// Whether any `dir` face of the block's models uses `sprite`.
+fn uses_sprite(assets: &Assets, state: BlockState, dir: Direction, sprite: &str) -> bool {
+    assets.get_variant_descs(state).any(|desc| {
+        desc.model.elements.iter().any(|element| {
+            face_for_direction(element, dir)
+                .is_some_and(|face| desc.model.resolve_texture(&face.texture) == Some(sprite))
+        })
+    })
+}
+
+/// Index into the standard 47 tile layout. `connects(dx, dy)` tells whether the
+/// neighbour `dx` to the right and `dy` up in texture space joins this face.
+fn ctm_index(connects: impl Fn(i32, i32) -> bool) -> usize {
+    let (left, right) = (connects(-1, 0), connects(1, 0));
+    let (down, up) = (connects(0, -1), connects(0, 1));
+
+    let index = match (left, right, down, up) {
+        (false, false, false, false) => 0,
+        (true, false, false, false) => 3,
+        (false, true, false, false) => 1,
+        (false, false, true, false) => 12,
+        (false, false, false, true) => 36,
+        (true, true, false, false) => 2,
+        (false, false, true, true) => 24,
+        (true, false, true, false) => 15,
+        (true, false, false, true) => 39,
+        (false, true, true, false) => 13,
+        (false, true, false, true) => 37,
+        (false, true, true, true) => 25,
+        (true, false, true, true) => 27,
+        (true, true, false, true) => 38,
+        (true, true, true, false) => 14,
+        (true, true, true, true) => 26,
+    };
+
+    // where two connected sides meet, the corner tile depends on the diagonal
+    let down_right = !connects(1, -1);
+    let down_left = !connects(-1, -1);
+    let up_right = !connects(1, 1);
+    let up_left = !connects(-1, 1);
+
+    match index {
+        13 if down_right => 4,
+        15 if down_left => 5,
+        37 if up_right => 16,
+        39 if up_left => 17,
+        14 => match (down_right, down_left) {
+            (true, true) => 7,
+            (false, true) => 31,
+            (true, false) => 29,
+            (false, false) => 14,
+        },
+        25 => match (down_right, up_right) {
+            (true, true) => 6,
+            (true, false) => 30,
+            (false, true) => 28,
+            (false, false) => 25,
+        },
+        27 => match (up_left, down_left) {
+            (true, true) => 19,
+            (false, true) => 41,
+            (true, false) => 43,
+            (false, false) => 27,
+        },
+        38 => match (up_left, up_right) {
+            (true, true) => 18,
+            (true, false) => 40,
+            (false, true) => 42,
+            (false, false) => 38,
+        },
+        26 => match (down_right, down_left, up_right, up_left) {
+            (true, true, true, true) => 46,
+            (false, true, true, true) => 9,
+            (true, false, true, true) => 21,
+            (true, true, false, true) => 8,
+            (true, true, true, false) => 20,
+            (true, true, false, false) => 11,
+            (false, false, true, true) => 22,
+            (false, true, false, true) => 23,
+            (true, false, true, false) => 10,
+            (true, false, false, true) => 34,
+            (false, true, true, false) => 35,
+            (true, false, false, false) => 32,
+            (false, true, false, false) => 33,
+            (false, false, true, false) => 44,
+            (false, false, false, true) => 45,
+            (false, false, false, false) => 26,
+        },
+        index => index,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The tile for a face joined by the neighbours at the given texture space
+    /// offsets.
+    fn tile(connected: &[(i32, i32)]) -> usize {
+        ctm_index(|dx, dy| connected.contains(&(dx, dy)))
+    }
+
+    const SIDES: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+    const DIAGONALS: [(i32, i32); 4] = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
+
+    #[test]
+    fn no_connections() {
+        assert_eq!(tile(&[]), 0);
+        // diagonals only matter where two connected sides meet
+        assert_eq!(tile(&DIAGONALS), 0);
+    }
+
+    #[test]
+    fn all_connections() {
+        assert_eq!(tile(&[SIDES, DIAGONALS].concat()), 26);
+    }
+
+    #[test]
+    fn straight_runs() {
+        assert_eq!(tile(&[(1, 0)]), 1);
+        assert_eq!(tile(&[(-1, 0), (1, 0)]), 2);
+        assert_eq!(tile(&[(-1, 0)]), 3);
+        assert_eq!(tile(&[(0, -1)]), 12);
+        assert_eq!(tile(&[(0, -1), (0, 1)]), 24);
+        assert_eq!(tile(&[(0, 1)]), 36);
+    }
+
+    #[test]
+    fn corners_without_diagonal() {
+        assert_eq!(tile(&[(1, 0), (0, -1)]), 4);
+        assert_eq!(tile(&[(-1, 0), (0, -1)]), 5);
+        assert_eq!(tile(&[(1, 0), (0, 1)]), 16);
+        assert_eq!(tile(&[(-1, 0), (0, 1)]), 17);
+        assert_eq!(tile(&SIDES), 46);
+    }
+
+    #[test]
+    fn corners_with_diagonal() {
+        assert_eq!(tile(&[(1, 0), (0, -1), (1, -1)]), 13);
+        assert_eq!(tile(&[(-1, 0), (0, -1), (-1, -1)]), 15);
+        assert_eq!(tile(&[(1, 0), (0, 1), (1, 1)]), 37);
+        assert_eq!(tile(&[(-1, 0), (0, 1), (-1, 1)]), 39);
+        // only the bottom right corner is cut off
+        assert_eq!(
+            tile(&[&SIDES[..], &[(-1, -1), (-1, 1), (1, 1)]].concat()),
+            32
+        );
+    }
+
+    #[test]
+    fn edges_pick_corners_from_diagonals() {
+        // connected everywhere but up
+        let sides = [(-1, 0), (1, 0), (0, -1)];
+        assert_eq!(tile(&sides), 7);
+        assert_eq!(tile(&[&sides[..], &[(1, -1), (-1, -1)]].concat()), 14);
+        assert_eq!(tile(&[&sides[..], &[(-1, -1)]].concat()), 29);
+        assert_eq!(tile(&[&sides[..], &[(1, -1)]].concat()), 31);
+    }
+}
@@ -0,0 +1,179 @@
+//! Plain full cubes, whose faces can be built by the gpu mesher instead of on
+//! the worker threads.
+
+use azalea::{
+    blocks::{BlockState, BlockTrait},
+    physics::collision::BlockWithShape,
+};
+use azalea_assets::{Assets, processed::model::RenderLayer};
+use glam::{IVec3, Vec3};
+
+use crate::renderer::{
+    chunk::LocalSection,
+    world_renderer::mesher::{
+        MeshBuilder,
+        block::{face_for_direction, face_is_occluded},
+        emission::is_emissive,
+        helpers::{FACES, generate_uv, remap_uv_to_atlas},
+        render_layer::render_layer,
+    },
+};
+
+/// Number of `u32`s holding one occluder bit per block of a padded section.
+pub const OCCLUDER_WORDS: usize = (18 * 18 * 18usize).div_ceil(32);
+
+/// Blocks drawn as a single opaque 16x16x16 cube, and the textures of their
+/// faces.
+pub struct CubeTable {
+    /// Cube id plus one for each block state, 0 for blocks that aren't simple
+    /// cubes.
+    ids: Vec<u32>,
+    /// Six faces per cube in the order of [`FACES`].
+    pub faces: Vec<CubeFace>,
+}
+
+/// Texture of one cube face, laid out as the gpu mesher reads it.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct CubeFace {
+    /// Uvs of the corners of the face.
+    pub uvs: [[f32; 2]; 4],
+    /// Texture array layer of the sprite.
+    pub layer: u32,
+}
+
+impl CubeTable {
+    /// Builds the table with face uvs inset by `uv_inset` texels, see
+    /// [`remap_uv_to_atlas`].
+    pub fn new(assets: &Assets, uv_inset: f32) -> Self {
+        let mut ids = Vec::with_capacity(BlockState::MAX_STATE as usize + 1);
+        let mut faces = Vec::new();
+
+        for raw in 0..=BlockState::MAX_STATE {
+            let state = BlockState::try_from(raw).unwrap();
+            match cube_faces(assets, state, uv_inset) {
+                Some(cube) => {
+                    faces.extend_from_slice(&cube);
+                    ids.push((faces.len() / 6) as u32);
+                }
+                None => ids.push(0),
+            }
+        }
+
+        Self { ids, faces }
+    }
+
+    pub fn cube_id(&self, state: BlockState) -> Option<u32> {
+        self.ids[state.id() as usize].checked_sub(1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.faces.len() / 6
+    }
+}
+
+/// The texture of each face if `state` is an opaque full cube without
+/// rotations, tints, connected textures or custom uvs.
+fn cube_faces(assets: &Assets, state: BlockState, uv_inset: f32) -> Option<[CubeFace; 6]> {
+    if state.is_air()
+        || !state.to_trait().behavior().can_occlude
+        || !state.is_collision_shape_full()
+        || is_emissive(state)
+        || render_layer(assets, state) != RenderLayer::Opaque
+    {
+        return None;
+    }
+
+    // blocks picking one of several models by position are meshed on the cpu
+    let [part] = assets.get_variant_parts(state) else {
+        return None;
+    };
+    let [desc] = part.as_slice() else {
+        return None;
+    };
+    let model = &desc.model;
+    let [element] = model.elements.as_slice() else {
+        return None;
+    };
+    if desc.x_rotation != 0
+        || desc.y_rotation != 0
+        || !model.ambient_occlusion
+        || element.rotation.is_some()
+        || element.from != Vec3::ZERO
+        || element.to != Vec3::splat(16.0)
+    {
+        return None;
+    }
+
+    let mut faces = [CubeFace::default(); 6];
+    for (face, cube_face) in FACES.iter().zip(&mut faces) {
+        let model_face = face_for_direction(element, face.dir)?;
+        let full_uv = model_face.uv.is_none_or(|uv| uv == [0.0, 0.0, 16.0, 16.0]);
+        if !full_uv
+            || model_face.cullface.is_none()
+            || model_face.rotation != 0
+            || model_face.tintindex != -1
+        {
+            return None;
+        }
+
+        let sprite = model.resolve_texture(&model_face.texture)?;
+        if assets.get_connected_texture(state, sprite).is_some() {
+            return None;
+        }
+        let spr = assets.sprite_uv(sprite)?;
+
+        let face_uvs = generate_uv(face.dir, None);
+        for (uv, face_uv) in cube_face.uvs.iter_mut().zip(face_uvs) {
+            *uv = remap_uv_to_atlas(face_uv, &spr, uv_inset);
+        }
+        cube_face.layer = spr.layer;
+    }
+
+    Some(faces)
+}
+
+/// Exposed cube faces of a section, meshed on the gpu.
+pub struct CubeFaces {
+    /// One face per entry, see [`pack_face`].
+    pub faces: Vec<u32>,
+    /// One bit per block of the padded section, set for blocks that darken the
+    /// ambient occlusion of their neighbours.
+    pub occluders: Vec<u32>,
+}
+
+/// Queues the faces of the cube at `local` that aren't hidden by a neighbour.
+pub fn mesh_cube(cube: u32, local: IVec3, builder: &mut MeshBuilder) {
+    for (i, face) in FACES.iter().enumerate() {
+        if !face_is_occluded(local, face.dir, builder.section, builder.fast_leaves) {
+            builder.cube_faces.push(pack_face(local, i, cube));
+        }
+    }
+}
+
+/// Packs a face as 4 bits each for the block's x, y and z in the section, 3
+/// bits for the index into [`FACES`] and the cube id in the remaining bits.
+fn pack_face(local: IVec3, face: usize, cube: u32) -> u32 {
+    let pos = local - IVec3::ONE;
+    pos.x as u32 | (pos.y as u32) << 4 | (pos.z as u32) << 8 | (face as u32) << 12 | cube << 15
+}
+
+/// Bit `(x * 18 + y) * 18 + z` is set if the block at `x, y, z` is an ambient
+/// occlusion occluder, the same test as [`super::helpers::compute_ao`].
+pub fn occluder_bits(section: &LocalSection) -> Vec<u32> {
+    let mut bits = vec![0; OCCLUDER_WORDS];
+    for x in 0..18 {
+        for y in 0..18 {
+            for z in 0..18 {
+                let Some(state) = section.blocks[x][y][z] else {
+                    continue;
+                };
+                if !state.is_air() && state.is_collision_shape_full() {
+                    let bit = (x * 18 + y) * 18 + z;
+                    bits[bit / 32] |= 1 << (bit % 32);
+                }
+            }
+        }
+    }
+    bits
+}
@@ -0,0 +1,46 @@
+//! Light emitted by blocks, azalea's registry does not carry it so the values
+//! are copied from vanilla's `Blocks`.
+
+use azalea::{
+    blocks::{BlockState, properties::Lit},
+    registry::Block,
+};
+
+/// Blocks emitting at least this much light are drawn at full brightness.
+const EMISSIVE_LIGHT: u8 = 10;
+
+/// Java: `BlockBehaviour.Properties.lightLevel`, only the brighter blocks.
+pub fn light_emission(state: BlockState) -> u8 {
+    let lit = || state.property::<Lit>().unwrap_or(false);
+
+    match Block::from(state) {
+        Block::Glowstone
+        | Block::SeaLantern
+        | Block::Shroomlight
+        | Block::JackOLantern
+        | Block::Lava
+        | Block::Beacon
+        | Block::Conduit
+        | Block::EndPortal
+        | Block::EndGateway
+        | Block::Fire
+        | Block::Lantern
+        | Block::OchreFroglight
+        | Block::VerdantFroglight
+        | Block::PearlescentFroglight => 15,
+        Block::RedstoneLamp | Block::Campfire if lit() => 15,
+        Block::Torch | Block::WallTorch | Block::EndRod => 14,
+        Block::SoulCampfire if lit() => 10,
+        Block::SoulFire
+        | Block::SoulLantern
+        | Block::SoulTorch
+        | Block::SoulWallTorch
+        | Block::CryingObsidian => 10,
+        Block::MagmaBlock => 3,
+        _ => 0,
+    }
+}
+
+pub fn is_emissive(state: BlockState) -> bool {
+    light_emission(state) >= EMISSIVE_LIGHT
+}
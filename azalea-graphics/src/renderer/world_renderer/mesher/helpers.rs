@@ -3,7 +3,7 @@ use azalea::{
     core::direction::Direction,
     physics::collision::BlockWithShape,
 };
-use azalea_assets::processed::{atlas::PlacedSprite, model::Cube};
+use azalea_assets::processed::{atlas::SpriteUv, model::Cube};
 use glam::IVec3;
 
 use crate::renderer::chunk::LocalSection;
@@ -70,19 +70,11 @@ pub const FACES: [Face; 6] = [
     },
 ];
 
-pub fn remap_uv_to_atlas(
-    uv_px: glam::Vec2,
-    spr: &PlacedSprite,
-    atlas_w: u32,
-    atlas_h: u32,
-) -> [f32; 2] {
-    let aw = atlas_w as f32;
-    let ah = atlas_h as f32;
-
-    let u0 = (spr.x as f32 + 0.5) / aw;
-    let v0 = (spr.y as f32 + 0.5) / ah;
-    let u1 = (spr.x as f32 + spr.width as f32 - 0.5) / aw;
-    let v1 = (spr.y as f32 + spr.height as f32 - 0.5) / ah;
+/// Maps a uv in `0..1` of the sprite into the atlas, inset by `inset` texels.
+/// Uvs are clamped to the sprite first, so a face covering the whole sprite
+/// spans exactly the inset rect and never reaches a neighbouring cell.
+pub fn remap_uv_to_atlas(uv_px: glam::Vec2, spr: &SpriteUv, inset: f32) -> [f32; 2] {
+    let [u0, v0, u1, v1] = spr.inset_uv(inset);
 
     let tu = (uv_px.x).clamp(0.0, 1.0);
     let tv = (uv_px.y).clamp(0.0, 1.0);
@@ -122,15 +114,8 @@ pub fn rotate_direction_vanilla(
     rots[(rots.len() as i32 + pos + offset) as usize % rots.len()]
 }
 
-pub fn quad_uvs(spr: &PlacedSprite, atlas_w: u32, atlas_h: u32) -> [[f32; 2]; 4] {
-    let aw = atlas_w as f32;
-    let ah = atlas_h as f32;
-
-    let u0 = spr.x as f32 / aw;
-    let v0 = spr.y as f32 / ah;
-    let u1 = (spr.x + spr.width) as f32 / aw;
-    let v1 = (spr.y + spr.height) as f32 / ah;
-
+pub fn quad_uvs(spr: &SpriteUv) -> [[f32; 2]; 4] {
+    let [u0, v0, u1, v1] = spr.uv;
     [[u0, v1], [u1, v1], [u1, v0], [u0, v0]]
 }
 
@@ -141,10 +126,10 @@ pub fn rotate_direction(dir: Direction, x_rot: i32, y_rot: i32) -> Direction {
     match x_rot.rem_euclid(360) {
         90 => {
             d = match d {
-                Direction::Up => Direction::South,
-                Direction::South => Direction::Down,
-                Direction::Down => Direction::North,
-                Direction::North => Direction::Up,
+                Direction::Up => Direction::North,
+                Direction::North => Direction::Down,
+                Direction::Down => Direction::South,
+                Direction::South => Direction::Up,
                 other => other,
             }
         }
@@ -159,10 +144,10 @@ pub fn rotate_direction(dir: Direction, x_rot: i32, y_rot: i32) -> Direction {
         }
         270 => {
             d = match d {
-                Direction::Up => Direction::North,
-                Direction::North => Direction::Down,
-                Direction::Down => Direction::South,
-                Direction::South => Direction::Up,
+                Direction::Up => Direction::South,
+                Direction::South => Direction::Down,
+                Direction::Down => Direction::North,
+                Direction::North => Direction::Up,
                 other => other,
             }
         }
@@ -213,22 +198,68 @@ pub fn rotate_uvs(uvs: [glam::Vec2; 4], deg: i32) -> [glam::Vec2; 4] {
 }
 
 /// Rotate offset coordinates by x and y rotations
-pub fn rotate_offset(mut p: glam::IVec3, x_rot: i32, y_rot: i32) -> glam::IVec3 {
+pub fn rotate_offset(p: glam::IVec3, x_rot: i32, y_rot: i32) -> glam::IVec3 {
+    rotate_position(p.as_vec3(), x_rot, y_rot).as_ivec3()
+}
+
+/// Rotate a position within the block around its center, first around the x
+/// axis and then around the y axis like vanilla's variant rotations.
+pub fn rotate_position(mut p: glam::Vec3, x_rot: i32, y_rot: i32) -> glam::Vec3 {
     match x_rot.rem_euclid(360) {
-        90 => p = glam::IVec3::new(p.x, 1 - p.z, p.y),
-        180 => p = glam::IVec3::new(p.x, 1 - p.y, 1 - p.z),
-        270 => p = glam::IVec3::new(p.x, p.z, 1 - p.y),
+        90 => p = glam::Vec3::new(p.x, p.z, 1.0 - p.y),
+        180 => p = glam::Vec3::new(p.x, 1.0 - p.y, 1.0 - p.z),
+        270 => p = glam::Vec3::new(p.x, 1.0 - p.z, p.y),
         _ => {}
     }
     match y_rot.rem_euclid(360) {
-        90 => p = glam::IVec3::new(1 - p.z, p.y, p.x),
-        180 => p = glam::IVec3::new(1 - p.x, p.y, 1 - p.z),
-        270 => p = glam::IVec3::new(p.z, p.y, 1 - p.x),
+        90 => p = glam::Vec3::new(1.0 - p.z, p.y, p.x),
+        180 => p = glam::Vec3::new(1.0 - p.x, p.y, 1.0 - p.z),
+        270 => p = glam::Vec3::new(p.z, p.y, 1.0 - p.x),
         _ => {}
     }
     p
 }
 
+/// Texture coordinates of a block local position on a face pointing `dir`,
+/// the same as [`generate_uv`] gives the corners of a full face without uvs.
+pub fn locked_uv(dir: Direction, pos: glam::Vec3) -> glam::Vec2 {
+    match dir {
+        Direction::Up => glam::Vec2::new(pos.x, pos.z),
+        Direction::Down => glam::Vec2::new(pos.x, 1.0 - pos.z),
+        Direction::North => glam::Vec2::new(1.0 - pos.x, 1.0 - pos.y),
+        Direction::South => glam::Vec2::new(pos.x, 1.0 - pos.y),
+        Direction::East => glam::Vec2::new(1.0 - pos.z, 1.0 - pos.y),
+        Direction::West => glam::Vec2::new(pos.z, 1.0 - pos.y),
+    }
+}
+
+/// Block local positions and uvs of the corners of `face` of `element`,
+/// rotated like the variant. With `uvlock` the uvs of a rotated face are
+/// projected from its rotated corners instead of taken from the model, so the
+/// texture stays aligned to the world.
+pub fn face_corners(
+    element: &Cube,
+    face: &Face,
+    model_uv: Option<[f32; 4]>,
+    x_rot: i32,
+    y_rot: i32,
+    uvlock: bool,
+) -> [(glam::Vec3, glam::Vec2); 4] {
+    let uvs = generate_uv(face.dir, model_uv);
+    let dir = rotate_direction(face.dir, x_rot, y_rot);
+    let locked = uvlock && (x_rot.rem_euclid(360), y_rot.rem_euclid(360)) != (0, 0);
+
+    std::array::from_fn(|i| {
+        let pos = rotate_position(
+            offset_to_coord(face.offsets[i], element) / 16.0,
+            x_rot,
+            y_rot,
+        );
+        let uv = if locked { locked_uv(dir, pos) } else { uvs[i] };
+        (pos, uv)
+    })
+}
+
 /// Convert offset to world coordinates
 pub fn offset_to_coord(offset: IVec3, element: &Cube) -> glam::Vec3 {
     glam::Vec3::new(
@@ -251,20 +282,20 @@ pub fn offset_to_coord(offset: IVec3, element: &Cube) -> glam::Vec3 {
 }
 
 /// Compute ambient occlusion for a vertex
+///
+/// Only blocks that are known to be there occlude. Padding from sections that
+/// are not loaded yet is left open, the section is re-meshed once they load so
+/// the edge matches the neighbour.
 pub fn compute_ao(local: IVec3, offset: IVec3, dir: Direction, section: &LocalSection) -> u32 {
     let get = |p: IVec3| {
         if p.x < 0 || p.y < 0 || p.z < 0 || p.x >= 18 || p.y >= 18 || p.z >= 18 {
             return false;
         }
-        let state =
-            section.blocks[p.x as usize][p.y as usize][p.z as usize].unwrap_or(BlockState::AIR);
-        let dyn_state = state.to_trait();
-
-        if state.is_air() {
+        let Some(state) = section.blocks[p.x as usize][p.y as usize][p.z as usize] else {
             return false;
-        }
+        };
 
-        state.is_collision_shape_full()
+        !state.is_air() && state.is_collision_shape_full()
     };
 
     let ox = offset.x * 2 - 1;
@@ -302,6 +333,22 @@ pub fn ao(side1: bool, side2: bool, corner: bool) -> u32 {
     }
 }
 
+/// A color derived from the id of `state`, the same for every run. Neighbouring
+/// ids get unrelated colors, so different states of a block stand out too.
+pub fn state_color(state: BlockState) -> [f32; 3] {
+    // murmur3's finalizer, spreads consecutive ids over all the bits
+    let mut h = state.id() as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^= h >> 16;
+
+    // kept away from black so ambient occlusion is still visible
+    let channel = |shift: u32| 0.2 + ((h >> shift) & 0xff) as f32 / 255.0 * 0.8;
+    [channel(0), channel(8), channel(16)]
+}
+
 /// Generate UV coordinates for a face
 
 pub fn generate_uv(dir: Direction, uvs: Option<[f32; 4]>) -> [glam::Vec2; 4] {
@@ -384,3 +431,148 @@ pub fn generate_uv(dir: Direction, uvs: Option<[f32; 4]>) -> [glam::Vec2; 4] {
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use azalea::{core::position::ChunkSectionPos, registry::Block};
+    use azalea_assets::processed::model::Faces;
+
+    use super::*;
+    use crate::renderer::world_renderer::mesher::test_section;
+
+    /// A stone floor at y = 0 with a pillar on each side of the x = 16 section
+    /// border.
+    fn world(pos: IVec3) -> Option<BlockState> {
+        let solid = pos.y == 0
+            || (pos.y == 1 && pos.x == 15 && pos.z == 3)
+            || (pos.y == 1 && pos.x == 16 && pos.z == 8);
+        Some(if solid {
+            BlockState::from(Block::Stone)
+        } else {
+            BlockState::AIR
+        })
+    }
+
+    fn full_cube() -> Cube {
+        Cube {
+            from: glam::Vec3::ZERO,
+            to: glam::Vec3::splat(16.0),
+            rotation: None,
+            faces: Faces {
+                down: None,
+                up: None,
+                north: None,
+                south: None,
+                west: None,
+                east: None,
+            },
+        }
+    }
+
+    #[test]
+    fn rotated_faces_face_rotated_direction() {
+        let cube = full_cube();
+        for x_rot in [0, 90, 180, 270] {
+            for y_rot in [0, 90, 180, 270] {
+                for face in &FACES {
+                    let dir = rotate_direction(face.dir, x_rot, y_rot);
+                    let on_face = |p: glam::Vec3| match dir {
+                        Direction::Up => p.y == 1.0,
+                        Direction::Down => p.y == 0.0,
+                        Direction::North => p.z == 0.0,
+                        Direction::South => p.z == 1.0,
+                        Direction::East => p.x == 1.0,
+                        Direction::West => p.x == 0.0,
+                    };
+                    let corners = face_corners(&cube, face, None, x_rot, y_rot, false);
+                    assert!(
+                        corners.iter().all(|&(p, _)| on_face(p)),
+                        "{:?} rotated by x {x_rot} y {y_rot} isn't {dir:?}",
+                        face.dir
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn uvlock_keeps_rotated_uvs_world_aligned() {
+        let cube = full_cube();
+        let up = &FACES[0];
+        let unrotated = face_corners(&cube, up, None, 0, 0, false);
+        let uv_at = |pos: glam::Vec3| unrotated.iter().find(|(p, _)| *p == pos).unwrap().1;
+
+        let rotated = face_corners(&cube, up, None, 0, 90, false);
+        let locked = face_corners(&cube, up, None, 0, 90, true);
+        for ((pos, uv), (locked_pos, locked_uv)) in rotated.into_iter().zip(locked) {
+            assert_eq!(pos, locked_pos);
+            // the texture turns with the block unless it's locked
+            assert_ne!(uv, uv_at(pos));
+            assert_eq!(locked_uv, uv_at(pos));
+        }
+    }
+
+    #[test]
+    fn full_face_stays_inside_inset_sprite() {
+        let spr = SpriteUv {
+            uv: [0.25, 0.5, 0.5, 0.75],
+            width: 16,
+            height: 16,
+            layer: 0,
+        };
+        let texel = 0.25 / 16.0;
+
+        let min = remap_uv_to_atlas(glam::Vec2::new(-1.0, 0.0), &spr, 0.5);
+        let max = remap_uv_to_atlas(glam::Vec2::new(2.0, 1.0), &spr, 0.5);
+        assert_eq!(min, [0.25 + texel * 0.5, 0.5 + texel * 0.5]);
+        assert_eq!(max, [0.5 - texel * 0.5, 0.75 - texel * 0.5]);
+
+        let none = remap_uv_to_atlas(glam::Vec2::ONE, &spr, 0.0);
+        assert_eq!(none, [0.5, 0.75]);
+
+        // a huge inset collapses onto the center instead of flipping
+        let center = remap_uv_to_atlas(glam::Vec2::ZERO, &spr, 100.0);
+        assert_eq!(center, [0.375, 0.625]);
+    }
+
+    #[test]
+    fn ao_continuous_across_sections() {
+        let west = test_section(ChunkSectionPos::new(0, 0, 0), world);
+        let east = test_section(ChunkSectionPos::new(1, 0, 0), world);
+
+        for z in 0..16 {
+            for oz in 0..2 {
+                // top of the floor at x = 15 in the west section and x = 16 in
+                // the east one share the vertices on the border
+                let west_ao = compute_ao(
+                    IVec3::new(16, 1, z + 1),
+                    IVec3::new(1, 1, oz),
+                    Direction::Up,
+                    &west,
+                );
+                let east_ao = compute_ao(
+                    IVec3::new(1, 1, z + 1),
+                    IVec3::new(0, 1, oz),
+                    Direction::Up,
+                    &east,
+                );
+                assert_eq!(west_ao, east_ao, "ao differs at z = {}", z + oz);
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_neighbours_do_not_occlude() {
+        let west = test_section(ChunkSectionPos::new(0, 0, 0), |pos| {
+            if pos.x >= 16 { None } else { world(pos) }
+        });
+
+        let ao = compute_ao(
+            IVec3::new(16, 1, 9),
+            IVec3::new(1, 1, 0),
+            Direction::Up,
+            &west,
+        );
+        assert_eq!(ao, 3);
+    }
+}
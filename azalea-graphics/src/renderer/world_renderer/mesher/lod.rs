@@ -0,0 +1,90 @@
+//! Reduced detail meshes for far away sections.
+//!
+//! A lod 1 section is meshed as an 8x8x8 grid of 2x2x2 cells that is scaled
+//! back up to the full section size. Cells are solid if any block inside them
+//! is, so the low detail mesh always covers the full detail one, and faces on
+//! the section border are never culled. Together this hides the cracks where
+//! sections of different levels meet.
+
+use azalea::{
+    blocks::{BlockState, BlockTrait},
+    core::position::ChunkSectionPos,
+    physics::collision::BlockWithShape,
+    registry::Block,
+};
+use glam::Vec3;
+
+use crate::renderer::{
+    chunk::LocalSection,
+    world_renderer::{BlockVertex, mesher::water::has_water},
+};
+
+/// Size of a cell in blocks at lod 1.
+pub const CELL_SIZE: usize = 2;
+/// Number of cells along each axis of a lod 1 section.
+pub const CELLS: i32 = (16 / CELL_SIZE) as i32;
+
+/// Builds a section where every block is one cell of `section`, neighbours are
+/// left empty so border faces are always emitted.
+pub fn downsample(section: &LocalSection) -> LocalSection {
+    let mut blocks = Box::new([[[None; 18]; 18]; 18]);
+
+    for x in 0..CELLS as usize {
+        for y in 0..CELLS as usize {
+            for z in 0..CELLS as usize {
+                blocks[x + 1][y + 1][z + 1] = Some(cell_block(section, x, y, z));
+            }
+        }
+    }
+
+    LocalSection {
+        blocks,
+        // biomes are looked up by block position, close enough at this distance
+        biomes: section.biomes.clone(),
+        spos: section.spos,
+    }
+}
+
+/// The block a cell is drawn as, the top most solid block so grass stays on
+/// top, otherwise water. Anything smaller than a full block is dropped,
+/// waterlogged ones turn into water.
+fn cell_block(section: &LocalSection, cx: usize, cy: usize, cz: usize) -> BlockState {
+    let mut water = None;
+
+    for y in (0..CELL_SIZE).rev() {
+        for x in 0..CELL_SIZE {
+            for z in 0..CELL_SIZE {
+                let Some(block) = section.blocks[cx * CELL_SIZE + x + 1][cy * CELL_SIZE + y + 1]
+                    [cz * CELL_SIZE + z + 1]
+                else {
+                    continue;
+                };
+                if block.is_air() {
+                    continue;
+                }
+
+                if block.to_trait().behavior().can_occlude && block.is_collision_shape_full() {
+                    return block;
+                }
+                if Block::from(block) == Block::Water {
+                    water.get_or_insert(block);
+                } else if has_water(block) {
+                    water.get_or_insert(BlockState::from(Block::Water));
+                }
+            }
+        }
+    }
+
+    water.unwrap_or(BlockState::AIR)
+}
+
+/// Scales vertices meshed from a downsampled section back up to the section
+/// size.
+pub fn scale_vertices(vertices: &mut [BlockVertex], spos: ChunkSectionPos) {
+    let origin = Vec3::new(spos.x as f32, spos.y as f32, spos.z as f32) * 16.0;
+
+    for vertex in vertices {
+        let pos = Vec3::from(vertex.position);
+        vertex.position = (origin + (pos - origin) * CELL_SIZE as f32).into();
+    }
+}
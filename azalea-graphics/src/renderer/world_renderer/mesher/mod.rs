@@ -1,11 +1,12 @@
 use std::{
     cmp::Ordering,
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     io::Cursor,
     sync::{
         Arc,
-        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering as AtomicOrdering},
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering as AtomicOrdering},
     },
+    time::{Duration, Instant},
 };
 
 use azalea::{
@@ -14,29 +15,46 @@ use azalea::{
         position::{ChunkPos, ChunkSectionPos},
         registry_holder::{BiomeData, RegistryHolder},
     },
-    registry::{Biome, Block, DataRegistry},
+    registry::{Biome, DataRegistry},
 };
-use azalea_assets::Assets;
+use azalea_assets::{Assets, processed::model::RenderLayer};
 use crossbeam::channel::{Receiver, Sender, unbounded};
 use glam::IVec3;
 use log::error;
 use parking_lot::{Mutex, RwLock};
 use simdnbt::Deserialize;
+use thread_priority::{ThreadPriority, set_current_thread_priority};
 
 use crate::renderer::{
     chunk::{LocalChunk, LocalSection},
+    entity_renderer::block_entity::is_block_entity,
     world_renderer::{
-        BlockVertex,
-        mesher::{block::mesh_block, water::mesh_water},
+        BlockEntity, BlockVertex, LeavesMode,
+        mesher::{
+            block::mesh_block,
+            cubes::mesh_cube,
+            water::{has_water, mesh_water},
+        },
         visibility::buffers::VisibilitySnapshot,
     },
 };
 
 mod block;
 mod block_colors;
+mod ctm;
+mod cubes;
+mod emission;
 mod helpers;
+mod lod;
+mod render_layer;
 mod water;
 
+pub use cubes::{CubeFace, CubeFaces, CubeTable, OCCLUDER_WORDS};
+
+/// A section is re-meshed at most this often, marks in between are coalesced
+/// into the next mesh.
+const MIN_REMESH_INTERVAL: Duration = Duration::from_millis(50);
+
 pub struct MeshData {
     pub vertices: Vec<BlockVertex>,
     pub indices: Vec<u32>,
@@ -47,14 +65,48 @@ struct WorkerContext {
     world: Arc<RwLock<azalea::world::Instance>>,
     dirty: Arc<Mutex<HashSet<ChunkSectionPos>>>,
     assets: Arc<Assets>,
+    cubes: Arc<CubeTable>,
     biome_cache: BiomeCache,
     shared_queue: SharedQueue,
     current_visibility: Mutex<Option<VisibilitySnapshot>>,
     result_tx: Sender<MeshResult>,
-    should_stop: AtomicBool,
+    /// Distance in chunks from which sections are meshed at lod 1, 0 disables
+    /// it.
+    lod_distance: AtomicU32,
+    /// Detail level of the last mesh built for each section.
+    mesh_lods: Mutex<HashMap<ChunkSectionPos, u8>>,
+    /// Leaves the faces of full cubes to the gpu mesher.
+    gpu_meshing: AtomicBool,
+    /// Bits of the `f32` number of texels face uvs are inset by.
+    uv_inset: AtomicU32,
+    /// Meshes leaves opaque and culls the faces behind them.
+    fast_leaves: AtomicBool,
+    /// Tints blocks by their state instead of their biome colors.
+    state_colors: AtomicBool,
+    /// Workers run at the lowest OS priority and yield after every section, so
+    /// they don't starve the render thread and the bot on machines with few
+    /// cores. Only read when a worker starts.
+    low_priority: AtomicBool,
+    /// Sections taking longer than this many nanoseconds to mesh are logged, 0
+    /// logs none.
+    slow_mesh_threshold_ns: AtomicU64,
+    /// Set to a new value from `next_generation` every time a section is
+    /// marked dirty, meshes built from an older generation are dropped.
+    /// Sections of unloaded chunks are removed. Only changed with `dirty`
+    /// locked.
+    generations: Mutex<HashMap<ChunkSectionPos, u64>>,
+    /// Shared by all sections, so a section that is unloaded and loaded again
+    /// never gets back a generation an old mesh was built from.
+    next_generation: AtomicU64,
+    /// When a worker last started meshing each section.
+    last_meshed: Mutex<HashMap<ChunkSectionPos, Instant>>,
 
     total_mesh_time_ns: AtomicU64,
     total_meshes: AtomicU64,
+    /// Meshes built without anything to draw.
+    empty_meshes: AtomicU64,
+    block_quads: AtomicU64,
+    water_quads: AtomicU64,
 }
 
 pub struct Mesher {
@@ -67,20 +119,56 @@ pub struct Mesher {
 
     worker_ctx: Arc<WorkerContext>,
 
-    worker_count: u32,
+    workers: Vec<Worker>,
 
     total_mesh_time_ns: AtomicU64,
     total_meshes: AtomicU64,
 }
 
+/// A running mesher worker thread, stopped by setting `stop` and unparking it.
+struct Worker {
+    handle: std::thread::JoinHandle<()>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Worker {
+    fn request_stop(&self) {
+        self.stop.store(true, AtomicOrdering::Release);
+        // the park token is kept if the thread isn't parked yet, so this can't be missed
+        self.handle.thread().unpark();
+    }
+
+    fn join(self) {
+        if self.handle.join().is_err() {
+            log::error!("Mesher worker panicked");
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 struct Job {
     prio: f32,
     spos: ChunkSectionPos,
+    lod: u8,
 }
 
+/// Higher for nearer sections, by their reversed depth, and for sections
+/// closer to the middle of the view.
 fn prio_for(vis: &VisibilitySnapshot, spos: ChunkSectionPos) -> f32 {
-    vis.section_depth(spos).unwrap_or(0.0)
+    let depth = vis.section_depth(spos).unwrap_or(0.0);
+    let center = (IVec3::new(spos.x, spos.y, spos.z) * 16 + 8).as_vec3();
+    let to_section = (center - vis.camera_pos).normalize_or_zero();
+    let alignment = to_section.dot(vis.forward) * 0.5 + 0.5;
+    depth * (1.0 + vis.view_weight * alignment)
+}
+
+fn lod_for(vis: &VisibilitySnapshot, spos: ChunkSectionPos, lod_distance: u32) -> u8 {
+    let dist = (spos.x - vis.cx).abs().max((spos.z - vis.cz).abs()) as u32;
+    if lod_distance > 0 && dist >= lod_distance {
+        1
+    } else {
+        0
+    }
 }
 
 struct SharedQueue {
@@ -131,7 +219,12 @@ impl SharedQueue {
         }
     }
 
-    fn clear_and_reprioritize(&self, vis: &VisibilitySnapshot, dirty: &HashSet<ChunkSectionPos>) {
+    fn clear_and_reprioritize(
+        &self,
+        vis: &VisibilitySnapshot,
+        dirty: &HashSet<ChunkSectionPos>,
+        lod_distance: u32,
+    ) {
         let mut jobs = Vec::new();
         let side = vis.radius * 2 + 1;
 
@@ -153,7 +246,8 @@ impl SharedQueue {
 
             if dirty.contains(&spos) {
                 let prio = prio_for(vis, spos);
-                jobs.push(Job { prio, spos });
+                let lod = lod_for(vis, spos, lod_distance);
+                jobs.push(Job { prio, spos, lod });
             }
         }
 
@@ -171,14 +265,23 @@ impl SharedQueue {
     }
 
     fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Number of jobs that haven't been picked up by a worker yet.
+    fn remaining(&self) -> usize {
         let idx = self.next_job_index.load(AtomicOrdering::Relaxed);
         let jobs = self.jobs.read();
-        idx >= jobs.len()
+        jobs.len().saturating_sub(idx)
     }
 }
 
 impl Mesher {
-    pub fn new(assets: Arc<Assets>, world: Arc<RwLock<azalea::world::Instance>>) -> Self {
+    pub fn new(
+        assets: Arc<Assets>,
+        world: Arc<RwLock<azalea::world::Instance>>,
+        cubes: Arc<CubeTable>,
+    ) -> Self {
         let num_threads = num_cpus::get().max(1) as u32 / 2;
 
         let (result_tx, result_rx) = unbounded::<MeshResult>();
@@ -188,19 +291,32 @@ impl Mesher {
         let shared_queue = SharedQueue::new();
         let current_visibility = Mutex::new(None::<VisibilitySnapshot>);
         let biome_cache = BiomeCache::from_registries(&world.read().registries);
-        let should_stop = AtomicBool::new(false);
 
         let worker_ctx = Arc::new(WorkerContext {
             world: Arc::clone(&world),
             dirty: Arc::clone(&dirty),
             assets: Arc::clone(&assets),
+            cubes,
             biome_cache,
             shared_queue,
             current_visibility,
             result_tx,
-            should_stop,
+            lod_distance: AtomicU32::new(0),
+            mesh_lods: Mutex::new(HashMap::new()),
+            gpu_meshing: AtomicBool::new(false),
+            uv_inset: AtomicU32::new(0.0f32.to_bits()),
+            fast_leaves: AtomicBool::new(false),
+            state_colors: AtomicBool::new(false),
+            low_priority: AtomicBool::new(false),
+            slow_mesh_threshold_ns: AtomicU64::new(0),
+            generations: Mutex::new(HashMap::new()),
+            next_generation: AtomicU64::new(0),
+            last_meshed: Mutex::new(HashMap::new()),
             total_mesh_time_ns: AtomicU64::new(0),
             total_meshes: AtomicU64::new(0),
+            empty_meshes: AtomicU64::new(0),
+            block_quads: AtomicU64::new(0),
+            water_quads: AtomicU64::new(0),
         });
 
         {
@@ -209,9 +325,15 @@ impl Mesher {
                 loop {
                     match visibility_rx.recv() {
                         Ok(new_vis) => {
+                            let lod_distance = ctx.lod_distance.load(AtomicOrdering::Relaxed);
+                            ctx.mark_lod_changes(&new_vis, lod_distance);
+
                             let dirty_set = ctx.dirty.lock().clone();
-                            ctx.shared_queue
-                                .clear_and_reprioritize(&new_vis, &dirty_set);
+                            ctx.shared_queue.clear_and_reprioritize(
+                                &new_vis,
+                                &dirty_set,
+                                lod_distance,
+                            );
                             *ctx.current_visibility.lock() = Some(new_vis);
                         }
                         Err(_) => break,
@@ -220,9 +342,9 @@ impl Mesher {
             });
         }
 
-        for i in 0..num_threads {
-            Self::spawn_worker(i, Arc::clone(&worker_ctx));
-        }
+        let workers = (0..num_threads)
+            .map(|i| Self::spawn_worker(i, Arc::clone(&worker_ctx)))
+            .collect();
 
         Self {
             result_rx,
@@ -231,7 +353,7 @@ impl Mesher {
             dirty,
             assets,
             worker_ctx,
-            worker_count: num_threads,
+            workers,
 
             total_mesh_time_ns: AtomicU64::new(0),
             total_meshes: AtomicU64::new(0),
@@ -254,65 +376,341 @@ impl Mesher {
         self.average_mesh_time_ns() / 1_000_000.0
     }
 
+    /// Biomes of the world's registries, parsed once when the mesher was
+    /// created.
+    pub fn biome_cache(&self) -> &BiomeCache {
+        &self.worker_ctx.biome_cache
+    }
+
+    /// Number of queued jobs that haven't been picked up by a worker yet.
+    pub fn pending_jobs(&self) -> usize {
+        self.worker_ctx.shared_queue.remaining()
+    }
+
+    /// Number of sections waiting to be meshed, including ones not yet queued.
+    pub fn dirty_count(&self) -> usize {
+        self.dirty.lock().len()
+    }
+
+    /// Total number of meshes built since the mesher was created.
+    pub fn completed_meshes(&self) -> u64 {
+        self.worker_ctx.total_meshes.load(AtomicOrdering::Relaxed)
+    }
+
+    /// Number of meshes built with nothing to draw, counted in
+    /// [`Self::completed_meshes`].
+    pub fn empty_meshes(&self) -> u64 {
+        self.worker_ctx.empty_meshes.load(AtomicOrdering::Relaxed)
+    }
+
+    /// Opaque and cutout quads generated since the mesher was created,
+    /// including ones replaced by later meshes of the same section.
+    pub fn block_quads(&self) -> u64 {
+        self.worker_ctx.block_quads.load(AtomicOrdering::Relaxed)
+    }
+
+    /// Water quads generated since the mesher was created, see
+    /// [`Self::block_quads`].
+    pub fn water_quads(&self) -> u64 {
+        self.worker_ctx.water_quads.load(AtomicOrdering::Relaxed)
+    }
+
+    /// Sections at least `distance` chunks away are meshed at lower detail, 0
+    /// always meshes at full detail.
+    pub fn set_lod_distance(&self, distance: u32) {
+        self.worker_ctx
+            .lod_distance
+            .store(distance, AtomicOrdering::Relaxed);
+    }
+
+    /// Logs sections taking longer than `threshold_ms` milliseconds to mesh at
+    /// debug level, 0 logs none.
+    pub fn set_slow_mesh_threshold(&self, threshold_ms: f32) {
+        let nanos = (threshold_ms.max(0.0) as f64 * 1_000_000.0) as u64;
+        self.worker_ctx
+            .slow_mesh_threshold_ns
+            .store(nanos, AtomicOrdering::Relaxed);
+    }
+
+    /// Leaves full cubes to the gpu mesher at full detail, every meshed section
+    /// is rebuilt when this changes.
+    pub fn set_gpu_meshing(&self, enabled: bool) {
+        let was = self
+            .worker_ctx
+            .gpu_meshing
+            .swap(enabled, AtomicOrdering::Relaxed);
+        if was != enabled {
+            self.remesh_all();
+        }
+    }
+
+    /// Insets face uvs by `texels` towards the sprite center, every meshed
+    /// section is rebuilt when this changes.
+    pub fn set_uv_inset(&self, texels: f32) {
+        let was = self
+            .worker_ctx
+            .uv_inset
+            .swap(texels.to_bits(), AtomicOrdering::Relaxed);
+        if was != texels.to_bits() {
+            self.remesh_all();
+        }
+    }
+
+    /// Switches between fast and fancy leaves, every meshed section is rebuilt
+    /// when this changes.
+    pub fn set_leaves_mode(&self, mode: LeavesMode) {
+        let fast = mode == LeavesMode::Fast;
+        let was = self
+            .worker_ctx
+            .fast_leaves
+            .swap(fast, AtomicOrdering::Relaxed);
+        if was != fast {
+            self.remesh_all();
+        }
+    }
+
+    /// Tints every block with [`helpers::state_color`] for debugging, every
+    /// meshed section is rebuilt when this changes. Full cubes stay on the
+    /// cpu while it's on, the gpu mesher has no tints.
+    pub fn set_state_colors(&self, enabled: bool) {
+        let was = self
+            .worker_ctx
+            .state_colors
+            .swap(enabled, AtomicOrdering::Relaxed);
+        if was != enabled {
+            self.remesh_all();
+        }
+    }
+
+    /// Marks every section that was meshed before dirty, so they are all
+    /// rebuilt with the current settings.
+    pub fn remesh_all(&self) {
+        let mesh_lods = self.worker_ctx.mesh_lods.lock();
+        let mut dirty = self.dirty.lock();
+        for &spos in mesh_lods.keys() {
+            self.worker_ctx.mark_dirty(&mut dirty, spos);
+        }
+    }
+
     pub fn submit_section(&self, spos: ChunkSectionPos) {
-        self.dirty.lock().insert(spos);
+        self.worker_ctx.mark_dirty(&mut self.dirty.lock(), spos);
     }
 
+    /// Also re-meshes the loaded chunks around `pos`, their borders were meshed
+    /// without its blocks.
     pub fn submit_chunk(&self, pos: ChunkPos) {
+        self.submit_around(pos, true);
+    }
+
+    /// Marks the sections of the loaded chunks around `pos` dirty, and of `pos`
+    /// itself if `center`.
+    fn submit_around(&self, pos: ChunkPos, center: bool) {
         let world = self.world.read();
         let min = world.chunks.min_y / 16;
         let max = min + world.chunks.height as i32 / 16;
-        for y in min..max {
-            let spos = ChunkSectionPos::new(pos.x, y, pos.z);
-            self.submit_section(spos);
+        for dx in -1..=1 {
+            for dz in -1..=1 {
+                let chunk = ChunkPos::new(pos.x + dx, pos.z + dz);
+                let skipped = if (dx, dz) == (0, 0) {
+                    !center
+                } else {
+                    world.chunks.get(&chunk).is_none()
+                };
+                if skipped {
+                    continue;
+                }
+                for y in min..max {
+                    self.submit_section(ChunkSectionPos::new(chunk.x, y, chunk.z));
+                }
+            }
         }
     }
 
     pub fn poll(&self) -> Option<MeshResult> {
-        self.result_rx.try_recv().ok()
+        loop {
+            let result = self.result_rx.try_recv().ok()?;
+            // meshed before its chunk was unloaded
+            let spos = result.blocks.section_pos;
+            if self
+                .world
+                .read()
+                .chunks
+                .get(&ChunkPos::new(spos.x, spos.z))
+                .is_some()
+            {
+                return Some(result);
+            }
+        }
+    }
+
+    /// Forgets the sections of an unloaded chunk, meshes still being built for
+    /// them are dropped. The chunks around it are re-meshed to show the faces
+    /// its blocks hid, see [`block::face_is_occluded`].
+    pub fn remove_chunk(&self, pos: ChunkPos) {
+        let (min, max) = {
+            let world = self.world.read();
+            let min = world.chunks.min_y / 16;
+            (min, min + world.chunks.height as i32 / 16)
+        };
+
+        {
+            // same lock order as `mark_lod_changes` and the workers
+            let mut mesh_lods = self.worker_ctx.mesh_lods.lock();
+            let mut dirty = self.dirty.lock();
+            let mut last_meshed = self.worker_ctx.last_meshed.lock();
+            let mut generations = self.worker_ctx.generations.lock();
+            for y in min..max {
+                let spos = ChunkSectionPos::new(pos.x, y, pos.z);
+                mesh_lods.remove(&spos);
+                dirty.remove(&spos);
+                last_meshed.remove(&spos);
+                generations.remove(&spos);
+            }
+        }
+
+        self.submit_around(pos, false);
     }
 
     pub fn update_visibility(&self, snapshot: VisibilitySnapshot) {
         let _ = self.visibility_tx.send(snapshot);
     }
 
-    fn spawn_worker(id: u32, ctx: Arc<WorkerContext>) {
-        std::thread::Builder::new()
+    /// Lowers the OS priority of the workers and makes them yield after every
+    /// section, the workers are restarted when this changes.
+    pub fn set_low_priority(&mut self, enabled: bool) {
+        let was = self
+            .worker_ctx
+            .low_priority
+            .swap(enabled, AtomicOrdering::Relaxed);
+        if was == enabled {
+            return;
+        }
+
+        for worker in &self.workers {
+            worker.request_stop();
+        }
+        let count = self.workers.len() as u32;
+        for worker in self.workers.drain(..) {
+            worker.join();
+        }
+        self.workers = (0..count)
+            .map(|i| Self::spawn_worker(i, Arc::clone(&self.worker_ctx)))
+            .collect();
+    }
+
+    fn spawn_worker(id: u32, ctx: Arc<WorkerContext>) -> Worker {
+        let stop = Arc::new(AtomicBool::new(false));
+        let worker_stop = Arc::clone(&stop);
+
+        let handle = std::thread::Builder::new()
             .name(format!("mesher-worker-{}", id))
             .spawn(move || {
+                let low_priority = ctx.low_priority.load(AtomicOrdering::Relaxed);
+                if low_priority && let Err(e) = set_current_thread_priority(ThreadPriority::Min) {
+                    log::warn!("Failed to lower the priority of mesher worker {id}: {e:?}");
+                }
+
                 loop {
-                    let job = match ctx.shared_queue.pop(&ctx.should_stop) {
+                    let job = match ctx.shared_queue.pop(&worker_stop) {
                         Some(j) => j,
                         None => break,
                     };
 
-                    {
+                    let generation = {
                         let mut d = ctx.dirty.lock();
-                        if !d.remove(&job.spos) {
+                        if !d.contains(&job.spos) {
                             continue;
                         }
-                    }
+                        // stays dirty and is queued again with the next
+                        // visibility update
+                        let mut last_meshed = ctx.last_meshed.lock();
+                        let now = Instant::now();
+                        if last_meshed
+                            .get(&job.spos)
+                            .is_some_and(|&t| now.duration_since(t) < MIN_REMESH_INTERVAL)
+                        {
+                            continue;
+                        }
+                        last_meshed.insert(job.spos, now);
+                        d.remove(&job.spos);
+                        ctx.generations.lock().get(&job.spos).copied()
+                    };
 
                     if let Some(local) = build_local_section(&ctx.world, job.spos) {
                         let t0 = std::time::Instant::now();
-                        let mesh = mesh_section(&local, &ctx.biome_cache, &ctx.assets);
+                        let state_colors = ctx.state_colors.load(AtomicOrdering::Relaxed);
+                        let cubes = (job.lod == 0
+                            && ctx.gpu_meshing.load(AtomicOrdering::Relaxed)
+                            && !state_colors)
+                            .then_some(&*ctx.cubes);
+                        let uv_inset = f32::from_bits(ctx.uv_inset.load(AtomicOrdering::Relaxed));
+                        let mesh = mesh_section(
+                            &local,
+                            &ctx.biome_cache,
+                            &ctx.assets,
+                            MeshOptions {
+                                lod: job.lod,
+                                cubes,
+                                uv_inset,
+                                fast_leaves: ctx.fast_leaves.load(AtomicOrdering::Relaxed),
+                                state_colors,
+                            },
+                        );
                         let elapsed = t0.elapsed();
 
                         let nanos = elapsed.as_nanos() as u64;
+                        let slow_threshold =
+                            ctx.slow_mesh_threshold_ns.load(AtomicOrdering::Relaxed);
+                        if slow_threshold != 0 && nanos > slow_threshold {
+                            log::debug!(
+                                "Meshing section {:?} at lod {} took {elapsed:?}, {} block and {} \
+                                 water quads",
+                                job.spos,
+                                job.lod,
+                                mesh.block_quads(),
+                                mesh.water_quads()
+                            );
+                        }
 
                         ctx.total_mesh_time_ns
                             .fetch_add(nanos, AtomicOrdering::Relaxed);
                         ctx.total_meshes.fetch_add(1, AtomicOrdering::Relaxed);
+                        if mesh.is_empty() {
+                            ctx.empty_meshes.fetch_add(1, AtomicOrdering::Relaxed);
+                        }
+                        ctx.block_quads
+                            .fetch_add(mesh.block_quads() as u64, AtomicOrdering::Relaxed);
+                        ctx.water_quads
+                            .fetch_add(mesh.water_quads() as u64, AtomicOrdering::Relaxed);
+
+                        {
+                            // the section changed or was unloaded while it was
+                            // meshed. Sending with the locks held keeps this
+                            // mesh from arriving after a newer one, and an
+                            // unloaded section from getting back into
+                            // `mesh_lods`.
+                            let mut mesh_lods = ctx.mesh_lods.lock();
+                            let generations = ctx.generations.lock();
+                            if generations.get(&job.spos).copied() != generation {
+                                continue;
+                            }
+                            mesh_lods.insert(job.spos, job.lod);
+                            let _ = ctx.result_tx.send(mesh);
+                        }
+                    }
 
-                        let _ = ctx.result_tx.send(mesh);
+                    if low_priority {
+                        std::thread::yield_now();
                     }
                 }
             })
             .unwrap();
+
+        Worker { handle, stop }
     }
 
     pub fn set_worker_threads(&mut self, new_thread_count: u32) {
-        let current = self.worker_count;
+        let current = self.workers.len() as u32;
 
         if new_thread_count == current {
             return;
@@ -320,11 +718,20 @@ impl Mesher {
 
         if new_thread_count > current {
             for i in current..new_thread_count {
-                Self::spawn_worker(i, Arc::clone(&self.worker_ctx));
+                self.workers
+                    .push(Self::spawn_worker(i, Arc::clone(&self.worker_ctx)));
+            }
+        } else {
+            let excess = self.workers.split_off(new_thread_count as usize);
+            for worker in &excess {
+                worker.request_stop();
+            }
+            // a worker finishes the section it's currently meshing before exiting
+            for worker in excess {
+                worker.join();
             }
         }
 
-        self.worker_count = new_thread_count;
         log::info!(
             "Worker thread count changed from {} to {}",
             current,
@@ -333,7 +740,39 @@ impl Mesher {
     }
 
     pub fn get_worker_thread_count(&self) -> u32 {
-        self.worker_count
+        self.workers.len() as u32
+    }
+}
+
+impl Drop for Mesher {
+    fn drop(&mut self) {
+        for worker in &self.workers {
+            worker.request_stop();
+        }
+        for worker in self.workers.drain(..) {
+            worker.join();
+        }
+        // the visibility thread exits on its own once `visibility_tx` is dropped
+    }
+}
+
+impl WorkerContext {
+    fn mark_dirty(&self, dirty: &mut HashSet<ChunkSectionPos>, spos: ChunkSectionPos) {
+        dirty.insert(spos);
+        let generation = self.next_generation.fetch_add(1, AtomicOrdering::Relaxed);
+        self.generations.lock().insert(spos, generation);
+    }
+
+    /// Marks sections dirty whose mesh was built at a different detail level
+    /// than the camera distance now asks for.
+    fn mark_lod_changes(&self, vis: &VisibilitySnapshot, lod_distance: u32) {
+        let mesh_lods = self.mesh_lods.lock();
+        let mut dirty = self.dirty.lock();
+        for (&spos, &lod) in mesh_lods.iter() {
+            if lod_for(vis, spos, lod_distance) != lod {
+                self.mark_dirty(&mut dirty, spos);
+            }
+        }
     }
 }
 
@@ -380,6 +819,26 @@ fn build_local_section(
 pub struct MeshResult {
     pub blocks: MeshData,
     pub water: MeshData,
+    pub block_entities: Vec<BlockEntity>,
+    /// Cube faces left to the gpu mesher, `None` if there are none.
+    pub cubes: Option<CubeFaces>,
+}
+
+impl MeshResult {
+    /// Opaque and cutout quads, including the cube faces left to the gpu
+    /// mesher.
+    pub fn block_quads(&self) -> usize {
+        self.blocks.indices.len() / 6 + self.cubes.as_ref().map_or(0, |c| c.faces.len())
+    }
+
+    pub fn water_quads(&self) -> usize {
+        self.water.indices.len() / 6
+    }
+
+    /// Whether the section has nothing to draw.
+    pub fn is_empty(&self) -> bool {
+        self.block_quads() == 0 && self.water_quads() == 0 && self.block_entities.is_empty()
+    }
 }
 
 pub struct MeshBuilder<'a> {
@@ -388,11 +847,20 @@ pub struct MeshBuilder<'a> {
     pub section: &'a LocalSection,
 
     pub biome_cache: &'a BiomeCache,
+    /// Texels face uvs are inset by, see [`helpers::remap_uv_to_atlas`].
+    pub uv_inset: f32,
+    /// See [`LeavesMode::Fast`].
+    pub fast_leaves: bool,
+    /// Tints blocks with [`helpers::state_color`] instead of their biome
+    /// colors.
+    pub state_colors: bool,
 
     block_vertices: Vec<BlockVertex>,
     block_indices: Vec<u32>,
     water_vertices: Vec<BlockVertex>,
     water_indices: Vec<u32>,
+    block_entities: Vec<BlockEntity>,
+    cube_faces: Vec<u32>,
 }
 
 impl<'a> MeshBuilder<'a> {
@@ -400,23 +868,23 @@ impl<'a> MeshBuilder<'a> {
         self.section.blocks[pos.x as usize][pos.y as usize][pos.z as usize]
     }
 
-    pub fn push_block_quad(&mut self, verts: [BlockVertex; 4]) {
-        let start = self.block_vertices.len() as u32;
-        self.block_vertices.extend_from_slice(&verts);
-        self.block_indices.extend_from_slice(&[
-            start,
-            start + 1,
-            start + 2,
-            start,
-            start + 2,
-            start + 3,
-        ]);
+    /// The pass `state` is drawn in, see [`render_layer::render_layer`].
+    pub fn render_layer(&self, state: BlockState) -> RenderLayer {
+        render_layer::render_layer(self.assets, state)
     }
 
-    pub fn push_water_quad(&mut self, verts: [BlockVertex; 4]) {
-        let start = self.water_vertices.len() as u32;
-        self.water_vertices.extend_from_slice(&verts);
-        self.water_indices.extend_from_slice(&[
+    /// Adds a quad to the mesh of `layer`. Opaque and cutout quads share the
+    /// block mesh, translucent ones are drawn with the water.
+    pub fn push_quad(&mut self, layer: RenderLayer, verts: [BlockVertex; 4]) {
+        let (vertices, indices) = match layer {
+            RenderLayer::Opaque | RenderLayer::Cutout => {
+                (&mut self.block_vertices, &mut self.block_indices)
+            }
+            RenderLayer::Translucent => (&mut self.water_vertices, &mut self.water_indices),
+        };
+        let start = vertices.len() as u32;
+        vertices.extend_from_slice(&verts);
+        indices.extend_from_slice(&[
             start,
             start + 1,
             start + 2,
@@ -427,6 +895,11 @@ impl<'a> MeshBuilder<'a> {
     }
 
     pub fn finish(self) -> MeshResult {
+        let cubes = (!self.cube_faces.is_empty()).then(|| CubeFaces {
+            occluders: cubes::occluder_bits(self.section),
+            faces: self.cube_faces,
+        });
+
         MeshResult {
             blocks: MeshData {
                 section_pos: self.section.spos,
@@ -438,24 +911,29 @@ impl<'a> MeshBuilder<'a> {
                 vertices: self.water_vertices,
                 indices: self.water_indices,
             },
+            block_entities: self.block_entities,
+            cubes,
         }
     }
 }
 
+/// Biome data keyed by registry (network) id.
 #[derive(Clone, Debug)]
 pub struct BiomeCache {
-    pub biomes: Vec<BiomeData>,
+    pub biomes: HashMap<u32, BiomeData>,
 }
 
 impl BiomeCache {
     fn from_registries(registries: &RegistryHolder) -> Self {
-        let mut biomes = Vec::new();
+        let mut biomes = HashMap::new();
 
         if let Some(biome_registry) = registries
             .map
             .get(&azalea::Identifier::new(Biome::NAME))
         {
-            for (_key, value) in biome_registry {
+            // registry entries are stored in network id order, so the index is the id even
+            // if an entry fails to parse and gets skipped
+            for (id, (_key, value)) in biome_registry.iter().enumerate() {
                 let mut nbt_bytes = Vec::new();
                 value.write(&mut nbt_bytes);
 
@@ -476,49 +954,389 @@ impl BiomeCache {
                     }
                 };
 
-                biomes.push(biome_data);
+                biomes.insert(id as u32, biome_data);
             }
         }
 
         BiomeCache { biomes }
     }
+
+    pub fn get(&self, biome: Biome) -> Option<&BiomeData> {
+        self.biomes.get(&biome.protocol_id())
+    }
+}
+
+/// How [`mesh_section`] meshes a section.
+#[derive(Clone, Copy, Default)]
+pub struct MeshOptions<'a> {
+    /// Meshes the section downsampled, 0 is full detail.
+    pub lod: u8,
+    /// Blocks that are cubes in this table are left out of the block mesh,
+    /// their faces end up in [`MeshResult::cubes`] instead.
+    pub cubes: Option<&'a CubeTable>,
+    /// Texels face uvs are inset by, see [`helpers::remap_uv_to_atlas`].
+    pub uv_inset: f32,
+    /// See [`LeavesMode::Fast`].
+    pub fast_leaves: bool,
+    /// Tints blocks with [`helpers::state_color`] instead of their biome
+    /// colors.
+    pub state_colors: bool,
 }
 
 pub fn mesh_section(
     section: &LocalSection,
     biome_cache: &BiomeCache,
     assets: &Assets,
+    options: MeshOptions,
 ) -> MeshResult {
+    let MeshOptions {
+        lod,
+        cubes,
+        uv_inset,
+        fast_leaves,
+        state_colors,
+    } = options;
     let block_colors = block_colors::BlockColors::create_default();
 
+    let lod_section;
+    let (meshed, size) = if lod > 0 {
+        lod_section = lod::downsample(section);
+        (&lod_section, lod::CELLS)
+    } else {
+        (section, 16)
+    };
+
     let mut builder = MeshBuilder {
         assets,
         block_colors: &block_colors,
-        section,
+        section: meshed,
         biome_cache,
+        uv_inset,
+        fast_leaves,
+        state_colors,
         block_vertices: Vec::with_capacity(1000),
         block_indices: Vec::with_capacity(1000),
         water_vertices: Vec::with_capacity(500),
         water_indices: Vec::with_capacity(500),
+        block_entities: Vec::new(),
+        cube_faces: Vec::new(),
     };
+    let origin = IVec3::new(section.spos.x, section.spos.y, section.spos.z) * 16;
 
+    // block entities are drawn at full detail regardless of the lod
     for y in 0..16 {
         for x in 0..16 {
             for z in 0..16 {
+                let block = section.blocks[x as usize + 1][y as usize + 1][z as usize + 1]
+                    .unwrap_or(BlockState::AIR);
+
+                if is_block_entity(block) {
+                    builder.block_entities.push(BlockEntity {
+                        pos: origin + IVec3::new(x, y, z),
+                        state: block,
+                    });
+                }
+            }
+        }
+    }
+
+    for y in 0..size {
+        for x in 0..size {
+            for z in 0..size {
                 let local = IVec3::new(x + 1, y + 1, z + 1);
-                let block = section.blocks[local.x as usize][local.y as usize][local.z as usize]
+                let block = meshed.blocks[local.x as usize][local.y as usize][local.z as usize]
                     .unwrap_or(BlockState::AIR);
 
                 if !block.is_air() {
-                    if Block::from(block) == Block::Water {
+                    if has_water(block) {
                         mesh_water(block, local, &mut builder);
                     }
 
-                    mesh_block(block, local, &mut builder);
+                    if let Some(cube) = cubes.and_then(|cubes| cubes.cube_id(block)) {
+                        mesh_cube(cube, local, &mut builder);
+                    } else {
+                        mesh_block(block, local, &mut builder);
+                    }
                 }
             }
         }
     }
 
+    if lod > 0 {
+        lod::scale_vertices(&mut builder.block_vertices, section.spos);
+        lod::scale_vertices(&mut builder.water_vertices, section.spos);
+    }
+
     builder.finish()
 }
+
+/// Meshes `state` alone at the origin with every face visible, for blocks
+/// drawn away from their section like falling sand.
+pub fn mesh_single_block(
+    state: BlockState,
+    biome_cache: &BiomeCache,
+    assets: &Assets,
+    uv_inset: f32,
+) -> MeshResult {
+    let mut blocks = Box::new([[[Some(BlockState::AIR); 18]; 18]; 18]);
+    blocks[1][1][1] = Some(state);
+    let section = LocalSection {
+        blocks,
+        biomes: Box::new([[[Default::default(); 4]; 4]; 4]),
+        spos: ChunkSectionPos::new(0, 0, 0),
+    };
+
+    mesh_section(
+        &section,
+        biome_cache,
+        assets,
+        MeshOptions {
+            uv_inset,
+            ..Default::default()
+        },
+    )
+}
+
+/// Builds the padded section at `spos` from the blocks given by `block_at`,
+/// for the tests of the mesher and its submodules.
+#[cfg(test)]
+fn test_section(
+    spos: ChunkSectionPos,
+    block_at: impl Fn(IVec3) -> Option<BlockState>,
+) -> LocalSection {
+    let origin = IVec3::new(spos.x, spos.y, spos.z) * 16;
+    let mut blocks = Box::new([[[None; 18]; 18]; 18]);
+    for x in 0..18 {
+        for y in 0..18 {
+            for z in 0..18 {
+                let local = IVec3::new(x as i32, y as i32, z as i32) - IVec3::ONE;
+                blocks[x][y][z] = block_at(origin + local);
+            }
+        }
+    }
+
+    LocalSection {
+        blocks,
+        biomes: Box::new([[[Default::default(); 4]; 4]; 4]),
+        spos,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use azalea::{blocks::properties::Waterlogged, registry::Block};
+    use azalea_assets::processed::{
+        VariantDesc,
+        atlas::{Atlas, PlacedSprite},
+        model::{BlockModel, Cube, Face, Faces},
+    };
+    use glam::Vec3;
+
+    use super::*;
+
+    /// A section at the origin holding `blocks` at their local positions and
+    /// air everywhere else, including the padding.
+    fn section(blocks: &[(IVec3, BlockState)]) -> LocalSection {
+        test_section(ChunkSectionPos::new(0, 0, 0), |pos| {
+            let state = blocks.iter().find(|&&(at, _)| at == pos);
+            Some(state.map_or(BlockState::AIR, |&(_, state)| state))
+        })
+    }
+
+    /// Stone as a full cube like `block/cube_all`, also used for ice, and the
+    /// sprites it and water are drawn with.
+    fn assets() -> Assets {
+        let face = |cullface: &str| {
+            Some(Face {
+                uv: None,
+                texture: "#all".to_string(),
+                cullface: Some(cullface.to_string()),
+                rotation: 0,
+                tintindex: -1,
+            })
+        };
+        let stone = BlockModel {
+            ambient_occlusion: true,
+            textures: HashMap::from([("all".to_string(), "block/stone".to_string())]),
+            elements: vec![Cube {
+                from: Vec3::ZERO,
+                to: Vec3::splat(16.0),
+                rotation: None,
+                faces: Faces {
+                    down: face("down"),
+                    up: face("up"),
+                    north: face("north"),
+                    south: face("south"),
+                    west: face("west"),
+                    east: face("east"),
+                },
+            }],
+            render_layer: None,
+        };
+        let cube = |model: &Arc<BlockModel>| {
+            vec![VariantDesc {
+                model: model.clone(),
+                x_rotation: 0,
+                y_rotation: 0,
+                uvlock: false,
+                weight: 1,
+            }]
+        };
+        let stone = Arc::new(stone);
+        let models = HashMap::from([
+            (BlockState::from(Block::Stone), cube(&stone)),
+            // vanilla draws ice translucent
+            (BlockState::from(Block::Ice), cube(&stone)),
+        ]);
+
+        let sprite = |x| PlacedSprite {
+            x,
+            y: 0,
+            width: 16,
+            height: 16,
+        };
+        let atlas = Atlas {
+            width: 48,
+            height: 16,
+            sprites: HashMap::from([
+                ("block/stone".to_string(), sprite(0)),
+                ("block/water_still".to_string(), sprite(16)),
+                ("block/water_flow".to_string(), sprite(32)),
+            ]),
+        };
+
+        Assets::from_models(models, atlas)
+    }
+
+    fn test_options() -> MeshOptions<'static> {
+        MeshOptions {
+            uv_inset: 0.5,
+            ..Default::default()
+        }
+    }
+
+    fn mesh(blocks: &[(IVec3, BlockState)]) -> MeshResult {
+        let biome_cache = BiomeCache {
+            biomes: HashMap::new(),
+        };
+        mesh_section(&section(blocks), &biome_cache, &assets(), test_options())
+    }
+
+    /// Whether every vertex of `mesh` lies inside the block at `pos`.
+    fn inside_block(mesh: &MeshData, pos: IVec3) -> bool {
+        mesh.vertices.iter().all(|v| {
+            let local = Vec3::from(v.position) - pos.as_vec3();
+            local.cmpge(Vec3::ZERO).all() && local.cmple(Vec3::ONE).all()
+        })
+    }
+
+    #[test]
+    fn single_cube_has_six_faces() {
+        let pos = IVec3::new(3, 4, 5);
+        let result = mesh(&[(pos, BlockState::from(Block::Stone))]);
+
+        assert_eq!(result.blocks.vertices.len(), 6 * 4);
+        assert_eq!(result.blocks.indices.len(), 6 * 6);
+        assert!(result.water.vertices.is_empty());
+        assert!(inside_block(&result.blocks, pos));
+        // nothing around it darkens the corners
+        assert!(result.blocks.vertices.iter().all(|v| v.ao == 3.0));
+    }
+
+    #[test]
+    fn shared_face_is_culled() {
+        let stone = BlockState::from(Block::Stone);
+        let result = mesh(&[(IVec3::new(3, 4, 5), stone), (IVec3::new(4, 4, 5), stone)]);
+
+        assert_eq!(result.blocks.vertices.len(), 10 * 4);
+        assert_eq!(result.blocks.indices.len(), 10 * 6);
+        // the faces at x = 4 are the hidden ones
+        let on_shared_plane = |v: &BlockVertex| v.position[0] == 4.0;
+        for quad in result.blocks.vertices.chunks(4) {
+            assert!(!quad.iter().all(on_shared_plane));
+        }
+    }
+
+    #[test]
+    fn single_block_is_fully_visible() {
+        let biome_cache = BiomeCache {
+            biomes: HashMap::new(),
+        };
+        let result =
+            mesh_single_block(BlockState::from(Block::Stone), &biome_cache, &assets(), 0.5);
+
+        assert_eq!(result.blocks.vertices.len(), 6 * 4);
+        assert!(inside_block(&result.blocks, IVec3::ZERO));
+    }
+
+    #[test]
+    fn faces_towards_unloaded_blocks_are_drawn() {
+        let stone = BlockState::from(Block::Stone);
+        let pos = IVec3::new(15, 4, 5);
+        let mut loaded = section(&[(pos, stone)]);
+        let mut unloaded = section(&[(pos, stone)]);
+        // the chunk to the east is loaded with stone in one and not loaded in
+        // the other
+        for y in 0..18 {
+            for z in 0..18 {
+                loaded.blocks[17][y][z] = Some(stone);
+                unloaded.blocks[17][y][z] = None;
+            }
+        }
+        let biome_cache = BiomeCache {
+            biomes: HashMap::new(),
+        };
+        let mesh =
+            |section: &LocalSection| mesh_section(section, &biome_cache, &assets(), test_options());
+
+        assert_eq!(mesh(&loaded).blocks.vertices.len(), 5 * 4);
+        assert_eq!(mesh(&unloaded).blocks.vertices.len(), 6 * 4);
+    }
+
+    #[test]
+    fn translucent_block_is_drawn_with_the_water() {
+        let pos = IVec3::new(3, 4, 5);
+        let result = mesh(&[(pos, BlockState::from(Block::Ice))]);
+
+        assert!(result.blocks.vertices.is_empty());
+        assert_eq!(result.water.vertices.len(), 6 * 4);
+        assert!(inside_block(&result.water, pos));
+    }
+
+    #[test]
+    fn water_source_is_lower_than_a_block() {
+        let pos = IVec3::new(8, 8, 8);
+        let result = mesh(&[(pos, BlockState::from(Block::Water))]);
+
+        assert!(result.blocks.vertices.is_empty());
+        // top, bottom and the four sides facing air
+        assert_eq!(result.water.vertices.len(), 6 * 4);
+        assert_eq!(result.water.indices.len(), 6 * 6);
+        assert!(inside_block(&result.water, pos));
+
+        let top = result
+            .water
+            .vertices
+            .iter()
+            .map(|v| v.position[1])
+            .fold(f32::MIN, f32::max);
+        assert!(top > pos.y as f32 && top < pos.y as f32 + 1.0);
+    }
+
+    #[test]
+    fn waterlogged_block_holds_water() {
+        let slab = (0..=BlockState::MAX_STATE)
+            .map(|raw| BlockState::try_from(raw).unwrap())
+            .find(|&state| {
+                Block::from(state) == Block::OakSlab
+                    && state.property::<Waterlogged>() == Some(true)
+            })
+            .unwrap();
+        let pos = IVec3::new(8, 8, 8);
+        let result = mesh(&[(pos, slab)]);
+
+        // the same water volume as a source, the slab itself has no model here
+        assert_eq!(result.water.vertices.len(), 6 * 4);
+        assert!(inside_block(&result.water, pos));
+    }
+}
@@ -0,0 +1,43 @@
+//! The pass each block is drawn in. Vanilla picks it in code, so its choices
+//! are copied here, and a model's `render_type` overrides them the way modded
+//! packs expect.
+
+use azalea::{
+    blocks::{BlockState, BlockTrait},
+    registry::Block,
+};
+use azalea_assets::{Assets, processed::model::RenderLayer};
+
+pub fn render_layer(assets: &Assets, state: BlockState) -> RenderLayer {
+    assets
+        .get_variant_descs(state)
+        .find_map(|desc| desc.model.render_layer)
+        .unwrap_or_else(|| vanilla_render_layer(state))
+}
+
+/// Java: `ItemBlockRenderTypes`, only its translucent blocks. Of the rest the
+/// blocks that don't occlude are drawn as cutout.
+fn vanilla_render_layer(state: BlockState) -> RenderLayer {
+    let block = state.to_trait();
+    let id = block.id();
+    let translucent = matches!(
+        Block::from(state),
+        Block::Water
+            | Block::Ice
+            | Block::FrostedIce
+            | Block::SlimeBlock
+            | Block::HoneyBlock
+            | Block::NetherPortal
+            | Block::TintedGlass
+            | Block::Tripwire
+    ) || id.ends_with("stained_glass")
+        || id.ends_with("stained_glass_pane");
+
+    if translucent {
+        RenderLayer::Translucent
+    } else if block.behavior().can_occlude {
+        RenderLayer::Opaque
+    } else {
+        RenderLayer::Cutout
+    }
+}
@@ -1,80 +1,148 @@
 use azalea::{
-    blocks::{BlockState, properties::WaterLevel},
+    blocks::{
+        BlockState,
+        properties::{WaterLevel, Waterlogged},
+    },
+    physics::collision::BlockWithShape,
     registry::Block,
 };
-use azalea_assets::processed::atlas::PlacedSprite;
+use azalea_assets::processed::{atlas::SpriteUv, model::RenderLayer};
 use glam::{IVec3, Vec3};
 
-use crate::renderer::world_renderer::{
-    BlockVertex,
-    mesher::{MeshBuilder, helpers::quad_uvs},
+use crate::renderer::{
+    chunk::LocalSection,
+    world_renderer::{
+        BlockVertex,
+        mesher::{
+            MeshBuilder,
+            helpers::{quad_uvs, state_color},
+        },
+    },
 };
 
-pub fn mesh_water(block: BlockState, local: IVec3, builder: &mut MeshBuilder) {
-    let tint = builder.block_colors.get_color(
-        block,
-        builder.section,
-        builder.biome_cache,
-        local,
-        0,
-        builder.assets,
-    );
-
-    let center_height = fluid_height(local, block, builder);
+/// Whether water is drawn in the block, water itself or a waterlogged block.
+pub fn has_water(state: BlockState) -> bool {
+    Block::from(state) == Block::Water || state.property::<Waterlogged>().unwrap_or(false)
+}
 
-    let h_north = fluid_height(local + IVec3::new(0, 0, -1), block, builder);
-    let h_south = fluid_height(local + IVec3::new(0, 0, 1), block, builder);
-    let h_east = fluid_height(local + IVec3::new(1, 0, 0), block, builder);
-    let h_west = fluid_height(local + IVec3::new(-1, 0, 0), block, builder);
+/// Meshes the water in `block`, which is either water or a waterlogged block
+/// holding a source.
+pub fn mesh_water(block: BlockState, local: IVec3, builder: &mut MeshBuilder) {
+    let tint = if builder.state_colors {
+        state_color(block)
+    } else {
+        builder.block_colors.get_color(
+            BlockState::from(Block::Water),
+            builder.section,
+            builder.biome_cache,
+            local,
+            0,
+            builder.assets,
+        )
+    };
 
-    let h_ne = average_heights(center_height, h_north, h_east);
-    let h_nw = average_heights(center_height, h_north, h_west);
-    let h_se = average_heights(center_height, h_south, h_east);
-    let h_sw = average_heights(center_height, h_south, h_west);
+    let corners = corner_heights(builder.section, local);
+    let [h_ne, h_nw, h_sw, h_se] = corners;
 
-    let still = builder.assets.get_sprite_rect("block/water_still").unwrap();
-    let flow = builder.assets.get_sprite_rect("block/water_flow").unwrap();
+    let still = builder.assets.sprite_uv("block/water_still").unwrap();
+    let flow = builder.assets.sprite_uv("block/water_flow").unwrap();
 
+    // like block faces, faces towards unloaded blocks are drawn
     if builder
         .block_state_at(local + IVec3::Y)
-        .map(Block::from)
-        .unwrap_or(Block::Water)
-        != Block::Water
+        .is_none_or(|above| !has_water(above))
     {
-        mesh_water_top(local, h_ne, h_nw, h_sw, h_se, still, flow, tint, builder);
+        mesh_water_top(local, h_ne, h_nw, h_sw, h_se, &still, &flow, tint, builder);
     }
 
     if builder
         .block_state_at(local - IVec3::Y)
-        .map(Block::from)
-        .unwrap_or(Block::Water)
-        != Block::Water
+        .is_none_or(|below| !has_water(below))
     {
-        mesh_water_bottom(local, still, tint, builder);
+        mesh_water_bottom(local, &still, tint, builder);
     }
 
-    mesh_water_sides(local, center_height, block, flow, tint, builder);
+    mesh_water_sides(local, corners, block, &flow, tint, builder);
 }
 
-fn fluid_height(local: IVec3, _block: BlockState, builder: &MeshBuilder) -> f32 {
-    let state = builder.section.blocks[local.x as usize][local.y as usize][local.z as usize]
-        .unwrap_or(BlockState::AIR);
-    if Block::from(state) == Block::Water {
-        let level = state.property::<WaterLevel>().unwrap() as u32;
-        if level == 0 {
-            1.0
+fn block_at(section: &LocalSection, local: IVec3) -> Option<BlockState> {
+    section.blocks[local.x as usize][local.y as usize][local.z as usize]
+}
+
+fn is_water(state: Option<BlockState>) -> bool {
+    state.is_some_and(has_water)
+}
+
+/// Height of the water in the block at `local` of the padded section, as in
+/// vanilla's `LiquidBlockRenderer`. Water below more water is full, other
+/// blocks are 0 if water could flow into them and -1 if they are solid or not
+/// loaded, which leaves them out of the corner averages. Waterlogged blocks
+/// hold a source.
+fn fluid_height(section: &LocalSection, local: IVec3) -> f32 {
+    let Some(state) = block_at(section, local) else {
+        return -1.0;
+    };
+    if !has_water(state) {
+        return if state.is_collision_shape_full() {
+            -1.0
         } else {
-            (8 - level) as f32 / 8.0
-        }
+            0.0
+        };
+    }
+    if is_water(block_at(section, local + IVec3::Y)) {
+        return 1.0;
+    }
+
+    // sources and falling water hold 8, flowing water one less per level
+    let level = state
+        .property::<WaterLevel>()
+        .map_or(0, |level| level as u32);
+    let amount = if level == 0 || level >= 8 {
+        8
     } else {
-        1.0
+        8 - level
+    };
+    amount as f32 / 9.0
+}
+
+/// Heights of the north east, north west, south west and south east corners
+/// of the water surface at `local`. Each corner averages the block with the
+/// three others touching it, which all lie in the section's padding, so the
+/// surface continues across section borders. Water below more water fills the
+/// whole block.
+fn corner_heights(section: &LocalSection, local: IVec3) -> [f32; 4] {
+    if is_water(block_at(section, local + IVec3::Y)) {
+        return [1.0; 4];
     }
+
+    let height = |offset: IVec3| fluid_height(section, local + offset);
+    let center = height(IVec3::ZERO);
+    let north = IVec3::new(0, 0, -1);
+    let south = IVec3::new(0, 0, 1);
+    let east = IVec3::new(1, 0, 0);
+    let west = IVec3::new(-1, 0, 0);
+
+    let corner =
+        |a: IVec3, b: IVec3| average_height(center, height(a), height(b), || height(a + b));
+    [
+        corner(north, east),
+        corner(north, west),
+        corner(south, west),
+        corner(south, east),
+    ]
 }
 
-fn average_heights(center: f32, a: f32, b: f32) -> f32 {
+/// Vanilla's `calculateAverageHeight`, heights close to full weigh ten times
+/// as much. The diagonal block only counts if water reaches the corner from
+/// one of the sides.
+fn average_height(center: f32, a: f32, b: f32, diagonal: impl FnOnce() -> f32) -> f32 {
+    if a >= 1.0 || b >= 1.0 {
+        return 1.0;
+    }
+
     let mut sum = 0.0;
     let mut weight = 0.0;
-    for &h in &[center, a, b] {
+    let mut add = |h: f32| {
         if h >= 0.8 {
             sum += h * 10.0;
             weight += 10.0;
@@ -82,7 +150,18 @@ fn average_heights(center: f32, a: f32, b: f32) -> f32 {
             sum += h;
             weight += 1.0;
         }
+    };
+    if a > 0.0 || b > 0.0 {
+        let diagonal = diagonal();
+        if diagonal >= 1.0 {
+            return 1.0;
+        }
+        add(diagonal);
     }
+    add(center);
+    add(a);
+    add(b);
+
     if weight > 0.0 { sum / weight } else { 0.0 }
 }
 
@@ -92,8 +171,8 @@ fn mesh_water_top(
     h_nw: f32,
     h_sw: f32,
     h_se: f32,
-    still: &PlacedSprite,
-    _flow: &PlacedSprite,
+    still: &SpriteUv,
+    _flow: &SpriteUv,
     tint: [f32; 3],
     builder: &mut MeshBuilder,
 ) {
@@ -103,35 +182,28 @@ fn mesh_water_top(
         (local.z - 1) as f32 + builder.section.spos.z as f32 * 16.0,
     );
 
+    // north is towards -z
     let positions = [
-        base + Vec3::new(0.0, h_sw, 0.0),
-        base + Vec3::new(0.0, h_nw, 1.0),
-        base + Vec3::new(1.0, h_ne, 1.0),
-        base + Vec3::new(1.0, h_se, 0.0),
+        base + Vec3::new(0.0, h_nw, 0.0),
+        base + Vec3::new(0.0, h_sw, 1.0),
+        base + Vec3::new(1.0, h_se, 1.0),
+        base + Vec3::new(1.0, h_ne, 0.0),
     ];
 
-    let uvs = quad_uvs(
-        still,
-        builder.assets.block_atlas.width,
-        builder.assets.block_atlas.height,
-    );
+    let uvs = quad_uvs(still);
 
     let quad: [BlockVertex; 4] = std::array::from_fn(|i| BlockVertex {
         position: positions[i].into(),
         ao: 3.0,
         uv: uvs[i],
         tint,
+        layer: still.layer,
     });
 
-    builder.push_water_quad(quad);
+    builder.push_quad(RenderLayer::Translucent, quad);
 }
 
-fn mesh_water_bottom(
-    local: IVec3,
-    still: &PlacedSprite,
-    tint: [f32; 3],
-    builder: &mut MeshBuilder,
-) {
+fn mesh_water_bottom(local: IVec3, still: &SpriteUv, tint: [f32; 3], builder: &mut MeshBuilder) {
     let base = Vec3::new(
         (local.x - 1) as f32 + builder.section.spos.x as f32 * 16.0,
         (local.y - 1) as f32 + builder.section.spos.y as f32 * 16.0,
@@ -145,27 +217,26 @@ fn mesh_water_bottom(
         base + Vec3::new(0.0, 0.0, 1.0),
     ];
 
-    let uvs = quad_uvs(
-        still,
-        builder.assets.block_atlas.width,
-        builder.assets.block_atlas.height,
-    );
+    let uvs = quad_uvs(still);
 
     let quad: [BlockVertex; 4] = std::array::from_fn(|i| BlockVertex {
         position: positions[i].into(),
         ao: 3.0,
         uv: uvs[i],
         tint,
+        layer: still.layer,
     });
 
-    builder.push_water_quad(quad);
+    builder.push_quad(RenderLayer::Translucent, quad);
 }
 
+/// Sides facing away from water, their top edges follow `corners` as given by
+/// [`corner_heights`].
 fn mesh_water_sides(
     local: IVec3,
-    height: f32,
+    corners: [f32; 4],
     _block: BlockState,
-    sprite: &PlacedSprite,
+    sprite: &SpriteUv,
     tint: [f32; 3],
     builder: &mut MeshBuilder,
 ) {
@@ -175,54 +246,103 @@ fn mesh_water_sides(
         (local.z - 1) as f32 + builder.section.spos.z as f32 * 16.0,
     );
 
+    let [h_ne, h_nw, h_sw, h_se] = corners;
     let dirs = [
         (
             IVec3::new(0, 0, -1),
             [Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)],
+            [h_nw, h_ne],
         ),
         (
             IVec3::new(0, 0, 1),
             [Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 1.0)],
+            [h_sw, h_se],
         ),
         (
             IVec3::new(-1, 0, 0),
             [Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0)],
+            [h_nw, h_sw],
         ),
         (
             IVec3::new(1, 0, 0),
             [Vec3::new(1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 1.0)],
+            [h_ne, h_se],
         ),
     ];
 
-    let uvs = quad_uvs(
-        sprite,
-        builder.assets.block_atlas.width,
-        builder.assets.block_atlas.height,
-    );
+    let uvs = quad_uvs(sprite);
+
+    for (offset, [low_a, low_b], [height_a, height_b]) in dirs {
+        if is_water(block_at(builder.section, local + offset)) {
+            continue;
+        }
+
+        let positions = [
+            base + low_a,
+            base + low_a + Vec3::new(0.0, height_a, 0.0),
+            base + low_b + Vec3::new(0.0, height_b, 0.0),
+            base + low_b,
+        ];
+
+        let quad: [BlockVertex; 4] = std::array::from_fn(|i| BlockVertex {
+            position: positions[i].into(),
+            ao: 3.0,
+            uv: uvs[i],
+            tint,
+            layer: sprite.layer,
+        });
+
+        builder.push_quad(RenderLayer::Translucent, quad);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use azalea::{blocks::blocks::Water, core::position::ChunkSectionPos};
+
+    use super::*;
+    use crate::renderer::world_renderer::mesher::test_section;
+
+    /// A stone floor at y = 0 with a stream one block deep flowing east from a
+    /// source at x = 12, across the x = 16 section border.
+    fn world(pos: IVec3) -> Option<BlockState> {
+        if pos.y == 0 {
+            return Some(BlockState::from(Block::Stone));
+        }
+        let level = pos.x - 12;
+        Some(if pos.y == 1 && (0..8).contains(&level) {
+            BlockState::from(Water {
+                level: WaterLevel::from(level as u16),
+            })
+        } else {
+            BlockState::AIR
+        })
+    }
+
+    #[test]
+    fn surface_slopes_continuously_across_sections() {
+        let west = test_section(ChunkSectionPos::new(0, 0, 0), world);
+        let east = test_section(ChunkSectionPos::new(1, 0, 0), world);
+
+        // the last block of the west section and the first of the east one
+        let [ne, _, _, se] = corner_heights(&west, IVec3::new(16, 2, 8));
+        let [_, nw, sw, _] = corner_heights(&east, IVec3::new(1, 2, 8));
+        assert_eq!((ne, se), (nw, sw));
 
-    for (offset, [low_a, low_b]) in dirs {
-        let neighbor = local + offset;
-        let maybe_state =
-            builder.section.blocks[neighbor.x as usize][neighbor.y as usize][neighbor.z as usize];
-
-        if let Some(state) = maybe_state {
-            if Block::from(state) != Block::Water {
-                let positions = [
-                    base + low_a,
-                    base + low_a + Vec3::new(0.0, height, 0.0),
-                    base + low_b + Vec3::new(0.0, height, 0.0),
-                    base + low_b,
-                ];
-
-                let quad: [BlockVertex; 4] = std::array::from_fn(|i| BlockVertex {
-                    position: positions[i].into(),
-                    ao: 3.0,
-                    uv: uvs[i],
-                    tint,
-                });
-
-                builder.push_water_quad(quad);
-            }
+        // the surface keeps falling towards the end of the stream
+        let mut previous = f32::INFINITY;
+        for x in 13..20 {
+            let (section, local) = if x < 16 {
+                (&west, IVec3::new(x + 1, 2, 8))
+            } else {
+                (&east, IVec3::new(x - 15, 2, 8))
+            };
+            let [ne, nw, sw, se] = corner_heights(section, local);
+            assert_eq!(ne, se);
+            assert_eq!(nw, sw);
+            assert!(nw > ne, "no slope at x = {x}: {nw} to {ne}");
+            assert!(nw <= previous);
+            previous = ne;
         }
     }
 }
@@ -1,17 +1,38 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use ash::vk;
-use azalea::core::position::ChunkSectionPos;
+use azalea::core::position::{ChunkPos, ChunkSectionPos};
+use vk_mem::MemoryUsage;
 
 use super::{
+    gpu_mesher::GpuMesher,
     mesher::{MeshResult, Mesher},
-    types::BlockVertex,
+    types::{BlockEntity, BlockVertex},
 };
-use crate::renderer::{frame_ctx::FrameCtx, mesh::Mesh, vulkan::context::VkContext};
+use crate::renderer::{
+    frame_ctx::FrameCtx,
+    mesh::Mesh,
+    vulkan::{buffer::Buffer, context::VkContext, frame_sync::MAX_FRAMES_IN_FLIGHT},
+};
+
+/// Smallest pooled buffer, smaller meshes get a buffer of this size.
+const MIN_POOLED_SIZE: vk::DeviceSize = 4096;
+/// Bytes of free buffers kept around for reuse, buffers freed past this are
+/// destroyed.
+const MAX_POOLED_BYTES: vk::DeviceSize = 64 * 1024 * 1024;
 
 pub struct MeshStore {
     pub blocks: HashMap<ChunkSectionPos, Mesh<BlockVertex>>,
     pub water: HashMap<ChunkSectionPos, Mesh<BlockVertex>>,
+    /// Full cubes meshed by the [`GpuMesher`], drawn like `blocks`.
+    pub gpu_blocks: HashMap<ChunkSectionPos, Mesh<BlockVertex>>,
+    pub block_entities: HashMap<ChunkSectionPos, Vec<BlockEntity>>,
+    /// Sections that were meshed without any geometry, skipped by the
+    /// visibility pass.
+    pub empty: HashSet<ChunkSectionPos>,
+    /// Buffers of the `blocks` and `water` meshes, reused when a section is
+    /// meshed again.
+    pool: BufferPool,
 }
 
 impl Default for MeshStore {
@@ -19,10 +40,77 @@ impl Default for MeshStore {
         Self {
             blocks: HashMap::new(),
             water: HashMap::new(),
+            gpu_blocks: HashMap::new(),
+            block_entities: HashMap::new(),
+            empty: HashSet::new(),
+            pool: BufferPool::default(),
         }
     }
 }
 
+/// Free list of device local mesh buffers, so re-meshing a section reuses the
+/// buffer of an old mesh instead of allocating a new one. Sizes are rounded up
+/// to a power of two, a freed buffer fits any later mesh of about its size.
+#[derive(Default)]
+struct BufferPool {
+    /// Buffers no frame uses anymore, by size.
+    free: HashMap<vk::DeviceSize, Vec<Buffer>>,
+    free_bytes: vk::DeviceSize,
+    /// Buffers of meshes replaced while recording each frame, free once that
+    /// frame's fence was waited on.
+    retired: [Vec<Buffer>; MAX_FRAMES_IN_FLIGHT],
+}
+
+impl BufferPool {
+    /// A buffer with room for at least `size` bytes.
+    fn take(&mut self, ctx: &VkContext, size: vk::DeviceSize) -> Buffer {
+        let size = size.next_power_of_two().max(MIN_POOLED_SIZE);
+        if let Some(buffer) = self.free.get_mut(&size).and_then(Vec::pop) {
+            self.free_bytes -= size;
+            return buffer;
+        }
+        Buffer::new(
+            ctx,
+            size,
+            vk::BufferUsageFlags::VERTEX_BUFFER
+                | vk::BufferUsageFlags::INDEX_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryUsage::AutoPreferDevice,
+            false,
+        )
+    }
+
+    /// Returns the buffer of a mesh that frames up to `frame` may still draw.
+    fn retire(&mut self, frame: usize, buffer: Buffer) {
+        self.retired[frame].push(buffer);
+    }
+
+    /// Frees the buffers retired the last time `frame` was recorded, the gpu
+    /// is done with them now that its fence was waited on.
+    fn recycle(&mut self, ctx: &VkContext, frame: usize) {
+        for mut buffer in self.retired[frame].drain(..) {
+            if self.free_bytes + buffer.size > MAX_POOLED_BYTES {
+                buffer.destroy(ctx);
+                continue;
+            }
+            self.free_bytes += buffer.size;
+            self.free.entry(buffer.size).or_default().push(buffer);
+        }
+    }
+
+    fn destroy(&mut self, ctx: &VkContext) {
+        let retired = self
+            .retired
+            .iter_mut()
+            .flat_map(|buffers| buffers.drain(..));
+        let free = self.free.drain().flat_map(|(_, buffers)| buffers);
+        for mut buffer in retired.chain(free) {
+            buffer.destroy(ctx);
+        }
+        self.free_bytes = 0;
+    }
+}
+
 impl MeshStore {
     pub fn insert_block(
         &mut self,
@@ -40,6 +128,54 @@ impl MeshStore {
         self.water.insert(key, mesh)
     }
 
+    pub fn insert_gpu_block(
+        &mut self,
+        key: ChunkSectionPos,
+        mesh: Mesh<BlockVertex>,
+    ) -> Option<Mesh<BlockVertex>> {
+        self.gpu_blocks.insert(key, mesh)
+    }
+
+    /// Removes the meshes and block entities of every section of the chunk at
+    /// `pos`, the meshes are returned to be destroyed once no frame uses them.
+    pub fn remove_chunk(&mut self, pos: ChunkPos) -> Vec<Mesh<BlockVertex>> {
+        let in_chunk = |spos: &ChunkSectionPos| spos.x == pos.x && spos.z == pos.z;
+        self.block_entities.retain(|spos, _| !in_chunk(spos));
+        self.empty.retain(|spos| !in_chunk(spos));
+
+        let mut removed = Vec::new();
+        for meshes in [&mut self.blocks, &mut self.water, &mut self.gpu_blocks] {
+            removed.extend(
+                meshes
+                    .extract_if(|spos, _| in_chunk(spos))
+                    .map(|(_, mesh)| mesh),
+            );
+        }
+        removed
+    }
+
+    /// Removes everything, for when the world is replaced. The meshes are
+    /// returned to be destroyed once no frame uses them.
+    pub fn clear(&mut self) -> Vec<Mesh<BlockVertex>> {
+        self.block_entities.clear();
+        self.empty.clear();
+        [&mut self.blocks, &mut self.water, &mut self.gpu_blocks]
+            .into_iter()
+            .flat_map(|meshes| meshes.drain().map(|(_, mesh)| mesh))
+            .collect()
+    }
+
+    /// Number of sections with a mesh or block entities.
+    pub fn section_count(&self) -> usize {
+        self.blocks
+            .keys()
+            .chain(self.water.keys())
+            .chain(self.gpu_blocks.keys())
+            .chain(self.block_entities.keys())
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
     pub fn drain_and_destroy(&mut self, ctx: &VkContext) {
         for (_, mut mesh) in self.blocks.drain() {
             mesh.destroy(ctx);
@@ -47,35 +183,90 @@ impl MeshStore {
         for (_, mut mesh) in self.water.drain() {
             mesh.destroy(ctx);
         }
+        for (_, mut mesh) in self.gpu_blocks.drain() {
+            mesh.destroy(ctx);
+        }
+        self.pool.destroy(ctx);
     }
 
-    pub fn process_mesher_results(&mut self, frame_ctx: &mut FrameCtx, mesher: &Option<Mesher>) {
+    /// Uploads finished meshes and queues their cube faces on `gpu_mesher`.
+    /// Stops after `max_uploads_per_frame` sections with geometry, the rest
+    /// stay in the mesher's queue.
+    pub fn process_mesher_results(
+        &mut self,
+        frame_ctx: &mut FrameCtx,
+        mesher: &Option<Mesher>,
+        gpu_mesher: &mut GpuMesher,
+    ) {
         let mut touched_buffers: Vec<vk::Buffer> = Vec::new();
+        let max_uploads = match frame_ctx.config.max_uploads_per_frame {
+            0 => usize::MAX,
+            max => max as usize,
+        };
+        let mut uploads = 0;
+        self.pool.recycle(frame_ctx.ctx, frame_ctx.frame_index);
+
+        while let Some(MeshResult {
+            blocks,
+            water,
+            block_entities,
+            cubes,
+        }) = mesher
+            .as_ref()
+            .filter(|_| uploads < max_uploads)
+            .and_then(|m| m.poll())
+        {
+            if !blocks.vertices.is_empty() || !water.vertices.is_empty() {
+                uploads += 1;
+            }
+
+            if blocks.vertices.is_empty() && water.vertices.is_empty() && cubes.is_none() {
+                self.empty.insert(blocks.section_pos);
+            } else {
+                self.empty.remove(&blocks.section_pos);
+            }
+
+            if block_entities.is_empty() {
+                self.block_entities.remove(&blocks.section_pos);
+            } else {
+                self.block_entities.insert(blocks.section_pos, block_entities);
+            }
+
+            match cubes {
+                Some(cubes) => gpu_mesher.queue(blocks.section_pos, cubes),
+                None => {
+                    gpu_mesher.cancel(blocks.section_pos);
+                    if let Some(old_mesh) = self.gpu_blocks.remove(&blocks.section_pos) {
+                        frame_ctx.delete(old_mesh.buffer);
+                    }
+                }
+            }
 
-        while let Some(MeshResult { blocks, water }) = mesher.as_ref().and_then(|m| m.poll()) {
             if !blocks.vertices.is_empty() {
                 let staging_mesh =
                     Mesh::new_staging(frame_ctx.ctx, &blocks.vertices, &blocks.indices);
-                let mesh = staging_mesh.upload(frame_ctx.ctx, frame_ctx.cmd);
+                let buffer = self.pool.take(frame_ctx.ctx, staging_mesh.buffer.size);
+                let mesh = staging_mesh.upload(frame_ctx.ctx, frame_ctx.cmd, buffer);
                 frame_ctx.delete(staging_mesh.buffer);
 
                 touched_buffers.push(mesh.buffer.buffer);
 
                 if let Some(old_mesh) = self.insert_block(blocks.section_pos, mesh) {
-                    frame_ctx.delete(old_mesh.buffer);
+                    self.pool.retire(frame_ctx.frame_index, old_mesh.buffer);
                 }
             }
 
             if !water.vertices.is_empty() {
                 let staging_mesh =
                     Mesh::new_staging(frame_ctx.ctx, &water.vertices, &water.indices);
-                let mesh = staging_mesh.upload(frame_ctx.ctx, frame_ctx.cmd);
+                let buffer = self.pool.take(frame_ctx.ctx, staging_mesh.buffer.size);
+                let mesh = staging_mesh.upload(frame_ctx.ctx, frame_ctx.cmd, buffer);
                 frame_ctx.delete(staging_mesh.buffer);
 
                 touched_buffers.push(mesh.buffer.buffer);
 
                 if let Some(old_mesh) = self.insert_water(water.section_pos, mesh) {
-                    frame_ctx.delete(old_mesh.buffer);
+                    self.pool.retire(frame_ctx.frame_index, old_mesh.buffer);
                 }
             }
         }
@@ -104,3 +295,46 @@ impl MeshStore {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clear_leaves_no_sections() {
+        let mut store = MeshStore::default();
+        for y in -4..4 {
+            let spos = ChunkSectionPos::new(3, y, -2);
+            store.block_entities.insert(spos, Vec::new());
+            store.empty.insert(ChunkSectionPos::new(0, y, 0));
+        }
+        assert_eq!(store.section_count(), 8);
+
+        assert!(store.clear().is_empty());
+        assert_eq!(store.section_count(), 0);
+        assert!(store.empty.is_empty());
+    }
+
+    #[test]
+    fn remove_chunk_keeps_other_chunks() {
+        let mut store = MeshStore::default();
+        for x in 0..2 {
+            store
+                .block_entities
+                .insert(ChunkSectionPos::new(x, 0, 0), Vec::new());
+            store.empty.insert(ChunkSectionPos::new(x, 1, 0));
+        }
+
+        store.remove_chunk(ChunkPos::new(0, 0));
+        assert_eq!(store.section_count(), 1);
+        assert!(
+            store
+                .block_entities
+                .contains_key(&ChunkSectionPos::new(1, 0, 0))
+        );
+        assert_eq!(
+            store.empty.iter().collect::<Vec<_>>(),
+            [&ChunkSectionPos::new(1, 1, 0)]
+        );
+    }
+}
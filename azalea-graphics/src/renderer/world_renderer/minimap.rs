@@ -0,0 +1,146 @@
+//! Top down map of the loaded chunk columns around the camera, drawn with egui
+//! in a corner of the screen. Each column is one cell colored by the height of
+//! its surface.
+
+use std::collections::{HashMap, HashSet};
+
+use azalea::{
+    core::position::ChunkPos,
+    world::{Instance, heightmap::HeightmapKind},
+};
+use egui::{Color32, Rect, Shape, Stroke, Vec2};
+use glam::Vec3;
+
+/// Side of the map on screen, in points.
+const SIZE: f32 = 200.0;
+/// Chunks shown from the camera to each edge of the map.
+const RADIUS: f32 = 16.0;
+/// Columns recolored per tick, so a burst of loaded chunks doesn't stall a
+/// frame.
+const UPDATES_PER_TICK: usize = 64;
+
+const LOW: Color32 = Color32::from_rgb(30, 60, 140);
+const MID: Color32 = Color32::from_rgb(70, 150, 60);
+const HIGH: Color32 = Color32::from_rgb(235, 235, 235);
+/// Columns without a heightmap.
+const UNKNOWN: Color32 = Color32::from_rgb(90, 90, 90);
+
+#[derive(Default)]
+pub struct Minimap {
+    cells: HashMap<ChunkPos, Color32>,
+    /// Columns that were loaded or changed since they were last colored.
+    dirty: HashSet<ChunkPos>,
+}
+
+impl Minimap {
+    /// Recolors the column at `pos` on one of the next ticks.
+    pub fn mark(&mut self, pos: ChunkPos) {
+        self.dirty.insert(pos);
+    }
+
+    pub fn remove(&mut self, pos: ChunkPos) {
+        self.cells.remove(&pos);
+        self.dirty.remove(&pos);
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.dirty.clear();
+    }
+
+    /// Colors some of the dirty columns from the surface heightmaps of
+    /// `world`.
+    pub fn update(&mut self, world: &Instance) {
+        let batch: Vec<_> = self.dirty.iter().take(UPDATES_PER_TICK).copied().collect();
+        for pos in batch {
+            self.dirty.remove(&pos);
+            let Some(chunk) = world.chunks.get(&pos) else {
+                continue;
+            };
+            let chunk = chunk.read();
+            let color = match chunk.heightmaps.get(&HeightmapKind::WorldSurface) {
+                Some(heightmap) => {
+                    let height = heightmap.get_first_available(8, 8);
+                    let t = (height - world.chunks.min_y) as f32 / world.chunks.height as f32;
+                    height_color(t)
+                }
+                None => UNKNOWN,
+            };
+            self.cells.insert(pos, color);
+        }
+    }
+
+    /// Draws the map in the top right corner, centered on `camera_pos` with
+    /// north up and an arrow pointing along `forward`.
+    pub fn show(&self, ctx: &egui::Context, camera_pos: Vec3, forward: Vec3) {
+        egui::Area::new(egui::Id::new("minimap"))
+            .anchor(egui::Align2::RIGHT_TOP, Vec2::new(-10.0, 10.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                let (rect, _) = ui.allocate_exact_size(Vec2::splat(SIZE), egui::Sense::hover());
+                let painter = ui.painter_at(rect);
+                painter.rect_filled(rect, 0.0, Color32::from_black_alpha(160));
+
+                let scale = SIZE / (RADIUS * 2.0);
+                let center = rect.center();
+                let to_screen = |x: f32, z: f32| {
+                    center + Vec2::new(x - camera_pos.x, z - camera_pos.z) / 16.0 * scale
+                };
+
+                let camera_chunk = ChunkPos::new(
+                    (camera_pos.x / 16.0).floor() as i32,
+                    (camera_pos.z / 16.0).floor() as i32,
+                );
+                let radius = RADIUS.ceil() as i32 + 1;
+                for (pos, &color) in &self.cells {
+                    if (pos.x - camera_chunk.x).abs() > radius
+                        || (pos.z - camera_chunk.z).abs() > radius
+                    {
+                        continue;
+                    }
+                    let min = to_screen(pos.x as f32 * 16.0, pos.z as f32 * 16.0);
+                    let cell = Rect::from_min_size(min, Vec2::splat(scale));
+                    painter.rect_filled(cell, 0.0, color);
+                }
+
+                let heading = Vec2::new(forward.x, forward.z);
+                let heading = if heading.length_sq() > 0.0 {
+                    heading.normalized()
+                } else {
+                    Vec2::new(0.0, -1.0)
+                };
+                let side = heading.rot90();
+                let arrow = vec![
+                    center + heading * 8.0,
+                    center - heading * 5.0 + side * 5.0,
+                    center - heading * 5.0 - side * 5.0,
+                ];
+                painter.add(Shape::convex_polygon(
+                    arrow,
+                    Color32::RED,
+                    Stroke::new(1.0, Color32::BLACK),
+                ));
+                painter.rect_stroke(
+                    rect,
+                    0.0,
+                    Stroke::new(1.0, Color32::from_gray(200)),
+                    egui::StrokeKind::Inside,
+                );
+            });
+    }
+}
+
+/// Blue at the bottom of the world through green to white at the top.
+fn height_color(t: f32) -> Color32 {
+    let t = t.clamp(0.0, 1.0);
+    if t < 0.5 {
+        lerp_color(LOW, MID, t * 2.0)
+    } else {
+        lerp_color(MID, HIGH, t * 2.0 - 1.0)
+    }
+}
+
+fn lerp_color(a: Color32, b: Color32, t: f32) -> Color32 {
+    let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    Color32::from_rgb(lerp(a.r(), b.r()), lerp(a.g(), b.g()), lerp(a.b(), b.b()))
+}
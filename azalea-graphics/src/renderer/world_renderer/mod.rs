@@ -1,58 +1,130 @@
-use std::{array::from_fn, cmp::Ordering, collections::HashMap, sync::Arc};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use ash::vk;
-use azalea::core::position::ChunkSectionPos;
+use azalea::{
+    blocks::{
+        BlockState,
+        fluid_state::{FluidKind, FluidState},
+    },
+    core::{
+        direction::Direction,
+        position::{BlockPos, ChunkPos, ChunkSectionPos},
+    },
+    physics::collision::BlockWithShape,
+};
 use azalea_assets::{Assets, processed::atlas::TextureEntry};
-use glam::{Vec3, Vec4};
-use image::GenericImageView;
+use glam::{IVec3, Mat4, Vec3, Vec4};
 use vk_mem::MemoryUsage;
 
 use crate::{
     app::WorldUpdate,
     renderer::{
-        frame_ctx::FrameCtx, hiz, render_targets::RenderTargets, timings, utils::create_framebuffers, vulkan::{
+        frame_ctx::FrameCtx, hiz, mesh::Mesh, post_process::Submersion, render_targets::{CLEAR_DEPTH, RenderTargets}, timings, utils::create_framebuffers, vulkan::{
             buffer::Buffer,
-            context::VkContext,
-            frame_sync::{FrameSync, MAX_FRAMES_IN_FLIGHT},
+            context::{DeviceFeatures, VkContext},
+            frame_sync::FrameSync,
             texture::Texture,
         }, world_renderer::{
             aabb_renderer::AabbRenderer,
             animation::AnimationManager,
-            mesher::Mesher,
+            beams::BeamRenderer,
+            celestial::{CelestialRenderer, SkyLight, celestial_angle, sun_direction},
+            clouds::CloudRenderer,
+            gpu_mesher::GpuMesher,
+            mesher::{CubeTable, MeshResult, Mesher, mesh_single_block},
             render_pass::create_world_render_pass,
-            types::{VisibilityUniform},
-            visibility::{buffers::VisibilityBuffers, compute::VisibilityCompute},
+            types::VisibilityUniform,
+            visibility::{aabb_visible, buffers::VisibilityBuffers, compute::VisibilityCompute},
+            weather::{Precipitation, WeatherRenderer},
         }
     },
 };
 
 mod aabb_renderer;
 mod animation;
+mod beams;
+mod celestial;
+mod clouds;
 mod descriptors;
+mod gpu_mesher;
+mod hook;
 mod mesher;
 mod meshes;
+mod minimap;
+mod oit;
 mod pipelines;
+mod raycast;
 mod render_pass;
 mod types;
-mod visibility;
+pub(crate) mod visibility;
+mod weather;
 
 use descriptors::Descriptors;
 use meshes::MeshStore;
+use oit::OitRenderer;
 use pipelines::{PipelineOptions, Pipelines};
-use types::BlockVertex;
+use types::{BlockHighlight, BlockVertex};
+pub use hook::{CustomRenderCtx, CustomRenderHook};
+pub use minimap::Minimap;
+pub use types::{
+    BannerLayer, BeaconBeam, BeamSegment, BlockEntity, DebugBox, FallingBlock, LeavesMode, Weather,
+    WorldBorder, WorldTime,
+};
+
+/// Color of the world border lines.
+const WORLD_BORDER_COLOR: [f32; 4] = [0.2, 0.5, 1.0, 1.0];
+/// Vanilla's selection box is black at 40% opacity.
+const SELECTION_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.4];
+const SELECTION_GROW: f32 = 0.002;
+/// Tint of primed TNT while it flashes. Vanilla draws a white overlay, the
+/// block shader can only multiply the texture, so it's brightened instead.
+const TNT_FLASH_TINT: f32 = 2.5;
 
 pub struct WorldRenderer {
     mesher: Option<Mesher>,
+    /// Set when a world is added, until the mesher first runs out of work.
+    meshing_world: bool,
 
     animation_manager: AnimationManager,
     mesh_store: MeshStore,
+    cubes: Arc<CubeTable>,
+    gpu_mesher: GpuMesher,
 
     hiz_compute: hiz::HiZCompute,
     visibility_compute: VisibilityCompute,
     visibility_buffers: Option<VisibilityBuffers>,
     aabb_renderer: AabbRenderer,
-
-    visibility_uniforms: [Buffer; MAX_FRAMES_IN_FLIGHT],
+    clouds: CloudRenderer,
+    oit: OitRenderer,
+    weather_renderer: WeatherRenderer,
+    beam_renderer: BeamRenderer,
+    celestial: CelestialRenderer,
+    ticks: u64,
+    debug_box: Option<DebugBox>,
+    /// Outline of the targeted block, one box per part of its shape.
+    selection: Vec<DebugBox>,
+    /// Blocks outlined with [`Self::highlight_blocks`].
+    highlights: Vec<BlockHighlight>,
+    world_border: Option<WorldBorder>,
+    weather: Weather,
+    time: WorldTime,
+    /// Patterns of the banners whose block entity data was sent.
+    banner_patterns: HashMap<IVec3, Vec<BannerLayer>>,
+    beacon_beams: Vec<BeaconBeam>,
+    falling_blocks: Vec<FallingBlock>,
+    /// Meshes of the blocks falling blocks were drawn as, see
+    /// [`mesher::mesh_single_block`].
+    falling_block_meshes: HashMap<BlockState, MeshResult>,
+    minimap: Minimap,
+    /// Eye position of the bot, once it was sent.
+    player_position: Option<Vec3>,
+    render_hooks: Vec<Box<dyn CustomRenderHook>>,
+
+    visibility_uniforms: Vec<Buffer>,
 
     render_pass: vk::RenderPass,
     framebuffers: Vec<vk::Framebuffer>,
@@ -65,33 +137,246 @@ pub struct WorldRenderer {
 
 pub struct WorldRendererFeatures {
     pub fill_mode_non_solid: bool,
+    pub dynamic_cull_mode: bool,
 }
 
 impl Default for WorldRendererFeatures {
     fn default() -> Self {
         Self {
             fill_mode_non_solid: false,
+            dynamic_cull_mode: false,
         }
     }
 }
 
+#[derive(Clone, Copy, Default)]
+pub struct MesherStats {
+    pub dirty: usize,
+    pub pending_jobs: usize,
+    pub completed: u64,
+    /// Completed meshes without anything to draw.
+    pub empty: u64,
+    pub block_quads: u64,
+    pub water_quads: u64,
+    /// Sections whose cube faces wait for the gpu mesher.
+    pub gpu_pending: usize,
+}
+
 #[derive(Clone, Copy)]
 pub struct WorldRendererConfig {
     pub wireframe_mode: bool,
+    /// Skips the back faces of terrain, turning it off helps to find faces with
+    /// the wrong winding.
+    pub backface_culling: bool,
     pub render_aabbs: bool,
+    /// Hides AABB lines behind terrain instead of drawing them on top.
+    pub aabb_depth_test: bool,
+    /// AABB line width in pixels.
+    pub aabb_line_width: f32,
     pub disable_visibilty: bool,
+    /// Skips the culling tests for sections that were meshed without any
+    /// geometry, they are only kept at the lowest meshing priority.
+    pub cull_empty_sections: bool,
+    /// Stress mode for benchmarking the draw path: no frustum or HiZ culling,
+    /// every loaded section and entity within the render distance is meshed and
+    /// drawn.
+    pub render_everything: bool,
+    /// Draws only this many loaded sections nearest to the camera, visible or
+    /// not, to narrow down which section a rendering bug comes from. 0 draws
+    /// every section.
+    pub debug_section_limit: u32,
     pub render_distance: u32,
+    /// Moves `render_distance` between `min_render_distance` and
+    /// `max_render_distance` to keep the frame time near
+    /// `target_frame_time_ms`.
+    pub adaptive_render_distance: bool,
+    pub target_frame_time_ms: f32,
+    pub min_render_distance: u32,
+    pub max_render_distance: u32,
+    /// Distance in chunks from which sections are meshed at lower detail, 0
+    /// disables it.
+    pub lod_distance: u32,
+    /// Meshes sections in the middle of the view as if they were up to
+    /// `1 + view_priority` times nearer, so what the camera looks at loads
+    /// first. 0 orders sections by distance alone.
+    pub view_priority: f32,
     pub worker_threads: u32,
+    /// Runs the mesher workers at the lowest OS priority so heavy meshing
+    /// doesn't starve the render thread or the bot.
+    pub low_priority_workers: bool,
+    /// Sections taking longer than this many milliseconds to mesh are logged
+    /// at debug level with their quad counts, 0 disables it.
+    pub slow_mesh_threshold_ms: f32,
+    /// Sections whose meshes are uploaded per frame, the others stay queued
+    /// for the next frames. 0 uploads everything that finished.
+    pub max_uploads_per_frame: u32,
+    /// Draws the sky gradient, otherwise the background is the clear color.
+    pub render_sky: bool,
+    /// Draws the sun and moon by the time of day the server sends.
+    pub render_sun_and_moon: bool,
+    /// Uses `override_day_time` for the sky and lighting instead of the time
+    /// the server sends.
+    pub override_time: bool,
+    pub override_day_time: u32,
+    pub render_clouds: bool,
+    pub fancy_clouds: bool,
+    pub cloud_height: f32,
+    /// Draws rain and snow particles while the server says it's raining.
+    pub render_weather: bool,
+    /// Particles drawn around the camera at the heaviest rain.
+    pub weather_particles: u32,
+    /// Draws the beams of active beacons sent by the plugin.
+    pub render_beacon_beams: bool,
+    /// Fogs and tints the view while the camera is in water or lava.
+    pub submerged_fog: bool,
+    /// Fog per block while the camera is in water.
+    pub water_fog_density: f32,
+    /// Constant depth bias pulling water towards the camera, in units of the
+    /// depth buffer precision, so it doesn't z-fight with coplanar block faces.
+    pub water_depth_bias: f32,
+    /// Depth bias of water scaled by the depth slope of each triangle, for
+    /// faces seen at grazing angles.
+    pub water_depth_bias_slope: f32,
+    /// Samples block and entity textures with linear instead of nearest filtering.
+    /// Without mipmaps or padding between atlas sprites, this bleeds neighbouring
+    /// sprites into each other at the sprite edges.
+    pub linear_filtering: bool,
+    /// Texels block face uvs are pulled in towards the sprite center, so
+    /// filtering and rounding at the face edges don't sample the neighbouring
+    /// atlas sprites. Half a texel is enough.
+    pub uv_inset: f32,
+    /// Anisotropic filtering level of the block atlas, 1 disables it. Clamped
+    /// to what the device supports, see [`Self::anisotropy`]. Does little
+    /// without mipmaps.
+    pub anisotropy: u8,
+    /// Whether leaves are drawn like vanilla's fast or fancy graphics.
+    pub leaves_mode: LeavesMode,
+    /// Draws every block in a flat color picked from its block state instead
+    /// of its textures, to check which model and variant a block resolved to.
+    pub state_colors: bool,
+    /// Draws a map of the loaded chunks around the camera in a corner.
+    pub show_minimap: bool,
+    /// Opacity of the debug UI's window backgrounds.
+    pub ui_opacity: f32,
+    /// Darkens the scene behind the debug UI.
+    pub ui_backdrop: bool,
+    /// Skips entities whose bounding box is outside the view frustum.
+    pub cull_entities: bool,
+    /// Entities further than this many blocks from the camera aren't drawn,
+    /// even if their chunk is.
+    pub entity_render_distance: f32,
+    /// Draws chests and beds with their entity models.
+    pub render_block_entities: bool,
+    /// Draws a soft shadow on the blocks under zombies and players.
+    pub render_entity_shadows: bool,
+    /// Draws entities in one unlit color instead of their textures, to tell
+    /// model and transform bugs apart from missing textures.
+    pub flat_entity_color: bool,
+    /// Freezes the world: no world updates, ticks or mesh uploads are applied.
+    pub pause_world_updates: bool,
+    /// How much ambient occlusion darkens blocks, 0 disables it and 1 is full
+    /// strength.
+    pub ao_strength: f32,
+    /// Builds the faces of full cubes in a compute shader instead of on the
+    /// mesher threads.
+    pub gpu_meshing: bool,
+    /// Draws the x, y and z axes at `gizmo_origin` and a chunk grid on its
+    /// ground plane.
+    pub render_gizmo: bool,
+    pub gizmo_origin: IVec3,
+    /// Draws the borders of the camera's chunk like vanilla's F3+G.
+    pub render_chunk_borders: bool,
+    /// Outlines the shape of the block the camera points at.
+    pub render_selection_outline: bool,
+    /// Blends water with weighted blended order independent transparency
+    /// instead of drawing the sections back to front. Only the water meshes go
+    /// through it, with the translucent blocks that are meshed into them;
+    /// clouds, weather and entities are still blended in draw order. Its
+    /// attachments are only allocated while this is on.
+    pub oit_translucency: bool,
 }
 
 impl Default for WorldRendererConfig {
     fn default() -> Self {
         Self {
             wireframe_mode: false,
+            backface_culling: true,
             render_aabbs: false,
+            aabb_depth_test: false,
+            aabb_line_width: 1.0,
             disable_visibilty: false,
+            cull_empty_sections: true,
+            render_everything: false,
+            debug_section_limit: 0,
             render_distance: 32,
+            adaptive_render_distance: false,
+            target_frame_time_ms: 16.6,
+            min_render_distance: 4,
+            max_render_distance: 32,
+            lod_distance: 0,
+            view_priority: 1.0,
             worker_threads: num_cpus::get() as u32 / 2,
+            low_priority_workers: false,
+            slow_mesh_threshold_ms: 20.0,
+            max_uploads_per_frame: 0,
+            render_sky: true,
+            render_sun_and_moon: true,
+            override_time: false,
+            override_day_time: 6000,
+            render_clouds: true,
+            fancy_clouds: true,
+            cloud_height: 192.0,
+            render_weather: true,
+            weather_particles: 4096,
+            render_beacon_beams: true,
+            submerged_fog: true,
+            water_fog_density: 0.08,
+            water_depth_bias: 1.0,
+            water_depth_bias_slope: 1.0,
+            linear_filtering: false,
+            uv_inset: 0.5,
+            anisotropy: 1,
+            leaves_mode: LeavesMode::Fancy,
+            state_colors: false,
+            show_minimap: false,
+            ui_opacity: 1.0,
+            ui_backdrop: false,
+            cull_entities: true,
+            entity_render_distance: 64.0,
+            render_block_entities: true,
+            render_entity_shadows: true,
+            flat_entity_color: false,
+            pause_world_updates: false,
+            ao_strength: 1.0,
+            gpu_meshing: false,
+            render_gizmo: false,
+            gizmo_origin: IVec3::ZERO,
+            render_chunk_borders: false,
+            render_selection_outline: true,
+            oit_translucency: false,
+        }
+    }
+}
+
+impl WorldRendererConfig {
+    /// The OIT pass has no wireframe pipeline, wireframes are drawn sorted.
+    pub fn uses_oit(&self) -> bool {
+        self.oit_translucency && !self.wireframe_mode
+    }
+
+    /// The anisotropy the block atlas is sampled with, `anisotropy` clamped to
+    /// the device limit.
+    pub fn anisotropy(&self, features: &DeviceFeatures) -> f32 {
+        (self.anisotropy as f32).clamp(1.0, features.max_anisotropy)
+    }
+
+    /// The filter block and entity textures are sampled with, see
+    /// [`Self::linear_filtering`].
+    pub fn texture_filter(&self) -> vk::Filter {
+        if self.linear_filtering {
+            vk::Filter::LINEAR
+        } else {
+            vk::Filter::NEAREST
         }
     }
 }
@@ -102,19 +387,32 @@ impl WorldRenderer {
         ctx: &VkContext,
         module: vk::ShaderModule,
         render_targets: &RenderTargets,
-        uniforms: &[Buffer; MAX_FRAMES_IN_FLIGHT],
+        uniforms: &[Buffer],
         options: WorldRendererFeatures,
+        config: &WorldRendererConfig,
     ) -> Self {
-        let atlas_image =
-            animation::create_initial_atlas(&assets.block_atlas, &assets.block_textures);
-        let blocks_texture = Texture::from_image(ctx, atlas_image);
-
-
+        let mut blocks_texture = match &assets.block_layers {
+            Some(layers) => Texture::from_layers(
+                ctx,
+                &animation::create_initial_layers(layers, &assets.block_textures),
+            ),
+            None => {
+                let atlas_image =
+                    animation::create_initial_atlas(&assets.block_atlas, &assets.block_textures);
+                Texture::from_layers(ctx, std::slice::from_ref(&atlas_image))
+            }
+        };
+        let default_sampler = blocks_texture.set_sampler(
+            ctx,
+            config.texture_filter(),
+            config.anisotropy(&ctx.features()),
+        );
+        unsafe { ctx.device().destroy_sampler(default_sampler, None) };
 
         let render_pass = create_world_render_pass(ctx, render_targets);
         let framebuffers = create_framebuffers(ctx, render_targets, render_pass);
 
-        let descriptors = Descriptors::new(ctx.device(), &uniforms, &blocks_texture);
+        let descriptors = Descriptors::new(ctx, &uniforms, &blocks_texture);
 
         let pipelines = Pipelines::new(
             ctx,
@@ -123,6 +421,7 @@ impl WorldRenderer {
             module,
             PipelineOptions {
                 wireframe_enabled: options.fill_mode_non_solid,
+                dynamic_cull_mode: options.dynamic_cull_mode,
             },
         );
 
@@ -133,15 +432,17 @@ impl WorldRenderer {
             &render_targets.depth_images,
         );
 
-        let visibility_uniforms: [_; MAX_FRAMES_IN_FLIGHT] = from_fn(|i| {
-            Buffer::new(
-                ctx,
-                size_of::<VisibilityUniform>() as u64,
-                vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
-                MemoryUsage::AutoPreferDevice,
-                false,
-            )
-        });
+        let visibility_uniforms: Vec<_> = (0..ctx.frames_in_flight())
+            .map(|_| {
+                Buffer::new(
+                    ctx,
+                    size_of::<VisibilityUniform>() as u64,
+                    vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+                    MemoryUsage::AutoPreferDevice,
+                    false,
+                )
+            })
+            .collect();
 
         let visibility_compute = VisibilityCompute::new(
             ctx,
@@ -151,11 +452,36 @@ impl WorldRenderer {
             32,
             1,
         );
-        let aabb_renderer = AabbRenderer::new(ctx, &visibility_uniforms, module, render_pass);
+        let aabb_renderer = AabbRenderer::new(
+            ctx,
+            &visibility_uniforms,
+            module,
+            render_pass,
+            descriptors.layout,
+        );
+        let clouds = CloudRenderer::new(ctx, module, render_pass, descriptors.layout);
+        let weather_renderer =
+            WeatherRenderer::new(ctx, module, render_pass, descriptors.layout, &assets);
+        let beam_renderer = BeamRenderer::new(ctx, module, render_pass, descriptors.layout);
+        let celestial =
+            CelestialRenderer::new(ctx, module, render_pass, descriptors.layout, &assets);
+        let oit = OitRenderer::new(
+            ctx,
+            module,
+            render_targets,
+            pipelines.layout,
+            options.dynamic_cull_mode,
+        );
+
+        let cubes = Arc::new(CubeTable::new(&assets, config.uv_inset));
+        let gpu_mesher = GpuMesher::new(ctx, module, &cubes);
 
         Self {
             mesher: None,
+            meshing_world: false,
             animation_manager: AnimationManager::from_textures(&assets.block_textures),
+            cubes,
+            gpu_mesher,
             hiz_compute,
 
             visibility_uniforms,
@@ -163,6 +489,25 @@ impl WorldRenderer {
             visibility_compute,
             visibility_buffers: None,
             aabb_renderer,
+            clouds,
+            oit,
+            weather_renderer,
+            beam_renderer,
+            celestial,
+            ticks: 0,
+            debug_box: None,
+            selection: Vec::new(),
+            highlights: Vec::new(),
+            world_border: None,
+            weather: Weather::default(),
+            time: WorldTime::default(),
+            banner_patterns: HashMap::new(),
+            beacon_beams: Vec::new(),
+            falling_blocks: Vec::new(),
+            falling_block_meshes: HashMap::new(),
+            minimap: Minimap::default(),
+            player_position: None,
+            render_hooks: Vec::new(),
             render_pass,
             framebuffers,
 
@@ -175,16 +520,45 @@ impl WorldRenderer {
     }
 
     pub fn tick(&mut self) {
+        self.ticks += 1;
+        if self.time.advancing {
+            self.time.day_time += 1;
+        }
         self.animation_manager.tick(&self.assets.block_textures);
+        if let Some(mesher) = &self.mesher {
+            self.minimap.update(&mesher.world.read());
+        }
     }
 
-    pub fn update_visibility(&mut self, ctx: &VkContext, frame_index: usize, camera_pos: Vec3) {
+    /// Sends the latest visibility results to the mesher, along with the view
+    /// so sections in front of the camera are meshed first. With
+    /// `render_everything` every section within the render distance counts as
+    /// visible.
+    pub fn update_visibility(
+        &mut self,
+        ctx: &VkContext,
+        frame_index: usize,
+        camera_pos: Vec3,
+        forward: Vec3,
+        config: &WorldRendererConfig,
+    ) {
         if let (Some(mesher), Some(vis_bufs)) = (&self.mesher, &mut self.visibility_buffers) {
-            let cx = (camera_pos.x / 16.0).floor() as i32;
-            let cy = (camera_pos.y / 16.0).floor() as i32;
-            let cz = (camera_pos.z / 16.0).floor() as i32;
             let min_y = self.mesher.as_ref().unwrap().world.read().chunks.min_y;
-            let snapshot = vis_bufs.snapshot(ctx, frame_index, cx, cz, min_y);
+            let mut snapshot = vis_bufs.snapshot(
+                ctx,
+                frame_index,
+                camera_pos,
+                forward,
+                config.view_priority,
+                min_y,
+            );
+            if config.render_everything {
+                // the lowest priority, so sections that were visible are still
+                // meshed first
+                for depth in snapshot.data.iter_mut().filter(|depth| **depth == 0.0) {
+                    *depth = f32::MIN_POSITIVE;
+                }
+            }
 
             mesher.update_visibility(snapshot);
         }
@@ -198,6 +572,66 @@ impl WorldRenderer {
         }
     }
 
+    /// The first solid block along the ray, see [`raycast::raycast_block`].
+    pub fn raycast_block(
+        &self,
+        origin: Vec3,
+        dir: Vec3,
+        max_dist: f32,
+    ) -> Option<(BlockPos, Direction)> {
+        let world = self.mesher.as_ref()?.world.read();
+        raycast::raycast_block(&world, origin, dir, max_dist)
+    }
+
+    /// The fluid `pos` is inside of, below the surface of the fluid in its
+    /// block.
+    pub fn submersion(&self, pos: Vec3) -> Option<Submersion> {
+        let world = self.mesher.as_ref()?.world.read();
+        let block = pos.floor().as_ivec3();
+        let state = world.get_block_state(BlockPos::new(block.x, block.y, block.z))?;
+        let fluid = FluidState::from(state);
+        if pos.y - block.y as f32 >= fluid.height() {
+            return None;
+        }
+        match fluid.kind {
+            FluidKind::Water => Some(Submersion::Water),
+            FluidKind::Lava => Some(Submersion::Lava),
+            FluidKind::Empty => None,
+        }
+    }
+
+    pub fn mesher_stats(&self) -> MesherStats {
+        if let Some(mesher) = &self.mesher {
+            MesherStats {
+                dirty: mesher.dirty_count(),
+                pending_jobs: mesher.pending_jobs(),
+                completed: mesher.completed_meshes(),
+                empty: mesher.empty_meshes(),
+                block_quads: mesher.block_quads(),
+                water_quads: mesher.water_quads(),
+                gpu_pending: self.gpu_mesher.pending(),
+            }
+        } else {
+            MesherStats::default()
+        }
+    }
+
+    /// Returns the number of meshed sections the first time nothing is left to
+    /// mesh after a world was added.
+    pub fn take_world_meshed(&mut self) -> Option<u64> {
+        if !self.meshing_world {
+            return None;
+        }
+
+        let stats = self.mesher_stats();
+        if stats.completed == 0 || stats.dirty > 0 || stats.pending_jobs > 0 {
+            return None;
+        }
+
+        self.meshing_world = false;
+        Some(stats.completed)
+    }
+
     pub fn update(
         &mut self,
         ctx: &VkContext,
@@ -210,8 +644,29 @@ impl WorldRenderer {
                 if let Some(mesher) = &self.mesher {
                     mesher.submit_chunk(chunk_pos);
                 }
+                self.minimap.mark(chunk_pos);
+            }
+            WorldUpdate::ChunkRemoved(chunk_pos) => {
+                if let Some(mesher) = &self.mesher {
+                    mesher.remove_chunk(chunk_pos);
+                }
+                self.gpu_mesher.cancel_chunk(chunk_pos);
+                self.minimap.remove(chunk_pos);
+
+                let frame = sync.last_frame();
+                for mesh in self.mesh_store.remove_chunk(chunk_pos) {
+                    sync.add_to_deletion_queue(frame, Box::new(mesh.buffer));
+                }
+                self.banner_patterns.retain(|pos, _| {
+                    (pos.x.div_euclid(16), pos.z.div_euclid(16)) != (chunk_pos.x, chunk_pos.z)
+                });
+                self.beacon_beams.retain(|beam| {
+                    (beam.pos.x.div_euclid(16), beam.pos.z.div_euclid(16))
+                        != (chunk_pos.x, chunk_pos.z)
+                });
             }
             WorldUpdate::SectionChange(spos) => {
+                self.minimap.mark(ChunkPos::new(spos.x, spos.z));
                 if let Some(mesher) = &self.mesher {
                     if let Some(vis) = &mut self.visibility_buffers {
                         mesher.submit_section(spos);
@@ -220,6 +675,24 @@ impl WorldRenderer {
             }
             WorldUpdate::WorldAdded(world) => {
                 unsafe { ctx.device().queue_wait_idle(ctx.graphics_queue()).unwrap() };
+
+                // a new world is a new dimension or server, nothing of the old
+                // one may stay around. Dropping the mesher joins its workers,
+                // so no old section can be queued after this
+                self.mesher = None;
+                self.gpu_mesher.cancel_all();
+                let frame = sync.last_frame();
+                let stale = self.mesh_store.clear();
+                log::debug!("Freeing {} meshes of the previous world", stale.len());
+                for mesh in stale {
+                    sync.add_to_deletion_queue(frame, Box::new(mesh.buffer));
+                }
+                self.selection.clear();
+                self.highlights.clear();
+                self.banner_patterns.clear();
+                self.beacon_beams.clear();
+                self.minimap.clear();
+
                 let world_read = world.read();
                 let max_height = world_read.chunks.height as i32 - world_read.chunks.min_y;
                 drop(world_read);
@@ -236,19 +709,76 @@ impl WorldRenderer {
 
                 let vb = self.visibility_buffers.as_ref().unwrap();
 
-                for f in 0..MAX_FRAMES_IN_FLIGHT {
+                for f in 0..ctx.frames_in_flight() {
                     self.visibility_compute
-                        .rewrite_frame_set(ctx.device(), f, &vb.outputs[f]);
+                        .rewrite_frame_set(ctx.device(), f, vb);
                 }
 
                 self.aabb_renderer
                     .recreate_descriptor_sets(ctx.device(), &vb.outputs);
 
-                self.mesher = Some(Mesher::new(self.assets.clone(), world));
+                let mut mesher = Mesher::new(self.assets.clone(), world, self.cubes.clone());
+                mesher.set_lod_distance(config.lod_distance);
+                mesher.set_gpu_meshing(config.gpu_meshing);
+                mesher.set_uv_inset(config.uv_inset);
+                mesher.set_leaves_mode(config.leaves_mode);
+                mesher.set_state_colors(config.state_colors);
+                mesher.set_low_priority(config.low_priority_workers);
+                mesher.set_slow_mesh_threshold(config.slow_mesh_threshold_ms);
+                mesher.set_worker_threads(config.worker_threads);
+                self.mesher = Some(mesher);
+                self.meshing_world = true;
+            }
+            WorldUpdate::DebugBox(debug_box) => self.debug_box = debug_box,
+            WorldUpdate::WorldBorder(border) => self.world_border = border,
+            WorldUpdate::Weather(weather) => self.weather = weather,
+            WorldUpdate::Time(time) => self.time = time,
+            WorldUpdate::BannerPatterns(pos, layers) => {
+                self.banner_patterns.insert(pos, layers);
             }
+            WorldUpdate::BeaconBeams(beams) => self.beacon_beams = beams,
+            WorldUpdate::HighlightBlocks(positions, color) => {
+                self.highlight_blocks(&positions, color)
+            }
+            WorldUpdate::ClearHighlights => self.clear_highlights(),
+            WorldUpdate::PlayerPosition(pos) => self.player_position = Some(pos),
+            WorldUpdate::AddRenderHook(hook) => self.render_hooks.push(hook),
         }
     }
 
+    pub fn player_position(&self) -> Option<Vec3> {
+        self.player_position
+    }
+
+    /// Ticks since the renderer started, the clouds and weather scroll with
+    /// them.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Time of day in ticks, between the ticks of the world.
+    fn day_time(&self, config: &WorldRendererConfig, partial_tick: f32) -> f64 {
+        if config.override_time {
+            return config.override_day_time as f64;
+        }
+        let partial = if self.time.advancing {
+            partial_tick as f64
+        } else {
+            0.0
+        };
+        self.time.day_time as f64 + partial
+    }
+
+    /// Unit vector towards the sun, below the horizon at night.
+    pub fn sun_direction(&self, config: &WorldRendererConfig, partial_tick: f32) -> Vec3 {
+        sun_direction(celestial_angle(self.day_time(config, partial_tick)))
+    }
+
+    /// Sky colors and ambient light for the current time of day.
+    pub fn sky_light(&self, config: &WorldRendererConfig, partial_tick: f32) -> SkyLight {
+        SkyLight::at(celestial_angle(self.day_time(config, partial_tick)))
+    }
+
     pub fn set_render_distance(&mut self, ctx: &VkContext, new_distance: u32) {
         if let Some(mesher) = &self.mesher {
             let world_read = mesher.world.read();
@@ -263,9 +793,9 @@ impl WorldRenderer {
                     unsafe { ctx.device().queue_wait_idle(ctx.graphics_queue()).unwrap() };
                     vb.recreate(ctx, radius, height);
 
-                    for f in 0..MAX_FRAMES_IN_FLIGHT {
+                    for f in 0..ctx.frames_in_flight() {
                         self.visibility_compute
-                            .rewrite_frame_set(ctx.device(), f, &vb.outputs[f]);
+                            .rewrite_frame_set(ctx.device(), f, vb);
                     }
 
                     self.aabb_renderer
@@ -275,6 +805,209 @@ impl WorldRenderer {
         }
     }
 
+    pub fn set_lod_distance(&mut self, distance: u32) {
+        if let Some(mesher) = &self.mesher {
+            mesher.set_lod_distance(distance);
+        }
+    }
+
+    pub fn set_low_priority_workers(&mut self, enabled: bool) {
+        if let Some(mesher) = &mut self.mesher {
+            mesher.set_low_priority(enabled);
+        }
+    }
+
+    pub fn set_slow_mesh_threshold(&mut self, threshold_ms: f32) {
+        if let Some(mesher) = &self.mesher {
+            mesher.set_slow_mesh_threshold(threshold_ms);
+        }
+    }
+
+    pub fn set_gpu_meshing(&mut self, enabled: bool) {
+        if let Some(mesher) = &self.mesher {
+            mesher.set_gpu_meshing(enabled);
+        }
+    }
+
+    pub fn set_leaves_mode(&mut self, mode: LeavesMode) {
+        if let Some(mesher) = &self.mesher {
+            mesher.set_leaves_mode(mode);
+        }
+    }
+
+    pub fn set_state_colors(&mut self, enabled: bool) {
+        if let Some(mesher) = &self.mesher {
+            mesher.set_state_colors(enabled);
+        }
+    }
+
+    /// Re-meshes every loaded section without reloading the world, e.g. to see
+    /// the effect of a mesher change. The old meshes stay drawn until the new
+    /// ones are uploaded.
+    pub fn remesh_all(&mut self) {
+        if let Some(mesher) = &self.mesher {
+            mesher.remesh_all();
+        }
+    }
+
+    /// Insets block face uvs by `texels` and re-meshes every section, the
+    /// device must be idle.
+    pub fn set_uv_inset(&mut self, ctx: &VkContext, texels: f32) {
+        self.cubes = Arc::new(CubeTable::new(&self.assets, texels));
+        self.falling_block_meshes.clear();
+        self.gpu_mesher.set_cubes(ctx, &self.cubes);
+        if let Some(mesher) = &self.mesher {
+            mesher.set_uv_inset(texels);
+        }
+    }
+
+    /// Rebuilds the atlas sampler with `filter` and `anisotropy`. The old one
+    /// goes through the deletion queue, and each frame's set is only rewritten
+    /// once that frame comes around again.
+    pub fn set_texture_filter(
+        &mut self,
+        ctx: &VkContext,
+        sync: &mut FrameSync,
+        filter: vk::Filter,
+        anisotropy: f32,
+    ) {
+        let old = self.blocks_texture.set_sampler(ctx, filter, anisotropy);
+        let frame = sync.last_frame();
+        sync.add_to_deletion_queue(frame, Box::new(old));
+        self.descriptors.mark_texture_dirty();
+    }
+
+    /// Outlines the shape of the block at `pos` like vanilla's selection box,
+    /// `None` removes the outline.
+    pub fn select_block(&mut self, pos: Option<BlockPos>) {
+        self.selection.clear();
+        let (Some(pos), Some(mesher)) = (pos, &self.mesher) else {
+            return;
+        };
+        let Some(state) = mesher.world.read().get_block_state(pos) else {
+            return;
+        };
+
+        // grown slightly so the lines don't z-fight with the block's faces
+        let origin = Vec3::new(pos.x as f32, pos.y as f32, pos.z as f32);
+        let to_vec3 = |v: azalea::core::position::Vec3| {
+            origin + Vec3::new(v.x as f32, v.y as f32, v.z as f32)
+        };
+        let grow = Vec3::splat(SELECTION_GROW);
+        for aabb in state.outline_shape().to_aabbs() {
+            self.selection.push(DebugBox {
+                min: to_vec3(aabb.min) - grow,
+                max: to_vec3(aabb.max) + grow,
+                color: SELECTION_COLOR,
+            });
+        }
+    }
+
+    /// Outlines the blocks at `positions` in `color` every frame, on top of the
+    /// ones already highlighted, until [`Self::clear_highlights`]. Useful to
+    /// show pathfinding targets or other goals of a bot.
+    pub fn highlight_blocks(&mut self, positions: &[BlockPos], color: [f32; 4]) {
+        self.highlights
+            .extend(positions.iter().map(|pos| BlockHighlight {
+                pos: [pos.x as f32, pos.y as f32, pos.z as f32, 0.0],
+                color,
+            }));
+    }
+
+    pub fn clear_highlights(&mut self) {
+        self.highlights.clear();
+    }
+
+    /// The world border as a box spanning the full build height of the world.
+    fn world_border_box(&self) -> Option<DebugBox> {
+        let border = self.world_border?;
+        let (min_y, height) = self
+            .mesher
+            .as_ref()
+            .map(|m| {
+                let world = m.world.read();
+                (world.chunks.min_y, world.chunks.height as i32)
+            })
+            .unwrap_or((0, 256));
+
+        Some(DebugBox {
+            min: Vec3::new(
+                (border.center[0] - border.radius) as f32,
+                min_y as f32,
+                (border.center[1] - border.radius) as f32,
+            ),
+            max: Vec3::new(
+                (border.center[0] + border.radius) as f32,
+                (min_y + height) as f32,
+                (border.center[1] + border.radius) as f32,
+            ),
+            color: WORLD_BORDER_COLOR,
+        })
+    }
+
+    /// Rain or snow particles drawn around the camera this frame, with how
+    /// many of them to draw.
+    fn precipitation(
+        &self,
+        config: &WorldRendererConfig,
+        camera_pos: Vec3,
+    ) -> Option<(Precipitation, u32)> {
+        let rain_level = self.weather.rain_level.clamp(0.0, 1.0);
+        let particles = (config.weather_particles as f32 * rain_level) as u32;
+        if !config.render_weather || particles == 0 {
+            return None;
+        }
+
+        let mesher = self.mesher.as_ref()?;
+        let pos = camera_pos.floor().as_ivec3();
+        let biome = mesher
+            .world
+            .read()
+            .get_biome(BlockPos::new(pos.x, pos.y, pos.z))?;
+        let data = mesher.biome_cache().get(biome)?;
+        Precipitation::in_biome(data, camera_pos.y).map(|kind| (kind, particles))
+    }
+
+    /// Sets the falling blocks and primed TNT to draw this frame.
+    pub fn set_falling_blocks(&mut self, blocks: Vec<FallingBlock>) {
+        self.falling_blocks = blocks;
+    }
+
+    /// Block entities of every meshed section.
+    pub fn block_entities(&self) -> impl Iterator<Item = &BlockEntity> {
+        self.mesh_store.block_entities.values().flatten()
+    }
+
+    pub fn minimap(&self) -> &Minimap {
+        &self.minimap
+    }
+
+    pub fn banner_patterns(&self) -> &HashMap<IVec3, Vec<BannerLayer>> {
+        &self.banner_patterns
+    }
+
+    /// Sections that currently have a block mesh, built on the mesher threads
+    /// or by the gpu mesher. Sections with only water or nothing to draw aren't
+    /// included.
+    pub fn loaded_sections(&self) -> Vec<ChunkSectionPos> {
+        self.meshed_sections().into_iter().collect()
+    }
+
+    /// Number of [`Self::loaded_sections`].
+    pub fn loaded_section_count(&self) -> usize {
+        self.meshed_sections().len()
+    }
+
+    fn meshed_sections(&self) -> HashSet<ChunkSectionPos> {
+        let store = &self.mesh_store;
+        store
+            .blocks
+            .keys()
+            .chain(store.gpu_blocks.keys())
+            .copied()
+            .collect()
+    }
+
     pub fn set_worker_threads(&mut self, ctx: &VkContext, new_thread_count: u32) {
         if let Some(mesher) = &mut self.mesher {
             mesher.set_worker_threads(new_thread_count);
@@ -293,18 +1026,13 @@ impl WorldRenderer {
             let cam_chunk_z = (camera_pos.z / CHUNK).floor() as i32;
             let grid_min_x = (cam_chunk_x) as f32 * CHUNK;
             let grid_min_z = (cam_chunk_z) as f32 * CHUNK;
-            let grid_origin_ws = Vec4::new(
-                grid_min_x,
-                (self
-                    .mesher
-                    .as_ref()
-                    .map(|m| m.world.read().chunks.min_y)
-                    .unwrap_or(0)
-                    / 16) as f32
-                    * CHUNK,
-                grid_min_z,
-                0.0,
-            );
+            let min_y = self
+                .mesher
+                .as_ref()
+                .map(|m| m.world.read().chunks.min_y)
+                .unwrap_or(0);
+            let grid_origin_ws =
+                Vec4::new(grid_min_x, (min_y / 16) as f32 * CHUNK, grid_min_z, 0.0);
 
             let visibility_uniform = VisibilityUniform {
                 view_proj,
@@ -317,6 +1045,18 @@ impl WorldRenderer {
                 &[visibility_uniform],
                 &self.visibility_uniforms[frame_ctx.frame_index],
             );
+
+            let empty = frame_ctx
+                .config
+                .cull_empty_sections
+                .then_some(&self.mesh_store.empty);
+            vb.write_empty_mask(
+                ctx,
+                frame_ctx.frame_index,
+                camera_pos,
+                min_y,
+                empty.into_iter().flatten(),
+            );
         }
 
         ctx.cmd_begin_debug_label(
@@ -324,10 +1064,28 @@ impl WorldRenderer {
             &format!("World Render Frame {}", frame_ctx.frame_index),
         );
 
-        ctx.cmd_begin_debug_label(frame_ctx.cmd, "Update meshes");
-        self.mesh_store
-            .process_mesher_results(frame_ctx, &self.mesher);
+        self.descriptors.update_frame_texture(
+            ctx.device(),
+            frame_ctx.frame_index,
+            &self.blocks_texture,
+        );
+
+        if !frame_ctx.config.pause_world_updates {
+            ctx.cmd_begin_debug_label(frame_ctx.cmd, "Update meshes");
+            self.mesh_store
+                .process_mesher_results(frame_ctx, &self.mesher, &mut self.gpu_mesher);
 
+            ctx.cmd_end_debug_label(frame_ctx.cmd);
+        }
+
+        ctx.cmd_begin_debug_label(frame_ctx.cmd, "GPU Meshing");
+        frame_ctx.begin_timestamp(timings::START_GPU_MESHING);
+        for (spos, mesh) in self.gpu_mesher.dispatch(frame_ctx) {
+            if let Some(old_mesh) = self.mesh_store.insert_gpu_block(spos, mesh) {
+                frame_ctx.delete(old_mesh.buffer);
+            }
+        }
+        frame_ctx.end_timestamp(timings::END_GPU_MESHING);
         ctx.cmd_end_debug_label(frame_ctx.cmd);
 
         ctx.cmd_begin_debug_label(frame_ctx.cmd, "Update dirty textures");
@@ -341,25 +1099,167 @@ impl WorldRenderer {
         frame_ctx.begin_timestamp(timings::START_TERRAIN_PASS);
         ctx.cmd_begin_debug_label(frame_ctx.cmd, "Main Render Pass");
         self.begin(frame_ctx);
+
+        if frame_ctx.config.render_sky {
+            ctx.cmd_begin_debug_label(frame_ctx.cmd, "Draw Sky");
+            self.celestial
+                .draw_sky(frame_ctx, self.descriptors.sets[frame_ctx.frame_index]);
+            ctx.cmd_end_debug_label(frame_ctx.cmd);
+        }
+
+        if frame_ctx.config.render_sun_and_moon {
+            ctx.cmd_begin_debug_label(frame_ctx.cmd, "Draw Sun and Moon");
+            self.celestial.draw(
+                frame_ctx,
+                self.descriptors.sets[frame_ctx.frame_index],
+                self.day_time(&frame_ctx.config, frame_ctx.partial_tick),
+                1.0 - self.weather.rain_level.clamp(0.0, 1.0),
+            );
+            ctx.cmd_end_debug_label(frame_ctx.cmd);
+        }
+
         self.draw(frame_ctx, camera_pos);
 
+        if !self.falling_blocks.is_empty() {
+            ctx.cmd_begin_debug_label(frame_ctx.cmd, "Draw Falling Blocks");
+            self.draw_falling_blocks(frame_ctx);
+            ctx.cmd_end_debug_label(frame_ctx.cmd);
+        }
+
+        if frame_ctx.config.render_clouds {
+            ctx.cmd_begin_debug_label(frame_ctx.cmd, "Draw Clouds");
+            self.clouds.draw(
+                frame_ctx,
+                self.descriptors.sets[frame_ctx.frame_index],
+                self.ticks,
+            );
+            ctx.cmd_end_debug_label(frame_ctx.cmd);
+        }
+
+        if let Some((precipitation, particles)) =
+            self.precipitation(&frame_ctx.config, camera_pos)
+        {
+            ctx.cmd_begin_debug_label(frame_ctx.cmd, "Draw Weather");
+            let world = self.mesher.as_ref().unwrap().world.read();
+            self.weather_renderer.draw(
+                frame_ctx,
+                self.descriptors.sets[frame_ctx.frame_index],
+                &world,
+                precipitation,
+                particles,
+                self.ticks,
+            );
+            ctx.cmd_end_debug_label(frame_ctx.cmd);
+        }
+
+        if frame_ctx.config.render_beacon_beams && !self.beacon_beams.is_empty() {
+            ctx.cmd_begin_debug_label(frame_ctx.cmd, "Draw Beacon Beams");
+            self.beam_renderer.draw(
+                frame_ctx,
+                self.descriptors.sets[frame_ctx.frame_index],
+                &self.beacon_beams,
+            );
+            ctx.cmd_end_debug_label(frame_ctx.cmd);
+        }
+
         if let Some(vb) = &mut self.visibility_buffers {
             if frame_ctx.config.render_aabbs {
                 ctx.cmd_begin_debug_label(frame_ctx.cmd, "Draw AABBs");
                 let side = (frame_ctx.config.render_distance * 2 + 1) as u32;
                 let instance_count = side * side * vb.height as u32;
-                self.aabb_renderer.draw(
-                    ctx.device(),
-                    frame_ctx.cmd,
-                    instance_count,
-                    frame_ctx.frame_index,
-                );
+                self.aabb_renderer.draw(frame_ctx, instance_count);
                 ctx.cmd_end_debug_label(frame_ctx.cmd);
             }
         }
 
+        let boxes = [self.debug_box, self.world_border_box()];
+        if boxes.iter().any(Option::is_some) {
+            ctx.cmd_begin_debug_label(frame_ctx.cmd, "Draw Debug Boxes");
+            for debug_box in boxes.iter().flatten() {
+                self.aabb_renderer.draw_box(
+                    frame_ctx,
+                    self.descriptors.sets[frame_ctx.frame_index],
+                    debug_box,
+                    false,
+                );
+            }
+            ctx.cmd_end_debug_label(frame_ctx.cmd);
+        }
+
+        if frame_ctx.config.render_selection_outline && !self.selection.is_empty() {
+            ctx.cmd_begin_debug_label(frame_ctx.cmd, "Draw Selection Outline");
+            for selection_box in &self.selection {
+                self.aabb_renderer.draw_box(
+                    frame_ctx,
+                    self.descriptors.sets[frame_ctx.frame_index],
+                    selection_box,
+                    true,
+                );
+            }
+            ctx.cmd_end_debug_label(frame_ctx.cmd);
+        }
+
+        if !self.highlights.is_empty() {
+            ctx.cmd_begin_debug_label(frame_ctx.cmd, "Draw Block Highlights");
+            self.aabb_renderer.draw_highlights(
+                frame_ctx,
+                self.descriptors.sets[frame_ctx.frame_index],
+                &self.highlights,
+            );
+            ctx.cmd_end_debug_label(frame_ctx.cmd);
+        }
+
+        if frame_ctx.config.render_gizmo {
+            ctx.cmd_begin_debug_label(frame_ctx.cmd, "Draw Gizmo");
+            self.aabb_renderer.draw_gizmo(
+                frame_ctx,
+                self.descriptors.sets[frame_ctx.frame_index],
+                frame_ctx.config.gizmo_origin,
+            );
+            ctx.cmd_end_debug_label(frame_ctx.cmd);
+        }
+
+        if frame_ctx.config.render_chunk_borders
+            && let Some(mesher) = &self.mesher
+        {
+            let (min_y, max_y) = {
+                let world = mesher.world.read();
+                let min_y = world.chunks.min_y;
+                (min_y, min_y + world.chunks.height as i32)
+            };
+            ctx.cmd_begin_debug_label(frame_ctx.cmd, "Draw Chunk Borders");
+            self.aabb_renderer.draw_chunk_borders(
+                frame_ctx,
+                self.descriptors.sets[frame_ctx.frame_index],
+                min_y,
+                max_y,
+            );
+            ctx.cmd_end_debug_label(frame_ctx.cmd);
+        }
+
+        if !self.render_hooks.is_empty() {
+            ctx.cmd_begin_debug_label(frame_ctx.cmd, "Custom Render Hooks");
+            let hook_ctx = CustomRenderCtx::new(
+                frame_ctx,
+                &self.aabb_renderer,
+                self.descriptors.sets[frame_ctx.frame_index],
+            );
+            for hook in &mut self.render_hooks {
+                hook.render(&hook_ctx);
+            }
+            ctx.cmd_end_debug_label(frame_ctx.cmd);
+        }
+
         self.end(frame_ctx);
 
+        if frame_ctx.config.uses_oit() {
+            ctx.cmd_begin_debug_label(frame_ctx.cmd, "OIT Pass");
+            self.oit.begin(frame_ctx);
+            self.draw_water(frame_ctx, self.oit.accumulate_pipeline);
+            self.oit.resolve(frame_ctx);
+            ctx.cmd_end_debug_label(frame_ctx.cmd);
+        }
+
         ctx.cmd_end_debug_label(frame_ctx.cmd);
         frame_ctx.end_timestamp(timings::END_TERRAIN_PASS);
 
@@ -372,6 +1272,7 @@ impl WorldRenderer {
         frame_ctx.end_timestamp(timings::START_VISIBILITY_COMPUTE);
         if let Some(vb) = &mut self.visibility_buffers
             && !frame_ctx.config.disable_visibilty
+            && !frame_ctx.config.render_everything
         {
             ctx.cmd_begin_debug_label(frame_ctx.cmd, "Visibility Compute");
             self.visibility_compute.dispatch(frame_ctx, vb);
@@ -412,7 +1313,7 @@ impl WorldRenderer {
             },
             vk::ClearValue {
                 depth_stencil: vk::ClearDepthStencilValue {
-                    depth: 0.0,
+                    depth: CLEAR_DEPTH,
                     stencil: 0,
                 },
             },
@@ -463,15 +1364,20 @@ impl WorldRenderer {
             frame_index,
             view_proj,
             config,
+            stats,
             ..
         } = frame_ctx;
         let device = ctx.device();
 
         ctx.cmd_begin_debug_label(*cmd, "Draw Blocks");
-        let current_pipeline = self.pipelines.block_pipeline(config.wireframe_mode);
+        let current_pipeline = self
+            .pipelines
+            .block_pipeline(config.wireframe_mode, config.backface_culling);
 
         unsafe {
             device.cmd_bind_pipeline(*cmd, vk::PipelineBindPoint::GRAPHICS, current_pipeline);
+            self.pipelines
+                .set_cull_mode(device, *cmd, config.backface_culling);
 
             device.cmd_bind_descriptor_sets(
                 *cmd,
@@ -483,15 +1389,18 @@ impl WorldRenderer {
             );
         }
 
-        for (pos, mesh) in &self.mesh_store.blocks {
-            let pos_min = Vec3::new(
-                pos.x as f32 * 16.0,
-                pos.y as f32 * 16.0,
-                pos.z as f32 * 16.0,
-            );
-            let pos_max = Vec3::new(pos_min.x + 16.0, pos_min.y + 16.0, pos_min.z + 16.0);
-
-            if !visibility::aabb_visible(view_proj, pos_min, pos_max) {
+        let nearest = self.nearest_sections(config, camera_pos);
+        for (pos, mesh) in self
+            .mesh_store
+            .blocks
+            .iter()
+            .chain(&self.mesh_store.gpu_blocks)
+        {
+            let drawn = match &nearest {
+                Some(nearest) => nearest.contains(pos),
+                None => !section_culled(config, view_proj, camera_pos, *pos),
+            };
+            if !drawn {
                 continue;
             }
 
@@ -507,38 +1416,78 @@ impl WorldRenderer {
                 );
                 device.cmd_draw_indexed(*cmd, mesh.index_count, 1, 0, 0, 0);
             }
+            stats.terrain.record(mesh.index_count);
         }
         ctx.cmd_end_debug_label(*cmd);
 
-        ctx.cmd_begin_debug_label(*cmd, "Draw Water");
-        let water_pipeline = self.pipelines.water_pipeline(config.wireframe_mode);
+        // otherwise drawn by the OIT pass after the world render pass
+        if !config.uses_oit() {
+            let water_pipeline = self
+                .pipelines
+                .water_pipeline(config.wireframe_mode, config.backface_culling);
+            self.draw_water(frame_ctx, water_pipeline);
+        }
+    }
+
+    /// Draws the water meshes with `pipeline`. Blending them into the color
+    /// attachment needs them sorted by distance, the OIT pass doesn't depend on
+    /// the order.
+    fn draw_water(&self, frame_ctx: &mut FrameCtx, pipeline: vk::Pipeline) {
+        let FrameCtx {
+            ctx,
+            cmd,
+            frame_index,
+            view_proj,
+            camera_pos,
+            config,
+            stats,
+            ..
+        } = frame_ctx;
+        let device = ctx.device();
 
+        ctx.cmd_begin_debug_label(*cmd, "Draw Water");
         unsafe {
-            device.cmd_bind_pipeline(*cmd, vk::PipelineBindPoint::GRAPHICS, water_pipeline);
+            device.cmd_bind_pipeline(*cmd, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            self.pipelines
+                .set_cull_mode(device, *cmd, config.backface_culling);
+            self.pipelines.set_water_depth_bias(
+                device,
+                *cmd,
+                config.water_depth_bias,
+                config.water_depth_bias_slope,
+            );
+            device.cmd_bind_descriptor_sets(
+                *cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipelines.layout,
+                0,
+                &[self.descriptors.sets[*frame_index]],
+                &[],
+            );
         }
 
         let mut water_meshes: Vec<_> = self.mesh_store.water.iter().collect();
-        water_meshes.sort_by(|(a, _), (b, _)| {
-            let dist = |pos: &ChunkSectionPos| {
-                camera_pos.distance_squared(glam::Vec3::new(
-                    pos.x as f32 * 16.0 + 8.0,
-                    pos.y as f32 * 16.0 + 8.0,
-                    pos.z as f32 * 16.0 + 8.0,
-                ))
-            };
-
-            dist(a).partial_cmp(&dist(b)).unwrap_or(Ordering::Equal)
-        });
+        if !config.uses_oit() {
+            water_meshes.sort_by(|(a, _), (b, _)| {
+                let dist = |pos: &ChunkSectionPos| {
+                    camera_pos.distance_squared(glam::Vec3::new(
+                        pos.x as f32 * 16.0 + 8.0,
+                        pos.y as f32 * 16.0 + 8.0,
+                        pos.z as f32 * 16.0 + 8.0,
+                    ))
+                };
+
+                dist(a).partial_cmp(&dist(b)).unwrap_or(Ordering::Equal)
+            });
+        }
 
+        let nearest = self.nearest_sections(config, *camera_pos);
         for (pos, mesh) in water_meshes {
-            let pos_min = Vec3::new(
-                pos.x as f32 * 16.0,
-                pos.y as f32 * 16.0,
-                pos.z as f32 * 16.0,
-            );
-            let pos_max = Vec3::new(pos_min.x + 16.0, pos_min.y + 16.0, pos_min.z + 16.0);
-
-            if !visibility::aabb_visible(view_proj, pos_min, pos_max) {
+            let drawn = match &nearest {
+                Some(nearest) => nearest.contains(pos),
+                None => !section_culled(config, view_proj, *camera_pos, *pos),
+            };
+            if !drawn {
                 continue;
             }
 
@@ -555,14 +1504,144 @@ impl WorldRenderer {
                 );
                 device.cmd_draw_indexed(*cmd, mesh.index_count, 1, 0, 0, 0);
             }
+            stats.terrain.record(mesh.index_count);
         }
         ctx.cmd_end_debug_label(*cmd);
     }
 
+    /// Draws the falling blocks with the block pipeline, from the meshes of
+    /// their blocks moved into place in a host buffer rebuilt every frame.
+    fn draw_falling_blocks(&mut self, frame_ctx: &mut FrameCtx) {
+        let Some(mesher) = &self.mesher else {
+            return;
+        };
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for block in &self.falling_blocks {
+            let origin = block.pos + Vec3::Y * 0.5;
+            let scale = block
+                .fuse
+                .map_or(1.0, |fuse| tnt_scale(fuse, frame_ctx.partial_tick));
+            let half = Vec3::splat(0.5 * scale);
+            if !frame_ctx.config.render_everything
+                && !aabb_visible(&frame_ctx.view_proj, origin - half, origin + half)
+            {
+                continue;
+            }
+
+            let mesh = self
+                .falling_block_meshes
+                .entry(block.state)
+                .or_insert_with(|| {
+                    mesh_single_block(
+                        block.state,
+                        mesher.biome_cache(),
+                        &self.assets,
+                        frame_ctx.config.uv_inset,
+                    )
+                });
+            let flashing = block.fuse.is_some_and(|fuse| fuse / 5 % 2 == 0);
+            for data in [&mesh.blocks, &mesh.water] {
+                let start = vertices.len() as u32;
+                vertices.extend(data.vertices.iter().map(|v| {
+                    let local = Vec3::from(v.position) - Vec3::splat(0.5);
+                    let mut v = BlockVertex {
+                        position: (origin + local * scale).into(),
+                        ..*v
+                    };
+                    if flashing {
+                        v.tint = [TNT_FLASH_TINT; 3];
+                        v.ao = BlockVertex::EMISSIVE_AO;
+                    }
+                    v
+                }));
+                indices.extend(data.indices.iter().map(|i| start + i));
+            }
+        }
+        if indices.is_empty() {
+            return;
+        }
+
+        let FrameCtx {
+            ctx,
+            cmd,
+            frame_index,
+            config,
+            stats,
+            ..
+        } = frame_ctx;
+        let device = ctx.device();
+        let mesh = Mesh::new_host(ctx, &vertices, &indices);
+        unsafe {
+            device.cmd_bind_pipeline(
+                *cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipelines
+                    .block_pipeline(config.wireframe_mode, config.backface_culling),
+            );
+            self.pipelines
+                .set_cull_mode(device, *cmd, config.backface_culling);
+            device.cmd_bind_descriptor_sets(
+                *cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipelines.layout,
+                0,
+                &[self.descriptors.sets[*frame_index]],
+                &[],
+            );
+            device.cmd_bind_vertex_buffers(*cmd, 0, &[mesh.buffer.buffer], &[mesh.vertex_offset]);
+            device.cmd_bind_index_buffer(
+                *cmd,
+                mesh.buffer.buffer,
+                mesh.index_offset,
+                vk::IndexType::UINT32,
+            );
+            device.cmd_draw_indexed(*cmd, mesh.index_count, 1, 0, 0, 0);
+        }
+        stats.terrain.record(mesh.index_count);
+        frame_ctx.delete(mesh.buffer);
+    }
+
+    /// The [`WorldRendererConfig::debug_section_limit`] sections with a mesh
+    /// that are nearest to the camera, `None` without a limit.
+    fn nearest_sections(
+        &self,
+        config: &WorldRendererConfig,
+        camera_pos: Vec3,
+    ) -> Option<HashSet<ChunkSectionPos>> {
+        if config.debug_section_limit == 0 {
+            return None;
+        }
+
+        let mut sections: Vec<_> = self
+            .mesh_store
+            .blocks
+            .keys()
+            .chain(self.mesh_store.gpu_blocks.keys())
+            .chain(self.mesh_store.water.keys())
+            .copied()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let center = |pos: &ChunkSectionPos| {
+            Vec3::new(pos.x as f32, pos.y as f32, pos.z as f32) * 16.0 + Vec3::splat(8.0)
+        };
+        sections.sort_by(|a, b| {
+            let (a, b) = (
+                camera_pos.distance_squared(center(a)),
+                camera_pos.distance_squared(center(b)),
+            );
+            a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+        });
+        sections.truncate(config.debug_section_limit as usize);
+        Some(sections.into_iter().collect())
+    }
+
     pub fn upload_dirty_textures(&mut self, frame_ctx: &mut FrameCtx) {
         let dirty = self
             .animation_manager
-            .dirty_textures(&self.assets.block_textures);
+            .dirty_textures(&self.assets.block_textures, frame_ctx.partial_tick);
         if dirty.is_empty() {
             return;
         }
@@ -570,44 +1649,56 @@ impl WorldRenderer {
         let mut buffer_data = Vec::new();
         let mut regions = Vec::new();
 
-        for (name, tex, (fw, fh), frame_idx) in dirty {
-            if let Some(placed) = self.assets.block_atlas.sprites.get(name) {
-                let (fx, fy) = tex
-                    .animation
-                    .as_ref()
-                    .unwrap()
-                    .get_frame(frame_idx, tex.size());
-
-                let frame_img = tex.data.view(fx, fy, fw, fh).to_image();
-                let bytes = frame_img.as_raw();
-
-                let offset = buffer_data.len() as vk::DeviceSize;
-                buffer_data.extend_from_slice(bytes);
-
-                regions.push(
-                    vk::BufferImageCopy::default()
-                        .buffer_offset(offset)
-                        .buffer_row_length(0)
-                        .buffer_image_height(0)
-                        .image_subresource(
-                            vk::ImageSubresourceLayers::default()
-                                .aspect_mask(vk::ImageAspectFlags::COLOR)
-                                .mip_level(0)
-                                .base_array_layer(0)
-                                .layer_count(1),
-                        )
-                        .image_offset(vk::Offset3D {
-                            x: placed.x as i32,
-                            y: placed.y as i32,
-                            z: 0,
-                        })
-                        .image_extent(vk::Extent3D {
-                            width: fw,
-                            height: fh,
-                            depth: 1,
-                        }),
-                );
-            }
+        for frame in dirty {
+            // animated sprites in the texture array replace their whole layer
+            let (layer, x, y, frame_img) = match &self.assets.block_layers {
+                Some(layers) => {
+                    let Some(&layer) = layers.layers.get(frame.name) else {
+                        continue;
+                    };
+                    (layer, 0, 0, layers.fit_frame(frame.image()))
+                }
+                None => {
+                    let Some(placed) = self.assets.get_sprite_rect(frame.name) else {
+                        continue;
+                    };
+                    debug_assert_eq!(
+                        (placed.width, placed.height),
+                        frame.size,
+                        "frame size of {} doesn't match its atlas rect",
+                        frame.name
+                    );
+                    (0, placed.x, placed.y, frame.image())
+                }
+            };
+            let (fw, fh) = frame_img.dimensions();
+
+            let offset = buffer_data.len() as vk::DeviceSize;
+            buffer_data.extend_from_slice(frame_img.as_raw());
+
+            regions.push(
+                vk::BufferImageCopy::default()
+                    .buffer_offset(offset)
+                    .buffer_row_length(0)
+                    .buffer_image_height(0)
+                    .image_subresource(
+                        vk::ImageSubresourceLayers::default()
+                            .aspect_mask(vk::ImageAspectFlags::COLOR)
+                            .mip_level(0)
+                            .base_array_layer(layer)
+                            .layer_count(1),
+                    )
+                    .image_offset(vk::Offset3D {
+                        x: x as i32,
+                        y: y as i32,
+                        z: 0,
+                    })
+                    .image_extent(vk::Extent3D {
+                        width: fw,
+                        height: fh,
+                        depth: 1,
+                    }),
+            );
         }
 
         let subresource = vk::ImageSubresourceRange {
@@ -615,7 +1706,7 @@ impl WorldRenderer {
             base_mip_level: 0,
             level_count: 1,
             base_array_layer: 0,
-            layer_count: 1,
+            layer_count: vk::REMAINING_ARRAY_LAYERS,
         };
 
         frame_ctx.pipeline_barrier(
@@ -652,11 +1743,35 @@ impl WorldRenderer {
         );
     }
 
+    /// Rebuilds the pipelines of the world pass and its compute passes from a
+    /// new shader module, the device must be idle.
+    pub fn reload_shaders(&mut self, ctx: &VkContext, module: vk::ShaderModule) {
+        self.pipelines.reload_shaders(ctx, self.render_pass, module);
+        self.oit.reload_shaders(
+            ctx,
+            module,
+            self.pipelines.layout,
+            self.pipelines.dynamic_cull_mode,
+        );
+        self.aabb_renderer
+            .reload_shaders(ctx, module, self.render_pass);
+        self.clouds.reload_shaders(ctx, module, self.render_pass);
+        self.weather_renderer
+            .reload_shaders(ctx, module, self.render_pass);
+        self.beam_renderer
+            .reload_shaders(ctx, module, self.render_pass);
+        self.celestial.reload_shaders(ctx, module, self.render_pass);
+        self.hiz_compute.reload_shaders(ctx, module);
+        self.visibility_compute.reload_shaders(ctx, module);
+        self.gpu_mesher.reload_shaders(ctx, module);
+    }
+
     pub fn recreate_swapchain(&mut self, ctx: &VkContext, render_targets: &RenderTargets) {
         for fb in self.framebuffers.drain(..) {
             unsafe { ctx.device().destroy_framebuffer(fb, None) };
         }
         self.framebuffers = create_framebuffers(ctx, render_targets, self.render_pass);
+        self.oit.recreate_swapchain(ctx, render_targets);
 
         self.hiz_compute.recreate(
             ctx,
@@ -667,6 +1782,12 @@ impl WorldRenderer {
             .recreate_image_sets(ctx, &render_targets.depth_pyramids);
     }
 
+    /// Rebuilds the OIT framebuffers after [`RenderTargets::set_oit`], the
+    /// device must be idle.
+    pub fn recreate_oit(&mut self, ctx: &VkContext, render_targets: &RenderTargets) {
+        self.oit.recreate_swapchain(ctx, render_targets);
+    }
+
     pub fn destroy(&mut self, ctx: &VkContext) {
         let device = ctx.device();
 
@@ -684,15 +1805,51 @@ impl WorldRenderer {
         if let Some(mut vb) = self.visibility_buffers.take() {
             vb.destroy(ctx);
         }
-        for i in 0..MAX_FRAMES_IN_FLIGHT {
-            self.visibility_uniforms[i].destroy(ctx);
+        for uniform in &mut self.visibility_uniforms {
+            uniform.destroy(ctx);
         }
         self.visibility_compute.destroy(ctx);
-        self.aabb_renderer.destroy(device);
+        self.gpu_mesher.destroy(ctx);
+        self.aabb_renderer.destroy(ctx);
+        self.clouds.destroy(ctx);
+        self.oit.destroy(ctx);
+        self.weather_renderer.destroy(ctx);
+        self.beam_renderer.destroy(ctx);
+        self.celestial.destroy(ctx);
+
+        self.pipelines.destroy(ctx);
+        self.descriptors.destroy(ctx);
+    }
+}
 
-        self.pipelines.destroy(device);
-        self.descriptors.destroy(device);
+/// Whether the section at `pos` is skipped this frame. In
+/// [`WorldRendererConfig::render_everything`] only the render distance limits
+/// what is drawn.
+fn section_culled(
+    config: &WorldRendererConfig,
+    view_proj: &Mat4,
+    camera_pos: Vec3,
+    pos: ChunkSectionPos,
+) -> bool {
+    if config.render_everything {
+        let camera_chunk = (camera_pos / 16.0).floor().as_ivec3();
+        let radius = config.render_distance as i32;
+        return (pos.x - camera_chunk.x).abs() > radius || (pos.z - camera_chunk.z).abs() > radius;
     }
+
+    let pos_min = Vec3::new(
+        pos.x as f32 * 16.0,
+        pos.y as f32 * 16.0,
+        pos.z as f32 * 16.0,
+    );
+    let pos_max = pos_min + Vec3::splat(16.0);
+    !visibility::aabb_visible(view_proj, pos_min, pos_max)
+}
+
+/// Primed TNT swells in its last 10 ticks like in vanilla.
+fn tnt_scale(fuse: i32, partial_tick: f32) -> f32 {
+    let t = (1.0 - (fuse as f32 - partial_tick + 1.0) / 10.0).clamp(0.0, 1.0);
+    1.0 + t.powi(4) * 0.3
 }
 
 fn calc_dirty_size(textures: &HashMap<String, TextureEntry>, dirty: &[&str]) -> vk::DeviceSize {
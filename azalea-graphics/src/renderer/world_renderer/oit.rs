@@ -0,0 +1,535 @@
+//! Weighted blended order independent transparency for the water meshes. The
+//! first subpass sums up the weighted colors and revealage of every
+//! translucent fragment, the second one resolves them over the opaque color.
+//! Runs after the world render pass and only reads its depth.
+
+use std::ffi::CString;
+
+use ash::vk;
+
+use crate::renderer::{
+    frame_ctx::FrameCtx,
+    render_targets::{OIT_ACCUM_FORMAT, OIT_REVEALAGE_FORMAT, RenderTargets},
+    vulkan::context::VkContext,
+    world_renderer::pipelines::{PipelineConfig, create_world_pipeline},
+};
+
+pub struct OitRenderer {
+    render_pass: vk::RenderPass,
+    framebuffers: Vec<vk::Framebuffer>,
+    /// Draws the water into the accumulation and revealage attachments.
+    pub accumulate_pipeline: vk::Pipeline,
+    resolve_set_layout: vk::DescriptorSetLayout,
+    resolve_layout: vk::PipelineLayout,
+    resolve_pipeline: vk::Pipeline,
+    pool: vk::DescriptorPool,
+    /// One set per render target image, reading its attachments.
+    resolve_sets: Vec<vk::DescriptorSet>,
+}
+
+impl OitRenderer {
+    pub fn new(
+        ctx: &VkContext,
+        module: vk::ShaderModule,
+        render_targets: &RenderTargets,
+        world_layout: vk::PipelineLayout,
+        dynamic_cull_mode: bool,
+    ) -> Self {
+        let device = ctx.device();
+        let render_pass = create_oit_render_pass(ctx, render_targets);
+
+        let accumulate_pipeline =
+            create_accumulate_pipeline(ctx, module, render_pass, world_layout, dynamic_cull_mode);
+
+        let bindings: Vec<_> = (0..2)
+            .map(|binding| {
+                vk::DescriptorSetLayoutBinding::default()
+                    .binding(binding)
+                    .descriptor_type(vk::DescriptorType::INPUT_ATTACHMENT)
+                    .descriptor_count(1)
+                    .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            })
+            .collect();
+        let resolve_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings),
+                    None,
+                )
+                .unwrap()
+        };
+        let resolve_layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::default().set_layouts(&[resolve_set_layout]),
+                    None,
+                )
+                .unwrap()
+        };
+        let resolve_pipeline = create_resolve_pipeline(ctx, module, render_pass, resolve_layout);
+
+        let mut oit = Self {
+            render_pass,
+            framebuffers: Vec::new(),
+            accumulate_pipeline,
+            resolve_set_layout,
+            resolve_layout,
+            resolve_pipeline,
+            pool: vk::DescriptorPool::null(),
+            resolve_sets: Vec::new(),
+        };
+        oit.create_frame_resources(ctx, render_targets);
+        oit
+    }
+
+    /// Rebuilds the pipelines from a new shader module, the device must be
+    /// idle.
+    pub fn reload_shaders(
+        &mut self,
+        ctx: &VkContext,
+        module: vk::ShaderModule,
+        world_layout: vk::PipelineLayout,
+        dynamic_cull_mode: bool,
+    ) {
+        for pipeline in [self.accumulate_pipeline, self.resolve_pipeline] {
+            ctx.untrack_object(pipeline);
+            unsafe { ctx.device().destroy_pipeline(pipeline, None) };
+        }
+        self.accumulate_pipeline = create_accumulate_pipeline(
+            ctx,
+            module,
+            self.render_pass,
+            world_layout,
+            dynamic_cull_mode,
+        );
+        self.resolve_pipeline =
+            create_resolve_pipeline(ctx, module, self.render_pass, self.resolve_layout);
+    }
+
+    /// Does nothing while the render targets have no OIT attachments.
+    fn create_frame_resources(&mut self, ctx: &VkContext, render_targets: &RenderTargets) {
+        if !render_targets.has_oit() {
+            return;
+        }
+        let device = ctx.device();
+        let extent = render_targets.extent();
+        let count = render_targets.image_count();
+
+        self.framebuffers = (0..count)
+            .map(|i| {
+                let attachments = [
+                    render_targets.image_view(i),
+                    render_targets.depth_images[i].default_view,
+                    render_targets.oit_accum[i].default_view,
+                    render_targets.oit_revealage[i].default_view,
+                ];
+                let info = vk::FramebufferCreateInfo::default()
+                    .render_pass(self.render_pass)
+                    .attachments(&attachments)
+                    .width(extent.width)
+                    .height(extent.height)
+                    .layers(1);
+                unsafe { device.create_framebuffer(&info, None).unwrap() }
+            })
+            .collect();
+
+        self.pool = unsafe {
+            device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::default()
+                        .pool_sizes(&[vk::DescriptorPoolSize {
+                            ty: vk::DescriptorType::INPUT_ATTACHMENT,
+                            descriptor_count: 2 * count as u32,
+                        }])
+                        .max_sets(count as u32),
+                    None,
+                )
+                .unwrap()
+        };
+        ctx.track_object(self.pool);
+        self.resolve_sets = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::default()
+                        .descriptor_pool(self.pool)
+                        .set_layouts(&vec![self.resolve_set_layout; count]),
+                )
+                .unwrap()
+        };
+
+        for (i, &set) in self.resolve_sets.iter().enumerate() {
+            let infos = [
+                vk::DescriptorImageInfo::default()
+                    .image_view(render_targets.oit_accum[i].default_view)
+                    .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+                vk::DescriptorImageInfo::default()
+                    .image_view(render_targets.oit_revealage[i].default_view)
+                    .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            ];
+            let writes: Vec<_> = infos
+                .iter()
+                .enumerate()
+                .map(|(binding, info)| {
+                    vk::WriteDescriptorSet::default()
+                        .dst_set(set)
+                        .dst_binding(binding as u32)
+                        .descriptor_type(vk::DescriptorType::INPUT_ATTACHMENT)
+                        .image_info(std::slice::from_ref(info))
+                })
+                .collect();
+            unsafe { device.update_descriptor_sets(&writes, &[]) };
+        }
+    }
+
+    fn destroy_frame_resources(&mut self, ctx: &VkContext) {
+        if self.pool == vk::DescriptorPool::null() {
+            return;
+        }
+        let device = ctx.device();
+        ctx.untrack_object(self.pool);
+        unsafe {
+            for fb in self.framebuffers.drain(..) {
+                device.destroy_framebuffer(fb, None);
+            }
+            // frees the sets with it
+            device.destroy_descriptor_pool(self.pool, None);
+        }
+        self.pool = vk::DescriptorPool::null();
+        self.resolve_sets.clear();
+    }
+
+    pub fn recreate_swapchain(&mut self, ctx: &VkContext, render_targets: &RenderTargets) {
+        self.destroy_frame_resources(ctx);
+        self.create_frame_resources(ctx, render_targets);
+    }
+
+    /// Begins the accumulation subpass, translucent geometry is drawn with
+    /// [`Self::accumulate_pipeline`] until [`Self::resolve`].
+    pub fn begin(&self, frame_ctx: &FrameCtx) {
+        let device = frame_ctx.ctx.device();
+        let cmd = frame_ctx.cmd;
+        let extent = frame_ctx.render_targets.extent();
+        // the color and depth are loaded, only the accumulation and revealage
+        // are cleared
+        let clear_values = [
+            vk::ClearValue::default(),
+            vk::ClearValue::default(),
+            vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: [0.0, 0.0, 0.0, 0.0],
+                },
+            },
+            vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: [1.0, 0.0, 0.0, 0.0],
+                },
+            },
+        ];
+
+        let rp_info = vk::RenderPassBeginInfo::default()
+            .render_pass(self.render_pass)
+            .framebuffer(self.framebuffers[frame_ctx.image_index as usize])
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            })
+            .clear_values(&clear_values);
+
+        unsafe {
+            device.cmd_begin_render_pass(cmd, &rp_info, vk::SubpassContents::INLINE);
+            device.cmd_set_viewport(
+                cmd,
+                0,
+                &[vk::Viewport {
+                    x: 0.0,
+                    y: 0.0,
+                    width: extent.width as f32,
+                    height: extent.height as f32,
+                    min_depth: 0.0,
+                    max_depth: 1.0,
+                }],
+            );
+            device.cmd_set_scissor(
+                cmd,
+                0,
+                &[vk::Rect2D {
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                    extent,
+                }],
+            );
+        }
+    }
+
+    /// Blends the accumulated translucency over the color attachment and ends
+    /// the pass.
+    pub fn resolve(&self, frame_ctx: &FrameCtx) {
+        let device = frame_ctx.ctx.device();
+        let cmd = frame_ctx.cmd;
+        unsafe {
+            device.cmd_next_subpass(cmd, vk::SubpassContents::INLINE);
+            device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, self.resolve_pipeline);
+            device.cmd_bind_descriptor_sets(
+                cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.resolve_layout,
+                0,
+                &[self.resolve_sets[frame_ctx.image_index as usize]],
+                &[],
+            );
+            device.cmd_draw(cmd, 3, 1, 0, 0);
+            device.cmd_end_render_pass(cmd);
+        }
+    }
+
+    pub fn destroy(&mut self, ctx: &VkContext) {
+        self.destroy_frame_resources(ctx);
+        let device = ctx.device();
+        ctx.untrack_object(self.accumulate_pipeline);
+        ctx.untrack_object(self.resolve_pipeline);
+        unsafe {
+            device.destroy_pipeline(self.accumulate_pipeline, None);
+            device.destroy_pipeline(self.resolve_pipeline, None);
+            device.destroy_pipeline_layout(self.resolve_layout, None);
+            device.destroy_descriptor_set_layout(self.resolve_set_layout, None);
+            device.destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
+
+fn create_oit_render_pass(ctx: &VkContext, render_targets: &RenderTargets) -> vk::RenderPass {
+    let color_attachment = vk::AttachmentDescription::default()
+        .format(render_targets.format())
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .load_op(vk::AttachmentLoadOp::LOAD)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .initial_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+
+    // left as the world render pass leaves it, for the HiZ pyramid
+    let depth_attachment = vk::AttachmentDescription::default()
+        .format(vk::Format::D32_SFLOAT)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .load_op(vk::AttachmentLoadOp::LOAD)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+
+    let oit_attachment = |format| {
+        vk::AttachmentDescription::default()
+            .format(format)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    };
+
+    let attachments = [
+        color_attachment,
+        depth_attachment,
+        oit_attachment(OIT_ACCUM_FORMAT),
+        oit_attachment(OIT_REVEALAGE_FORMAT),
+    ];
+
+    let accumulate_refs = [
+        vk::AttachmentReference {
+            attachment: 2,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        },
+        vk::AttachmentReference {
+            attachment: 3,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        },
+    ];
+    let depth_ref = vk::AttachmentReference {
+        attachment: 1,
+        layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+    };
+    let preserve = [0];
+
+    let input_refs = [
+        vk::AttachmentReference {
+            attachment: 2,
+            layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        },
+        vk::AttachmentReference {
+            attachment: 3,
+            layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        },
+    ];
+    let color_ref = vk::AttachmentReference {
+        attachment: 0,
+        layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+    };
+
+    let subpasses = [
+        vk::SubpassDescription::default()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&accumulate_refs)
+            .depth_stencil_attachment(&depth_ref)
+            .preserve_attachments(&preserve),
+        vk::SubpassDescription::default()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .input_attachments(&input_refs)
+            .color_attachments(std::slice::from_ref(&color_ref)),
+    ];
+
+    let dependencies = [
+        // the world render pass' color and depth writes
+        vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            dst_subpass: 0,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ
+                | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ,
+            dependency_flags: vk::DependencyFlags::empty(),
+        },
+        vk::SubpassDependency {
+            src_subpass: 0,
+            dst_subpass: 1,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            dst_access_mask: vk::AccessFlags::INPUT_ATTACHMENT_READ,
+            dependency_flags: vk::DependencyFlags::BY_REGION,
+        },
+        // the HiZ pyramid reads the depth and the entities draw over the color
+        vk::SubpassDependency {
+            src_subpass: 1,
+            dst_subpass: vk::SUBPASS_EXTERNAL,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_stage_mask: vk::PipelineStageFlags::COMPUTE_SHADER
+                | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            dst_access_mask: vk::AccessFlags::SHADER_READ
+                | vk::AccessFlags::COLOR_ATTACHMENT_READ
+                | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dependency_flags: vk::DependencyFlags::empty(),
+        },
+    ];
+
+    let info = vk::RenderPassCreateInfo::default()
+        .attachments(&attachments)
+        .subpasses(&subpasses)
+        .dependencies(&dependencies);
+
+    unsafe { ctx.device().create_render_pass(&info, None).unwrap() }
+}
+
+fn create_accumulate_pipeline(
+    ctx: &VkContext,
+    module: vk::ShaderModule,
+    render_pass: vk::RenderPass,
+    world_layout: vk::PipelineLayout,
+    dynamic_cull_mode: bool,
+) -> vk::Pipeline {
+    create_world_pipeline(
+        ctx,
+        render_pass,
+        world_layout,
+        module,
+        "terrain::water_vert",
+        "terrain::water_oit_frag",
+        PipelineConfig {
+            polygon_mode: vk::PolygonMode::FILL,
+            enable_blend: true,
+            oit_accumulate: true,
+            depth_write: false,
+            cull_mode: vk::CullModeFlags::BACK,
+            dynamic_cull_mode,
+            depth_bias: true,
+            samples: vk::SampleCountFlags::TYPE_1,
+            alpha_to_coverage: false,
+        },
+    )
+}
+
+fn create_resolve_pipeline(
+    ctx: &VkContext,
+    module: vk::ShaderModule,
+    render_pass: vk::RenderPass,
+    layout: vk::PipelineLayout,
+) -> vk::Pipeline {
+    let vert_entry = CString::new("oit::resolve_vert").unwrap();
+    let frag_entry = CString::new("oit::resolve_frag").unwrap();
+    let stages = [
+        vk::PipelineShaderStageCreateInfo::default()
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .module(module)
+            .name(&vert_entry),
+        vk::PipelineShaderStageCreateInfo::default()
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .module(module)
+            .name(&frag_entry),
+    ];
+
+    // the fullscreen triangle is generated in the shader
+    let vertex_input = vk::PipelineVertexInputStateCreateInfo::default();
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+    let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+        .viewport_count(1)
+        .scissor_count(1);
+    let rasterizer = vk::PipelineRasterizationStateCreateInfo::default()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .line_width(1.0);
+    let multisampling = vk::PipelineMultisampleStateCreateInfo::default()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+    let color_blend_attachment = vk::PipelineColorBlendAttachmentState::default()
+        .color_write_mask(
+            vk::ColorComponentFlags::R
+                | vk::ColorComponentFlags::G
+                | vk::ColorComponentFlags::B
+                | vk::ColorComponentFlags::A,
+        )
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ONE)
+        .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+        .alpha_blend_op(vk::BlendOp::ADD);
+    let color_blending = vk::PipelineColorBlendStateCreateInfo::default()
+        .attachments(std::slice::from_ref(&color_blend_attachment));
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state =
+        vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+    let pipeline_info = vk::GraphicsPipelineCreateInfo::default()
+        .stages(&stages)
+        .vertex_input_state(&vertex_input)
+        .input_assembly_state(&input_assembly)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterizer)
+        .multisample_state(&multisampling)
+        .color_blend_state(&color_blending)
+        .dynamic_state(&dynamic_state)
+        .layout(layout)
+        .render_pass(render_pass)
+        .subpass(1);
+
+    let pipeline = unsafe {
+        ctx.device()
+            .create_graphics_pipelines(
+                vk::PipelineCache::null(),
+                std::slice::from_ref(&pipeline_info),
+                None,
+            )
+            .unwrap()[0]
+    };
+    ctx.track_object(pipeline);
+    pipeline
+}
@@ -1,6 +1,10 @@
 use ash::{Device, vk};
 
-use crate::renderer::{vulkan::context::VkContext, world_renderer::types::BlockVertex};
+use crate::renderer::{
+    render_targets::{DEPTH_BIAS_SIGN, DEPTH_COMPARE_OP},
+    vulkan::context::VkContext,
+    world_renderer::types::BlockVertex,
+};
 
 fn create_shader_module(device: &Device, code: &[u32]) -> vk::ShaderModule {
     let info = vk::ShaderModuleCreateInfo::default().code(&code);
@@ -25,7 +29,22 @@ pub fn create_world_pipeline_layout(
 pub struct PipelineConfig {
     pub polygon_mode: vk::PolygonMode,
     pub enable_blend: bool,
+    /// Writes to the accumulation and revealage attachments of the order
+    /// independent transparency pass instead of the color attachment,
+    /// `enable_blend` is ignored.
+    pub oit_accumulate: bool,
     pub depth_write: bool,
+    pub cull_mode: vk::CullModeFlags,
+    /// Leaves the cull mode to `cmd_set_cull_mode`, `cull_mode` is ignored.
+    pub dynamic_cull_mode: bool,
+    /// Offsets the depth by the bias set with `cmd_set_depth_bias`.
+    pub depth_bias: bool,
+    /// Sample count of the render pass attachments.
+    pub samples: vk::SampleCountFlags,
+    /// Turns the alpha of cutout texels into sample coverage so their edges
+    /// antialias instead of discarding whole pixels, only enabled when
+    /// `samples` is more than one.
+    pub alpha_to_coverage: bool,
 }
 
 pub fn create_world_pipeline(
@@ -70,12 +89,16 @@ pub fn create_world_pipeline(
 
     let rasterizer = vk::PipelineRasterizationStateCreateInfo::default()
         .polygon_mode(config.polygon_mode)
-        .cull_mode(vk::CullModeFlags::BACK)
+        .cull_mode(config.cull_mode)
         .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .depth_bias_enable(config.depth_bias)
         .line_width(1.0);
 
     let multisampling = vk::PipelineMultisampleStateCreateInfo::default()
-        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        .rasterization_samples(config.samples)
+        .alpha_to_coverage_enable(
+            config.alpha_to_coverage && config.samples != vk::SampleCountFlags::TYPE_1,
+        );
 
     let mut color_blend_attachment = vk::PipelineColorBlendAttachmentState::default()
         .color_write_mask(
@@ -99,12 +122,49 @@ pub fn create_world_pipeline(
     let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
         .depth_test_enable(true)
         .depth_write_enable(config.depth_write)
-        .depth_compare_op(vk::CompareOp::GREATER_OR_EQUAL);
+        .depth_compare_op(DEPTH_COMPARE_OP);
 
-    let attachments = [color_blend_attachment];
-    let color_blending = vk::PipelineColorBlendStateCreateInfo::default().attachments(&attachments);
+    // the weighted colors are summed up and the revealage is multiplied by
+    // `1 - alpha`, neither depends on the order of the fragments
+    let oit_attachments = [
+        vk::PipelineColorBlendAttachmentState::default()
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::ONE)
+            .dst_color_blend_factor(vk::BlendFactor::ONE)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ONE)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+            .alpha_blend_op(vk::BlendOp::ADD),
+        vk::PipelineColorBlendAttachmentState::default()
+            .color_write_mask(vk::ColorComponentFlags::R)
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::ZERO)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_COLOR)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+            .alpha_blend_op(vk::BlendOp::ADD),
+    ];
+    let attachments: &[_] = if config.oit_accumulate {
+        &oit_attachments
+    } else {
+        std::slice::from_ref(&color_blend_attachment)
+    };
+    let color_blending = vk::PipelineColorBlendStateCreateInfo::default().attachments(attachments);
 
-    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let mut dynamic_states = vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    if config.dynamic_cull_mode {
+        dynamic_states.push(vk::DynamicState::CULL_MODE);
+    }
+    if config.depth_bias {
+        dynamic_states.push(vk::DynamicState::DEPTH_BIAS);
+    }
     let dynamic_state =
         vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
 
@@ -128,6 +188,7 @@ pub fn create_world_pipeline(
             .expect("Failed to create pipeline")
     };
     let pipeline = pipelines[0];
+    ctx.track_object(pipeline);
 
     pipeline
 }
@@ -138,10 +199,16 @@ pub struct Pipelines {
     pub block_wire: Option<vk::Pipeline>,
     pub water: vk::Pipeline,
     pub water_wire: Option<vk::Pipeline>,
+    /// Pipelines without backface culling, only needed when the cull mode
+    /// can't be set dynamically.
+    pub block_no_cull: Option<vk::Pipeline>,
+    pub water_no_cull: Option<vk::Pipeline>,
+    pub dynamic_cull_mode: bool,
 }
 
 pub struct PipelineOptions {
     pub wireframe_enabled: bool,
+    pub dynamic_cull_mode: bool,
 }
 
 impl Pipelines {
@@ -153,7 +220,32 @@ impl Pipelines {
         opts: PipelineOptions,
     ) -> Self {
         let layout = create_world_pipeline_layout(ctx.device(), descriptor_set_layout);
+        Self::with_layout(ctx, render_pass, layout, module, opts)
+    }
 
+    /// Rebuilds the pipelines from a new shader module, keeping the layout. The
+    /// device must be idle.
+    pub fn reload_shaders(
+        &mut self,
+        ctx: &VkContext,
+        render_pass: vk::RenderPass,
+        module: vk::ShaderModule,
+    ) {
+        let opts = PipelineOptions {
+            wireframe_enabled: self.block_wire.is_some(),
+            dynamic_cull_mode: self.dynamic_cull_mode,
+        };
+        self.destroy_pipelines(ctx);
+        *self = Self::with_layout(ctx, render_pass, self.layout, module, opts);
+    }
+
+    fn with_layout(
+        ctx: &VkContext,
+        render_pass: vk::RenderPass,
+        layout: vk::PipelineLayout,
+        module: vk::ShaderModule,
+        opts: PipelineOptions,
+    ) -> Self {
         let block = create_world_pipeline(
             ctx,
             render_pass,
@@ -164,7 +256,13 @@ impl Pipelines {
             PipelineConfig {
                 polygon_mode: vk::PolygonMode::FILL,
                 enable_blend: false,
+                oit_accumulate: false,
                 depth_write: true,
+                cull_mode: vk::CullModeFlags::BACK,
+                dynamic_cull_mode: opts.dynamic_cull_mode,
+                depth_bias: false,
+                samples: vk::SampleCountFlags::TYPE_1,
+                alpha_to_coverage: true,
             },
         );
         let block_wire = if opts.wireframe_enabled {
@@ -178,7 +276,13 @@ impl Pipelines {
                 PipelineConfig {
                     polygon_mode: vk::PolygonMode::LINE,
                     enable_blend: false,
+                    oit_accumulate: false,
                     depth_write: true,
+                    cull_mode: vk::CullModeFlags::BACK,
+                    dynamic_cull_mode: opts.dynamic_cull_mode,
+                    depth_bias: false,
+                    samples: vk::SampleCountFlags::TYPE_1,
+                    alpha_to_coverage: true,
                 },
             ))
         } else {
@@ -195,7 +299,13 @@ impl Pipelines {
             PipelineConfig {
                 polygon_mode: vk::PolygonMode::FILL,
                 enable_blend: true,
+                oit_accumulate: false,
                 depth_write: false,
+                cull_mode: vk::CullModeFlags::BACK,
+                dynamic_cull_mode: opts.dynamic_cull_mode,
+                depth_bias: true,
+                samples: vk::SampleCountFlags::TYPE_1,
+                alpha_to_coverage: false,
             },
         );
         let water_wire = if opts.wireframe_enabled {
@@ -209,48 +319,145 @@ impl Pipelines {
                 super::pipelines::PipelineConfig {
                     polygon_mode: vk::PolygonMode::LINE,
                     enable_blend: true,
+                    oit_accumulate: false,
                     depth_write: false,
+                    cull_mode: vk::CullModeFlags::BACK,
+                    dynamic_cull_mode: opts.dynamic_cull_mode,
+                    depth_bias: true,
+                    samples: vk::SampleCountFlags::TYPE_1,
+                    alpha_to_coverage: false,
                 },
             ))
         } else {
             None
         };
 
+        let (block_no_cull, water_no_cull) = if opts.dynamic_cull_mode {
+            (None, None)
+        } else {
+            let block_no_cull = create_world_pipeline(
+                ctx,
+                render_pass,
+                layout,
+                module,
+                "terrain::block_vert",
+                "terrain::block_frag",
+                PipelineConfig {
+                    polygon_mode: vk::PolygonMode::FILL,
+                    enable_blend: false,
+                    oit_accumulate: false,
+                    depth_write: true,
+                    cull_mode: vk::CullModeFlags::NONE,
+                    dynamic_cull_mode: false,
+                    depth_bias: false,
+                    samples: vk::SampleCountFlags::TYPE_1,
+                    alpha_to_coverage: true,
+                },
+            );
+            let water_no_cull = create_world_pipeline(
+                ctx,
+                render_pass,
+                layout,
+                module,
+                "terrain::water_vert",
+                "terrain::water_frag",
+                PipelineConfig {
+                    polygon_mode: vk::PolygonMode::FILL,
+                    enable_blend: true,
+                    oit_accumulate: false,
+                    depth_write: false,
+                    cull_mode: vk::CullModeFlags::NONE,
+                    dynamic_cull_mode: false,
+                    depth_bias: true,
+                    samples: vk::SampleCountFlags::TYPE_1,
+                    alpha_to_coverage: false,
+                },
+            );
+            (Some(block_no_cull), Some(water_no_cull))
+        };
+
         Self {
             layout,
             block,
             block_wire,
             water,
             water_wire,
+            block_no_cull,
+            water_no_cull,
+            dynamic_cull_mode: opts.dynamic_cull_mode,
         }
     }
 
-    pub fn block_pipeline(&self, wireframe_mode: bool) -> vk::Pipeline {
+    /// Without dynamic cull mode, wireframes are always culled.
+    pub fn block_pipeline(&self, wireframe_mode: bool, backface_culling: bool) -> vk::Pipeline {
         if wireframe_mode {
             self.block_wire.unwrap_or(self.block)
+        } else if !backface_culling {
+            self.block_no_cull.unwrap_or(self.block)
         } else {
             self.block
         }
     }
-    pub fn water_pipeline(&self, wireframe_mode: bool) -> vk::Pipeline {
+    pub fn water_pipeline(&self, wireframe_mode: bool, backface_culling: bool) -> vk::Pipeline {
         if wireframe_mode {
             self.water_wire.unwrap_or(self.water)
+        } else if !backface_culling {
+            self.water_no_cull.unwrap_or(self.water)
         } else {
             self.water
         }
     }
 
-    pub fn destroy(&mut self, device: &Device) {
+    /// Sets the cull mode of the bound pipeline if it is dynamic, must be
+    /// called after every bind.
+    pub fn set_cull_mode(&self, device: &Device, cmd: vk::CommandBuffer, backface_culling: bool) {
+        if !self.dynamic_cull_mode {
+            return;
+        }
+        let cull_mode = if backface_culling {
+            vk::CullModeFlags::BACK
+        } else {
+            vk::CullModeFlags::NONE
+        };
+        unsafe { device.cmd_set_cull_mode(cmd, cull_mode) };
+    }
+
+    /// Pulls the bound water pipeline `constant` depth units plus `slope`
+    /// times the depth slope of each triangle towards the camera.
+    pub fn set_water_depth_bias(
+        &self,
+        device: &Device,
+        cmd: vk::CommandBuffer,
+        constant: f32,
+        slope: f32,
+    ) {
         unsafe {
-            if let Some(p) = self.block_wire.take() {
-                device.destroy_pipeline(p, None);
-            }
-            if let Some(p) = self.water_wire.take() {
-                device.destroy_pipeline(p, None);
-            }
-            device.destroy_pipeline(self.block, None);
-            device.destroy_pipeline(self.water, None);
-            device.destroy_pipeline_layout(self.layout, None);
+            device.cmd_set_depth_bias(
+                cmd,
+                constant * DEPTH_BIAS_SIGN,
+                0.0,
+                slope * DEPTH_BIAS_SIGN,
+            )
+        };
+    }
+
+    pub fn destroy(&mut self, ctx: &VkContext) {
+        self.destroy_pipelines(ctx);
+        unsafe { ctx.device().destroy_pipeline_layout(self.layout, None) };
+    }
+
+    fn destroy_pipelines(&mut self, ctx: &VkContext) {
+        let pipelines = [
+            Some(self.block),
+            Some(self.water),
+            self.block_wire.take(),
+            self.water_wire.take(),
+            self.block_no_cull.take(),
+            self.water_no_cull.take(),
+        ];
+        for p in pipelines.into_iter().flatten() {
+            ctx.untrack_object(p);
+            unsafe { ctx.device().destroy_pipeline(p, None) };
         }
     }
 }
@@ -0,0 +1,85 @@
+use azalea::{
+    core::{direction::Direction, position::BlockPos},
+    registry::Block,
+    world::Instance,
+};
+use glam::{IVec3, Vec3};
+
+/// Walks the blocks along the ray with a DDA voxel traversal and returns the
+/// first one that isn't air or a fluid, together with the face the ray entered
+/// it through.
+pub fn raycast_block(
+    world: &Instance,
+    origin: Vec3,
+    dir: Vec3,
+    max_dist: f32,
+) -> Option<(BlockPos, Direction)> {
+    let dir = dir.normalize_or_zero();
+    if dir == Vec3::ZERO {
+        return None;
+    }
+
+    let mut pos = origin.floor().as_ivec3();
+    let step = IVec3::new(
+        dir.x.signum() as i32,
+        dir.y.signum() as i32,
+        dir.z.signum() as i32,
+    );
+
+    // distance along the ray to cross one block on each axis, and to the next
+    // block boundary on each axis
+    let mut t_delta = [f32::INFINITY; 3];
+    let mut t_max = [f32::INFINITY; 3];
+    for axis in 0..3 {
+        if dir[axis] == 0.0 {
+            continue;
+        }
+        t_delta[axis] = 1.0 / dir[axis].abs();
+        let boundary = if dir[axis] > 0.0 {
+            pos[axis] as f32 + 1.0 - origin[axis]
+        } else {
+            origin[axis] - pos[axis] as f32
+        };
+        t_max[axis] = boundary * t_delta[axis];
+    }
+
+    // a ray starting inside a block hits the face it points away from
+    let dominant = dir.abs().max_position();
+    let mut face = entered_face(dominant, step[dominant]);
+
+    loop {
+        if is_target(world, pos) {
+            return Some((BlockPos::new(pos.x, pos.y, pos.z), face));
+        }
+
+        let axis = (0..3)
+            .min_by(|&a, &b| t_max[a].total_cmp(&t_max[b]))
+            .unwrap();
+        if t_max[axis] > max_dist {
+            return None;
+        }
+
+        pos[axis] += step[axis];
+        t_max[axis] += t_delta[axis];
+        face = entered_face(axis, step[axis]);
+    }
+}
+
+/// The face of a block that is entered when moving by `step` along `axis`.
+fn entered_face(axis: usize, step: i32) -> Direction {
+    match (axis, step > 0) {
+        (0, true) => Direction::West,
+        (0, false) => Direction::East,
+        (1, true) => Direction::Down,
+        (1, false) => Direction::Up,
+        (_, true) => Direction::North,
+        (_, false) => Direction::South,
+    }
+}
+
+fn is_target(world: &Instance, pos: IVec3) -> bool {
+    let Some(state) = world.get_block_state(BlockPos::new(pos.x, pos.y, pos.z)) else {
+        return false;
+    };
+    !state.is_air() && !matches!(Block::from(state), Block::Water | Block::Lava)
+}
@@ -2,9 +2,11 @@ use ash::vk;
 
 use crate::renderer::{render_targets::RenderTargets, vulkan::context::VkContext};
 
+/// The first pass of a frame and the only one that clears the color and depth,
+/// the entity, oit, post process and ui passes load what it left.
 pub fn create_world_render_pass(ctx: &VkContext, render_targets: &RenderTargets) -> vk::RenderPass {
     let color_attachment = vk::AttachmentDescription::default()
-        .format(render_targets.swapchain.format)
+        .format(render_targets.format())
         .samples(vk::SampleCountFlags::TYPE_1)
         .load_op(vk::AttachmentLoadOp::CLEAR)
         .store_op(vk::AttachmentStoreOp::STORE)
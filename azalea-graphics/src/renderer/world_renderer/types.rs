@@ -6,12 +6,21 @@ use ash::vk;
 #[derive(Clone, Copy, Debug)]
 pub struct BlockVertex {
     pub position: [f32; 3],
+    /// Ambient occlusion from 0 to 3, the sign bit marks emissive vertices.
     pub ao: f32,
     pub uv: [f32; 2],
     pub tint: [f32; 3],
+    /// Layer of the block texture array, `uv` is relative to the layer. The
+    /// top bit is [`Self::OPAQUE_LAYER`].
+    pub layer: u32,
 }
 
 impl BlockVertex {
+    /// `ao` of emissive vertices, they are drawn at full brightness.
+    pub const EMISSIVE_AO: f32 = -3.0;
+    /// Set in `layer` for faces whose transparent texels are drawn opaque.
+    pub const OPAQUE_LAYER: u32 = 1 << 31;
+
     pub fn binding_description() -> vk::VertexInputBindingDescription {
         vk::VertexInputBindingDescription::default()
             .binding(0)
@@ -45,6 +54,12 @@ impl BlockVertex {
                 format: vk::Format::R32G32B32_SFLOAT,
                 offset: offset_of!(BlockVertex, tint) as u32,
             },
+            vk::VertexInputAttributeDescription {
+                binding: 0,
+                location: 4,
+                format: vk::Format::R32_UINT,
+                offset: offset_of!(BlockVertex, layer) as u32,
+            },
         ]
     }
 }
@@ -58,3 +73,110 @@ pub struct VisibilityUniform {
     pub radius: i32,
     pub height: i32,
 }
+
+/// An axis aligned box drawn as colored lines in the world pass.
+#[derive(Clone, Copy, Debug)]
+pub struct DebugBox {
+    pub min: glam::Vec3,
+    pub max: glam::Vec3,
+    pub color: [f32; 4],
+}
+
+/// A block outlined by [`super::WorldRenderer::highlight_blocks`], laid out as
+/// the instance input of the highlight pipeline.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct BlockHighlight {
+    /// Lowest corner of the block, w is unused.
+    pub pos: [f32; 4],
+    pub color: [f32; 4],
+}
+
+/// A square world border centered on `center` (x, z), extending `radius` blocks
+/// in each direction.
+#[derive(Clone, Copy, Debug)]
+pub struct WorldBorder {
+    pub center: [f64; 2],
+    pub radius: f64,
+}
+
+/// Weather sent by the server, which azalea doesn't keep track of either.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Weather {
+    /// How hard it rains or snows, from 0 for a clear sky to 1.
+    pub rain_level: f32,
+}
+
+/// Time of day sent by the server, azalea ignores it too.
+#[derive(Clone, Copy, Debug)]
+pub struct WorldTime {
+    /// Ticks since the start of the first day, 6000 is noon.
+    pub day_time: u64,
+    /// Whether the time keeps moving between updates, unset with the
+    /// `doDaylightCycle` gamerule off.
+    pub advancing: bool,
+}
+
+impl Default for WorldTime {
+    /// Noon, so the sun is up before the server sends the time.
+    fn default() -> Self {
+        Self {
+            day_time: 6000,
+            advancing: false,
+        }
+    }
+}
+
+/// A block that is drawn by the entity renderer, like chests and beds.
+#[derive(Clone, Copy, Debug)]
+pub struct BlockEntity {
+    pub pos: glam::IVec3,
+    pub state: azalea::blocks::BlockState,
+}
+
+/// Vanilla's leaves graphics setting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LeavesMode {
+    /// Leaves are opaque and hide the faces behind them, including those of
+    /// other leaves.
+    Fast,
+    /// Leaves are cut out and every face is drawn.
+    Fancy,
+}
+
+/// One dyed pattern of a banner, from its block entity data.
+#[derive(Clone, Debug)]
+pub struct BannerLayer {
+    /// Pattern id without the namespace, like `stripe_top`.
+    pub pattern: String,
+    /// sRGB color of the dye.
+    pub color: [f32; 3],
+}
+
+/// The beam of an active beacon, colored by the stained glass above it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BeaconBeam {
+    /// Position of the beacon block, the beam starts at its bottom.
+    pub pos: glam::IVec3,
+    /// Parts of the beam from the bottom up, the last one reaches far above
+    /// the world.
+    pub segments: Vec<BeamSegment>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BeamSegment {
+    /// Blocks the segment covers.
+    pub height: u32,
+    /// sRGB color of the segment.
+    pub color: [f32; 3],
+}
+
+/// A falling block or primed TNT entity, drawn like a block of the world.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FallingBlock {
+    /// Bottom center of the block.
+    pub pos: glam::Vec3,
+    pub state: azalea::blocks::BlockState,
+    /// Ticks until primed TNT explodes, `None` for falling blocks.
+    pub fuse: Option<i32>,
+}
@@ -1,13 +1,8 @@
-use std::array::from_fn;
-
 use ash::vk;
 use azalea::core::position::ChunkSectionPos;
+use glam::Vec3;
 
-use crate::renderer::vulkan::{
-    buffer::Buffer,
-    context::VkContext,
-    frame_sync::{FrameSync, MAX_FRAMES_IN_FLIGHT},
-};
+use crate::renderer::vulkan::{buffer::Buffer, context::VkContext, frame_sync::FrameSync};
 
 #[derive(Clone)]
 pub struct VisibilitySnapshot {
@@ -18,6 +13,13 @@ pub struct VisibilitySnapshot {
     pub cx: i32,
     pub cz: i32,
     pub min_y: i32,
+
+    pub camera_pos: Vec3,
+    /// Direction the camera looks in, sections along it are meshed first.
+    pub forward: Vec3,
+    /// How much sections in front of the camera are preferred, see
+    /// `WorldRendererConfig::view_priority`.
+    pub view_weight: f32,
 }
 
 impl VisibilitySnapshot {
@@ -65,8 +67,11 @@ impl VisibilitySnapshot {
 }
 
 pub struct VisibilityBuffers {
-    pub outputs: [Buffer; MAX_FRAMES_IN_FLIGHT],
-    pub readbacks: [Buffer; MAX_FRAMES_IN_FLIGHT],
+    pub outputs: Vec<Buffer>,
+    pub readbacks: Vec<Buffer>,
+    /// One bit per entry for each frame, set for sections known to have no
+    /// geometry, see [`Self::write_empty_mask`].
+    pub empty_masks: Vec<Buffer>,
     pub radius: i32,
     pub height: i32,
     pub entry_count: usize,
@@ -81,29 +86,50 @@ impl VisibilityBuffers {
         (count, bytes)
     }
 
+    fn create_empty_masks(ctx: &VkContext, entry_count: usize) -> Vec<Buffer> {
+        let bytes = (entry_count.div_ceil(32) * std::mem::size_of::<u32>()) as vk::DeviceSize;
+        (0..ctx.frames_in_flight())
+            .map(|_| {
+                Buffer::new(
+                    ctx,
+                    bytes,
+                    vk::BufferUsageFlags::STORAGE_BUFFER,
+                    vk_mem::MemoryUsage::AutoPreferHost,
+                    true,
+                )
+            })
+            .collect()
+    }
+
     pub fn new(ctx: &VkContext, radius: i32, height: i32) -> Self {
         let (entry_count, byte_size) = Self::calc(radius, height);
-        let outputs = from_fn(|_| {
-            Buffer::new(
-                ctx,
-                byte_size,
-                vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC,
-                vk_mem::MemoryUsage::AutoPreferDevice,
-                false,
-            )
-        });
-        let readbacks = from_fn(|_| {
-            Buffer::new(
-                ctx,
-                byte_size,
-                vk::BufferUsageFlags::TRANSFER_DST,
-                vk_mem::MemoryUsage::AutoPreferHost,
-                true,
-            )
-        });
+        let outputs = (0..ctx.frames_in_flight())
+            .map(|_| {
+                Buffer::new(
+                    ctx,
+                    byte_size,
+                    vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC,
+                    vk_mem::MemoryUsage::AutoPreferDevice,
+                    false,
+                )
+            })
+            .collect();
+        let readbacks = (0..ctx.frames_in_flight())
+            .map(|_| {
+                Buffer::new(
+                    ctx,
+                    byte_size,
+                    vk::BufferUsageFlags::TRANSFER_DST,
+                    vk_mem::MemoryUsage::AutoPreferHost,
+                    true,
+                )
+            })
+            .collect();
+        let empty_masks = Self::create_empty_masks(ctx, entry_count);
         Self {
             outputs,
             readbacks,
+            empty_masks,
             radius,
             height,
             entry_count,
@@ -121,31 +147,69 @@ impl VisibilityBuffers {
         for b in &mut self.readbacks {
             b.destroy(ctx);
         }
+        for b in &mut self.empty_masks {
+            b.destroy(ctx);
+        }
         let (entry_count, byte_size) = Self::calc(radius, height);
-        self.outputs = std::array::from_fn(|_| {
-            Buffer::new(
-                ctx,
-                byte_size,
-                vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC,
-                vk_mem::MemoryUsage::AutoPreferDevice,
-                false,
-            )
-        });
-        self.readbacks = std::array::from_fn(|_| {
-            Buffer::new(
-                ctx,
-                byte_size,
-                vk::BufferUsageFlags::TRANSFER_DST,
-                vk_mem::MemoryUsage::AutoPreferHost,
-                true,
-            )
-        });
+        self.outputs = (0..ctx.frames_in_flight())
+            .map(|_| {
+                Buffer::new(
+                    ctx,
+                    byte_size,
+                    vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC,
+                    vk_mem::MemoryUsage::AutoPreferDevice,
+                    false,
+                )
+            })
+            .collect();
+        self.readbacks = (0..ctx.frames_in_flight())
+            .map(|_| {
+                Buffer::new(
+                    ctx,
+                    byte_size,
+                    vk::BufferUsageFlags::TRANSFER_DST,
+                    vk_mem::MemoryUsage::AutoPreferHost,
+                    true,
+                )
+            })
+            .collect();
+        self.empty_masks = Self::create_empty_masks(ctx, entry_count);
         self.radius = radius;
         self.height = height;
         self.entry_count = entry_count;
         self.byte_size = byte_size;
     }
 
+    /// Sets the bits of `frame`'s empty mask for the sections in `empty` that
+    /// are inside the grid around the camera, clearing all others.
+    pub fn write_empty_mask<'a>(
+        &mut self,
+        ctx: &VkContext,
+        frame: usize,
+        camera_pos: Vec3,
+        min_y: i32,
+        empty: impl Iterator<Item = &'a ChunkSectionPos>,
+    ) {
+        let side = self.radius * 2 + 1;
+        let cx = (camera_pos.x / 16.0).floor() as i32;
+        let cz = (camera_pos.z / 16.0).floor() as i32;
+        let mut mask = vec![0u32; self.entry_count.div_ceil(32)];
+        for spos in empty {
+            let dx = spos.x - cx + self.radius;
+            let dy = spos.y - min_y / 16;
+            let dz = spos.z - cz + self.radius;
+            if !(0..side).contains(&dx)
+                || !(0..side).contains(&dz)
+                || !(0..self.height).contains(&dy)
+            {
+                continue;
+            }
+            let index = ((dy * side + dz) * side + dx) as usize;
+            mask[index / 32] |= 1 << (index % 32);
+        }
+        self.empty_masks[frame].upload_data(ctx, 0, &mask);
+    }
+
     pub fn copy_to_readback(&self, ctx: &VkContext, cmd: vk::CommandBuffer, frame_idx: usize) {
         self.outputs[frame_idx].copy_to(ctx, &self.readbacks[frame_idx], cmd);
     }
@@ -154,8 +218,9 @@ impl VisibilityBuffers {
         &mut self,
         ctx: &VkContext,
         frame_idx: usize,
-        cx: i32,
-        cz: i32,
+        camera_pos: Vec3,
+        forward: Vec3,
+        view_weight: f32,
         min_y: i32,
     ) -> VisibilitySnapshot {
         let allocator = ctx.allocator();
@@ -170,10 +235,13 @@ impl VisibilityBuffers {
         VisibilitySnapshot {
             radius: self.radius,
             height: self.height,
-            cx,
-            cz,
+            cx: (camera_pos.x / 16.0).floor() as i32,
+            cz: (camera_pos.z / 16.0).floor() as i32,
             min_y: min_y / 16,
             data,
+            camera_pos,
+            forward,
+            view_weight,
         }
     }
 
@@ -194,28 +262,36 @@ impl VisibilityBuffers {
         for (frame, b) in &mut self.readbacks.iter().enumerate() {
             sync.add_to_deletion_queue(frame, Box::new(b.clone()));
         }
+        for (frame, b) in &mut self.empty_masks.iter().enumerate() {
+            sync.add_to_deletion_queue(frame, Box::new(b.clone()));
+        }
 
         let (entry_count, byte_size) = Self::calc(new_radius, new_height);
 
-        self.outputs = std::array::from_fn(|_| {
-            Buffer::new(
-                ctx,
-                byte_size,
-                vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC,
-                vk_mem::MemoryUsage::AutoPreferDevice,
-                false,
-            )
-        });
-
-        self.readbacks = std::array::from_fn(|_| {
-            Buffer::new(
-                ctx,
-                byte_size,
-                vk::BufferUsageFlags::TRANSFER_DST,
-                vk_mem::MemoryUsage::AutoPreferHost,
-                true,
-            )
-        });
+        self.outputs = (0..ctx.frames_in_flight())
+            .map(|_| {
+                Buffer::new(
+                    ctx,
+                    byte_size,
+                    vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC,
+                    vk_mem::MemoryUsage::AutoPreferDevice,
+                    false,
+                )
+            })
+            .collect();
+
+        self.readbacks = (0..ctx.frames_in_flight())
+            .map(|_| {
+                Buffer::new(
+                    ctx,
+                    byte_size,
+                    vk::BufferUsageFlags::TRANSFER_DST,
+                    vk_mem::MemoryUsage::AutoPreferHost,
+                    true,
+                )
+            })
+            .collect();
+        self.empty_masks = Self::create_empty_masks(ctx, entry_count);
 
         self.radius = new_radius;
         self.height = new_height;
@@ -230,5 +306,8 @@ impl VisibilityBuffers {
         for b in &mut self.readbacks {
             b.destroy(ctx);
         }
+        for b in &mut self.empty_masks {
+            b.destroy(ctx);
+        }
     }
 }
@@ -6,7 +6,7 @@ use ash::{
 
 use crate::renderer::{
     frame_ctx::FrameCtx,
-    vulkan::{buffer::Buffer, context::VkContext, frame_sync::MAX_FRAMES_IN_FLIGHT},
+    vulkan::{buffer::Buffer, context::VkContext},
     world_renderer::{
         hiz::HiZPyramid, types::VisibilityUniform, visibility::buffers::VisibilityBuffers,
     },
@@ -21,7 +21,7 @@ pub struct VisibilityCompute {
     pub pool_frame: vk::DescriptorPool,
     pub pool_image: vk::DescriptorPool,
 
-    pub sets_frame: [vk::DescriptorSet; MAX_FRAMES_IN_FLIGHT],
+    pub sets_frame: Vec<vk::DescriptorSet>,
     pub sets_image: Vec<vk::DescriptorSet>,
 
     pub radius: i32,
@@ -32,12 +32,13 @@ impl VisibilityCompute {
     pub fn new(
         ctx: &VkContext,
         module: vk::ShaderModule,
-        uniform_buffers: &[Buffer; MAX_FRAMES_IN_FLIGHT],
+        uniform_buffers: &[Buffer],
         pyramids: &[HiZPyramid],
         radius: i32,
         height: i32,
     ) -> Self {
         let d = ctx.device();
+        let frames = uniform_buffers.len();
         let images = pyramids.len();
 
         let frame_bindings = [
@@ -51,6 +52,11 @@ impl VisibilityCompute {
                 .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
                 .descriptor_count(1)
                 .stage_flags(vk::ShaderStageFlags::COMPUTE),
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(2)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
         ];
         let layout_frame = unsafe {
             d.create_descriptor_set_layout(
@@ -73,12 +79,6 @@ impl VisibilityCompute {
             .unwrap()
         };
 
-        let entry = std::ffi::CString::new("visibility::cull_chunks").unwrap();
-        let stage = vk::PipelineShaderStageCreateInfo::default()
-            .stage(vk::ShaderStageFlags::COMPUTE)
-            .module(module)
-            .name(&entry);
-
         let set_layouts = [layout_frame, layout_image];
         let pipeline_layout = unsafe {
             d.create_pipeline_layout(
@@ -87,16 +87,7 @@ impl VisibilityCompute {
             )
             .unwrap()
         };
-        let pipeline = unsafe {
-            d.create_compute_pipelines(
-                vk::PipelineCache::null(),
-                &[vk::ComputePipelineCreateInfo::default()
-                    .stage(stage)
-                    .layout(pipeline_layout)],
-                None,
-            )
-            .unwrap()[0]
-        };
+        let pipeline = create_pipeline(ctx, module, pipeline_layout);
 
         let pool_frame = unsafe {
             d.create_descriptor_pool(
@@ -104,14 +95,14 @@ impl VisibilityCompute {
                     .pool_sizes(&[
                         vk::DescriptorPoolSize {
                             ty: vk::DescriptorType::STORAGE_BUFFER,
-                            descriptor_count: MAX_FRAMES_IN_FLIGHT as u32,
+                            descriptor_count: frames as u32 * 2,
                         },
                         vk::DescriptorPoolSize {
                             ty: vk::DescriptorType::UNIFORM_BUFFER,
-                            descriptor_count: MAX_FRAMES_IN_FLIGHT as u32,
+                            descriptor_count: frames as u32,
                         },
                     ])
-                    .max_sets(MAX_FRAMES_IN_FLIGHT as u32),
+                    .max_sets(frames as u32),
                 None,
             )
             .unwrap()
@@ -128,25 +119,19 @@ impl VisibilityCompute {
             )
             .unwrap()
         };
+        ctx.track_object(pool_frame);
+        ctx.track_object(pool_image);
 
-        let sets_frame = {
-            let layouts = [layout_frame; MAX_FRAMES_IN_FLIGHT];
-            let flat = unsafe {
-                d.allocate_descriptor_sets(
-                    &vk::DescriptorSetAllocateInfo::default()
-                        .descriptor_pool(pool_frame)
-                        .set_layouts(&layouts),
-                )
-                .unwrap()
-            };
-            let mut arr = [vk::DescriptorSet::null(); MAX_FRAMES_IN_FLIGHT];
-            for i in 0..MAX_FRAMES_IN_FLIGHT {
-                arr[i] = flat[i];
-            }
-            arr
+        let sets_frame = unsafe {
+            d.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::default()
+                    .descriptor_pool(pool_frame)
+                    .set_layouts(&vec![layout_frame; frames]),
+            )
+            .unwrap()
         };
 
-        for i in 0..MAX_FRAMES_IN_FLIGHT {
+        for i in 0..frames {
             unsafe {
                 d.update_descriptor_sets(
                     &[WriteDescriptorSet::default()
@@ -257,22 +242,42 @@ impl VisibilityCompute {
         }
     }
 
-    pub fn rewrite_frame_set(&self, device: &Device, frame_index: usize, output_buffer: &Buffer) {
+    /// Points `frame_index`'s set at its output buffer and empty mask in
+    /// `vis_buffers`.
+    pub fn rewrite_frame_set(
+        &self,
+        device: &Device,
+        frame_index: usize,
+        vis_buffers: &VisibilityBuffers,
+    ) {
         let out = vk::DescriptorBufferInfo {
-            buffer: output_buffer.buffer,
+            buffer: vis_buffers.outputs[frame_index].buffer,
             offset: 0,
-            range: output_buffer.size,
+            range: vis_buffers.outputs[frame_index].size,
         };
-        let write = vk::WriteDescriptorSet::default()
-            .dst_set(self.sets_frame[frame_index])
-            .dst_binding(0)
-            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-            .buffer_info(std::slice::from_ref(&out));
-        unsafe { device.update_descriptor_sets(std::slice::from_ref(&write), &[]) };
+        let empty = vk::DescriptorBufferInfo {
+            buffer: vis_buffers.empty_masks[frame_index].buffer,
+            offset: 0,
+            range: vis_buffers.empty_masks[frame_index].size,
+        };
+        let writes = [
+            vk::WriteDescriptorSet::default()
+                .dst_set(self.sets_frame[frame_index])
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(std::slice::from_ref(&out)),
+            vk::WriteDescriptorSet::default()
+                .dst_set(self.sets_frame[frame_index])
+                .dst_binding(2)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(std::slice::from_ref(&empty)),
+        ];
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
     }
 
     pub fn recreate_image_sets(&mut self, ctx: &VkContext, pyramids: &[HiZPyramid]) {
         let d = ctx.device();
+        ctx.untrack_object(self.pool_image);
         unsafe { d.destroy_descriptor_pool(self.pool_image, None) };
         let images = pyramids.len();
 
@@ -288,6 +293,7 @@ impl VisibilityCompute {
             )
             .unwrap()
         };
+        ctx.track_object(self.pool_image);
         self.sets_image = unsafe {
             d.allocate_descriptor_sets(
                 &vk::DescriptorSetAllocateInfo::default()
@@ -311,7 +317,17 @@ impl VisibilityCompute {
         }
     }
 
+    /// Rebuilds the pipeline from a new shader module, the device must be idle.
+    pub fn reload_shaders(&mut self, ctx: &VkContext, module: vk::ShaderModule) {
+        ctx.untrack_object(self.pipeline);
+        unsafe { ctx.device().destroy_pipeline(self.pipeline, None) };
+        self.pipeline = create_pipeline(ctx, module, self.pipeline_layout);
+    }
+
     pub fn destroy(&mut self, ctx: &VkContext) {
+        ctx.untrack_object(self.pipeline);
+        ctx.untrack_object(self.pool_frame);
+        ctx.untrack_object(self.pool_image);
         unsafe {
             let d = ctx.device();
             d.destroy_pipeline(self.pipeline, None);
@@ -323,3 +339,29 @@ impl VisibilityCompute {
         }
     }
 }
+
+fn create_pipeline(
+    ctx: &VkContext,
+    module: vk::ShaderModule,
+    pipeline_layout: vk::PipelineLayout,
+) -> vk::Pipeline {
+    let entry = std::ffi::CString::new("visibility::cull_chunks").unwrap();
+    let stage = vk::PipelineShaderStageCreateInfo::default()
+        .stage(vk::ShaderStageFlags::COMPUTE)
+        .module(module)
+        .name(&entry);
+
+    let pipeline = unsafe {
+        ctx.device()
+            .create_compute_pipelines(
+                vk::PipelineCache::null(),
+                &[vk::ComputePipelineCreateInfo::default()
+                    .stage(stage)
+                    .layout(pipeline_layout)],
+                None,
+            )
+            .unwrap()[0]
+    };
+    ctx.track_object(pipeline);
+    pipeline
+}
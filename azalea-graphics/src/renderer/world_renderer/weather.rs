@@ -0,0 +1,442 @@
+use std::ffi::CString;
+
+use ash::vk;
+use azalea::{
+    core::{position::ChunkPos, registry_holder::BiomeData},
+    world::{Instance, heightmap::HeightmapKind},
+};
+use azalea_assets::Assets;
+use glam::IVec2;
+use log::warn;
+use vk_mem::MemoryUsage;
+
+use crate::renderer::{
+    frame_ctx::FrameCtx,
+    render_targets::DEPTH_COMPARE_OP,
+    vulkan::{buffer::Buffer, context::VkContext, texture::Texture},
+};
+
+/// Half the side of the box around the camera that particles fall through, in
+/// blocks.
+const WEATHER_RADIUS: i32 = 24;
+/// Side of the grid of column heights that stop the particles under roofs.
+const GRID_SIDE: i32 = WEATHER_RADIUS * 2;
+/// Vertices for one particle quad.
+const QUAD_VERTICES: u32 = 6;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct WeatherPushConstants {
+    pub camera: [f32; 4],
+    pub grid_origin: [i32; 2],
+    pub grid_side: i32,
+    pub kind: u32,
+    pub radius: f32,
+    pub alpha: f32,
+}
+
+/// What falls from the sky where the camera is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Precipitation {
+    Rain,
+    Snow,
+}
+
+impl Precipitation {
+    /// What falls in `biome` at height `y`, it gets cold enough for snow higher
+    /// up like in vanilla, without the noise vanilla adds to the height.
+    pub fn in_biome(biome: &BiomeData, y: f32) -> Option<Self> {
+        if !biome.has_precipitation {
+            return None;
+        }
+        let temperature = biome.temperature - (y - 80.0).max(0.0) * 0.05 / 40.0;
+        Some(if temperature < 0.15 {
+            Self::Snow
+        } else {
+            Self::Rain
+        })
+    }
+}
+
+pub struct WeatherRenderer {
+    set_layout: vk::DescriptorSetLayout,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+    pool: vk::DescriptorPool,
+    sets: Vec<vk::DescriptorSet>,
+    rain: Texture,
+    snow: Texture,
+    /// Per frame heights of the columns around the camera, see
+    /// [`column_heights`].
+    heights: Vec<Buffer>,
+    grid: Vec<i32>,
+}
+
+impl WeatherRenderer {
+    pub fn new(
+        ctx: &VkContext,
+        module: vk::ShaderModule,
+        render_pass: vk::RenderPass,
+        world_set_layout: vk::DescriptorSetLayout,
+        assets: &Assets,
+    ) -> Self {
+        let device = ctx.device();
+        let frames = ctx.frames_in_flight();
+
+        let rain = load_texture(ctx, assets, "textures/environment/rain.png");
+        let snow = load_texture(ctx, assets, "textures/environment/snow.png");
+
+        let grid = vec![i32::MIN; (GRID_SIDE * GRID_SIDE) as usize];
+        let heights: Vec<_> = (0..frames)
+            .map(|_| {
+                Buffer::new(
+                    ctx,
+                    (grid.len() * size_of::<i32>()) as vk::DeviceSize,
+                    vk::BufferUsageFlags::STORAGE_BUFFER,
+                    MemoryUsage::AutoPreferHost,
+                    true,
+                )
+            })
+            .collect();
+
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(2)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::VERTEX),
+        ];
+        let set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings),
+                    None,
+                )
+                .unwrap()
+        };
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(2 * frames as u32),
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(frames as u32),
+        ];
+        let pool = unsafe {
+            device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::default()
+                        .pool_sizes(&pool_sizes)
+                        .max_sets(frames as u32),
+                    None,
+                )
+                .unwrap()
+        };
+        ctx.track_object(pool);
+        let layouts = vec![set_layout; frames];
+        let sets = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::default()
+                        .descriptor_pool(pool)
+                        .set_layouts(&layouts),
+                )
+                .unwrap()
+        };
+
+        let image_infos = [&rain, &snow].map(|texture| vk::DescriptorImageInfo {
+            sampler: texture.sampler,
+            image_view: texture.view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        });
+        for (set, buffer) in sets.iter().zip(&heights) {
+            let buffer_info = vk::DescriptorBufferInfo::default()
+                .buffer(buffer.buffer)
+                .range(vk::WHOLE_SIZE);
+            let writes = [
+                vk::WriteDescriptorSet::default()
+                    .dst_set(*set)
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(std::slice::from_ref(&image_infos[0])),
+                vk::WriteDescriptorSet::default()
+                    .dst_set(*set)
+                    .dst_binding(1)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(std::slice::from_ref(&image_infos[1])),
+                vk::WriteDescriptorSet::default()
+                    .dst_set(*set)
+                    .dst_binding(2)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(std::slice::from_ref(&buffer_info)),
+            ];
+            unsafe { device.update_descriptor_sets(&writes, &[]) };
+        }
+
+        let pipeline_layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::default()
+                        .set_layouts(&[world_set_layout, set_layout])
+                        .push_constant_ranges(&[vk::PushConstantRange {
+                            stage_flags: vk::ShaderStageFlags::VERTEX
+                                | vk::ShaderStageFlags::FRAGMENT,
+                            offset: 0,
+                            size: size_of::<WeatherPushConstants>() as u32,
+                        }]),
+                    None,
+                )
+                .unwrap()
+        };
+        let pipeline = Self::create_pipeline(ctx, module, render_pass, pipeline_layout);
+
+        Self {
+            set_layout,
+            pipeline_layout,
+            pipeline,
+            pool,
+            sets,
+            rain,
+            snow,
+            heights,
+            grid,
+        }
+    }
+
+    /// Rebuilds the pipeline from a new shader module, the device must be idle.
+    pub fn reload_shaders(
+        &mut self,
+        ctx: &VkContext,
+        module: vk::ShaderModule,
+        render_pass: vk::RenderPass,
+    ) {
+        ctx.untrack_object(self.pipeline);
+        unsafe { ctx.device().destroy_pipeline(self.pipeline, None) };
+        self.pipeline = Self::create_pipeline(ctx, module, render_pass, self.pipeline_layout);
+    }
+
+    fn create_pipeline(
+        ctx: &VkContext,
+        module: vk::ShaderModule,
+        render_pass: vk::RenderPass,
+        pipeline_layout: vk::PipelineLayout,
+    ) -> vk::Pipeline {
+        let device = ctx.device();
+
+        let vert_entry = CString::new("weather::weather_vert").unwrap();
+        let frag_entry = CString::new("weather::weather_frag").unwrap();
+        let stages = [
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(module)
+                .name(&vert_entry),
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(module)
+                .name(&frag_entry),
+        ];
+
+        // No vertex input (geometry generated in shader)
+        let vertex_input = vk::PipelineVertexInputStateCreateInfo::default();
+
+        let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+
+        let rasterizer = vk::PipelineRasterizationStateCreateInfo::default()
+            .polygon_mode(vk::PolygonMode::FILL)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .line_width(1.0);
+
+        let multisampling = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+        let color_blend_attachment = vk::PipelineColorBlendAttachmentState::default()
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ONE)
+            .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+            .alpha_blend_op(vk::BlendOp::ADD);
+
+        let color_blending = vk::PipelineColorBlendStateCreateInfo::default()
+            .attachments(std::slice::from_ref(&color_blend_attachment));
+
+        // translucent like clouds, hidden by terrain but never written
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(true)
+            .depth_write_enable(false)
+            .depth_compare_op(DEPTH_COMPARE_OP);
+
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state =
+            vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&stages)
+            .vertex_input_state(&vertex_input)
+            .input_assembly_state(&input_assembly)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterizer)
+            .multisample_state(&multisampling)
+            .color_blend_state(&color_blending)
+            .depth_stencil_state(&depth_stencil)
+            .dynamic_state(&dynamic_state)
+            .layout(pipeline_layout)
+            .render_pass(render_pass)
+            .subpass(0);
+
+        let pipeline = unsafe {
+            device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    std::slice::from_ref(&pipeline_info),
+                    None,
+                )
+                .unwrap()[0]
+        };
+        ctx.track_object(pipeline);
+        pipeline
+    }
+
+    /// Draws `particles` falling rain or snow particles around the camera,
+    /// must be called inside the world render pass.
+    pub fn draw(
+        &mut self,
+        frame_ctx: &FrameCtx,
+        world_set: vk::DescriptorSet,
+        world: &Instance,
+        precipitation: Precipitation,
+        particles: u32,
+        ticks: u64,
+    ) {
+        let camera_pos = frame_ctx.camera_pos;
+        let grid_origin = IVec2::new(camera_pos.x.floor() as i32, camera_pos.z.floor() as i32)
+            - IVec2::splat(WEATHER_RADIUS);
+        column_heights(world, grid_origin, &mut self.grid);
+        self.heights[frame_ctx.frame_index].upload_data(frame_ctx.ctx, 0, &self.grid);
+
+        let time = ticks as f64 + frame_ctx.partial_tick as f64;
+        let push_constants = WeatherPushConstants {
+            // wrap the time to keep float precision over long sessions, the
+            // particle pattern repeats long before this anyway
+            camera: camera_pos.extend((time % 65536.0) as f32).to_array(),
+            grid_origin: grid_origin.to_array(),
+            grid_side: GRID_SIDE,
+            kind: match precipitation {
+                Precipitation::Rain => 0,
+                Precipitation::Snow => 1,
+            },
+            radius: WEATHER_RADIUS as f32,
+            alpha: 0.8,
+        };
+
+        let device = frame_ctx.ctx.device();
+        let cmd = frame_ctx.cmd;
+        unsafe {
+            device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, self.pipeline);
+            device.cmd_bind_descriptor_sets(
+                cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[world_set, self.sets[frame_ctx.frame_index]],
+                &[],
+            );
+            device.cmd_push_constants(
+                cmd,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                0,
+                std::slice::from_raw_parts(
+                    &push_constants as *const _ as *const u8,
+                    std::mem::size_of::<WeatherPushConstants>(),
+                ),
+            );
+            device.cmd_draw(cmd, QUAD_VERTICES, particles, 0, 0);
+        }
+    }
+
+    pub fn destroy(&mut self, ctx: &VkContext) {
+        let device = ctx.device();
+        self.rain.destroy(ctx);
+        self.snow.destroy(ctx);
+        for buffer in &mut self.heights {
+            buffer.destroy(ctx);
+        }
+        ctx.untrack_object(self.pipeline);
+        ctx.untrack_object(self.pool);
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_descriptor_pool(self.pool, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+    }
+}
+
+/// Loads a weather texture, a missing one is replaced by a transparent pixel
+/// so the renderer still works without it.
+pub(super) fn load_texture(ctx: &VkContext, assets: &Assets, id: &str) -> Texture {
+    let image = image::open(assets.get_path(id))
+        .map(|image| image.into_rgba8())
+        .unwrap_or_else(|e| {
+            warn!("Failed to load {id}: {e}");
+            image::RgbaImage::new(1, 1)
+        });
+    Texture::from_image(ctx, image)
+}
+
+/// Fills `heights` with the lowest y above the highest motion blocking block
+/// of each column of the `GRID_SIDE` wide grid starting at `origin`, columns in
+/// unloaded chunks get `i32::MIN`.
+fn column_heights(world: &Instance, origin: IVec2, heights: &mut [i32]) {
+    heights.fill(i32::MIN);
+
+    let min_chunk = origin >> 4;
+    let max_chunk = (origin + IVec2::splat(GRID_SIDE - 1)) >> 4;
+    for cx in min_chunk.x..=max_chunk.x {
+        for cz in min_chunk.y..=max_chunk.y {
+            let Some(chunk) = world.chunks.get(&ChunkPos::new(cx, cz)) else {
+                continue;
+            };
+            let chunk = chunk.read();
+            let Some(heightmap) = chunk.heightmaps.get(&HeightmapKind::MotionBlocking) else {
+                continue;
+            };
+
+            for lx in 0..16 {
+                for lz in 0..16 {
+                    let x = cx * 16 + lx - origin.x;
+                    let z = cz * 16 + lz - origin.y;
+                    if (0..GRID_SIDE).contains(&x) && (0..GRID_SIDE).contains(&z) {
+                        heights[(z * GRID_SIDE + x) as usize] =
+                            heightmap.get_first_available(lx as u8, lz as u8);
+                    }
+                }
+            }
+        }
+    }
+}